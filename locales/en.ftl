@@ -0,0 +1,5 @@
+now-playing = Now playing: { $track }
+handoff-done = Playback handed off to { $host }
+unknown-rfid-tag = Unknown RFID tag: { $id }
+no-matching-tracks = No analyzed tracks match, analyzing library in background
+track-not-analyzed = Current track is not analyzed yet, analyzing library in background
@@ -0,0 +1,5 @@
+now-playing = Сейчас играет: { $track }
+handoff-done = Воспроизведение передано на { $host }
+unknown-rfid-tag = Неизвестная RFID-метка: { $id }
+no-matching-tracks = Нет подходящих проанализированных треков, библиотека анализируется в фоне
+track-not-analyzed = Текущий трек ещё не проанализирован, библиотека анализируется в фоне
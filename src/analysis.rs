@@ -0,0 +1,438 @@
+use rodio::{Decoder, Source};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+// Блок измерения громкости EBU R128 — 400 мс с шагом 100 мс
+const LOUDNESS_STEP_SECS: f32 = 0.1;
+const LOUDNESS_BLOCK_STEPS: usize = 4;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+// Сколько секунд трека анализируется; длительность считается по всему файлу
+const ANALYSIS_SECS: u32 = 120;
+const HOP: usize = 256;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+const FFT_SIZE: usize = 2048;
+// Спектр считается примерно раз в полсекунды, этого достаточно для среднего
+const FFT_STRIDE: usize = 22050;
+
+// Увеличивается при добавлении новых признаков, чтобы старые записи пересчитались
+pub const ANALYSIS_VERSION: u32 = 4;
+
+pub const MOODS: [&str; 4] = ["calm", "chill", "upbeat", "energetic"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TrackAnalysis {
+    pub version: u32,
+    pub mtime: u64,
+    pub duration: f32,
+    pub bpm: Option<f32>,
+    pub first_beat: f32,
+    // Средний уровень RMS, дБFS
+    pub energy: f32,
+    // Средний спектральный центроид, Гц
+    pub centroid: f32,
+    pub mood: Option<String>,
+    // Оценка истинного пика по всему файлу, дБTP
+    pub peak: f32,
+    // Интегральная громкость EBU R128 по всему файлу, LUFS
+    pub loudness: Option<f32>,
+}
+
+// Декодированный моно-сигнал, общий для всех видов анализа
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub duration: f32,
+    // Истинный пик по всем каналам всего файла, линейный
+    pub peak: f32,
+    pub loudness: Option<f32>,
+}
+
+pub fn decode(path: &Path, max_secs: u32) -> Result<DecodedAudio, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+    let total_duration = decoder.total_duration();
+    let limit = max_secs as usize * sample_rate as usize * channels;
+
+    let mut samples = Vec::with_capacity(limit / channels);
+    let mut frame_sum = 0.0f32;
+    let mut count = 0usize;
+    let mut peak = TruePeak::new(channels);
+    let mut loudness = Loudness::new(channels, sample_rate);
+
+    // Сигнал для анализа берётся из начала, а пик и громкость — по всему файлу
+    for sample in decoder {
+        let sample = sample as f32 / i16::MAX as f32;
+        peak.push(count % channels, sample);
+        loudness.push(count % channels, sample);
+        count += 1;
+        if count > limit {
+            continue;
+        }
+        frame_sum += sample;
+        if count.is_multiple_of(channels) {
+            samples.push(frame_sum / channels as f32);
+            frame_sum = 0.0;
+        }
+    }
+
+    let duration = match total_duration {
+        Some(d) => d.as_secs_f32(),
+        None => count as f32 / (sample_rate as f32 * channels as f32),
+    };
+
+    Ok(DecodedAudio {
+        samples,
+        sample_rate,
+        duration,
+        peak: peak.value,
+        loudness: loudness.integrated(),
+    })
+}
+
+// Пик с учётом межотсчётных выбросов: 4-кратная интерполяция Катмулла-Рома
+struct TruePeak {
+    history: Vec<[f32; 4]>,
+    value: f32,
+}
+
+impl TruePeak {
+    fn new(channels: usize) -> Self {
+        Self {
+            history: vec![[0.0; 4]; channels],
+            value: 0.0,
+        }
+    }
+
+    fn push(&mut self, channel: usize, sample: f32) {
+        let h = &mut self.history[channel];
+        h.rotate_left(1);
+        h[3] = sample;
+        self.value = self.value.max(sample.abs());
+
+        let [p0, p1, p2, p3] = *h;
+        for t in [0.25f32, 0.5, 0.75] {
+            let value = 0.5
+                * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+                    + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t);
+            self.value = self.value.max(value.abs());
+        }
+    }
+}
+
+// Громкость по EBU R128 / ITU-R BS.1770: K-фильтр, блоки по 400 мс,
+// абсолютный порог -70 LUFS и относительный на 10 LU ниже средней громкости
+struct Loudness {
+    filters: Vec<[Biquad; 2]>,
+    // Сумма квадратов текущего шага по всем каналам
+    step_energy: f64,
+    step_len: usize,
+    step_frames: usize,
+    channels: usize,
+    steps: Vec<f64>,
+}
+
+impl Loudness {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+        Self {
+            filters: vec![[Biquad::shelf(rate), Biquad::high_pass(rate)]; channels],
+            step_energy: 0.0,
+            step_len: ((sample_rate as f32 * LOUDNESS_STEP_SECS) as usize).max(1),
+            step_frames: 0,
+            channels,
+            steps: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, channel: usize, sample: f32) {
+        let [shelf, high_pass] = &mut self.filters[channel];
+        let weighted = high_pass.process(shelf.process(sample as f64));
+        self.step_energy += weighted * weighted;
+        if channel + 1 < self.channels {
+            return;
+        }
+        self.step_frames += 1;
+        if self.step_frames == self.step_len {
+            self.steps.push(self.step_energy / self.step_len as f64);
+            self.step_energy = 0.0;
+            self.step_frames = 0;
+        }
+    }
+
+    fn integrated(&self) -> Option<f32> {
+        let lufs = |energy: f64| -0.691 + 10.0 * energy.max(1e-20).log10();
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(LOUDNESS_BLOCK_STEPS)
+            .map(|w| w.iter().sum::<f64>() / LOUDNESS_BLOCK_STEPS as f64)
+            .filter(|&energy| lufs(energy) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let mean = blocks.iter().sum::<f64>() / blocks.len() as f64;
+        let threshold = lufs(mean) + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&energy| lufs(energy) > threshold)
+            .collect();
+        let mean = gated.iter().sum::<f64>() / gated.len().max(1) as f64;
+        Some(lufs(mean) as f32)
+    }
+}
+
+// Коэффициенты K-фильтра BS.1770, пересчитанные под частоту файла
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn shelf(rate: f64) -> Self {
+        let k = (std::f64::consts::PI * 1681.974450955533 / rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    fn high_pass(rate: f64) -> Self {
+        let k = (std::f64::consts::PI * 38.13547087602444 / rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    // Транспонированная прямая форма II
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+pub fn analyze(path: &Path) -> Result<TrackAnalysis, String> {
+    let audio = decode(path, ANALYSIS_SECS)?;
+
+    let hop_secs = HOP as f32 / audio.sample_rate as f32;
+    let onsets = onset_envelope(&audio.samples);
+    let tempo = detect_tempo(&onsets, hop_secs);
+
+    let energy = rms_db(&audio.samples);
+    let centroid = spectral_centroid(&audio.samples, audio.sample_rate);
+    let bpm = tempo.map(|(bpm, _)| bpm);
+
+    Ok(TrackAnalysis {
+        version: ANALYSIS_VERSION,
+        mtime: file_mtime(path),
+        duration: audio.duration,
+        bpm,
+        first_beat: tempo.map(|(_, beat)| beat).unwrap_or(0.0),
+        energy,
+        centroid,
+        mood: Some(classify_mood(bpm, energy, centroid).to_string()),
+        peak: 20.0 * (audio.peak + 1e-10).log10(),
+        loudness: audio.loudness,
+    })
+}
+
+fn rms_db(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return -96.0;
+    }
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    (10.0 * (mean_square + 1e-10).log10()).max(-96.0)
+}
+
+fn spectral_centroid(samples: &[f32], sample_rate: u32) -> f32 {
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect();
+    let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+
+    let mut total = 0.0;
+    let mut frames = 0;
+    let mut buffer = vec![Complex::new(0.0, 0.0); FFT_SIZE];
+
+    for start in (0..samples.len().saturating_sub(FFT_SIZE)).step_by(FFT_STRIDE) {
+        for (i, slot) in buffer.iter_mut().enumerate() {
+            *slot = Complex::new(samples[start + i] * window[i], 0.0);
+        }
+        fft.process(&mut buffer);
+
+        let (weighted, sum) = buffer[..FFT_SIZE / 2].iter().enumerate().fold(
+            (0.0, 0.0),
+            |(weighted, sum), (bin, c)| {
+                let magnitude = c.norm();
+                (weighted + bin as f32 * bin_hz * magnitude, sum + magnitude)
+            },
+        );
+
+        // Тишину не учитываем
+        if sum > 1e-3 {
+            total += weighted / sum;
+            frames += 1;
+        }
+    }
+
+    if frames == 0 {
+        0.0
+    } else {
+        total / frames as f32
+    }
+}
+
+// Нормированные признаки 0..1: темп, громкость, яркость
+pub fn feature_vector(analysis: &TrackAnalysis) -> [f32; 3] {
+    let scale = |value: f32, low: f32, high: f32| ((value - low) / (high - low)).clamp(0.0, 1.0);
+    [
+        scale(analysis.bpm.unwrap_or(110.0), 70.0, 150.0),
+        scale(analysis.energy, -30.0, -8.0),
+        scale(analysis.centroid, 1000.0, 4000.0),
+    ]
+}
+
+// Грубая оценка "возбуждения" трека по трём признакам
+fn classify_mood(bpm: Option<f32>, energy: f32, centroid: f32) -> &'static str {
+    let features = feature_vector(&TrackAnalysis {
+        bpm,
+        energy,
+        centroid,
+        ..Default::default()
+    });
+    let arousal = features.iter().sum::<f32>() / features.len() as f32;
+
+    match arousal {
+        a if a < 0.3 => MOODS[0],
+        a if a < 0.5 => MOODS[1],
+        a if a < 0.7 => MOODS[2],
+        _ => MOODS[3],
+    }
+}
+
+pub fn file_mtime(path: &Path) -> u64 {
+    path.metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Положительный прирост энергии по окнам HOP — грубая функция атак
+fn onset_envelope(samples: &[f32]) -> Vec<f32> {
+    let energies: Vec<f32> = samples
+        .chunks(HOP)
+        .map(|chunk| (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32 + 1e-9).ln())
+        .collect();
+
+    energies
+        .windows(2)
+        .map(|w| (w[1] - w[0]).max(0.0))
+        .collect()
+}
+
+// Автокорреляция функции атак; возвращает темп и время первой доли
+fn detect_tempo(onsets: &[f32], hop_secs: f32) -> Option<(f32, f32)> {
+    let min_lag = (60.0 / MAX_BPM / hop_secs).floor() as usize;
+    let max_lag = (60.0 / MIN_BPM / hop_secs).ceil() as usize;
+    if min_lag == 0 || onsets.len() < max_lag * 4 {
+        return None;
+    }
+
+    let correlation = |lag: usize| -> f32 {
+        onsets
+            .iter()
+            .zip(&onsets[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+    };
+
+    let scores: Vec<f32> = (min_lag..=max_lag + 1).map(correlation).collect();
+    let (best, _) = scores[..scores.len() - 1]
+        .iter()
+        .enumerate()
+        .skip(1)
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+
+    // Параболическая интерполяция пика для точности меньше одного окна
+    let (prev, peak, next) = (scores[best - 1], scores[best], scores[best + 1]);
+    let denom = prev - 2.0 * peak + next;
+    let shift = if denom.abs() > f32::EPSILON {
+        (0.5 * (prev - next) / denom).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let lag = (min_lag + best) as f32 + shift;
+
+    let mut bpm = 60.0 / (lag * hop_secs);
+    while bpm < 80.0 {
+        bpm *= 2.0;
+    }
+    while bpm > 160.0 {
+        bpm /= 2.0;
+    }
+
+    // Фаза сетки долей: сдвиг с наибольшей суммой атак
+    let period = (min_lag + best).max(1);
+    let phase = (0..period)
+        .max_by(|&a, &b| {
+            let sum = |p: usize| onsets.iter().skip(p).step_by(period).sum::<f32>();
+            sum(a).total_cmp(&sum(b))
+        })
+        .unwrap_or(0);
+
+    Some(((bpm * 10.0).round() / 10.0, phase as f32 * hop_secs))
+}
+
+pub fn beat_period(analysis: &TrackAnalysis) -> Option<Duration> {
+    analysis
+        .bpm
+        .filter(|bpm| *bpm > 0.0)
+        .map(|bpm| Duration::from_secs_f32(60.0 / bpm))
+}
+
+// Диапазон темпа вида "120-130" или "124"
+pub fn parse_bpm_range(value: &str) -> Option<(f32, f32)> {
+    match value.split_once('-') {
+        Some((low, high)) => {
+            let (low, high) = (low.trim().parse().ok()?, high.trim().parse().ok()?);
+            (low <= high).then_some((low, high))
+        }
+        None => {
+            let bpm: f32 = value.trim().parse().ok()?;
+            Some((bpm - 1.0, bpm + 1.0))
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::tags;
+use image::imageops::FilterType;
+use image::ImageFormat;
+use std::io::Cursor;
+use std::path::Path;
+use symphonia::core::meta::{StandardVisualKey, Visual};
+
+// Имена файлов обложки рядом с треком, в порядке предпочтения
+const FOLDER_COVERS: [&str; 4] = ["cover", "folder", "front", "album"];
+
+pub struct Cover {
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+// Встроенная в теги обложка, иначе cover.jpg и подобные из папки трека
+pub fn find_cover(path: &Path) -> Option<Cover> {
+    embedded_cover(path).or_else(|| folder_cover(path))
+}
+
+fn embedded_cover(path: &Path) -> Option<Cover> {
+    let visuals: Vec<Visual> = tags::probe(path)?
+        .revisions
+        .iter()
+        .flat_map(|revision| revision.visuals().iter().cloned())
+        .collect();
+
+    let visual = visuals
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .or_else(|| visuals.first())?;
+    Some(Cover {
+        mime: visual.media_type.clone(),
+        data: visual.data.to_vec(),
+    })
+}
+
+fn folder_cover(path: &Path) -> Option<Cover> {
+    let dir = path.parent()?;
+    for name in FOLDER_COVERS {
+        for (ext, mime) in [
+            ("jpg", "image/jpeg"),
+            ("jpeg", "image/jpeg"),
+            ("png", "image/png"),
+        ] {
+            let candidate = dir.join(format!("{}.{}", name, ext));
+            if let Ok(data) = std::fs::read(&candidate) {
+                return Some(Cover {
+                    mime: mime.to_string(),
+                    data,
+                });
+            }
+        }
+    }
+    None
+}
+
+// JPEG со стороной не больше `size`; без size исходный JPEG отдаётся как есть
+pub fn to_jpeg(cover: &Cover, size: Option<u32>) -> Result<Vec<u8>, String> {
+    if size.is_none() && cover.mime == "image/jpeg" {
+        return Ok(cover.data.clone());
+    }
+
+    let mut image = image::load_from_memory(&cover.data).map_err(|e| e.to_string())?;
+    if let Some(size) = size {
+        if image.width() > size || image.height() > size {
+            image = image.resize(size, size, FilterType::Triangle);
+        }
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    image
+        .into_rgb8()
+        .write_to(&mut out, ImageFormat::Jpeg)
+        .map_err(|e| e.to_string())?;
+    Ok(out.into_inner())
+}
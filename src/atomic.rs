@@ -0,0 +1,36 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Запись через временный файл рядом и rename: при сбое или отключении питания
+// на диске остаётся либо старый файл, либо новый целиком, но не обрезанный
+pub fn write(path: &Path, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let temp = sibling(path, &format!(".tmp-{}", std::process::id()));
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(data.as_ref())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+    // Сам rename надёжен, только когда записана и папка
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// "config.json" + ".bak" -> "config.json.bak"
+pub fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
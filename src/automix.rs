@@ -0,0 +1,70 @@
+use crate::clock;
+use rodio::Sink;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const FADE_STEPS: u32 = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AutomixConfig {
+    pub enabled: bool,
+    pub crossfade_secs: f32,
+    pub match_tempo: bool,
+    // Максимальное относительное изменение скорости входящего трека
+    pub max_tempo_change: f32,
+}
+
+impl Default for AutomixConfig {
+    fn default() -> Self {
+        AutomixConfig {
+            enabled: false,
+            crossfade_secs: 8.0,
+            match_tempo: true,
+            max_tempo_change: 0.08,
+        }
+    }
+}
+
+impl AutomixConfig {
+    pub fn crossfade(&self) -> Duration {
+        Duration::from_secs_f32(self.crossfade_secs.max(0.0))
+    }
+
+    // Скорость входящего трека, при которой его темп совпадёт с текущим
+    pub fn tempo_ratio(&self, outgoing_bpm: Option<f32>, incoming_bpm: Option<f32>) -> f32 {
+        if !self.match_tempo {
+            return 1.0;
+        }
+
+        match (outgoing_bpm, incoming_bpm) {
+            (Some(out), Some(inc)) if inc > 0.0 => {
+                let ratio = out / inc;
+                if (ratio - 1.0).abs() <= self.max_tempo_change {
+                    ratio
+                } else {
+                    1.0
+                }
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+// Уводит громкость уходящего трека в ноль и поднимает громкость нового
+pub fn crossfade(old: Sink, new: Arc<Mutex<Sink>>, volume: f32, duration: Duration) {
+    thread::spawn(move || {
+        let step = duration / FADE_STEPS;
+
+        for i in 1..=FADE_STEPS {
+            let t = i as f32 / FADE_STEPS as f32;
+            old.set_volume(volume * (1.0 - t));
+            new.lock().unwrap().set_volume(volume * t);
+            clock::sleep(step);
+        }
+
+        old.stop();
+    });
+}
@@ -0,0 +1,233 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Время проверяется раз в столько отсчётов, а не на каждом
+const CHECK_SAMPLES: u64 = 64;
+// Пауза между выборками длиннее этого — новый период вывода
+const BURST_GAP: Duration = Duration::from_millis(1);
+// Дольше вывод не опрашивает источник только на паузе или при перемотке
+const IDLE_GAP: Duration = Duration::from_millis(500);
+// Запас на дрожание планировщика, прежде чем считать буфер опустевшим
+const UNDERRUN_MARGIN: f64 = 0.005;
+// Позиция не движется при воспроизведении — вывод завис
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+// Столько срывов за окно — вывод переоткрывается
+const UNDERRUN_BURST: u64 = 10;
+const UNDERRUN_WINDOW: Duration = Duration::from_secs(10);
+// Частота, для которой задаётся период в PIPEWIRE_LATENCY
+const HINT_RATE: u32 = 48000;
+
+static UNDERRUNS: AtomicU64 = AtomicU64::new(0);
+static PERIOD_US: AtomicU64 = AtomicU64::new(0);
+static LATENCY_US: AtomicU64 = AtomicU64::new(0);
+
+// Размер периода в кадрах и число периодов в буфере вывода
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct BufferConfig {
+    pub period_frames: Option<u32>,
+    pub periods: Option<u32>,
+}
+
+impl BufferConfig {
+    // rodio не даёт задать размер буфера cpal, поэтому он передаётся звуковому
+    // серверу через окружение. Вызывается до открытия вывода
+    pub fn apply(&self) {
+        let Some(frames) = self.period_frames else {
+            return;
+        };
+        let periods = self.periods.unwrap_or(2).max(1);
+        env::set_var("PIPEWIRE_LATENCY", format!("{}/{}", frames, HINT_RATE));
+        let total_ms = (frames * periods) as f64 * 1000.0 / HINT_RATE as f64;
+        env::set_var("PULSE_LATENCY_MSEC", format!("{}", total_ms.ceil() as u64));
+    }
+}
+
+pub fn underruns() -> u64 {
+    UNDERRUNS.load(Ordering::Relaxed)
+}
+
+// Средний период, которым вывод забирает звук, мс
+pub fn period_ms() -> f64 {
+    PERIOD_US.load(Ordering::Relaxed) as f64 / 1000.0
+}
+
+// Сколько звука успевает накопиться в буфере вывода, мс
+pub fn latency_ms() -> f64 {
+    LATENCY_US.load(Ordering::Relaxed) as f64 / 1000.0
+}
+
+// Проверяется главным циклом: пишет срывы в журнал и решает, когда вывод
+// пора переоткрыть с той же позиции
+pub struct Watchdog {
+    last_position: Duration,
+    last_progress: Instant,
+    underruns: u64,
+    window_start: Instant,
+    window_underruns: u64,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            last_position: Duration::ZERO,
+            last_progress: Instant::now(),
+            underruns: underruns(),
+            window_start: Instant::now(),
+            window_underruns: 0,
+        }
+    }
+
+    // Причина переоткрыть вывод, если он завис или часто срывается
+    pub fn check(&mut self, playing: bool, position: Duration) -> Option<String> {
+        let total = underruns();
+        if total > self.underruns {
+            eprintln!("Audio underrun ({} total)", total);
+            self.window_underruns += total - self.underruns;
+            self.underruns = total;
+        }
+        if self.window_start.elapsed() >= UNDERRUN_WINDOW {
+            self.window_start = Instant::now();
+            self.window_underruns = 0;
+        }
+        if self.window_underruns >= UNDERRUN_BURST {
+            let count = self.window_underruns;
+            self.window_underruns = 0;
+            self.last_progress = Instant::now();
+            return Some(format!(
+                "{} underruns in {}s",
+                count,
+                UNDERRUN_WINDOW.as_secs()
+            ));
+        }
+
+        if !playing || position != self.last_position {
+            self.last_position = position;
+            self.last_progress = Instant::now();
+        } else if self.last_progress.elapsed() >= STALL_TIMEOUT {
+            self.last_progress = Instant::now();
+            return Some(format!("output stalled at {:.1}s", position.as_secs_f32()));
+        }
+        None
+    }
+}
+
+// Следит, как вывод забирает отсчёты. Между выборками буфер расходуется
+// в реальном времени; если он успел бы опустеть — вывод недополучил звук
+pub struct Monitor<S> {
+    input: S,
+    rate: f64,
+    samples: u64,
+    last_check: Option<Instant>,
+    burst_samples: u64,
+    // Оценка звука в буфере вывода, с
+    buffered: f64,
+    // Наибольшее замеченное заполнение буфера, с
+    capacity: f64,
+}
+
+impl<S> Monitor<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S) -> Self {
+        let rate = (input.sample_rate() * input.channels() as u32).max(1) as f64;
+        Self {
+            input,
+            rate,
+            samples: 0,
+            last_check: None,
+            burst_samples: 0,
+            buffered: 0.0,
+            capacity: 0.0,
+        }
+    }
+
+    fn check(&mut self) {
+        let now = Instant::now();
+        let Some(last) = self.last_check.replace(now) else {
+            return;
+        };
+        let gap = now - last;
+        self.burst_samples += CHECK_SAMPLES;
+        if gap < BURST_GAP {
+            return;
+        }
+
+        // Закончилась выборка одного периода
+        let burst = self.burst_samples as f64 / self.rate;
+        self.burst_samples = 0;
+        if gap >= IDLE_GAP {
+            self.buffered = 0.0;
+            return;
+        }
+
+        self.buffered += burst;
+        self.capacity = self.capacity.max(self.buffered);
+        self.buffered = self.buffered.min(self.capacity) - gap.as_secs_f64();
+        if self.buffered < -UNDERRUN_MARGIN {
+            UNDERRUNS.fetch_add(1, Ordering::Relaxed);
+            self.buffered = 0.0;
+        }
+
+        store_smoothed(&PERIOD_US, burst);
+        store_smoothed(&LATENCY_US, self.capacity);
+    }
+}
+
+fn store_smoothed(value: &AtomicU64, secs: f64) {
+    let old = value.load(Ordering::Relaxed) as f64;
+    let new = secs * 1_000_000.0;
+    let smoothed = if old == 0.0 {
+        new
+    } else {
+        old * 0.9 + new * 0.1
+    };
+    value.store(smoothed as u64, Ordering::Relaxed);
+}
+
+impl<S> Iterator for Monitor<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        self.samples += 1;
+        if self.samples.is_multiple_of(CHECK_SAMPLES) {
+            self.check();
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for Monitor<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.last_check = None;
+        self.input.try_seek(pos)
+    }
+}
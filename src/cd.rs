@@ -0,0 +1,571 @@
+use crate::digest::{base64, sha1};
+use crate::plugin::{Control, InputSource, TrackMetadata};
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+// Ioctl из linux/cdrom.h
+const CDROMREADTOCHDR: libc::c_ulong = 0x5305;
+const CDROMREADTOCENTRY: libc::c_ulong = 0x5306;
+const CDROMREADAUDIO: libc::c_ulong = 0x530e;
+const CDROM_DRIVE_STATUS: libc::c_ulong = 0x5326;
+const CDROM_DISC_STATUS: libc::c_ulong = 0x5327;
+const CDSL_CURRENT: libc::c_int = libc::c_int::MAX;
+const CDS_DISC_OK: libc::c_int = 4;
+const CDS_AUDIO: libc::c_int = 100;
+const CDS_MIXED: libc::c_int = 105;
+const CDROM_LBA: u8 = 0x01;
+const CDROM_LEADOUT: u8 = 0xAA;
+const CDROM_DATA_TRACK: u8 = 0x04;
+
+const RATE: u32 = 44100;
+// Сектор CD-DA: 588 стереокадров по 16 бит
+const SECTOR_BYTES: usize = 2352;
+const SECTORS_PER_SECOND: u32 = 75;
+// Секторов за одно чтение
+const READ_SECTORS: u32 = 25;
+// Смещение первого сектора: двухсекундный зазор перед первым треком
+const LBA_OFFSET: u32 = 150;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// Трек диска в очереди: "cdda:/dev/cdrom#3"
+const SCHEME: &str = "cdda:";
+const MUSICBRAINZ: &str = "https://musicbrainz.org/ws/2/discid";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CdConfig {
+    pub device: PathBuf,
+    // Следить за приводом и сообщать о вставленном диске
+    pub detect: bool,
+    // Сразу ставить вставленный диск в очередь
+    pub autoplay: bool,
+    // Названия треков по идентификатору диска; запрос идёт через curl
+    pub musicbrainz: bool,
+    pub flac_path: PathBuf,
+    // Куда складывать рип; по умолчанию папка музыки
+    pub rip_dir: Option<PathBuf>,
+}
+
+impl Default for CdConfig {
+    fn default() -> Self {
+        Self {
+            device: PathBuf::from("/dev/cdrom"),
+            detect: false,
+            autoplay: false,
+            musicbrainz: true,
+            flac_path: PathBuf::from("flac"),
+            rip_dir: None,
+        }
+    }
+}
+
+impl InputSource for CdConfig {
+    fn name(&self) -> &str {
+        "cd"
+    }
+
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        let mut inserted = false;
+        loop {
+            let audio = has_audio_disc(&self.device);
+            if audio && !inserted {
+                println!("Audio CD inserted in {}", self.device.display());
+                if self.autoplay {
+                    let _ = control.send("cd play");
+                }
+            }
+            inserted = audio;
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+pub fn track_path(device: &Path, number: u8) -> PathBuf {
+    PathBuf::from(format!("{}{}#{}", SCHEME, device.display(), number))
+}
+
+// "cdda:/dev/cdrom#3" -> ("/dev/cdrom", 3)
+pub fn split(path: &Path) -> Option<(PathBuf, u8)> {
+    let text = path.to_str()?.strip_prefix(SCHEME)?;
+    let (device, number) = text.rsplit_once('#')?;
+    Some((PathBuf::from(device), number.parse().ok()?))
+}
+
+pub fn is_cd_track(path: &Path) -> bool {
+    split(path).is_some()
+}
+
+// Без O_NONBLOCK открытие ждёт диск в приводе
+fn open_device(device: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(device)
+}
+
+fn has_audio_disc(device: &Path) -> bool {
+    let Ok(file) = open_device(device) else {
+        return false;
+    };
+    let fd = file.as_raw_fd();
+    let drive = unsafe { libc::ioctl(fd, CDROM_DRIVE_STATUS, CDSL_CURRENT) };
+    if drive != CDS_DISC_OK {
+        return false;
+    }
+    let disc = unsafe { libc::ioctl(fd, CDROM_DISC_STATUS, 0) };
+    disc == CDS_AUDIO || disc == CDS_MIXED
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct TocHeader {
+    first: u8,
+    last: u8,
+}
+
+// struct cdrom_tocentry с адресом в виде LBA
+#[repr(C)]
+#[derive(Default)]
+struct TocEntry {
+    track: u8,
+    // Младшие 4 бита — adr, старшие — ctrl
+    adr_ctrl: u8,
+    format: u8,
+    lba: libc::c_int,
+    datamode: u8,
+}
+
+#[repr(C)]
+struct ReadAudio {
+    lba: libc::c_int,
+    addr_format: u8,
+    frames: libc::c_int,
+    buf: *mut u8,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TocTrack {
+    pub number: u8,
+    pub start: u32,
+    pub end: u32,
+    pub audio: bool,
+}
+
+impl TocTrack {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64((self.end - self.start) as f64 / SECTORS_PER_SECOND as f64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Toc {
+    pub first: u8,
+    pub last: u8,
+    pub tracks: Vec<TocTrack>,
+    pub leadout: u32,
+}
+
+pub fn read_toc(device: &Path) -> Result<Toc, String> {
+    if !has_audio_disc(device) {
+        return Err(format!("no audio CD in {}", device.display()));
+    }
+    let file = open_device(device).map_err(|e| format!("{}: {}", device.display(), e))?;
+    let fd = file.as_raw_fd();
+    let mut header = TocHeader::default();
+    if unsafe { libc::ioctl(fd, CDROMREADTOCHDR, &mut header) } < 0 {
+        return Err(io::Error::last_os_error().to_string());
+    }
+    let entry = |track: u8| -> Result<TocEntry, String> {
+        let mut entry = TocEntry {
+            track,
+            format: CDROM_LBA,
+            ..Default::default()
+        };
+        if unsafe { libc::ioctl(fd, CDROMREADTOCENTRY, &mut entry) } < 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(entry)
+    };
+
+    let leadout = entry(CDROM_LEADOUT)?.lba.max(0) as u32;
+    let mut tracks = Vec::new();
+    for number in header.first..=header.last {
+        let entry = entry(number)?;
+        tracks.push(TocTrack {
+            number,
+            start: entry.lba.max(0) as u32,
+            end: leadout,
+            audio: (entry.adr_ctrl >> 4) & CDROM_DATA_TRACK == 0,
+        });
+    }
+    for i in 1..tracks.len() {
+        tracks[i - 1].end = tracks[i].start;
+    }
+    Ok(Toc {
+        first: header.first,
+        last: header.last,
+        tracks,
+        leadout,
+    })
+}
+
+impl Toc {
+    // Идентификатор диска MusicBrainz: SHA-1 от номеров треков и смещений,
+    // base64 с ".", "_" и "-" вместо "+", "/" и "="
+    pub fn disc_id(&self) -> String {
+        let mut text = format!("{:02X}{:02X}", self.first, self.last);
+        text.push_str(&format!("{:08X}", self.leadout + LBA_OFFSET));
+        for number in 1..100u8 {
+            let offset = self
+                .tracks
+                .iter()
+                .find(|track| track.number == number)
+                .map(|track| track.start + LBA_OFFSET)
+                .unwrap_or(0);
+            text.push_str(&format!("{:08X}", offset));
+        }
+        base64(&sha1(text.as_bytes()))
+            .replace('+', ".")
+            .replace('/', "_")
+            .replace('=', "-")
+    }
+
+    pub fn audio_tracks(&self) -> impl Iterator<Item = &TocTrack> {
+        self.tracks.iter().filter(|track| track.audio)
+    }
+
+    pub fn track(&self, number: u8) -> Option<&TocTrack> {
+        self.tracks.iter().find(|track| track.number == number)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Release {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    // Номер трека -> название
+    pub tracks: HashMap<u8, String>,
+}
+
+// Ответы MusicBrainz по идентификатору диска; неудачный запрос тоже запоминается
+fn releases() -> &'static Mutex<HashMap<String, Option<Release>>> {
+    static RELEASES: OnceLock<Mutex<HashMap<String, Option<Release>>>> = OnceLock::new();
+    RELEASES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn cached_release(disc_id: &str) -> Option<Release> {
+    releases().lock().unwrap().get(disc_id).cloned().flatten()
+}
+
+pub fn lookup(disc_id: &str) -> Option<Release> {
+    if let Some(release) = releases().lock().unwrap().get(disc_id) {
+        return release.clone();
+    }
+    let release = match fetch_release(disc_id) {
+        Ok(release) => Some(release),
+        Err(e) => {
+            eprintln!("MusicBrainz lookup for {} failed: {}", disc_id, e);
+            None
+        }
+    };
+    releases()
+        .lock()
+        .unwrap()
+        .insert(disc_id.to_string(), release.clone());
+    release
+}
+
+fn fetch_release(disc_id: &str) -> Result<Release, String> {
+    let url = format!(
+        "{}/{}?inc=artist-credits+recordings&fmt=json",
+        MUSICBRAINZ, disc_id
+    );
+    let output = Command::new("curl")
+        .args(["-sfL", "--max-time", "10", "-A", "NSmp/0.1", &url])
+        .output()
+        .map_err(|e| format!("curl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("curl exited with {}", output.status));
+    }
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+    let release = json["releases"]
+        .get(0)
+        .ok_or("disc is not in MusicBrainz")?;
+
+    let artist = release["artist-credit"].as_array().map(|credits| {
+        credits
+            .iter()
+            .map(|credit| {
+                format!(
+                    "{}{}",
+                    credit["name"].as_str().unwrap_or_default(),
+                    credit["joinphrase"].as_str().unwrap_or_default()
+                )
+            })
+            .collect::<String>()
+    });
+    // Носитель, к которому относится этот диск, а не первый в многодисковом издании
+    let media = release["media"].as_array().cloned().unwrap_or_default();
+    let medium = media
+        .iter()
+        .find(|medium| {
+            medium["discs"]
+                .as_array()
+                .is_some_and(|discs| discs.iter().any(|disc| disc["id"] == disc_id))
+        })
+        .or(media.first());
+    let tracks = medium
+        .and_then(|medium| medium["tracks"].as_array())
+        .map(|tracks| {
+            tracks
+                .iter()
+                .filter_map(|track| {
+                    let number = track["position"].as_u64()? as u8;
+                    Some((number, track["title"].as_str()?.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Release {
+        title: release["title"].as_str().map(str::to_string),
+        artist: artist.filter(|artist| !artist.is_empty()),
+        tracks,
+    })
+}
+
+// Название трека из MusicBrainz, если диск уже искали, иначе "Track N"
+pub fn probe(path: &Path) -> Option<(TrackMetadata, f32)> {
+    let (device, number) = split(path)?;
+    let toc = read_toc(&device).ok()?;
+    let track = toc.track(number)?;
+    let release = cached_release(&toc.disc_id()).unwrap_or_default();
+    let tags = TrackMetadata {
+        path: path.to_path_buf(),
+        title: release
+            .tracks
+            .get(&number)
+            .cloned()
+            .unwrap_or_else(|| format!("Track {}", number)),
+        artist: release.artist,
+        album: release.title,
+        track_number: Some(number as u32),
+    };
+    Some((tags, track.duration().as_secs_f32()))
+}
+
+struct Reader {
+    file: File,
+}
+
+impl Reader {
+    fn open(device: &Path) -> Result<Self, String> {
+        let file = open_device(device).map_err(|e| format!("{}: {}", device.display(), e))?;
+        Ok(Self { file })
+    }
+
+    // Сырые сектора CD-DA: 16-битные отсчёты little-endian, левый канал первым
+    fn read(&self, lba: u32, sectors: u32, buf: &mut Vec<u8>) -> Result<(), String> {
+        buf.resize(sectors as usize * SECTOR_BYTES, 0);
+        let mut request = ReadAudio {
+            lba: lba as libc::c_int,
+            addr_format: CDROM_LBA,
+            frames: sectors as libc::c_int,
+            buf: buf.as_mut_ptr(),
+        };
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), CDROMREADAUDIO, &mut request) } < 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+}
+
+pub struct CdSource {
+    reader: Reader,
+    start: u32,
+    end: u32,
+    // Следующий сектор для чтения
+    lba: u32,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl CdSource {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let (device, number) = split(path).ok_or("not a CD track")?;
+        let toc = read_toc(&device)?;
+        let track = toc
+            .track(number)
+            .filter(|track| track.audio)
+            .ok_or_else(|| format!("no audio track {} on the disc", number))?;
+        Ok(Self {
+            reader: Reader::open(&device)?,
+            start: track.start,
+            end: track.end,
+            lba: track.start,
+            buffer: Vec::new(),
+            position: 0,
+        })
+    }
+
+    fn fill(&mut self) -> bool {
+        if self.lba >= self.end {
+            return false;
+        }
+        let sectors = READ_SECTORS.min(self.end - self.lba);
+        if let Err(e) = self.reader.read(self.lba, sectors, &mut self.buffer) {
+            eprintln!("CD read error at sector {}: {}", self.lba, e);
+            return false;
+        }
+        self.lba += sectors;
+        self.position = 0;
+        true
+    }
+}
+
+impl Iterator for CdSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position + 1 >= self.buffer.len() && !self.fill() {
+            return None;
+        }
+        let sample =
+            i16::from_le_bytes([self.buffer[self.position], self.buffer[self.position + 1]]);
+        self.position += 2;
+        Some(sample as f32 / 32768.0)
+    }
+}
+
+impl Source for CdSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(
+            (self.end - self.start) as f64 / SECTORS_PER_SECOND as f64,
+        ))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let sector = (pos.as_secs_f64() * SECTORS_PER_SECOND as f64) as u32;
+        self.lba = (self.start + sector).min(self.end);
+        self.buffer.clear();
+        self.position = 0;
+        Ok(())
+    }
+}
+
+// "Artist/Album/01 Title.flac" без символов, недопустимых в именах файлов
+fn file_name(text: &str) -> String {
+    let cleaned: String = text
+        .chars()
+        .map(|c| if matches!(c, '/' | '\0') { '_' } else { c })
+        .collect();
+    match cleaned.trim() {
+        "" | "." | ".." => "_".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// Каждый аудиотрек кодируется внешним flac в свой файл; возвращает пути файлов
+pub fn rip(config: &CdConfig, dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let toc = read_toc(&config.device)?;
+    let release = if config.musicbrainz {
+        lookup(&toc.disc_id()).unwrap_or_default()
+    } else {
+        Release::default()
+    };
+    let artist = release
+        .artist
+        .clone()
+        .unwrap_or_else(|| "Unknown Artist".to_string());
+    let album = release.title.clone().unwrap_or_else(|| toc.disc_id());
+    let dir = dir.join(file_name(&artist)).join(file_name(&album));
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+    let reader = Reader::open(&config.device)?;
+    let mut ripped = Vec::new();
+    let mut buf = Vec::new();
+    for track in toc.audio_tracks() {
+        let title = release
+            .tracks
+            .get(&track.number)
+            .cloned()
+            .unwrap_or_else(|| format!("Track {}", track.number));
+        let path = dir.join(format!("{:02} {}.flac", track.number, file_name(&title)));
+        let tags = [
+            format!("TITLE={}", title),
+            format!("ARTIST={}", artist),
+            format!("ALBUM={}", album),
+            format!("TRACKNUMBER={}", track.number),
+            format!("MUSICBRAINZ_DISCID={}", toc.disc_id()),
+        ];
+        let mut command = Command::new(&config.flac_path);
+        command.args([
+            "--silent",
+            "--force",
+            "--force-raw-format",
+            "--endian=little",
+            "--sign=signed",
+            "--channels=2",
+            "--bps=16",
+            "--sample-rate=44100",
+        ]);
+        for tag in &tags {
+            command.arg("-T").arg(tag);
+        }
+        let mut child = command
+            .arg("-o")
+            .arg(&path)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", config.flac_path.display(), e))?;
+
+        let mut stdin = child.stdin.take().ok_or("flac has no stdin")?;
+        let mut lba = track.start;
+        let mut result = Ok(());
+        while lba < track.end && result.is_ok() {
+            let sectors = READ_SECTORS.min(track.end - lba);
+            result = reader
+                .read(lba, sectors, &mut buf)
+                .and_then(|()| stdin.write_all(&buf).map_err(|e| e.to_string()));
+            lba += sectors;
+        }
+        drop(stdin);
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if let Err(e) = result {
+            let _ = fs::remove_file(&path);
+            return Err(format!("track {}: {}", track.number, e));
+        }
+        if !status.success() {
+            return Err(format!(
+                "flac exited with {} on track {}",
+                status, track.number
+            ));
+        }
+        println!("Ripped {}", path.display());
+        ripped.push(path);
+    }
+    Ok(ripped)
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+// Сколько последних правок очереди помнится для "queue changes"; клиенту,
+// отставшему сильнее, очередь отдаётся целиком
+const KEPT_CHANGES: usize = 200;
+
+// Одна правка очереди. Перестановка одного трека — отдельный вид, иначе
+// перенос из начала в конец выглядел бы заменой всей очереди
+#[derive(Debug, Clone)]
+pub enum Change {
+    // На месте index удалено remove треков и вставлены insert
+    Splice {
+        index: usize,
+        remove: usize,
+        insert: Vec<PathBuf>,
+    },
+    Move {
+        from: usize,
+        to: usize,
+    },
+}
+
+// Номер ревизии очереди и журнал правок, по которому клиент догоняет
+// очередь, не перечитывая её целиком
+pub struct QueueLog {
+    revision: u64,
+    last: Vec<PathBuf>,
+    changes: VecDeque<(u64, Change)>,
+}
+
+impl QueueLog {
+    pub fn new(files: &[PathBuf]) -> Self {
+        Self {
+            revision: 0,
+            last: files.to_vec(),
+            changes: VecDeque::new(),
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // Сравнивает очередь с прошлым снимком; ревизия растёт, только если она изменилась
+    pub fn update(&mut self, files: &[PathBuf]) -> bool {
+        let Some(change) = diff(&self.last, files) else {
+            return false;
+        };
+        self.revision += 1;
+        self.last = files.to_vec();
+        if self.changes.len() == KEPT_CHANGES {
+            self.changes.pop_front();
+        }
+        self.changes.push_back((self.revision, change));
+        true
+    }
+
+    // Правки после ревизии since; None — журнал их уже не помнит или ревизия из будущего
+    pub fn since(&self, since: u64) -> Option<Vec<&(u64, Change)>> {
+        if since > self.revision {
+            return None;
+        }
+        let oldest = self
+            .changes
+            .front()
+            .map_or(self.revision, |(revision, _)| revision - 1);
+        if since < oldest {
+            return None;
+        }
+        Some(
+            self.changes
+                .iter()
+                .filter(|(revision, _)| *revision > since)
+                .collect(),
+        )
+    }
+}
+
+// Общие начало и конец отбрасываются, середина — одна правка
+fn diff(old: &[PathBuf], new: &[PathBuf]) -> Option<Change> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    if prefix == old.len() && prefix == new.len() {
+        return None;
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = &old[prefix..old.len() - suffix];
+    let inserted = &new[prefix..new.len() - suffix];
+
+    let last = removed.len().saturating_sub(1);
+    if removed.len() == inserted.len() && removed.len() > 1 {
+        // Трек из начала середины ушёл в её конец или наоборот
+        if removed[1..] == inserted[..last] && removed[0] == inserted[last] {
+            return Some(Change::Move {
+                from: prefix,
+                to: prefix + last,
+            });
+        }
+        if removed[..last] == inserted[1..] && removed[last] == inserted[0] {
+            return Some(Change::Move {
+                from: prefix + last,
+                to: prefix,
+            });
+        }
+    }
+    Some(Change::Splice {
+        index: prefix,
+        remove: removed.len(),
+        insert: inserted.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(names: &str) -> Vec<PathBuf> {
+        names
+            .chars()
+            .map(|name| PathBuf::from(name.to_string()))
+            .collect()
+    }
+
+    // Так очередь догоняет клиент
+    fn apply(files: &mut Vec<PathBuf>, change: &Change) {
+        match change {
+            Change::Splice {
+                index,
+                remove,
+                insert,
+            } => {
+                files.splice(*index..*index + *remove, insert.iter().cloned());
+            }
+            Change::Move { from, to } => {
+                let file = files.remove(*from);
+                files.insert(*to, file);
+            }
+        }
+    }
+
+    #[test]
+    fn changes_replay_to_the_new_queue() {
+        let steps = [
+            "abcde", "abcdef", "bcdef", "cdefb", "bcdef", "bXYef", "bXYef", "",
+        ];
+        let mut log = QueueLog::new(&queue(steps[0]));
+        for step in &steps[1..] {
+            log.update(&queue(step));
+        }
+        assert_eq!(log.revision(), 6);
+
+        let mut files = queue(steps[0]);
+        for (_, change) in log.since(0).unwrap() {
+            apply(&mut files, change);
+        }
+        assert!(files.is_empty());
+        assert_eq!(log.since(6).unwrap().len(), 0);
+        assert_eq!(log.since(4).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn single_track_moves_are_moves() {
+        assert!(matches!(
+            diff(&queue("abcde"), &queue("acdeb")),
+            Some(Change::Move { from: 1, to: 4 })
+        ));
+        assert!(matches!(
+            diff(&queue("abcde"), &queue("aebcd")),
+            Some(Change::Move { from: 4, to: 1 })
+        ));
+        assert!(matches!(
+            diff(&queue("abcde"), &queue("abXde")),
+            Some(Change::Splice {
+                index: 2,
+                remove: 1,
+                ..
+            })
+        ));
+        assert!(diff(&queue("abc"), &queue("abc")).is_none());
+    }
+
+    #[test]
+    fn forgotten_or_future_revisions_need_a_full_reload() {
+        let mut log = QueueLog::new(&[]);
+        for n in 0..=KEPT_CHANGES {
+            log.update(&vec![PathBuf::from("a"); n + 1]);
+        }
+        let revision = log.revision();
+        assert_eq!(revision, KEPT_CHANGES as u64 + 1);
+        assert!(log.since(0).is_none());
+        assert_eq!(log.since(1).unwrap().len(), KEPT_CHANGES);
+        assert!(log.since(revision + 1).is_none());
+    }
+}
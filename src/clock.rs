@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// Часы таймера сна, будильников, расписания и кроссфейда. С --simulate-time они идут быстрее
+// настоящих и их можно перевести вперёд, чтобы проверить расписание, не дожидаясь его
+struct Clock {
+    start: Instant,
+    // Настоящее время суток в момент start
+    wall: SystemTime,
+    speed: f64,
+    simulated: bool,
+}
+
+static CLOCK: OnceLock<Clock> = OnceLock::new();
+// На сколько часы переведены вперёд, мкс
+static OFFSET: AtomicU64 = AtomicU64::new(0);
+
+fn clock() -> &'static Clock {
+    CLOCK.get_or_init(|| Clock {
+        start: Instant::now(),
+        wall: SystemTime::now(),
+        speed: 1.0,
+        simulated: false,
+    })
+}
+
+// Вызывается один раз до запуска потоков; speed — во сколько раз быстрее настоящего времени
+pub fn simulate(speed: f64) -> Result<(), String> {
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err(format!("invalid time speed: {}", speed));
+    }
+    CLOCK
+        .set(Clock {
+            start: Instant::now(),
+            wall: SystemTime::now(),
+            speed,
+            simulated: true,
+        })
+        .map_err(|_| "clock already started".to_string())
+}
+
+pub fn is_simulated() -> bool {
+    clock().simulated
+}
+
+pub fn speed() -> f64 {
+    clock().speed
+}
+
+pub fn now() -> Instant {
+    let clock = clock();
+    let offset = Duration::from_micros(OFFSET.load(Ordering::Relaxed));
+    if clock.speed == 1.0 {
+        return Instant::now() + offset;
+    }
+    clock.start + clock.start.elapsed().mul_f64(clock.speed) + offset
+}
+
+// Время суток по этим часам: для расписания, которое тоже можно ускорить
+pub fn wall() -> SystemTime {
+    let clock = clock();
+    if clock.speed == 1.0 {
+        return SystemTime::now() + Duration::from_micros(OFFSET.load(Ordering::Relaxed));
+    }
+    clock.wall + now().duration_since(clock.start)
+}
+
+// Настоящий сон, укороченный в speed раз
+pub fn sleep(duration: Duration) {
+    thread::sleep(duration.div_f64(clock().speed));
+}
+
+// Переводит часы вперёд; назад они не идут, чтобы не ломать Instant.
+// Настоящее время не трогается, поэтому только с --simulate-time
+pub fn advance(by: Duration) -> Result<(), String> {
+    if !is_simulated() {
+        return Err("clock is not simulated; start with --simulate-time".to_string());
+    }
+    OFFSET.fetch_add(by.as_micros() as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+pub struct TimerQueue {
+    now: Duration,
+    // Порядок постановки: одновременные действия выполняются так, как были отложены
+    seq: u64,
+    jobs: Vec<(Duration, u64, Job)>,
+}
+
+// Отложенные действия горячих клавиш. Live — поток со сном, Virtual — очередь, которую
+// продвигает advance_to: записи нажатий и тесты проигрываются без настоящих пауз
+#[derive(Clone)]
+pub enum Timers {
+    Live(Instant),
+    Virtual(Arc<Mutex<TimerQueue>>),
+}
+
+impl Timers {
+    pub fn live() -> Self {
+        Timers::Live(Instant::now())
+    }
+
+    pub fn simulated() -> Self {
+        Timers::Virtual(Arc::default())
+    }
+
+    // Время с создания
+    pub fn elapsed(&self) -> Duration {
+        match self {
+            Timers::Live(start) => start.elapsed(),
+            Timers::Virtual(queue) => queue.lock().unwrap().now,
+        }
+    }
+
+    pub fn after(&self, delay: Duration, job: impl FnOnce() + Send + 'static) {
+        match self {
+            Timers::Live(_) => {
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    job();
+                });
+            }
+            Timers::Virtual(queue) => {
+                let mut queue = queue.lock().unwrap();
+                let at = queue.now + delay;
+                queue.seq += 1;
+                let seq = queue.seq;
+                queue.jobs.push((at, seq, Box::new(job)));
+            }
+        }
+    }
+
+    // Ждёт момента at от создания; виртуальное время переводится сразу,
+    // а наступившие по пути действия выполняются по порядку
+    pub fn advance_to(&self, at: Duration) {
+        let queue = match self {
+            Timers::Live(start) => {
+                thread::sleep(at.saturating_sub(start.elapsed()));
+                return;
+            }
+            Timers::Virtual(queue) => queue,
+        };
+        loop {
+            // Действие выполняется без блокировки: оно может отложить следующее
+            let job = {
+                let mut queue = queue.lock().unwrap();
+                let next = (0..queue.jobs.len())
+                    .filter(|&i| queue.jobs[i].0 <= at)
+                    .min_by_key(|&i| (queue.jobs[i].0, queue.jobs[i].1));
+                match next {
+                    Some(i) => {
+                        let (due, _, job) = queue.jobs.swap_remove(i);
+                        queue.now = queue.now.max(due);
+                        job
+                    }
+                    None => {
+                        queue.now = queue.now.max(at);
+                        return;
+                    }
+                }
+            };
+            job();
+        }
+    }
+}
+
+// Общие часы процесса переводятся только вперёд, поэтому тесты с ними идут по одному
+#[cfg(test)]
+pub(crate) fn simulated_for_test() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = simulate(1.0);
+    assert!(is_simulated());
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_moves_now_and_wall() {
+        let _clock = simulated_for_test();
+        let (now, wall) = (now(), wall());
+        advance(Duration::from_secs(3600)).unwrap();
+        assert!(super::now().duration_since(now) >= Duration::from_secs(3600));
+        let moved = super::wall().duration_since(wall).unwrap();
+        assert!(moved >= Duration::from_secs(3600) && moved < Duration::from_secs(3660));
+    }
+
+    #[test]
+    fn virtual_timers_fire_in_order_when_due() {
+        let timers = Timers::simulated();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        for (delay, name) in [(300, "b"), (100, "a"), (300, "c")] {
+            let fired = fired.clone();
+            let at = timers.clone();
+            timers.after(Duration::from_millis(delay), move || {
+                fired.lock().unwrap().push((at.elapsed().as_millis(), name));
+            });
+        }
+
+        timers.advance_to(Duration::from_millis(299));
+        assert_eq!(*fired.lock().unwrap(), vec![(100, "a")]);
+        assert_eq!(timers.elapsed(), Duration::from_millis(299));
+
+        timers.advance_to(Duration::from_millis(1000));
+        assert_eq!(
+            *fired.lock().unwrap(),
+            vec![(100, "a"), (300, "b"), (300, "c")]
+        );
+    }
+
+    #[test]
+    fn virtual_timers_run_jobs_scheduled_by_jobs() {
+        let timers = Timers::simulated();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let (inner, at) = (fired.clone(), timers.clone());
+        timers.after(Duration::from_millis(100), move || {
+            let again = at.clone();
+            at.after(Duration::from_millis(100), move || {
+                inner.lock().unwrap().push(again.elapsed().as_millis());
+            });
+        });
+        timers.advance_to(Duration::from_millis(250));
+        assert_eq!(*fired.lock().unwrap(), vec![200]);
+    }
+}
@@ -0,0 +1,420 @@
+use serde::Serialize;
+
+// Описание команд протокола для клиентов, строящих интерфейс динамически
+#[derive(Serialize, Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub args: &'static [ArgSpec],
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    // number, integer, string, path, enum, json;
+    // duration — "90", "1m30s", "1:30" или "500ms", percent — "40" или "40%"
+    pub kind: &'static str,
+    pub required: bool,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub values: &'static [&'static str],
+}
+
+const fn arg(name: &'static str, kind: &'static str, required: bool) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        required,
+        values: &[],
+    }
+}
+
+const fn choice(name: &'static str, required: bool, values: &'static [&'static str]) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind: "enum",
+        required,
+        values,
+    }
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "hello",
+        description: "Report protocol version and enabled features",
+        args: &[],
+    },
+    CommandSpec {
+        name: "commands",
+        description: "List supported commands with argument schemas",
+        args: &[],
+    },
+    CommandSpec {
+        name: "subscribe",
+        description: "Keep the connection open and receive newline-delimited JSON events; all topics but position by default",
+        args: &[arg("topics", "string", false)],
+    },
+    CommandSpec {
+        name: "whoami",
+        description: "Show the user the connection is authenticated as",
+        args: &[],
+    },
+    CommandSpec {
+        name: "play",
+        description: "Resume playback, build the queue from a filter or the favorite tracks, or start noise:<kind>",
+        args: &[
+            choice(
+                "filter",
+                false,
+                &[
+                    "--bpm",
+                    "--mood",
+                    "--similar",
+                    "favorites",
+                    "noise:white",
+                    "noise:pink",
+                    "noise:brown",
+                    "noise:off",
+                ],
+            ),
+            arg("value", "string", false),
+        ],
+    },
+    CommandSpec {
+        name: "pause",
+        description: "Toggle pause",
+        args: &[],
+    },
+    CommandSpec {
+        name: "auto_pause",
+        description: "Pause if playing and remember why",
+        args: &[arg("reason", "string", true)],
+    },
+    CommandSpec {
+        name: "auto_resume",
+        description: "Resume if paused for the given reason",
+        args: &[arg("reason", "string", true)],
+    },
+    CommandSpec {
+        name: "status",
+        description: "Report track, position, volume, queue and playback state as JSON",
+        args: &[],
+    },
+    CommandSpec {
+        name: "stats",
+        description: "Report skip counts, the most skipped tracks, limited (clipping) samples, output underruns and measured latency",
+        args: &[],
+    },
+    CommandSpec {
+        name: "queue_end",
+        description: "Choose what happens after the last track; hooks are set in the config",
+        args: &[choice(
+            "action",
+            true,
+            &["repeat", "stop", "quit", "suspend"],
+        )],
+    },
+    CommandSpec {
+        name: "repeat_off",
+        description: "Stop after the last track of the queue",
+        args: &[],
+    },
+    CommandSpec {
+        name: "repeat_one",
+        description: "Repeat the current track",
+        args: &[],
+    },
+    CommandSpec {
+        name: "repeat_all",
+        description: "Start the queue over after the last track",
+        args: &[],
+    },
+    CommandSpec {
+        name: "queue",
+        description: "List the queue as JSON, clear it except the current track (may need confirmation), or report the edits after a revision with changes --since REV",
+        args: &[
+            choice("action", true, &["list", "clear", "changes"]),
+            arg("since", "string", false),
+        ],
+    },
+    CommandSpec {
+        name: "quit",
+        description: "Save state and stop the daemon (may need confirmation)",
+        args: &[
+            choice("flag", false, &["--force", "--confirm"]),
+            arg("token", "string", false),
+        ],
+    },
+    CommandSpec {
+        name: "stop",
+        description: "Stop playback and keep the daemon running; play starts the current track again",
+        args: &[],
+    },
+    CommandSpec {
+        name: "next",
+        description: "Skip to the next track",
+        args: &[],
+    },
+    CommandSpec {
+        name: "prev",
+        description: "Go back to the previous track",
+        args: &[],
+    },
+    CommandSpec {
+        name: "search",
+        description: "Find library tracks whose path contains all the words",
+        args: &[arg("query", "string", true)],
+    },
+    CommandSpec {
+        name: "play-dir",
+        description: "Play a folder once, then return to the previous queue",
+        args: &[arg("path", "path", true)],
+    },
+    CommandSpec {
+        name: "noise_volume",
+        description: "Set noise and ambience volume in percent",
+        args: &[arg("percent", "percent", true)],
+    },
+    CommandSpec {
+        name: "playlist",
+        description: "Repair moved paths in a saved playlist, or delete it (may need confirmation)",
+        args: &[
+            choice("action", true, &["repair", "delete"]),
+            arg("name", "string", true),
+            arg("--map old=new", "string", false),
+        ],
+    },
+    CommandSpec {
+        name: "loop",
+        description: "Play a clip as a seamless loop until the next track",
+        args: &[choice("kind", true, &["file"]), arg("path", "path", true)],
+    },
+    CommandSpec {
+        name: "preview-start",
+        description: "Play a short excerpt of a queue track (by index) or a library file at reduced volume on a separate output, leaving playback untouched",
+        args: &[arg("track", "string", true)],
+    },
+    CommandSpec {
+        name: "preview-stop",
+        description: "Stop the excerpt started by preview-start",
+        args: &[],
+    },
+    CommandSpec {
+        name: "interrupt",
+        description: "Pause the music, play a file, then resume where it stopped",
+        args: &[arg("path", "path", true)],
+    },
+    CommandSpec {
+        name: "output",
+        description: "Switch or reopen the audio output, keeping the position",
+        args: &[arg("name", "string", false)],
+    },
+    CommandSpec {
+        name: "load",
+        description: "Replace the queue with a directory, file or m3u playlist",
+        args: &[arg("path", "path", true)],
+    },
+    CommandSpec {
+        name: "load_playlist",
+        description: "Replace the queue with an m3u/m3u8 playlist, by file or saved name",
+        args: &[arg("name", "string", true)],
+    },
+    CommandSpec {
+        name: "save_playlist",
+        description: "Save the queue and current track as <name>.m3u8 in the playlists folder",
+        args: &[arg("name", "string", true)],
+    },
+    CommandSpec {
+        name: "volume",
+        description: "Set volume to a percentage, change it by +N or -N, or show it without an argument",
+        args: &[arg("volume", "string", false)],
+    },
+    CommandSpec {
+        name: "volume_up",
+        description: "Raise volume by a percentage (default volume_step from the config, 10)",
+        args: &[arg("percent", "percent", false)],
+    },
+    CommandSpec {
+        name: "volume_down",
+        description: "Lower volume by a percentage (default volume_step from the config, 10)",
+        args: &[arg("percent", "percent", false)],
+    },
+    CommandSpec {
+        name: "seek_forward",
+        description: "Seek forward by a duration, bare numbers in seconds (default 10)",
+        args: &[arg("offset", "duration", false)],
+    },
+    CommandSpec {
+        name: "seek_backward",
+        description: "Seek backward by a duration, bare numbers in seconds (default 10)",
+        args: &[arg("offset", "duration", false)],
+    },
+    CommandSpec {
+        name: "seek",
+        description: "Seek to a position like 1:30, or by an offset like +10s or -1m",
+        args: &[arg("position", "string", true)],
+    },
+    CommandSpec {
+        name: "shuffle",
+        description: "Enable, disable or toggle shuffled order without repeats; folders plays whole folders in order, picking the next folder at random",
+        args: &[choice("state", false, &["on", "off", "toggle", "folders"])],
+    },
+    CommandSpec {
+        name: "add",
+        description: "Append a file, folder or playlist to the end of the queue",
+        args: &[arg("path", "path", true)],
+    },
+    CommandSpec {
+        name: "remove",
+        description:
+            "Remove the queue entry at an index; removing the current track plays the next",
+        args: &[arg("index", "integer", true)],
+    },
+    CommandSpec {
+        name: "move",
+        description: "Move a queue entry to another index",
+        args: &[arg("from", "integer", true), arg("to", "integer", true)],
+    },
+    CommandSpec {
+        name: "clear",
+        description: "Clear the queue except the current track (may need confirmation)",
+        args: &[],
+    },
+    CommandSpec {
+        name: "sleep",
+        description: "Fade out over the last 30 seconds and pause or quit after a duration, bare numbers in minutes; off cancels, no argument shows the time left",
+        args: &[
+            arg("after", "duration", false),
+            choice("action", false, &["pause", "quit"]),
+        ],
+    },
+    CommandSpec {
+        name: "history",
+        description: "List the last played tracks with play times, newest first (20 by default); history commands lists recent commands sent to this daemon, oldest first",
+        args: &[arg("count", "integer", false)],
+    },
+    CommandSpec {
+        name: "most_played",
+        description: "List the most played tracks with play counts and last play times (20 by default)",
+        args: &[arg("count", "integer", false)],
+    },
+    CommandSpec {
+        name: "repeat-last",
+        description: "Run the last recorded command again and return its reply",
+        args: &[],
+    },
+    CommandSpec {
+        name: "rate",
+        description: "Rate the current track from 1 to 5 or clear its rating; no argument shows the rating",
+        args: &[arg("rating", "string", false)],
+    },
+    CommandSpec {
+        name: "favorite",
+        description: "Mark or unmark the current track as a favorite, toggling by default",
+        args: &[choice("state", false, &["on", "off", "toggle"])],
+    },
+    CommandSpec {
+        name: "hotkeys",
+        description: "Enable, disable or toggle all global hotkeys except hotkey_toggle; no argument shows the state",
+        args: &[choice("state", false, &["on", "off", "toggle"])],
+    },
+    CommandSpec {
+        name: "clock",
+        description: "Show the timer clock speed, or move a simulated clock (--simulate-time) forward by a duration",
+        args: &[
+            choice("action", false, &["advance"]),
+            arg("offset", "duration", false),
+        ],
+    },
+    CommandSpec {
+        name: "errors",
+        description: "List recent playback errors (decode, device, missing), oldest first, or clear them",
+        args: &[choice("action", false, &["clear"])],
+    },
+    CommandSpec {
+        name: "cd",
+        description: "List audio CD tracks with MusicBrainz titles, play the disc from a track, or rip it to FLAC in the background",
+        args: &[
+            choice("action", true, &["list", "play", "rip"]),
+            arg("track", "integer", false),
+        ],
+    },
+    CommandSpec {
+        name: "rescan",
+        description: "Rescan the whole music directory in the background, or pick up changes under one path",
+        args: &[arg("path", "path", false)],
+    },
+    CommandSpec {
+        name: "drive",
+        description: "Index music on a newly mounted drive, or drop its tracks from the library and queue",
+        args: &[
+            choice("action", true, &["added", "removed"]),
+            arg("path", "path", true),
+        ],
+    },
+    CommandSpec {
+        name: "goto",
+        description: "Play the queue entry at an index",
+        args: &[arg("index", "integer", true)],
+    },
+    CommandSpec {
+        name: "scan",
+        description: "Fast-forward or rewind with rising speed until stopped",
+        args: &[choice("direction", true, &["forward", "backward", "stop"])],
+    },
+    CommandSpec {
+        name: "automix",
+        description: "Enable, disable or toggle tempo-matched crossfades",
+        args: &[choice("state", false, &["on", "off", "toggle"])],
+    },
+    CommandSpec {
+        name: "crossfade",
+        description: "Set the automix crossfade length, bare numbers in seconds, or show it without an argument",
+        args: &[arg("duration", "duration", false)],
+    },
+    CommandSpec {
+        name: "autofill",
+        description: "Extend the queue with similar tracks or the playlist mix when it runs out",
+        args: &[choice("mode", true, &["similar", "mix", "off"])],
+    },
+    CommandSpec {
+        name: "mix",
+        description: "Replace the queue with tracks drawn from several playlists in proportion to their weights, e.g. chill:0.7 upbeat:0.3; show the mix without an argument",
+        args: &[arg("playlists", "string", false)],
+    },
+    CommandSpec {
+        name: "context",
+        description: "List contexts, switch to one (queue, modes, volume and output together), or save the current setup under a name",
+        args: &[
+            choice("action", false, &["list", "switch", "save"]),
+            arg("name", "string", false),
+        ],
+    },
+    CommandSpec {
+        name: "analyze",
+        description: "Analyze the library in the background",
+        args: &[],
+    },
+    CommandSpec {
+        name: "similar",
+        description: "List tracks that sound like the current one",
+        args: &[arg("count", "integer", false)],
+    },
+    CommandSpec {
+        name: "generate",
+        description: "Build the queue from listening history",
+        args: &[
+            choice("kind", true, &["rediscover", "on-this-day"]),
+            arg("limit", "integer", false),
+        ],
+    },
+    CommandSpec {
+        name: "handoff",
+        description: "Move playback to another NSmp instance",
+        args: &[arg("host", "string", true), arg("token", "string", false)],
+    },
+    CommandSpec {
+        name: "restore",
+        description: "Resume playback from a handoff snapshot",
+        args: &[arg("snapshot", "json", true)],
+    },
+];
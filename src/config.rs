@@ -0,0 +1,393 @@
+use crate::atomic;
+use crate::automix::AutomixConfig;
+use crate::buffer::BufferConfig;
+use crate::cd::CdConfig;
+use crate::context::ContextConfig;
+use crate::daemon::daemonize;
+use crate::decode::DecoderConfig;
+use crate::focus::HotkeyContext;
+use crate::hotkeys::HotkeyBackend;
+use crate::jack::HeadphonesConfig;
+use crate::lastfm::LastfmConfig;
+use crate::listenbrainz::ListenBrainzConfig;
+use crate::noise::NoiseConfig;
+use crate::player::{Autofill, QueueEnd};
+use crate::plugin::PluginConfig;
+use crate::preload::PreloadConfig;
+use crate::press::PressTiming;
+use crate::preview::PreviewConfig;
+use crate::recovery::{self, Fallback};
+use crate::removable::RemovableConfig;
+use crate::replaygain::ReplayGainConfig;
+#[cfg(feature = "rfid")]
+use crate::rfid::RfidConfig;
+#[cfg(feature = "rotary")]
+use crate::rotary::RotaryConfig;
+use crate::scan::ScanOptions;
+use crate::schedule::ScheduleEntry;
+use crate::skip::SkipConfig;
+use crate::watch::WatchConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_DATABASE: &str = "music_player_db.json";
+const DEFAULT_LIBRARY_DB: &str = "music_player_library.db";
+const DEFAULT_STATE_FILE: &str = "music_player_state.json";
+pub const DEFAULT_CONTEXTS_DIR: &str = "contexts";
+// Последний конфиг, который удалось прочитать, лежит рядом с этим суффиксом
+const CONFIG_BACKUP: &str = ".bak";
+const DEFAULT_LOG_FILE: &str = "music_player.log";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Config {
+    pub(crate) hotkeys: HashMap<String, String>,
+    // Команда -> условия, при которых её горячая клавиша действует
+    #[serde(default)]
+    pub(crate) hotkey_contexts: HashMap<String, HotkeyContext>,
+    // Пороги двойного ("Key:double") и долгого ("Key:long") нажатия
+    #[serde(default)]
+    pub(crate) hotkey_timing: PressTiming,
+    // Комбинация, которая включает и выключает все остальные горячие клавиши
+    #[serde(default)]
+    pub(crate) hotkey_toggle: Option<String>,
+    // Откуда читать клавиши: rdev (X11) или evdev (/dev/input, в том числе под Wayland)
+    #[serde(default)]
+    pub(crate) hotkey_backend: HotkeyBackend,
+    // Устройства для evdev; пусто — все /dev/input/event*
+    #[serde(default)]
+    pub(crate) hotkey_devices: Vec<PathBuf>,
+    music_dir: Option<String>,
+    pub(crate) volume: f32,
+    // На сколько процентов меняют громкость volume_up и volume_down без аргумента
+    #[serde(default = "default_volume_step")]
+    pub(crate) volume_step: f32,
+    // Общее усиление, дБ; положительное ограничивается запасом трека
+    #[serde(default)]
+    pub(crate) preamp_db: f32,
+    // Выравнивание громкости по тегам REPLAYGAIN_* или по анализу
+    #[serde(default)]
+    pub(crate) replaygain: ReplayGainConfig,
+    // Куда save_playlist пишет очередь и где load_playlist ищет имена
+    #[serde(default = "default_playlists_dir")]
+    pub(crate) playlists_dir: String,
+    #[serde(default = "default_output")]
+    pub(crate) output: String,
+    #[serde(default)]
+    pub(crate) plugins: Vec<PluginConfig>,
+    #[serde(default = "default_database")]
+    pub(crate) database: String,
+    // SQLite-база библиотеки: пути, теги, длительности, число прослушиваний
+    #[serde(default = "default_library_db")]
+    pub(crate) library_db: String,
+    // Очередь, позиция и режимы для продолжения после перезапуска; null отключает
+    #[serde(default = "default_state_file")]
+    pub(crate) state_file: Option<String>,
+    // Контексты: имя -> очередь, режимы, громкость и вывод при первом переключении.
+    // Дальше каждый контекст помнит своё состояние в contexts_dir
+    #[serde(default)]
+    pub(crate) contexts: HashMap<String, ContextConfig>,
+    #[serde(default = "default_contexts_dir")]
+    pub(crate) contexts_dir: String,
+    // Куда демон пишет stdout и stderr
+    #[serde(default = "default_log_file")]
+    pub(crate) log_file: String,
+    #[serde(default)]
+    pub(crate) scan: ScanOptions,
+    #[serde(default)]
+    pub(crate) automix: AutomixConfig,
+    #[serde(default)]
+    pub(crate) autofill: Autofill,
+    // Плейлисты с весами для autofill = "mix", например "chill:0.7 upbeat:0.3"
+    #[serde(default)]
+    pub(crate) mix: Option<String>,
+    // Когда играть и что: часы работы магазина или офиса
+    #[serde(default)]
+    pub(crate) schedule: Vec<ScheduleEntry>,
+    #[serde(default)]
+    pub(crate) on_queue_end: QueueEnd,
+    #[serde(default)]
+    pub(crate) confirm_destructive: bool,
+    #[serde(default)]
+    pub(crate) shuffle: bool,
+    // Перемешивать папки (альбомы), а не треки
+    #[serde(default)]
+    pub(crate) shuffle_folders: bool,
+    // Когда ручной next считается пропуском и влияют ли пропуски на перемешивание
+    #[serde(default)]
+    pub(crate) skips: SkipConfig,
+    #[serde(default)]
+    pub(crate) headphones: HeadphonesConfig,
+    // Привод аудио-CD, поиск названий в MusicBrainz и рип во FLAC
+    #[serde(default)]
+    pub(crate) cd: CdConfig,
+    // USB-диски и карты памяти с музыкой
+    #[serde(default)]
+    pub(crate) removable: RemovableConfig,
+    // Следить за папкой с музыкой и подхватывать новые и удалённые файлы
+    #[serde(default)]
+    pub(crate) watch: WatchConfig,
+    // Скробблинг на Last.fm; без этого раздела выключен
+    #[serde(default)]
+    pub(crate) lastfm: Option<LastfmConfig>,
+    // Отправка прослушиваний на ListenBrainz, вместе с Last.fm или вместо него
+    #[serde(default)]
+    pub(crate) listenbrainz: Option<ListenBrainzConfig>,
+    #[serde(default)]
+    pub(crate) noise: NoiseConfig,
+    // Отрывки треков для preview-start
+    #[serde(default)]
+    pub(crate) preview: PreviewConfig,
+    // Сколько треков загружать заранее и на сколько секунд декодировать вперёд
+    #[serde(default)]
+    pub(crate) preload: PreloadConfig,
+    // Цепочки декодеров по расширениям
+    #[serde(default)]
+    pub(crate) decoders: DecoderConfig,
+    // Следующий трек заранее ставится в sink и начинается без паузы
+    #[serde(default = "default_gapless")]
+    pub(crate) gapless: bool,
+    // Период и число периодов буфера вывода; меньше — ниже задержка, больше — меньше срывов
+    #[serde(default)]
+    pub(crate) buffer: BufferConfig,
+    // Адрес для управления по сети, например "127.0.0.1:6601"; нелокальный адрес
+    // вроде "0.0.0.0:6601" слушается, только если заданы токены в users
+    #[serde(default)]
+    pub(crate) listen: Option<String>,
+    // Адрес встроенного HTTP-сервера, например "127.0.0.1:6680"; "0.0.0.0:6680" открывает
+    // его для телефона в локальной сети, и тогда стоит задать токены в users
+    #[cfg(feature = "http")]
+    #[serde(default)]
+    pub(crate) http: Option<String>,
+    // Токен клиента -> имя пользователя для личной истории
+    #[serde(default)]
+    pub(crate) users: HashMap<String, String>,
+    #[cfg(feature = "rotary")]
+    #[serde(default)]
+    pub(crate) rotary: Vec<RotaryConfig>,
+    #[cfg(feature = "rfid")]
+    #[serde(default)]
+    pub(crate) rfid: Option<RfidConfig>,
+}
+
+pub fn default_output() -> String {
+    "default".to_string()
+}
+
+pub fn default_playlists_dir() -> String {
+    "playlists".to_string()
+}
+
+pub fn default_volume_step() -> f32 {
+    10.0
+}
+
+fn default_gapless() -> bool {
+    true
+}
+
+fn default_database() -> String {
+    DEFAULT_DATABASE.to_string()
+}
+
+fn default_state_file() -> Option<String> {
+    Some(DEFAULT_STATE_FILE.to_string())
+}
+
+fn default_contexts_dir() -> String {
+    DEFAULT_CONTEXTS_DIR.to_string()
+}
+
+fn default_log_file() -> String {
+    DEFAULT_LOG_FILE.to_string()
+}
+
+fn default_library_db() -> String {
+    DEFAULT_LIBRARY_DB.to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut hotkeys = HashMap::new();
+        hotkeys.insert("next".to_string(), "XF86AudioNext".to_string());
+        hotkeys.insert("prev".to_string(), "XF86AudioPrev".to_string());
+        hotkeys.insert("pause".to_string(), "XF86AudioPlay".to_string());
+        hotkeys.insert("stop".to_string(), "XF86AudioStop".to_string());
+
+        Config {
+            hotkeys,
+            hotkey_contexts: HashMap::new(),
+            hotkey_timing: PressTiming::default(),
+            hotkey_toggle: None,
+            hotkey_backend: HotkeyBackend::default(),
+            hotkey_devices: Vec::new(),
+            music_dir: None,
+            volume: 0.7,
+            volume_step: default_volume_step(),
+            preamp_db: 0.0,
+            replaygain: ReplayGainConfig::default(),
+            playlists_dir: default_playlists_dir(),
+            output: default_output(),
+            plugins: Vec::new(),
+            database: default_database(),
+            library_db: default_library_db(),
+            state_file: default_state_file(),
+            contexts: HashMap::new(),
+            contexts_dir: default_contexts_dir(),
+            log_file: default_log_file(),
+            scan: ScanOptions::default(),
+            automix: AutomixConfig::default(),
+            autofill: Autofill::Off,
+            mix: None,
+            schedule: Vec::new(),
+            on_queue_end: QueueEnd::Repeat,
+            confirm_destructive: false,
+            shuffle: false,
+            shuffle_folders: false,
+            skips: SkipConfig::default(),
+            headphones: HeadphonesConfig::default(),
+            cd: CdConfig::default(),
+            removable: RemovableConfig::default(),
+            watch: WatchConfig::default(),
+            lastfm: None,
+            listenbrainz: None,
+            noise: NoiseConfig::default(),
+            preview: PreviewConfig::default(),
+            preload: PreloadConfig::default(),
+            decoders: DecoderConfig::default(),
+            gapless: default_gapless(),
+            buffer: BufferConfig::default(),
+            listen: None,
+            #[cfg(feature = "http")]
+            http: None,
+            users: HashMap::new(),
+            #[cfg(feature = "rotary")]
+            rotary: Vec::new(),
+            #[cfg(feature = "rfid")]
+            rfid: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn set_music_dir(&mut self, path: &Path) {
+        self.music_dir = Some(path.to_string_lossy().into_owned());
+    }
+
+    pub(crate) fn music_dir(&self) -> PathBuf {
+        PathBuf::from(self.music_dir.as_deref().unwrap_or("."))
+    }
+
+    // Уводит процесс в фон, предварительно сделав пути абсолютными
+    pub fn daemonize(&mut self) -> Result<(), String> {
+        self.absolutize()?;
+        daemonize(Path::new(&self.log_file))
+    }
+
+    // Демон уходит в "/", поэтому относительные пути считаются от каталога запуска
+    fn absolutize(&mut self) -> Result<(), String> {
+        let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+        let resolve = |path: &mut String| *path = cwd.join(&*path).to_string_lossy().into_owned();
+        resolve(self.music_dir.get_or_insert_with(|| ".".to_string()));
+        resolve(&mut self.database);
+        resolve(&mut self.library_db);
+        resolve(&mut self.playlists_dir);
+        resolve(&mut self.log_file);
+        resolve(&mut self.contexts_dir);
+        if let Some(ref mut state_file) = self.state_file {
+            resolve(state_file);
+        }
+        if let Some(ref mut dir) = self.noise.ambience_dir {
+            resolve(dir);
+        }
+        for plugin in &mut self.plugins {
+            plugin.path = cwd.join(&plugin.path);
+        }
+        if let Some(ref mut soundfont) = self.decoders.soundfont {
+            *soundfont = cwd.join(&*soundfont);
+        }
+        if let Some(ref mut dir) = self.cd.rip_dir {
+            *dir = cwd.join(&*dir);
+        }
+        if let Some(ref mut lastfm) = self.lastfm {
+            resolve(&mut lastfm.queue_file);
+        }
+        if let Some(ref mut listenbrainz) = self.listenbrainz {
+            resolve(&mut listenbrainz.queue_file);
+        }
+        Ok(())
+    }
+}
+
+// Конфиг, который прочитался, копируется в .bak; испорченный (например, обрезанный
+// при сбое) откладывается в .broken, а на его место возвращается эта копия
+pub fn load_config(path: &Path) -> Result<Config, String> {
+    if !path.exists() {
+        let config = Config::default();
+        save_config(path, &config)?;
+        return Ok(config);
+    }
+    let error = match read_config(path) {
+        Ok(config) => {
+            backup_config(path);
+            return Ok(config);
+        }
+        Err(e) => e,
+    };
+    // Демон запускается в любом случае: с последней рабочей копией или с
+    // настройками по умолчанию, а сбой виден в status
+    let backup = atomic::sibling(path, CONFIG_BACKUP);
+    let broken = recovery::set_aside(path);
+    let (config, fallback) = match read_config(&backup) {
+        Ok(config) => (config, Fallback::Backup),
+        Err(_) => (Config::default(), Fallback::Defaults),
+    };
+    if broken.is_some() {
+        let restored = match fallback {
+            Fallback::Backup => fs::copy(&backup, path)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Fallback::Defaults => save_config(path, &config),
+        };
+        if let Err(e) = restored {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+    recovery::record(recovery::Recovery {
+        file: path.to_path_buf(),
+        error,
+        broken,
+        fallback,
+    });
+    Ok(config)
+}
+
+// Без побочных эффектов: для отчёта и проверки копии
+pub fn read_config(path: &Path) -> Result<Config, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&data).map_err(|e| e.to_string())
+}
+
+fn backup_config(path: &Path) {
+    let backup = atomic::sibling(path, CONFIG_BACKUP);
+    let Ok(data) = fs::read(path) else {
+        return;
+    };
+    if fs::read(&backup).is_ok_and(|old| old == data) {
+        return;
+    }
+    if let Err(e) = atomic::write(&backup, data) {
+        eprintln!("Failed to back up config to {}: {}", backup.display(), e);
+    }
+}
+
+pub fn save_config(path: &Path, config: &Config) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let data = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    atomic::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    backup_config(path);
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const TOKEN_TTL: Duration = Duration::from_secs(30);
+
+// Команды, которые уничтожают то, что долго собиралось
+const DESTRUCTIVE: [&str; 4] = ["quit", "queue clear", "clear", "playlist delete"];
+
+// Токен -> команда, которую он подтверждает
+static PENDING: OnceLock<Mutex<HashMap<String, (String, Instant)>>> = OnceLock::new();
+
+pub fn is_destructive(cmd: &str) -> bool {
+    DESTRUCTIVE
+        .iter()
+        .any(|prefix| cmd == *prefix || cmd.starts_with(&format!("{} ", prefix)))
+}
+
+// Команда без --force/--confirm, если её можно выполнять,
+// иначе ответ "CONFIRM <token>" для повторной отправки с --confirm <token>
+pub fn check(cmd: &str) -> Result<String, String> {
+    let (command, force, token) = split_flags(cmd);
+    let command = command.to_string();
+    if force {
+        return Ok(command);
+    }
+
+    let mut pending = PENDING
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    pending.retain(|_, (_, issued)| issued.elapsed() < TOKEN_TTL);
+
+    if let Some(token) = token {
+        if pending.get(token).is_some_and(|(c, _)| *c == command) {
+            pending.remove(token);
+            return Ok(command);
+        }
+        return Err("ERR invalid or expired confirmation token\n".to_string());
+    }
+
+    let token = new_token(pending.len());
+    let reply = format!(
+        "CONFIRM {} repeat as: {} --confirm {}\n",
+        token, command, token
+    );
+    pending.insert(token, (command, Instant::now()));
+    Err(reply)
+}
+
+// Флаги снимаются только с конца команды: "--force" внутри пути или названия
+// остаётся частью аргумента
+pub fn split_flags(cmd: &str) -> (&str, bool, Option<&str>) {
+    let mut rest = cmd.trim();
+    let mut force = false;
+    let mut token = None;
+    loop {
+        if let Some(head) = strip_word(rest, "--force") {
+            force = true;
+            rest = head;
+            continue;
+        }
+        if let Some((head, last)) = rest.rsplit_once(char::is_whitespace) {
+            if let Some(head) = strip_word(head.trim_end(), "--confirm") {
+                token = Some(last);
+                rest = head;
+                continue;
+            }
+        }
+        return (rest, force, token);
+    }
+}
+
+// text без последнего слова word
+fn strip_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let head = text.strip_suffix(word)?;
+    (head.is_empty() || head.ends_with(char::is_whitespace)).then(|| head.trim_end())
+}
+
+// Угадать не нужно уметь: токен лишь защищает от случайного повтора
+fn new_token(salt: usize) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    format!("{:08x}", (nanos ^ (salt as u64).rotate_left(32)) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(reply: &str) -> &str {
+        reply.split_whitespace().nth(1).unwrap()
+    }
+
+    #[test]
+    fn only_destructive_commands_need_confirmation() {
+        assert!(is_destructive("quit"));
+        assert!(is_destructive("queue clear"));
+        assert!(is_destructive("playlist delete road"));
+        assert!(!is_destructive("queue list"));
+        assert!(!is_destructive("quitter"));
+    }
+
+    #[test]
+    fn flags_come_off_the_end_only() {
+        assert_eq!(split_flags("quit --force"), ("quit", true, None));
+        assert_eq!(
+            split_flags("queue clear --confirm 1a2b --force"),
+            ("queue clear", true, Some("1a2b"))
+        );
+        assert_eq!(
+            split_flags("playlist delete --force mix"),
+            ("playlist delete --force mix", false, None)
+        );
+    }
+
+    #[test]
+    fn token_confirms_its_command_once() {
+        assert_eq!(check("quit --force").unwrap(), "quit");
+        let reply = check("queue clear").unwrap_err();
+        assert!(reply.starts_with("CONFIRM "), "{}", reply);
+        let token = token(&reply).to_string();
+        assert!(reply.ends_with(&format!("queue clear --confirm {}\n", token)));
+
+        let other = check(&format!("quit --confirm {}", token)).unwrap_err();
+        assert!(other.starts_with("ERR "), "{}", other);
+        let confirmed = format!("queue clear --confirm {}", token);
+        assert_eq!(check(&confirmed).unwrap(), "queue clear");
+        assert!(check(&confirmed).unwrap_err().starts_with("ERR "));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::atomic;
+use crate::player::{Autofill, Repeat};
+use crate::state::PlaybackState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Заготовка контекста из конфига: с неё контекст начинается, пока у него нет
+// сохранённого состояния. Что не задано, остаётся как в текущем контексте
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub playlist: Option<String>,
+    pub mix: Option<String>,
+    // 0.0..1.0, как volume в конфиге
+    pub volume: Option<f32>,
+    pub output: Option<String>,
+    pub shuffle: Option<bool>,
+    pub repeat: Option<Repeat>,
+}
+
+// Сохранённый контекст: очередь с позицией, режимы, громкость и устройство вывода
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContextState {
+    #[serde(flatten)]
+    pub playback: PlaybackState,
+    pub output: String,
+    #[serde(default)]
+    pub autofill: Autofill,
+    #[serde(default)]
+    pub mix: Option<String>,
+}
+
+// Имя идёт в имя файла, поэтому без слэшей и точек
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+fn file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+// None — контекст ещё ни разу не сохранялся
+pub fn load(dir: &Path, name: &str) -> Result<Option<ContextState>, String> {
+    let path = file(dir, name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&data)
+        .map(Some)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn save(dir: &Path, name: &str, state: &ContextState) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let data = serde_json::to_string(state).map_err(|e| e.to_string())?;
+    let path = file(dir, name);
+    atomic::write(&path, data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Имена сохранённых контекстов
+pub fn saved(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = path.file_stem()?.to_str()?;
+            (path.extension()? == "json" && valid_name(name)).then(|| name.to_string())
+        })
+        .collect()
+}
@@ -0,0 +1,515 @@
+use crate::buffer::Watchdog;
+use crate::config::Config;
+use crate::db::{self, Database};
+use crate::hotkeys::HotkeyInput;
+#[cfg(feature = "http")]
+use crate::http;
+use crate::lastfm::Lastfm;
+use crate::library::LibraryDb;
+use crate::listenbrainz::ListenBrainz;
+use crate::listeners::{command_server, send_command, tcp_server};
+use crate::mix::Mix;
+use crate::player::{spawn_rescan, Autofill, MusicPlayer, Repeat};
+use crate::plugin::{Control, DefaultOutput, ErrorKind, PlayerPlugins, PluginRegistry};
+use crate::preload::Preloader;
+use crate::recovery::{self, Fallback};
+use crate::schedule::Schedule;
+use crate::scrobble::Scrobbler;
+use crate::state::{self, PlaybackState};
+use crate::watch::LibraryWatch;
+use crate::{automix, events, gme, paths, tags};
+#[cfg(feature = "dbus")]
+use crate::{dbus, mpris};
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DB_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+// Запускает движок: плагины, серверы команд и главный цикл; возвращается только при ошибке запуска
+pub fn run(config: Config) -> Result<(), String> {
+    let music_dir = config.music_dir();
+    // До запуска потоков: маску сигналов наследуют все они
+    let signals = block_signals();
+
+    let mut registry = PluginRegistry::default();
+    register_builtin_plugins(&mut registry, &config, &music_dir);
+    for plugin in &config.plugins {
+        if let Err(e) = registry.load_library(plugin) {
+            eprintln!("Failed to load plugin: {}", e);
+        }
+    }
+    let library_db = Arc::new(Mutex::new(LibraryDb::open(Path::new(&config.library_db))?));
+    registry.register_metadata(Box::new(tags::TagReader::new(Arc::clone(&library_db))));
+    if let Some(ref lastfm) = config.lastfm {
+        registry.register_surface(Box::new(Scrobbler::new(
+            Box::new(Lastfm::new(lastfm.clone())),
+            PathBuf::from(&lastfm.queue_file),
+            Arc::clone(&library_db),
+        )));
+    }
+    if let Some(ref listenbrainz) = config.listenbrainz {
+        registry.register_surface(Box::new(Scrobbler::new(
+            Box::new(ListenBrainz::new(listenbrainz.clone())),
+            PathBuf::from(&listenbrainz.queue_file),
+            Arc::clone(&library_db),
+        )));
+    }
+
+    paths::create_runtime_dirs()?;
+    let _ = fs::remove_file(paths::socket());
+    save_pid()?;
+
+    let control = Control::new(|cmd| send_command(cmd).map_err(|e| e.to_string()));
+    registry.start_inputs(&control);
+    let plugins = registry.into_player_plugins(&control);
+
+    config.buffer.apply();
+    let (stream, handle) = plugins.open_output(&config.output)?;
+    let sink = Arc::new(Mutex::new(
+        Sink::try_new(&handle).map_err(|e| e.to_string())?,
+    ));
+    sink.lock().unwrap().set_volume(config.volume);
+
+    let (player, rescan) = open_player(&config, plugins, library_db)?;
+    let player = Arc::new(Mutex::new(player));
+    spawn_signal_handler(signals, Arc::clone(&player), Arc::clone(&sink));
+    if rescan {
+        spawn_rescan(Arc::clone(&player));
+    }
+
+    let player_clone = Arc::clone(&player);
+    let sink_clone = Arc::clone(&sink);
+    let users = config.users.clone();
+    thread::spawn(move || {
+        command_server(player_clone, sink_clone, users);
+    });
+
+    if let Some(addr) = config.listen.clone() {
+        let player_clone = Arc::clone(&player);
+        let sink_clone = Arc::clone(&sink);
+        let users = config.users.clone();
+        thread::spawn(move || {
+            tcp_server(addr, player_clone, sink_clone, users);
+        });
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(addr) = config.http.clone() {
+        let player_clone = Arc::clone(&player);
+        let sink_clone = Arc::clone(&sink);
+        let users = config.users.clone();
+        thread::spawn(move || {
+            http::http_server(addr, player_clone, sink_clone, users);
+        });
+    }
+
+    // Без файла состояния (первый запуск) очередь играет с начала
+    let resume = config
+        .state_file
+        .as_ref()
+        .map(PathBuf::from)
+        .filter(|path| path.exists())
+        .and_then(|path| match state::load(&path) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                // Испорченное состояние не должно затереться первым же сохранением
+                recovery::record(recovery::Recovery {
+                    broken: recovery::set_aside(&path),
+                    file: path,
+                    error: e,
+                    fallback: Fallback::Defaults,
+                });
+                None
+            }
+        });
+    main_loop(player, sink, stream, handle, resume);
+    Ok(())
+}
+
+// Плеер с настройками из конфига. Библиотека сразу берётся из базы, если она там есть;
+// тогда второе значение true и папку надо пересканировать в фоне
+fn open_player(
+    config: &Config,
+    plugins: PlayerPlugins,
+    library_db: Arc<Mutex<LibraryDb>>,
+) -> Result<(MusicPlayer, bool), String> {
+    let music_dir = config.music_dir();
+    let db = Arc::new(Mutex::new(Database::open(Path::new(&config.database))?));
+    let cached = if music_dir.is_dir() {
+        library_db.lock().unwrap().tracks(&music_dir)?
+    } else {
+        Vec::new()
+    };
+    let rescan = !cached.is_empty();
+    let mut scan_options = config.scan.clone();
+    scan_options.extensions = config.decoders.extensions();
+    gme::set_loops(config.decoders.game_loops);
+    let mut player = MusicPlayer::new(
+        music_dir,
+        cached,
+        plugins,
+        db,
+        library_db,
+        config.automix.clone(),
+        scan_options,
+    )
+    .map_err(|e| e.to_string())?;
+    player.autofill = config.autofill;
+    player.set_queue_end(config.on_queue_end.clone());
+    player.confirm_destructive = config.confirm_destructive;
+    player.shuffle = config.shuffle;
+    player.shuffle_folders = config.shuffle_folders;
+    player.skips = config.skips.clone();
+    player.refill_shuffle();
+    player.output = config.output.clone();
+    player.preamp_db = config.preamp_db;
+    player.replaygain = config.replaygain.clone();
+    player.playlists_dir = PathBuf::from(&config.playlists_dir);
+    if let Some(ref spec) = config.mix {
+        match Mix::parse(spec, &player.playlists_dir) {
+            Ok(mix) => player.mix = Some(mix),
+            Err(e) => eprintln!("Failed to load mix: {}", e),
+        }
+    }
+    if player.autofill == Autofill::Mix && player.mix.is_none() {
+        player.autofill = Autofill::Off;
+    }
+    player.preloader = Preloader::new(config.preload.resolve(&player.music_dir));
+    player.decoders = config.decoders.clone();
+    player.gapless = config.gapless;
+    player.noise = config.noise.clone();
+    player.preview = config.preview.clone();
+    player.cd = config.cd.clone();
+    player.state_file = config.state_file.as_ref().map(PathBuf::from);
+    player.contexts = config.contexts.clone();
+    player.contexts_dir = PathBuf::from(&config.contexts_dir);
+    player.volume_step = config.volume_step;
+    if player.automix.enabled
+        || player.autofill == Autofill::Similar
+        || player.replaygain.needs_analysis()
+    {
+        db::spawn_analyzer(Arc::clone(&player.db), player.library.clone());
+    }
+    Ok((player, rescan))
+}
+
+// Плеер и sink в том виде, в каком их делят серверы команд
+pub type PlayerHandles = (Arc<Mutex<MusicPlayer>>, Arc<Mutex<Sink>>);
+
+// Плеер без устройства вывода, плагинов и серверов: команды выполняются как обычно,
+// но звук никуда не идёт. Для проверки протокола команд в тестах
+pub fn headless(config: &Config) -> Result<PlayerHandles, String> {
+    let library_db = Arc::new(Mutex::new(LibraryDb::open(Path::new(&config.library_db))?));
+    let control = Control::new(|_| Err("headless player has no command server".to_string()));
+    let plugins = PluginRegistry::default().into_player_plugins(&control);
+    let (sink, mut output) = Sink::new_idle();
+    sink.set_volume(config.volume);
+    // Звук уходит в никуда с настоящей скоростью: иначе sink ждёт конца трека вечно.
+    // Когда sink отброшен, очередь пустеет и поток завершается
+    thread::spawn(move || loop {
+        let chunk = (output.sample_rate() * output.channels() as u32 / 100) as usize;
+        if output.by_ref().take(chunk).count() < chunk {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    });
+    let (player, _) = open_player(config, plugins, library_db)?;
+    Ok((Arc::new(Mutex::new(player)), Arc::new(Mutex::new(sink))))
+}
+
+fn register_builtin_plugins(registry: &mut PluginRegistry, config: &Config, music_dir: &Path) {
+    registry.register_output(Box::new(DefaultOutput));
+    registry.register_surface(Box::new(events::EventFeed));
+    registry.register_input(Box::new(HotkeyInput {
+        hotkeys: config.hotkeys.clone(),
+        contexts: config.hotkey_contexts.clone(),
+        timing: config.hotkey_timing.clone(),
+        toggle: config.hotkey_toggle.clone(),
+        backend: config.hotkey_backend,
+        devices: config.hotkey_devices.clone(),
+    }));
+
+    if config.headphones.auto_pause {
+        registry.register_input(Box::new(config.headphones.clone()));
+    }
+    if config.cd.detect {
+        registry.register_input(Box::new(config.cd.clone()));
+    }
+    if config.removable.enabled {
+        registry.register_input(Box::new(config.removable.clone()));
+    }
+    if config.watch.enabled {
+        registry.register_input(Box::new(LibraryWatch::new(music_dir, &config.watch)));
+    }
+    if !config.schedule.is_empty() {
+        match Schedule::new(&config.schedule) {
+            Ok(schedule) => registry.register_input(Box::new(schedule)),
+            Err(e) => eprintln!("Schedule disabled: {}", e),
+        }
+    }
+
+    #[cfg(feature = "dbus")]
+    registry.register_surface(Box::new(dbus::TrackSignal::new()));
+    #[cfg(feature = "dbus")]
+    registry.register_surface(Box::new(mpris::Mpris::new()));
+
+    #[cfg(feature = "rotary")]
+    for rotary in &config.rotary {
+        registry.register_input(Box::new(rotary.clone()));
+    }
+
+    #[cfg(feature = "rfid")]
+    if let Some(ref rfid) = config.rfid {
+        registry.register_input(Box::new(rfid.clone()));
+    }
+}
+
+// Двойной fork с setsid: демон не лидер сессии и не может снова получить терминал.
+// stdin из /dev/null, stdout и stderr дописываются в лог
+pub fn daemonize(log_file: &Path) -> Result<(), String> {
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .map_err(|e| format!("{}: {}", log_file.display(), e))?;
+    let null = fs::File::open("/dev/null").map_err(|e| e.to_string())?;
+
+    unsafe {
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error().to_string()),
+            0 => {}
+            _ => process::exit(0),
+        }
+        if libc::setsid() < 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error().to_string()),
+            0 => {}
+            _ => process::exit(0),
+        }
+        libc::umask(0o022);
+        if libc::chdir(c"/".as_ptr()) < 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        if libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) < 0
+            || libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO) < 0
+            || libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) < 0
+        {
+            return Err(io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+// SIGTERM и SIGINT не прерывают потоки, а ждут своего в spawn_signal_handler
+fn block_signals() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        set
+    }
+}
+
+// Сохраняет базу и состояние, убирает сокет и PID-файл и выходит
+fn spawn_signal_handler(
+    signals: libc::sigset_t,
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+) {
+    thread::spawn(move || {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            eprintln!("Failed to wait for signals");
+            return;
+        }
+        println!("Received signal {}, shutting down", signal);
+        let player = player.lock().unwrap();
+        let sink = sink.lock().unwrap();
+        player.shutdown(&sink);
+    });
+}
+
+fn save_pid() -> Result<(), String> {
+    fs::write(paths::pid_file(), process::id().to_string()).map_err(|e| e.to_string())
+}
+
+fn main_loop(
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+    // Устройство открыто, пока жив поток
+    mut _stream: OutputStream,
+    mut handle: OutputStreamHandle,
+    resume: Option<PlaybackState>,
+) {
+    {
+        let mut player = player.lock().unwrap();
+        let sink = sink.lock().unwrap();
+        let resumed = match resume {
+            Some(mut state) => {
+                if let Some(context) = state.context.take() {
+                    player.context = context;
+                }
+                match player.resume(state, &sink) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        eprintln!("Failed to restore playback state: {}", e);
+                        false
+                    }
+                }
+            }
+            None => false,
+        };
+        if !resumed {
+            let result = player.play(&sink);
+            player.stop_on_error(result);
+        }
+    }
+
+    let db = Arc::clone(&player.lock().unwrap().db);
+    let mut last_flush = Instant::now();
+    let mut last_tick = Instant::now();
+    let mut watchdog = Watchdog::new();
+
+    loop {
+        if last_flush.elapsed() >= DB_FLUSH_INTERVAL {
+            last_flush = Instant::now();
+            if let Err(e) = db.lock().unwrap().save_if_dirty() {
+                eprintln!("Failed to save database: {}", e);
+            }
+            // После сбоя или выключения питания теряется не больше минуты
+            let player = player.lock().unwrap();
+            player.save_state(&sink.lock().unwrap());
+        }
+
+        {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let playing = !player.stopped && !sink.is_paused() && !sink.empty();
+            if let Some(reason) = watchdog.check(playing, sink.get_pos()) {
+                let message = format!("{}; reopening output {}", reason, player.output);
+                player.report_error(ErrorKind::Device, None, &message);
+                player.pending_output = Some(player.output.clone());
+            }
+        }
+
+        // Поток вывода не передаётся между потоками, поэтому переоткрывается здесь
+        let pending_output = player.lock().unwrap().pending_output.take();
+        if let Some(name) = pending_output {
+            let mut player = player.lock().unwrap();
+            let mut current = sink.lock().unwrap();
+            match player.plugins.open_output(&name) {
+                Ok((new_stream, new_handle)) => {
+                    match player.rebuild_sink(&mut current, &new_handle) {
+                        Ok(()) => {
+                            _stream = new_stream;
+                            handle = new_handle;
+                            player.output = name;
+                        }
+                        Err(e) => {
+                            let message = format!("failed to switch output to {}: {}", name, e);
+                            player.report_error(ErrorKind::Device, None, &message);
+                        }
+                    }
+                }
+                Err(e) => {
+                    let message = format!("failed to switch output to {}: {}", name, e);
+                    player.report_error(ErrorKind::Device, None, &message);
+                }
+            }
+        }
+
+        {
+            let mut player = player.lock().unwrap();
+            let current = sink.lock().unwrap();
+            if let Some(path) = player.pending_interrupt.take() {
+                if let Err(e) = player.start_interrupt(&path, &current, &handle) {
+                    eprintln!("Failed to play {}: {}", path.display(), e);
+                }
+            }
+            player.finish_interrupt(&current);
+            player.scan_step(&current, last_tick.elapsed());
+            last_tick = Instant::now();
+            player.sleep_step(&current);
+            player.stream_song_step(&current);
+            if !player.stopped && !current.is_paused() {
+                events::position(|| player.position_json(&current));
+            }
+
+            if let Some(name) = player.pending_noise.take() {
+                if let Err(e) = player.start_noise(&name, &handle) {
+                    eprintln!("Failed to play noise {}: {}", name, e);
+                }
+            }
+            if let Some(clip) = player.pending_preview.take() {
+                match Sink::try_new(&handle) {
+                    Ok(preview) => {
+                        preview.set_volume(player.user_volume(&current) * player.preview.volume);
+                        preview.append(clip);
+                        if let Some(previous) = player.preview_sink.replace(preview) {
+                            previous.stop();
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to open preview output: {}", e);
+                        player.preview_track = None;
+                    }
+                }
+            }
+            if player.preview_sink.as_ref().is_some_and(Sink::empty) {
+                player.stop_preview();
+            }
+        }
+
+        let beat_wait = {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            player.gapless_step(&sink);
+            player.plugins.flush_events();
+            if player.stopped {
+                None
+            } else if sink.empty() && player.repeat == Repeat::One {
+                let result = player.play(&sink);
+                player.stop_on_error(result);
+                None
+            } else if sink.empty() && player.at_queue_end() {
+                let result = player.queue_finished(&sink);
+                player.stop_on_error(result);
+                None
+            } else if sink.empty() {
+                let result = player.next(&sink);
+                player.stop_on_error(result);
+                None
+            } else if player.automix_due(&sink) {
+                Some(player.beat_wait(&sink))
+            } else {
+                None
+            }
+        };
+
+        if let Some(wait) = beat_wait {
+            thread::sleep(wait);
+            let mut player = player.lock().unwrap();
+            let mut current = sink.lock().unwrap();
+            let volume = current.volume();
+            match player.automix_transition(&mut current, &handle) {
+                Ok(old) => {
+                    automix::crossfade(old, Arc::clone(&sink), volume, player.automix.crossfade())
+                }
+                Err(e) => eprintln!("Automix transition failed: {}", e),
+            }
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+}
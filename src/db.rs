@@ -0,0 +1,222 @@
+use crate::analysis::{self, TrackAnalysis};
+use crate::atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+static ANALYZER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayRecord {
+    pub path: PathBuf,
+    pub time: u64,
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DatabaseFile {
+    tracks: HashMap<PathBuf, TrackAnalysis>,
+    #[serde(default)]
+    plays: Vec<PlayRecord>,
+}
+
+pub struct Database {
+    path: PathBuf,
+    data: DatabaseFile,
+    dirty: bool,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let data: DatabaseFile = if path.exists() {
+            let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            serde_json::from_str(&text).map_err(|e| e.to_string())?
+        } else {
+            DatabaseFile::default()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            data,
+            dirty: false,
+        })
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        let data = serde_json::to_string(&self.data).map_err(|e| e.to_string())?;
+        atomic::write(&self.path, data).map_err(|e| e.to_string())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn save_if_dirty(&mut self) -> Result<(), String> {
+        if self.dirty {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn record_play(&mut self, path: &Path, user: Option<&str>) {
+        self.data.plays.push(PlayRecord {
+            path: path.to_path_buf(),
+            time: now(),
+            user: user.map(str::to_string),
+        });
+        self.dirty = true;
+    }
+
+    // История пользователя; без авторизации учитываются все прослушивания
+    fn plays_of<'a>(&'a self, user: Option<&'a str>) -> impl Iterator<Item = &'a PlayRecord> {
+        self.data
+            .plays
+            .iter()
+            .filter(move |play| user.is_none() || play.user.as_deref() == user)
+    }
+
+    // Много слушалось в прошлом году, но не за последние `recent_days`
+    pub fn rediscover(&self, user: Option<&str>, recent_days: u64, limit: usize) -> Vec<PathBuf> {
+        let now = now();
+        let recent_start = now.saturating_sub(recent_days * DAY);
+        let year_start = recent_start.saturating_sub(365 * DAY);
+
+        let mut counts: HashMap<&PathBuf, usize> = HashMap::new();
+        let mut recent = HashSet::new();
+        for play in self.plays_of(user) {
+            if play.time >= recent_start {
+                recent.insert(&play.path);
+            } else if play.time >= year_start {
+                *counts.entry(&play.path).or_default() += 1;
+            }
+        }
+
+        counts.retain(|path, _| !recent.contains(path));
+        top_tracks(counts, limit)
+    }
+
+    // Треки, которые слушали в этот же день (±3 дня) в прошлые годы
+    pub fn on_this_day(&self, user: Option<&str>, limit: usize) -> Vec<PathBuf> {
+        let now = now();
+        let year = 365 * DAY + DAY / 4;
+
+        let mut counts: HashMap<&PathBuf, usize> = HashMap::new();
+        for play in self.plays_of(user) {
+            let age = now.saturating_sub(play.time);
+            if age < year - 3 * DAY {
+                continue;
+            }
+            let offset = age % year;
+            if offset <= 3 * DAY || offset >= year - 3 * DAY {
+                *counts.entry(&play.path).or_default() += 1;
+            }
+        }
+
+        top_tracks(counts, limit)
+    }
+
+    // Результат анализа, если файл не менялся с момента анализа
+    pub fn analysis(&self, path: &Path) -> Option<&TrackAnalysis> {
+        self.data.tracks.get(path).filter(|a| {
+            a.version == analysis::ANALYSIS_VERSION && a.mtime == analysis::file_mtime(path)
+        })
+    }
+
+    // k ближайших к треку кандидатов по вектору признаков
+    pub fn nearest(&self, path: &Path, candidates: &[&PathBuf], k: usize) -> Vec<PathBuf> {
+        let Some(target) = self.analysis(path).map(analysis::feature_vector) else {
+            return Vec::new();
+        };
+
+        let mut scored: Vec<(f32, &PathBuf)> = candidates
+            .iter()
+            .filter_map(|candidate| {
+                let features = analysis::feature_vector(self.analysis(candidate)?);
+                let distance: f32 = target
+                    .iter()
+                    .zip(features)
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum();
+                Some((distance, *candidate))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+        scored
+            .into_iter()
+            .take(k)
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+
+    pub fn insert(&mut self, path: PathBuf, analysis: TrackAnalysis) {
+        self.data.tracks.insert(path, analysis);
+        self.dirty = true;
+    }
+}
+
+// Фоновый анализ треков, которых ещё нет в базе; одновременно работает один анализатор
+pub fn spawn_analyzer(db: Arc<Mutex<Database>>, files: Vec<PathBuf>) {
+    if ANALYZER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    thread::spawn(move || {
+        let mut pending = 0;
+
+        for path in files {
+            if db.lock().unwrap().analysis(&path).is_some() {
+                continue;
+            }
+
+            match analysis::analyze(&path) {
+                Ok(result) => {
+                    db.lock().unwrap().insert(path, result);
+                    pending += 1;
+                }
+                Err(e) => eprintln!("Failed to analyze {}: {}", path.display(), e),
+            }
+
+            if pending >= 20 {
+                pending = 0;
+                if let Err(e) = db.lock().unwrap().save() {
+                    eprintln!("Failed to save database: {}", e);
+                }
+            }
+        }
+
+        if pending > 0 {
+            if let Err(e) = db.lock().unwrap().save() {
+                eprintln!("Failed to save database: {}", e);
+            }
+        }
+
+        ANALYZER_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+// Существующие треки по убыванию числа прослушиваний
+fn top_tracks(counts: HashMap<&PathBuf, usize>, limit: usize) -> Vec<PathBuf> {
+    let mut tracks: Vec<(&PathBuf, usize)> = counts
+        .into_iter()
+        .filter(|(path, _)| path.exists())
+        .collect();
+    tracks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    tracks
+        .into_iter()
+        .take(limit)
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,109 @@
+use crate::plugin::{ControlSurface, PlaybackError, TrackMetadata};
+use std::collections::HashMap;
+use std::time::Duration;
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+const OBJECT_PATH: &str = "/org/nsmp";
+const INTERFACE: &str = "org.nsmp";
+
+// Сигнал org.nsmp.TrackChanged(path, tags, position) на сессионной шине
+// для программ, которым не нужен весь MPRIS:
+// dbus-monitor "type='signal',interface='org.nsmp',member='TrackChanged'".
+// PlaybackError(kind, path, message) — kind: decode, device или missing; path пустой для device.
+// StatusChanged(changes) — только изменившиеся "paused", "volume" или "queue_length",
+// не чаще десяти раз в секунду
+pub struct TrackSignal {
+    connection: Option<Connection>,
+}
+
+impl TrackSignal {
+    pub fn new() -> Self {
+        Self { connection: None }
+    }
+
+    fn status_changed(&self, key: &str, value: Value) {
+        let Some(ref connection) = self.connection else {
+            return;
+        };
+        let body = HashMap::from([(key, value)]);
+        if let Err(e) = connection.emit_signal(
+            None::<&str>,
+            OBJECT_PATH,
+            INTERFACE,
+            "StatusChanged",
+            &(body,),
+        ) {
+            eprintln!("Failed to emit StatusChanged: {}", e);
+        }
+    }
+}
+
+impl ControlSurface for TrackSignal {
+    fn name(&self) -> &str {
+        "dbus"
+    }
+
+    fn start(&mut self, _control: crate::plugin::Control) -> Result<(), String> {
+        self.connection = Some(Connection::session().map_err(|e| e.to_string())?);
+        Ok(())
+    }
+
+    fn track_changed(&mut self, track: &TrackMetadata, position: Duration) {
+        let Some(ref connection) = self.connection else {
+            return;
+        };
+
+        let mut tags = HashMap::new();
+        tags.insert("title", track.title.clone());
+        if let Some(ref artist) = track.artist {
+            tags.insert("artist", artist.clone());
+        }
+        if let Some(ref album) = track.album {
+            tags.insert("album", album.clone());
+        }
+        if let Some(number) = track.track_number {
+            tags.insert("tracknumber", number.to_string());
+        }
+
+        let body = (
+            track.path.to_string_lossy().into_owned(),
+            tags,
+            position.as_secs_f64(),
+        );
+        if let Err(e) =
+            connection.emit_signal(None::<&str>, OBJECT_PATH, INTERFACE, "TrackChanged", &body)
+        {
+            eprintln!("Failed to emit TrackChanged: {}", e);
+        }
+    }
+
+    fn playback_error(&mut self, error: &PlaybackError) {
+        let Some(ref connection) = self.connection else {
+            return;
+        };
+        let path = error
+            .path
+            .as_deref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let body = (error.kind.as_str(), path, error.message.as_str());
+        if let Err(e) =
+            connection.emit_signal(None::<&str>, OBJECT_PATH, INTERFACE, "PlaybackError", &body)
+        {
+            eprintln!("Failed to emit PlaybackError: {}", e);
+        }
+    }
+
+    fn playback_changed(&mut self, paused: bool) {
+        self.status_changed("paused", Value::from(paused));
+    }
+
+    fn volume_changed(&mut self, volume: f32) {
+        self.status_changed("volume", Value::from(f64::from(volume)));
+    }
+
+    fn queue_changed(&mut self, length: usize, _revision: u64) {
+        self.status_changed("queue_length", Value::from(length as u32));
+    }
+}
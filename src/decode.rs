@@ -0,0 +1,495 @@
+use crate::cd::{self, CdSource};
+use crate::dsd::{self, DsdSource};
+use crate::gme::{self, GameMusicSource};
+use crate::midi::{self, MidiSource};
+#[cfg(feature = "opus")]
+use crate::opus::{self, OpusDecoder};
+use crate::preload::TrackReader;
+use crate::stream::{self, HttpStream, StreamConfig};
+use crate::tracker::{self, TrackerSource};
+use rodio::source::SeekError;
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecRegistry, Decoder as CodecDecoder, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+pub type DecodedSource = Box<dyn Source<Item = f32> + Send>;
+
+// Во что ffmpeg переводит поток; дальше rodio сам передискретизирует
+const FFMPEG_CHANNELS: u16 = 2;
+const FFMPEG_RATE: u32 = 48000;
+// Что ещё попадает в библиотеку, когда ffmpeg включён
+const FFMPEG_EXTENSIONS: [&str; 16] = [
+    "opus", "wma", "ape", "wv", "aif", "aiff", "alac", "mka", "webm", "ac3", "dts", "mpc", "tta",
+    "amr", "caf", "mp2",
+];
+// Здесь rodio часто не справляется (AAC в MP4) или не умеет вовсе (Opus),
+// поэтому по умолчанию первой пробуется symphonia
+const SYMPHONIA_FIRST: [&str; 5] = ["m4a", "m4b", "mp4", "aac", "opus"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // Декодеры rodio: hound, lewton, claxon, minimp3
+    Rodio,
+    // Собственный разбор контейнера и кодека через symphonia
+    Symphonia,
+    // DSF и DFF, переводятся в PCM
+    Dsd,
+    // mod, xm, it, s3m и прочие трекерные форматы через libopenmpt
+    Tracker,
+    // VGM, NSF, SPC, GBS и прочая музыка из игр через libgme
+    Gme,
+    // MIDI через fluidsynth с SoundFont из настроек
+    Midi,
+    // Внешний процесс ffmpeg; работает, только если включён в настройках
+    Ffmpeg,
+}
+
+// Какие декодеры и в каком порядке пробовать для каждого расширения.
+// Если первый не открыл файл, берётся следующий
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DecoderConfig {
+    // Цепочка для расширений без своей записи
+    pub default: Vec<Backend>,
+    // "m4a": ["symphonia"], "opus": ["symphonia", "rodio"]...
+    pub formats: HashMap<String, Vec<Backend>>,
+    // Последняя попытка для всего, что не открыли остальные: ffmpeg -i file -f f32le -
+    pub ffmpeg: bool,
+    pub ffmpeg_path: PathBuf,
+    // Файл .sf2 для MIDI; без него MIDI не сканируется и не играет
+    pub soundfont: Option<PathBuf>,
+    // Сколько раз повторять петлю трека игровой музыки перед затуханием
+    pub game_loops: u32,
+    pub stream: StreamConfig,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        Self {
+            default: vec![Backend::Rodio, Backend::Symphonia],
+            formats: HashMap::new(),
+            ffmpeg: false,
+            ffmpeg_path: PathBuf::from("ffmpeg"),
+            soundfont: None,
+            game_loops: 2,
+            stream: StreamConfig::default(),
+        }
+    }
+}
+
+impl DecoderConfig {
+    fn chain(&self, path: &Path) -> Vec<Backend> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .unwrap_or_default();
+        let mut chain = match self.formats.get(&ext) {
+            Some(chain) => chain.clone(),
+            None if dsd::is_dsd(&ext) => vec![Backend::Dsd],
+            None if tracker::is_tracker(&ext) => vec![Backend::Tracker],
+            None if gme::is_game_music(&ext) => vec![Backend::Gme],
+            None if midi::is_midi(&ext) => vec![Backend::Midi],
+            None if SYMPHONIA_FIRST.contains(&ext.as_str()) => {
+                vec![Backend::Symphonia, Backend::Rodio]
+            }
+            None => self.default.clone(),
+        };
+        if self.ffmpeg {
+            if !chain.contains(&Backend::Ffmpeg) {
+                chain.push(Backend::Ffmpeg);
+            }
+        } else {
+            chain.retain(|backend| *backend != Backend::Ffmpeg);
+        }
+        chain
+    }
+
+    // Расширения с явной цепочкой тоже попадают в библиотеку при сканировании
+    pub fn extensions(&self) -> Vec<String> {
+        let mut extensions: Vec<String> =
+            self.formats.keys().map(|ext| ext.to_lowercase()).collect();
+        if self.ffmpeg {
+            extensions.extend(FFMPEG_EXTENSIONS.iter().map(|ext| ext.to_string()));
+        }
+        if self.soundfont.is_some() && midi::available() {
+            extensions.extend(midi::extensions().iter().map(|ext| ext.to_string()));
+        }
+        #[cfg(feature = "opus")]
+        if opus::available() {
+            extensions.push("opus".to_string());
+        }
+        extensions
+    }
+
+    // open вызывается заново для каждой попытки: декодер забирает читателя себе.
+    // path может указывать на подпесню модуля, open получает сам файл
+    pub fn decode(
+        &self,
+        path: &Path,
+        mut open: impl FnMut(&Path) -> io::Result<TrackReader>,
+    ) -> io::Result<DecodedSource> {
+        // Трек аудио-CD читается прямо с привода, мимо цепочки декодеров
+        if cd::is_cd_track(path) {
+            return CdSource::new(path)
+                .map(|source| Box::new(source) as DecodedSource)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+        if stream::is_stream(path) {
+            return self.decode_stream(path);
+        }
+        let (file, subsong) = tracker::split_subsong(path);
+        let path = file.as_path();
+        let mut open = || open(path);
+        let mut errors = Vec::new();
+        for backend in self.chain(path) {
+            let result = match backend {
+                Backend::Rodio => Decoder::new(open()?)
+                    .map(|decoder| Box::new(decoder.convert_samples()) as DecodedSource)
+                    .map_err(|e| e.to_string()),
+                Backend::Symphonia => SymphoniaSource::new(Box::new(open()?), path)
+                    .map(|source| Box::new(source) as DecodedSource),
+                Backend::Dsd => {
+                    DsdSource::new(open()?).map(|source| Box::new(source) as DecodedSource)
+                }
+                Backend::Tracker => TrackerSource::new(open()?, subsong)
+                    .map(|source| Box::new(source) as DecodedSource),
+                Backend::Gme => GameMusicSource::new(open()?, subsong)
+                    .map(|source| Box::new(source) as DecodedSource),
+                Backend::Midi => match self.soundfont {
+                    Some(ref soundfont) => MidiSource::new(open()?, soundfont)
+                        .map(|source| Box::new(source) as DecodedSource),
+                    None => Err("no soundfont configured".to_string()),
+                },
+                Backend::Ffmpeg => FfmpegSource::new(&self.ffmpeg_path, path)
+                    .map(|source| Box::new(source) as DecodedSource),
+            };
+            match result {
+                Ok(source) => return Ok(source),
+                Err(e) => errors.push(format!("{:?}: {}", backend, e)),
+            }
+        }
+        if errors.is_empty() {
+            errors.push("no decoders configured".to_string());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), errors.join("; ")),
+        ))
+    }
+}
+
+impl DecoderConfig {
+    // Поток разбирает symphonia; ffmpeg, если включён, открывает адрес сам
+    fn decode_stream(&self, url: &Path) -> io::Result<DecodedSource> {
+        let result = HttpStream::open(url, &self.stream)
+            .and_then(|reader| SymphoniaSource::new(Box::new(reader), url))
+            .map(|source| Box::new(source) as DecodedSource);
+        let result = match result {
+            Err(e) if self.ffmpeg => FfmpegSource::new(&self.ffmpeg_path, url)
+                .map(|source| Box::new(source) as DecodedSource)
+                .map_err(|ffmpeg| format!("Symphonia: {}; Ffmpeg: {}", e, ffmpeg)),
+            result => result,
+        };
+        result.map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", url.display(), e),
+            )
+        })
+    }
+}
+
+impl MediaSource for TrackReader {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        match self {
+            TrackReader::File(file) => file.get_ref().metadata().ok().map(|meta| meta.len()),
+            TrackReader::Memory(cursor) => Some(cursor.get_ref().len() as u64),
+        }
+    }
+}
+
+pub struct SymphoniaSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    track_id: u32,
+    samples: Vec<f32>,
+    position: usize,
+    // После точной перемотки отбрасываются отсчёты до нужного места
+    skip: usize,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+// Кодеки symphonia и, если собран с ним, Opus через libopus
+fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+    CODECS.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut registry);
+        #[cfg(feature = "opus")]
+        registry.register_all::<OpusDecoder>();
+        registry
+    })
+}
+
+impl SymphoniaSource {
+    fn new(reader: Box<dyn MediaSource>, path: &Path) -> Result<Self, String> {
+        let stream = MediaSourceStream::new(reader, Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+        let probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions {
+                    enable_gapless: true,
+                    ..Default::default()
+                },
+                &MetadataOptions::default(),
+            )
+            .map_err(|e| e.to_string())?;
+        let format = probed.format;
+        let track = format
+            .default_track()
+            .ok_or_else(|| "no audio track".to_string())?;
+        let params = &track.codec_params;
+        let decoder = codecs()
+            .make(params, &DecoderOptions::default())
+            .map_err(|e| e.to_string())?;
+        let total_duration = params.time_base.zip(params.n_frames).map(|(base, frames)| {
+            let time = base.calc_time(frames);
+            Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+        });
+
+        let mut source = Self {
+            track_id: track.id,
+            channels: params.channels.map(|c| c.count() as u16).unwrap_or(2),
+            sample_rate: params.sample_rate.unwrap_or(44100),
+            format,
+            decoder,
+            samples: Vec::new(),
+            position: 0,
+            skip: 0,
+            total_duration,
+        };
+        // Первый пакет сразу: ошибка кодека должна случиться здесь, а не в sink
+        if !source.decode_next() {
+            return Err("no audio data".to_string());
+        }
+        Ok(source)
+    }
+
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+                    self.channels = spec.channels.count() as u16;
+                    self.sample_rate = spec.rate;
+                    self.samples.clear();
+                    self.samples.extend_from_slice(buffer.samples());
+                    self.position = self.skip.min(self.samples.len());
+                    self.skip -= self.position;
+                    if self.position < self.samples.len() {
+                        return true;
+                    }
+                }
+                // Битый пакет пропускается, как это делают другие плееры
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Iterator for SymphoniaSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.samples.len() && !self.decode_next() {
+            return None;
+        }
+        let sample = self.samples[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for SymphoniaSource {
+    // Формат может смениться на границе пакета
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len() - self.position)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::from(pos),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|e| SeekError::Other(Box::new(e)))?;
+        self.decoder.reset();
+        let frames = seeked.required_ts.saturating_sub(seeked.actual_ts) as usize;
+        self.skip = frames * self.channels as usize;
+        self.samples.clear();
+        self.position = 0;
+        self.decode_next();
+        Ok(())
+    }
+}
+
+// PCM из ffmpeg. Перемотка перезапускает процесс с -ss
+pub struct FfmpegSource {
+    program: PathBuf,
+    path: PathBuf,
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl FfmpegSource {
+    fn new(program: &Path, path: &Path) -> Result<Self, String> {
+        let (child, mut stdout) = spawn_ffmpeg(program, path, Duration::ZERO)?;
+        // Пустой вывод значит, что ffmpeg файл не разобрал
+        if stdout.fill_buf().map_err(|e| e.to_string())?.is_empty() {
+            let mut child = child;
+            let status = child.wait().map_err(|e| e.to_string())?;
+            return Err(format!("ffmpeg produced no audio ({})", status));
+        }
+        Ok(Self {
+            program: program.to_path_buf(),
+            path: path.to_path_buf(),
+            child,
+            stdout,
+        })
+    }
+}
+
+fn spawn_ffmpeg(
+    program: &Path,
+    path: &Path,
+    start: Duration,
+) -> Result<(Child, BufReader<ChildStdout>), String> {
+    let mut child = Command::new(program)
+        .args(["-nostdin", "-v", "error", "-ss"])
+        .arg(format!("{:.3}", start.as_secs_f64()))
+        .arg("-i")
+        .arg(path)
+        .args(["-vn", "-f", "f32le", "-ac"])
+        .arg(FFMPEG_CHANNELS.to_string())
+        .arg("-ar")
+        .arg(FFMPEG_RATE.to_string())
+        .arg("-")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: {}", program.display(), e))?;
+
+    // Сообщения ffmpeg в журнал; непрочитанный stderr остановил бы процесс
+    if let Some(stderr) = child.stderr.take() {
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                eprintln!("ffmpeg: {}", line);
+            }
+        });
+    }
+    let stdout = child.stdout.take().ok_or("ffmpeg has no stdout")?;
+    Ok((child, BufReader::new(stdout)))
+}
+
+impl Iterator for FfmpegSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut bytes = [0u8; 4];
+        self.stdout.read_exact(&mut bytes).ok()?;
+        Some(f32::from_le_bytes(bytes))
+    }
+}
+
+impl Source for FfmpegSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        FFMPEG_CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        FFMPEG_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let (child, stdout) = spawn_ffmpeg(&self.program, &self.path, pos)
+            .map_err(|e| SeekError::Other(Box::new(io::Error::other(e))))?;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.child = child;
+        self.stdout = stdout;
+        Ok(())
+    }
+}
+
+impl Drop for FfmpegSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
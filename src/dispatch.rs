@@ -0,0 +1,1206 @@
+use crate::analysis::{self, TrackAnalysis};
+use crate::hotkeys::HOTKEYS_ENABLED;
+use crate::mix::Mix;
+use crate::player::{spawn_rescan, volume_json, Autofill, MusicPlayer, QueueEnd, Repeat, Snapshot};
+use crate::scan::scan_music;
+use crate::units::{self, Seek, Volume};
+use crate::{
+    buffer, cd, clock, commands, confirm, context, db, handoff, i18n, limiter, playlist, recovery,
+    seamless, PROTOCOL_VERSION,
+};
+use rodio::Sink;
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SEARCH_LIMIT: usize = 50;
+// seek_forward и seek_backward без аргумента
+const SEEK_STEP: Duration = Duration::from_secs(10);
+// Сколько самых пропускаемых треков показывает stats
+const MOST_SKIPPED: usize = 10;
+// Сколько последних команд отдаёт "history commands"
+const COMMAND_HISTORY: usize = 100;
+// Сколько прослушиваний отдают "history" и "most_played" без числа
+const PLAYED_TRACKS: usize = 20;
+// Команды, запускающие воспроизведение: следующие прослушивания записываются
+// тому, кто их прислал, а анонимная команда снимает прежнего слушателя
+const LISTENING: [&str; 13] = [
+    "play",
+    "next",
+    "prev",
+    "goto",
+    "load",
+    "load_playlist",
+    "play-dir",
+    "mix",
+    "generate",
+    "similar",
+    "context",
+    "cd",
+    "restore",
+];
+
+// Запросы состояния не засоряют историю команд
+const UNRECORDED: [&str; 7] = [
+    "status",
+    "hello",
+    "commands",
+    "whoami",
+    "history",
+    "repeat-last",
+    "subscribe",
+];
+
+// Возможности, по которым клиенты решают, какие команды доступны
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = vec![
+        "auth", "tcp", "handoff", "analysis", "automix", "history", "events",
+    ];
+    if cfg!(feature = "rotary") {
+        features.push("rotary");
+    }
+    if cfg!(feature = "rfid") {
+        features.push("rfid");
+    }
+    if cfg!(feature = "dbus") {
+        features.push("dbus");
+        features.push("mpris");
+    }
+    if cfg!(feature = "http") {
+        features.push("http");
+    }
+    if cfg!(feature = "opus") {
+        features.push("opus");
+    }
+    if cfg!(feature = "tui") {
+        features.push("tui");
+    }
+    features
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandRecord {
+    time: u64,
+    command: String,
+}
+
+// user — кто прислал команду; None — без авторизации или локальный ввод
+pub fn handle_command(
+    player: &Arc<Mutex<MusicPlayer>>,
+    sink: &Arc<Mutex<Sink>>,
+    cmd: &str,
+    user: Option<&str>,
+) -> String {
+    let cmd = cmd.trim();
+    let (name, arg) = cmd.split_once(' ').unwrap_or((cmd, ""));
+    match name {
+        "repeat-last" => {
+            let last = player
+                .lock()
+                .unwrap()
+                .command_history
+                .back()
+                .map(|record| record.command.clone());
+            return match last {
+                Some(last) => handle_command(player, sink, &last, user),
+                None => "ERR no command to repeat\n".to_string(),
+            };
+        }
+        "history" => {
+            let arg = arg.trim();
+            if arg == "commands" {
+                let history = &player.lock().unwrap().command_history;
+                return serde_json::json!({ "commands": history }).to_string() + "\n";
+            }
+            let limit = match arg {
+                "" => PLAYED_TRACKS,
+                limit => match limit.parse() {
+                    Ok(limit) => limit,
+                    Err(_) => return format!("ERR unknown history: {}\n", arg),
+                },
+            };
+            let player = player.lock().unwrap();
+            let plays = player.library_db.lock().unwrap().history(limit);
+            return match plays {
+                Ok(plays) => {
+                    let plays: Vec<_> = plays
+                        .into_iter()
+                        .map(|(path, time)| {
+                            serde_json::json!({
+                                "time": time,
+                                "track": path,
+                                "display": player.plugins.metadata(&path).display(),
+                            })
+                        })
+                        .collect();
+                    serde_json::json!({ "plays": plays }).to_string() + "\n"
+                }
+                Err(e) => format!("ERR {}\n", e),
+            };
+        }
+        _ => {}
+    }
+
+    let reply = execute_command(player, sink, cmd, user);
+    // Разрушительные команды не повторяются без подтверждения, поэтому не запоминаются
+    if !reply.starts_with("ERR")
+        && !UNRECORDED.contains(&name)
+        && !confirm::is_destructive(cmd)
+        && !cmd.is_empty()
+    {
+        let mut player = player.lock().unwrap();
+        if player.command_history.len() == COMMAND_HISTORY {
+            player.command_history.pop_front();
+        }
+        player.command_history.push_back(CommandRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            command: cmd.to_string(),
+        });
+    }
+    reply
+}
+
+fn execute_command(
+    player: &Arc<Mutex<MusicPlayer>>,
+    sink: &Arc<Mutex<Sink>>,
+    cmd: &str,
+    user: Option<&str>,
+) -> String {
+    let confirmed;
+    let mut cmd = cmd.trim();
+    if confirm::is_destructive(cmd) {
+        if player.lock().unwrap().confirm_destructive {
+            match confirm::check(cmd) {
+                Ok(command) => confirmed = command,
+                Err(reply) => return reply,
+            }
+        } else {
+            confirmed = confirm::split_flags(cmd).0.to_string();
+        }
+        cmd = confirmed.trim();
+    }
+
+    let (name, arg) = cmd.split_once(' ').unwrap_or((cmd, ""));
+    let arg = arg.trim();
+    let mut reply = String::new();
+    if LISTENING.contains(&name) {
+        player.lock().unwrap().listener = user.map(str::to_string);
+    }
+
+    match name {
+        "next" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if !player.stopped {
+                player.count_skip(sink.get_pos());
+            }
+            if let Err(e) = player.next(&sink) {
+                reply = format!("ERR {}\n", e);
+            }
+        }
+        "prev" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if let Err(e) = player.prev(&sink) {
+                reply = format!("ERR {}\n", e);
+            }
+        }
+        "automix" => {
+            let mut player = player.lock().unwrap();
+            player.automix.enabled = match arg {
+                "on" => true,
+                "off" => false,
+                _ => !player.automix.enabled,
+            };
+            if player.automix.enabled {
+                db::spawn_analyzer(Arc::clone(&player.db), player.library.clone());
+            }
+        }
+        "similar" => {
+            let player = player.lock().unwrap();
+            let k = arg.parse().unwrap_or(10);
+            for path in player.similar(k) {
+                reply.push_str(&format!("{}\n", path.display()));
+            }
+        }
+        "autofill" => {
+            let mut player = player.lock().unwrap();
+            player.autofill = match arg {
+                "similar" => Autofill::Similar,
+                "mix" if player.mix.is_some() => Autofill::Mix,
+                "mix" => return "ERR no mix set, use mix <playlist>[:weight]...\n".to_string(),
+                _ => Autofill::Off,
+            };
+            if player.autofill == Autofill::Similar {
+                db::spawn_analyzer(Arc::clone(&player.db), player.library.clone());
+            }
+        }
+        "mix" => {
+            let mut player = player.lock().unwrap();
+            match arg {
+                "" => {
+                    if let Some(ref mix) = player.mix {
+                        reply = format!("{}\n", mix);
+                    }
+                }
+                "off" => {
+                    player.mix = None;
+                    if player.autofill == Autofill::Mix {
+                        player.autofill = Autofill::Off;
+                    }
+                }
+                spec => match Mix::parse(spec, &player.playlists_dir) {
+                    // Микс сразу сменяет очередь, как load_playlist
+                    Ok(mut mix) => {
+                        let sink = sink.lock().unwrap();
+                        if let Some(first) = mix.next_track() {
+                            player.set_queue(vec![first], 0);
+                            if let Err(e) = player.play(&sink) {
+                                reply = format!("ERR {}\n", e);
+                            }
+                        }
+                        player.mix = Some(mix);
+                        player.autofill = Autofill::Mix;
+                    }
+                    Err(e) => reply = format!("ERR {}\n", e),
+                },
+            }
+        }
+        "generate" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let (kind, limit) = arg.split_once(' ').unwrap_or((arg, ""));
+            let limit = limit.parse().unwrap_or(50);
+            let tracks = match kind {
+                "rediscover" => player.db.lock().unwrap().rediscover(user, 90, limit),
+                "on-this-day" => player.db.lock().unwrap().on_this_day(user, limit),
+                _ => return format!("ERR unknown playlist generator: {}\n", kind),
+            };
+
+            for path in &tracks {
+                reply.push_str(&format!("{}\n", path.display()));
+            }
+            if !tracks.is_empty() {
+                player.set_queue(tracks, 0);
+                if let Err(e) = player.play(&sink) {
+                    reply = format!("ERR {}\n", e);
+                }
+            }
+        }
+        "hello" => {
+            reply = serde_json::json!({
+                "protocol": PROTOCOL_VERSION,
+                "version": env!("CARGO_PKG_VERSION"),
+                "features": enabled_features(),
+            })
+            .to_string()
+                + "\n";
+        }
+        "subscribe" => reply = "ERR subscribe needs a socket connection\n".to_string(),
+        "commands" => {
+            reply = serde_json::to_string(commands::COMMANDS).unwrap_or_default() + "\n";
+        }
+        "whoami" => reply = format!("{}\n", user.unwrap_or("anonymous")),
+        "analyze" => {
+            let player = player.lock().unwrap();
+            db::spawn_analyzer(Arc::clone(&player.db), player.library.clone());
+        }
+        "play" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let (flag, value) = arg.split_once(' ').unwrap_or((arg, ""));
+            match flag {
+                "--bpm" => match analysis::parse_bpm_range(value) {
+                    Some((low, high)) => {
+                        let in_range =
+                            |a: &TrackAnalysis| a.bpm.is_some_and(|bpm| bpm >= low && bpm <= high);
+                        if let Err(e) = player.play_filtered(&sink, in_range) {
+                            reply = format!("ERR {}\n", e);
+                        }
+                    }
+                    None => reply = format!("ERR invalid BPM range: {}\n", value),
+                },
+                "--mood" if analysis::MOODS.contains(&value) => {
+                    let matches = |a: &TrackAnalysis| a.mood.as_deref() == Some(value);
+                    if let Err(e) = player.play_filtered(&sink, matches) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                "--mood" => {
+                    reply = format!(
+                        "ERR unknown mood: {} (expected one of {})\n",
+                        value,
+                        analysis::MOODS.join(", ")
+                    )
+                }
+                "noise:off" => {
+                    if let Some(noise) = player.noise_sink.take() {
+                        noise.stop();
+                    }
+                }
+                _ if flag.starts_with("noise:") => {
+                    player.pending_noise = Some(flag["noise:".len()..].to_string());
+                }
+                "favorites" => {
+                    if let Err(e) = player.play_favorites(&sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                "--similar" => {
+                    let k = value.parse().unwrap_or(10);
+                    if let Err(e) = player.play_similar(k) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                _ if player.stopped => {
+                    if let Err(e) = player.play(&sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                _ => {
+                    sink.play();
+                    player.pause_reason = None;
+                    player.plugins.playback_changed(false);
+                }
+            }
+        }
+        "handoff" => {
+            let (host, token) = arg.split_once(' ').unwrap_or((arg, ""));
+            let token = Some(token.trim()).filter(|t| !t.is_empty());
+            let snapshot = {
+                let player = player.lock().unwrap();
+                let sink = sink.lock().unwrap();
+                player.snapshot(&sink)
+            };
+            // Пока другая машина отвечает, плеер не заблокирован и слушается остальных команд
+            match handoff::send_snapshot(host, token, &snapshot) {
+                Ok(()) => {
+                    let mut player = player.lock().unwrap();
+                    let sink = sink.lock().unwrap();
+                    sink.pause();
+                    player.plugins.playback_changed(true);
+                    reply = i18n::tr("handoff-done", &[("host", host)]) + "\n";
+                }
+                Err(e) => reply = format!("ERR handoff failed: {}\n", e),
+            }
+        }
+        "restore" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            match serde_json::from_str::<Snapshot>(arg) {
+                Ok(snapshot) => {
+                    if let Err(e) = player.restore(snapshot, &sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                Err(e) => reply = format!("ERR invalid snapshot: {}\n", e),
+            }
+        }
+        "output" => {
+            let mut player = player.lock().unwrap();
+            let name = if arg.is_empty() {
+                player.output.clone()
+            } else {
+                arg.to_string()
+            };
+            player.pending_output = Some(name);
+        }
+        "load" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if let Err(e) = player.load(Path::new(arg), &sink) {
+                reply = format!("ERR failed to load {}: {}\n", arg, e);
+            }
+        }
+        "search" => {
+            let player = player.lock().unwrap();
+            reply = player.search_json(arg, SEARCH_LIMIT).to_string() + "\n";
+        }
+        "context" => {
+            let mut player = player.lock().unwrap();
+            let (action, name) = arg.split_once(' ').unwrap_or((arg, ""));
+            let name = name.trim();
+            match action {
+                "" | "list" => {
+                    let mut names: Vec<String> = context::saved(&player.contexts_dir);
+                    names.extend(player.contexts.keys().cloned());
+                    names.push(player.context.clone());
+                    names.sort();
+                    names.dedup();
+                    reply = serde_json::json!({ "current": player.context, "contexts": names })
+                        .to_string()
+                        + "\n";
+                }
+                "switch" | "save" if !context::valid_name(name) => {
+                    reply = format!("ERR invalid context name: {}\n", name);
+                }
+                "switch" => {
+                    let sink = sink.lock().unwrap();
+                    if let Err(e) = player.switch_context(name, &sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                "save" => {
+                    let sink = sink.lock().unwrap();
+                    if let Err(e) = player.save_context(name, &sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                _ => reply = format!("ERR unknown context action: {}\n", action),
+            }
+        }
+        "load_playlist" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let loaded = playlist::find(arg, &player.playlists_dir).and_then(|path| {
+                if playlist::is_playlist(&path) {
+                    playlist::load_with_current(&path)
+                } else {
+                    Err(format!("{}: not an m3u playlist", path.display()))
+                }
+            });
+            match loaded {
+                Ok((files, current)) => {
+                    player.set_queue(files, current);
+                    if let Err(e) = player.play(&sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "save_playlist" => {
+            let player = player.lock().unwrap();
+            let saved = playlist::resolve(arg, &player.playlists_dir).and_then(|path| {
+                playlist::save(&path, &player.files, player.current_index).map(|_| path)
+            });
+            match saved {
+                Ok(path) => reply = format!("{}\n", path.display()),
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "play-dir" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if let Err(e) = player.play_dir(Path::new(arg), &sink) {
+                reply = format!("ERR {}\n", e);
+            }
+        }
+        "cd" => reply = handle_cd(player, sink, arg),
+        "drive" => {
+            let (action, path) = arg.split_once(' ').unwrap_or((arg, ""));
+            let path = Path::new(path.trim());
+            match action {
+                // Носитель сканируется без блокировки плеера
+                "added" => {
+                    let scan_options = player.lock().unwrap().scan_options.clone();
+                    match scan_music(path, &scan_options) {
+                        Ok(files) => player.lock().unwrap().add_drive(path, files),
+                        Err(e) => reply = format!("ERR no music on {}: {}\n", path.display(), e),
+                    }
+                }
+                "removed" => {
+                    let mut player = player.lock().unwrap();
+                    let sink = sink.lock().unwrap();
+                    player.remove_drive(path, &sink);
+                }
+                _ => reply = format!("ERR unknown drive action: {}\n", arg),
+            }
+        }
+        "noise_volume" => {
+            let mut player = player.lock().unwrap();
+            match units::parse_percent(arg) {
+                Ok(volume) => {
+                    player.noise.volume = volume;
+                    if let Some(ref noise) = player.noise_sink {
+                        noise.set_volume(player.noise.volume);
+                    }
+                }
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "playlist" => {
+            let player = player.lock().unwrap();
+            let mut words = arg.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("repair"), Some(name)) => {
+                    let mut maps = Vec::new();
+                    while let Some(word) = words.next() {
+                        match (word, words.next().and_then(playlist::parse_map)) {
+                            ("--map", Some(map)) => maps.push(map),
+                            _ => {
+                                reply = "ERR usage: playlist repair <name> [--map old=new]...\n"
+                                    .to_string();
+                                return reply;
+                            }
+                        }
+                    }
+                    let repaired = playlist::resolve(name, &player.playlists_dir)
+                        .and_then(|path| playlist::repair(&path, &maps, &player.library));
+                    match repaired {
+                        Ok(report) => {
+                            reply = serde_json::json!({
+                                "kept": report.kept,
+                                "mapped": report.mapped,
+                                "matched": report.matched,
+                                "missing": report.missing,
+                            })
+                            .to_string()
+                                + "\n";
+                        }
+                        Err(e) => reply = format!("ERR {}\n", e),
+                    }
+                }
+                (Some("delete"), Some(name)) => {
+                    let deleted = playlist::resolve(name, &player.playlists_dir)
+                        .and_then(|path| playlist::delete(&path));
+                    if let Err(e) = deleted {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                _ => {
+                    reply = "ERR usage: playlist repair <name> [--map old=new]... | playlist delete <name>\n"
+                        .to_string()
+                }
+            }
+        }
+        "loop" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            match arg.split_once(' ') {
+                Some(("file", path)) => match seamless::LoopSource::open(Path::new(path)) {
+                    Ok(source) => {
+                        sink.stop();
+                        sink.set_speed(1.0);
+                        sink.append(source);
+                        sink.play();
+                        player.looping = true;
+                        player.queued = None;
+                        player.pause_reason = None;
+                        player.plugins.playback_changed(false);
+                    }
+                    Err(e) => reply = format!("ERR {}\n", e),
+                },
+                _ => reply = "ERR usage: loop file <path>\n".to_string(),
+            }
+        }
+        "preview-start" => {
+            if let Err(e) = player.lock().unwrap().start_preview(arg) {
+                reply = format!("ERR {}\n", e);
+            }
+        }
+        "preview-stop" => player.lock().unwrap().stop_preview(),
+        "interrupt" => {
+            let path = Path::new(arg);
+            if path.is_file() {
+                player.lock().unwrap().pending_interrupt = Some(path.to_path_buf());
+            } else {
+                reply = format!("ERR {}: not a file\n", arg);
+            }
+        }
+        "pause" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if sink.is_paused() {
+                sink.play();
+                player.pause_reason = None;
+            } else {
+                sink.pause();
+                player.pause_reason = Some("user".to_string());
+            }
+            player.plugins.playback_changed(sink.is_paused());
+        }
+        "auto_pause" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if !sink.is_paused() {
+                sink.pause();
+                player.pause_reason = Some(arg.to_string());
+                player.plugins.playback_changed(true);
+            }
+        }
+        "auto_resume" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if sink.is_paused() && player.pause_reason.as_deref() == Some(arg) {
+                sink.play();
+                player.pause_reason = None;
+                player.plugins.playback_changed(false);
+            }
+        }
+        "stats" => {
+            let skipped = player
+                .lock()
+                .unwrap()
+                .library_db
+                .lock()
+                .unwrap()
+                .skipped()
+                .unwrap_or_default();
+            let mut most_skipped: Vec<_> = skipped.iter().collect();
+            most_skipped.sort_by(|a, b| b.1.skips.cmp(&a.1.skips).then_with(|| a.0.cmp(b.0)));
+            let most_skipped: Vec<_> = most_skipped
+                .into_iter()
+                .take(MOST_SKIPPED)
+                .map(|(path, counts)| {
+                    serde_json::json!({
+                        "track": path,
+                        "plays": counts.plays,
+                        "skips": counts.skips,
+                    })
+                })
+                .collect();
+            reply = serde_json::json!({
+                "skips": skipped.values().map(|counts| counts.skips).sum::<u64>(),
+                "most_skipped": most_skipped,
+                "clipping": limiter::clipped(),
+                "underruns": buffer::underruns(),
+                "period_ms": buffer::period_ms(),
+                "latency_ms": buffer::latency_ms(),
+            })
+            .to_string()
+                + "\n";
+        }
+        "most_played" => {
+            let limit = match arg.trim() {
+                "" => Ok(PLAYED_TRACKS),
+                limit => limit
+                    .parse()
+                    .map_err(|_| format!("invalid count: {}", limit)),
+            };
+            let player = player.lock().unwrap();
+            let tracks =
+                limit.and_then(|limit| player.library_db.lock().unwrap().most_played(limit));
+            reply = match tracks {
+                Ok(tracks) => {
+                    let tracks: Vec<_> = tracks
+                        .into_iter()
+                        .map(|track| {
+                            serde_json::json!({
+                                "track": track.path,
+                                "display": player.plugins.metadata(&track.path).display(),
+                                "plays": track.plays,
+                                "last_played": track.last_played,
+                            })
+                        })
+                        .collect();
+                    serde_json::json!({ "tracks": tracks }).to_string() + "\n"
+                }
+                Err(e) => format!("ERR {}\n", e),
+            };
+        }
+        "status" => {
+            let player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let (left, unknown) = player.queue_remaining(&sink);
+            let track = player.plugins.metadata(&player.files[player.current_index]);
+            let rating = player
+                .library_db
+                .lock()
+                .unwrap()
+                .rating(&player.files[player.current_index]);
+            let state = if player.stopped {
+                "stopped"
+            } else if sink.is_paused() {
+                "paused"
+            } else {
+                "playing"
+            };
+            reply = serde_json::json!({
+                "state": state,
+                "pause_reason": player.pause_reason,
+                "track": player.files[player.current_index],
+                "title": track.title,
+                "artist": track.artist,
+                "album": track.album,
+                "track_number": track.track_number,
+                "display": track.display(),
+                "index": player.current_index,
+                "position": sink.get_pos().as_secs_f32(),
+                "volume": sink.volume(),
+                "repeat": player.repeat,
+                "shuffle": player.shuffle,
+                "shuffle_folders": player.shuffle_folders,
+                "autofill": player.autofill,
+                "mix": player.mix.as_ref().map(ToString::to_string),
+                "context": player.context,
+                "queue_length": player.files.len(),
+                "queue_revision": player.queue_log.revision(),
+                "queue_remaining": left.as_secs(),
+                "queue_remaining_text": format!("{} left", format_duration(left)),
+                "queue_unknown_durations": unknown,
+                "sleep_remaining": player.sleep_remaining().map(|left| left.as_secs()),
+                "preview": player.preview_track,
+                "rating": rating.and_then(|(rating, _)| rating),
+                "favorite": rating.is_some_and(|(_, favorite)| favorite),
+                "hotkeys": HOTKEYS_ENABLED.load(Ordering::Relaxed),
+                "recovered": recovery::recovered(),
+            })
+            .to_string()
+                + "\n";
+        }
+        "stop" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            player.stop(&sink);
+        }
+        "quit" => {
+            let player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            player.shutdown(&sink);
+        }
+        "queue" => match arg {
+            "clear" => {
+                let mut player = player.lock().unwrap();
+                let sink = sink.lock().unwrap();
+                player.clear_queue();
+                player.unqueue(&sink);
+            }
+            "list" => {
+                let player = player.lock().unwrap();
+                reply = player.queue_json().to_string() + "\n";
+            }
+            _ if arg.starts_with("changes") => {
+                let since = arg["changes".len()..].trim();
+                let since = since.strip_prefix("--since").unwrap_or(since).trim();
+                match since.parse::<u64>() {
+                    Ok(since) => {
+                        let player = player.lock().unwrap();
+                        reply = player.queue_changes_json(since).to_string() + "\n";
+                    }
+                    Err(_) => reply = format!("ERR invalid revision: {}\n", since),
+                }
+            }
+            _ => reply = format!("ERR unknown queue action: {}\n", arg),
+        },
+        "queue_end" => match QueueEnd::parse(arg) {
+            Some(action) => player.lock().unwrap().set_queue_end(action),
+            None => reply = format!("ERR unknown queue end action: {}\n", arg),
+        },
+        "repeat_off" => player.lock().unwrap().set_repeat(Repeat::Off),
+        "repeat_one" => player.lock().unwrap().set_repeat(Repeat::One),
+        "repeat_all" => player.lock().unwrap().set_repeat(Repeat::All),
+        "volume_up" | "volume_down" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let step = match arg {
+                "" => Ok(player.volume_step / 100.0),
+                _ => units::parse_percent(arg),
+            };
+            match step {
+                Ok(step) => {
+                    let vol = match name {
+                        "volume_up" => sink.volume() + step,
+                        _ => sink.volume() - step,
+                    };
+                    reply = player.set_volume(&sink, vol);
+                }
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "volume" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            if arg.is_empty() {
+                reply = volume_json(sink.volume());
+            } else {
+                reply = match units::parse_volume(arg) {
+                    Ok(Volume::Set(volume)) => player.set_volume(&sink, volume),
+                    Ok(Volume::Change(delta)) => player.set_volume(&sink, sink.volume() + delta),
+                    Err(e) => format!("ERR {}\n", e),
+                };
+            }
+        }
+        "shuffle" => {
+            let mut player = player.lock().unwrap();
+            match arg {
+                "on" => (player.shuffle, player.shuffle_folders) = (true, false),
+                "folders" => (player.shuffle, player.shuffle_folders) = (true, true),
+                "off" => (player.shuffle, player.shuffle_folders) = (false, false),
+                "" | "toggle" => player.shuffle = !player.shuffle,
+                _ => reply = format!("ERR unknown shuffle mode: {}\n", arg),
+            }
+            player.refill_shuffle();
+        }
+        "add" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            match player.add_to_queue(Path::new(arg), &sink) {
+                Ok(added) => reply = serde_json::json!({ "added": added }).to_string() + "\n",
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "remove" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let result = match arg.parse::<usize>() {
+                Ok(index) => player.remove_from_queue(index, &sink),
+                Err(_) => Err(format!("invalid queue index: {}", arg)),
+            };
+            if let Err(e) = result {
+                reply = format!("ERR {}\n", e);
+            }
+        }
+        "move" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let indices = arg
+                .split_once(' ')
+                .and_then(|(from, to)| Some((from.parse().ok()?, to.trim().parse().ok()?)));
+            let result = match indices {
+                Some((from, to)) => player.move_in_queue(from, to, &sink),
+                None => Err(format!("expected two queue indices: {}", arg)),
+            };
+            if let Err(e) = result {
+                reply = format!("ERR {}\n", e);
+            }
+        }
+        "clear" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            player.clear_queue();
+            player.unqueue(&sink);
+        }
+        "sleep" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            let (after, action) = arg.split_once(' ').unwrap_or((arg, "pause"));
+            match (after, action.trim()) {
+                ("", _) => {
+                    let left = player.sleep_remaining().map(|left| left.as_secs());
+                    reply = serde_json::json!({ "sleep_remaining": left }).to_string() + "\n";
+                }
+                ("off", _) => player.cancel_sleep(&sink),
+                (after, action @ ("pause" | "quit")) => {
+                    match units::parse_duration(after, units::MINUTE) {
+                        Ok(after) if !after.is_zero() => {
+                            player.set_sleep(&sink, after, action == "quit")
+                        }
+                        Ok(_) => reply = "ERR sleep timer must be longer than zero\n".to_string(),
+                        Err(e) => reply = format!("ERR {}\n", e),
+                    }
+                }
+                _ => reply = format!("ERR unknown sleep action: {}\n", action.trim()),
+            }
+        }
+        "rate" => {
+            let player = player.lock().unwrap();
+            let track = &player.files[player.current_index];
+            let library_db = player.library_db.lock().unwrap();
+            let result = match arg {
+                "" => Ok(()),
+                "clear" => library_db.set_rating(track, None),
+                _ => match arg.parse::<u8>() {
+                    Ok(rating @ 1..=5) => library_db.set_rating(track, Some(rating)),
+                    _ => Err(format!("invalid rating: {} (expected 1-5 or clear)", arg)),
+                },
+            };
+            reply = match result.map(|_| library_db.rating(track)) {
+                Ok(Some((rating, favorite))) => {
+                    serde_json::json!({
+                        "track": track,
+                        "rating": rating,
+                        "favorite": favorite,
+                    })
+                    .to_string()
+                        + "\n"
+                }
+                Ok(None) => format!("ERR {} is not in the library\n", track.display()),
+                Err(e) => format!("ERR {}\n", e),
+            };
+        }
+        "favorite" => {
+            let player = player.lock().unwrap();
+            let track = &player.files[player.current_index];
+            let library_db = player.library_db.lock().unwrap();
+            reply = match library_db.rating(track) {
+                Some((rating, current)) => {
+                    let favorite = match arg {
+                        "" | "toggle" => Ok(!current),
+                        "on" => Ok(true),
+                        "off" => Ok(false),
+                        _ => Err(format!("invalid favorite state: {}", arg)),
+                    };
+                    match favorite.and_then(|favorite| {
+                        library_db.set_favorite(track, favorite).map(|_| favorite)
+                    }) {
+                        Ok(favorite) => {
+                            serde_json::json!({
+                                "track": track,
+                                "rating": rating,
+                                "favorite": favorite,
+                            })
+                            .to_string()
+                                + "\n"
+                        }
+                        Err(e) => format!("ERR {}\n", e),
+                    }
+                }
+                None => format!("ERR {} is not in the library\n", track.display()),
+            };
+        }
+        "hotkeys" => {
+            let current = HOTKEYS_ENABLED.load(Ordering::Relaxed);
+            let enabled = match arg {
+                "" => Some(current),
+                "on" => Some(true),
+                "off" => Some(false),
+                "toggle" => Some(!current),
+                _ => None,
+            };
+            match enabled {
+                Some(enabled) => {
+                    if enabled != current {
+                        HOTKEYS_ENABLED.store(enabled, Ordering::Relaxed);
+                        println!("Hotkeys {}", if enabled { "enabled" } else { "disabled" });
+                    }
+                    reply = serde_json::json!({ "hotkeys": enabled }).to_string() + "\n";
+                }
+                None => reply = format!("ERR invalid hotkeys state: {}\n", arg),
+            }
+        }
+        "clock" => {
+            let (action, value) = arg.split_once(' ').unwrap_or((arg, ""));
+            match (action, units::parse_duration(value, units::SECOND)) {
+                ("", _) => {
+                    reply = serde_json::json!({
+                        "simulated": clock::is_simulated(),
+                        "speed": clock::speed(),
+                        "time": clock::wall().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                    })
+                    .to_string()
+                        + "\n";
+                }
+                ("advance", Ok(offset)) => {
+                    if let Err(e) = clock::advance(offset) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                ("advance", Err(e)) => reply = format!("ERR {}\n", e),
+                _ => reply = format!("ERR invalid clock command: {}\n", arg),
+            }
+        }
+        "errors" => {
+            let mut player = player.lock().unwrap();
+            match arg {
+                "" => {
+                    reply = serde_json::json!({ "errors": player.errors }).to_string() + "\n";
+                }
+                "clear" => player.errors.clear(),
+                _ => reply = format!("ERR unknown errors action: {}\n", arg),
+            }
+        }
+        "goto" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            match arg.parse::<usize>() {
+                Ok(index) if index < player.files.len() => {
+                    player.current_index = index;
+                    player.shuffle_bag.retain(|&i| i != index);
+                    if let Err(e) = player.play(&sink) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                _ => reply = format!("ERR invalid queue index: {}\n", arg),
+            }
+        }
+        "scan" => {
+            let mut player = player.lock().unwrap();
+            let sink = sink.lock().unwrap();
+            match arg {
+                "forward" => player.start_scan(&sink, true),
+                "backward" => player.start_scan(&sink, false),
+                "stop" => player.stop_scan(&sink),
+                _ => reply = format!("ERR unknown scan direction: {}\n", arg),
+            }
+        }
+        "seek_forward" | "seek_backward" => {
+            let sink = sink.lock().unwrap();
+            let offset = match arg {
+                "" => Ok(SEEK_STEP),
+                _ => units::parse_duration(arg, units::SECOND),
+            };
+            match offset {
+                Ok(offset) => {
+                    let position = match name {
+                        "seek_forward" => sink.get_pos() + offset,
+                        _ => sink.get_pos().saturating_sub(offset),
+                    };
+                    if let Err(e) = sink.try_seek(position) {
+                        reply = format!("ERR {}\n", e);
+                    }
+                }
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "seek" => {
+            let sink = sink.lock().unwrap();
+            let position = units::parse_seek(arg).map(|seek| match seek {
+                Seek::To(position) => position,
+                Seek::Forward(offset) => sink.get_pos() + offset,
+                Seek::Backward(offset) => sink.get_pos().saturating_sub(offset),
+            });
+            match position.and_then(|position| sink.try_seek(position).map_err(|e| e.to_string())) {
+                Ok(()) => {}
+                Err(e) => reply = format!("ERR {}\n", e),
+            }
+        }
+        "crossfade" => {
+            let mut player = player.lock().unwrap();
+            if !arg.is_empty() {
+                match units::parse_duration(arg, units::SECOND) {
+                    Ok(duration) => player.automix.crossfade_secs = duration.as_secs_f32(),
+                    Err(e) => reply = format!("ERR {}\n", e),
+                }
+            }
+            if reply.is_empty() {
+                reply = serde_json::json!({ "crossfade": player.automix.crossfade_secs })
+                    .to_string()
+                    + "\n";
+            }
+        }
+        "rescan" if arg.is_empty() => spawn_rescan(Arc::clone(player)),
+        "rescan" => reply = rescan_path(player, sink, Path::new(arg)),
+        "" => reply = "ERR empty command\n".to_string(),
+        _ => reply = format!("ERR unknown command: {}\n", name),
+    }
+
+    reply
+}
+
+// cd list | cd play [трек] | cd rip. Привод и MusicBrainz опрашиваются без блокировки плеера
+fn handle_cd(player: &Arc<Mutex<MusicPlayer>>, sink: &Arc<Mutex<Sink>>, arg: &str) -> String {
+    let (config, music_dir) = {
+        let player = player.lock().unwrap();
+        (player.cd.clone(), player.music_dir.clone())
+    };
+    let (action, track) = arg.split_once(' ').unwrap_or((arg, ""));
+    match action {
+        "list" | "play" => {}
+        "rip" => {
+            let dir = config.rip_dir.clone().unwrap_or(music_dir);
+            let player = Arc::clone(player);
+            thread::spawn(move || match cd::rip(&config, &dir) {
+                Ok(files) => {
+                    println!("Ripped {} tracks to {}", files.len(), dir.display());
+                    spawn_rescan(player);
+                }
+                Err(e) => eprintln!("Failed to rip CD: {}", e),
+            });
+            return String::new();
+        }
+        _ => return format!("ERR unknown cd action: {}\n", arg),
+    }
+
+    let toc = match cd::read_toc(&config.device) {
+        Ok(toc) => toc,
+        Err(e) => return format!("ERR {}\n", e),
+    };
+    let disc_id = toc.disc_id();
+    let release = if config.musicbrainz {
+        cd::lookup(&disc_id).unwrap_or_default()
+    } else {
+        cd::Release::default()
+    };
+
+    if action == "list" {
+        let tracks: Vec<serde_json::Value> = toc
+            .tracks
+            .iter()
+            .map(|track| {
+                serde_json::json!({
+                    "number": track.number,
+                    "title": release.tracks.get(&track.number),
+                    "duration": track.duration().as_secs_f32(),
+                    "audio": track.audio,
+                    "path": cd::track_path(&config.device, track.number),
+                })
+            })
+            .collect();
+        return serde_json::json!({
+            "disc_id": disc_id,
+            "title": release.title,
+            "artist": release.artist,
+            "tracks": tracks,
+        })
+        .to_string()
+            + "\n";
+    }
+
+    let files: Vec<PathBuf> = toc
+        .audio_tracks()
+        .map(|track| cd::track_path(&config.device, track.number))
+        .collect();
+    if files.is_empty() {
+        return "ERR no audio tracks on the disc\n".to_string();
+    }
+    let index = match track {
+        "" => 0,
+        number => match number
+            .parse::<u8>()
+            .ok()
+            .and_then(|number| toc.audio_tracks().position(|track| track.number == number))
+        {
+            Some(index) => index,
+            None => return format!("ERR no audio track {} on the disc\n", number),
+        },
+    };
+    let mut player = player.lock().unwrap();
+    let sink = sink.lock().unwrap();
+    player.set_queue(files, index);
+    match player.play(&sink) {
+        Ok(()) => String::new(),
+        Err(e) => format!("ERR {}\n", e),
+    }
+}
+
+// Пересканирует файл или папку внутри библиотеки; пропавший путь просто забывается
+fn rescan_path(player: &Arc<Mutex<MusicPlayer>>, sink: &Arc<Mutex<Sink>>, path: &Path) -> String {
+    let (music_dir, scan_options, library_db) = {
+        let player = player.lock().unwrap();
+        (
+            player.music_dir.clone(),
+            player.scan_options.clone(),
+            Arc::clone(&player.library_db),
+        )
+    };
+    if !path.starts_with(&music_dir) {
+        return format!("ERR {} is outside the music directory\n", path.display());
+    }
+    // Плейлист в папке с музыкой — не часть библиотеки
+    if playlist::is_playlist(path) {
+        return String::new();
+    }
+    let files = match scan_music(path, &scan_options) {
+        Ok(files) => files,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return format!("ERR {}: {}\n", path.display(), e);
+        }
+        // Удалён, пуст или не музыка
+        Err(_) => Vec::new(),
+    };
+    if let Err(e) = library_db.lock().unwrap().sync(path, &files) {
+        eprintln!("Failed to update library database: {}", e);
+    }
+    let mut player = player.lock().unwrap();
+    let sink = sink.lock().unwrap();
+    player.update_library(path, files, &sink);
+    String::new()
+}
+
+// "2h13m", "13m", "45s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60) {
+        (0, 0) => format!("{}s", secs),
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h{:02}m", hours, minutes),
+    }
+}
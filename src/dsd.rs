@@ -0,0 +1,410 @@
+use crate::preload::TrackReader;
+use rodio::source::SeekError;
+use rodio::Source;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+// Частота PCM на выходе для DSD64; для DSD128 и выше прореживание больше
+const PCM_RATE: u32 = 88200;
+// Длина фильтра в байтах DSD на каждые 4 байта прореживания
+const TAPS_PER_STEP: usize = 16;
+// Полоса пропускания фильтра, Гц; выше неё в DSD в основном шум модулятора
+const CUTOFF_HZ: f64 = 24000.0;
+// Байт на канал за одно чтение в DFF
+const DFF_BLOCK: usize = 4096;
+
+pub fn is_dsd(ext: &str) -> bool {
+    matches!(ext, "dsf" | "dff")
+}
+
+#[derive(Clone, Copy)]
+enum Layout {
+    // DSF: блоки по block_size байт на канал, младший бит первый
+    Dsf { block_size: usize },
+    // DSDIFF: байты каналов чередуются, старший бит первый
+    Dff,
+}
+
+// Файл DSF или DFF, переведённый в PCM фильтром нижних частот с прореживанием
+pub struct DsdSource {
+    reader: TrackReader,
+    layout: Layout,
+    channels: usize,
+    dsd_rate: u32,
+    data_start: u64,
+    // Байт DSD на канал во всём файле и уже прочитано
+    data_len: u64,
+    read: u64,
+    // Байт DSD на один отсчёт PCM
+    step: usize,
+    // table[k][byte]: вклад k-го байта окна фильтра
+    table: Vec<[f32; 256]>,
+    windows: Vec<Vec<u8>>,
+    samples: Vec<f32>,
+    position: usize,
+    // Отсчёты, которые надо пропустить после перемотки внутри блока
+    skip: usize,
+}
+
+impl DsdSource {
+    pub fn new(mut reader: TrackReader) -> Result<Self, String> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        let header = match &magic {
+            b"DSD " => parse_dsf(&mut reader),
+            b"FRM8" => parse_dff(&mut reader),
+            _ => return Err("not a DSF or DSDIFF file".to_string()),
+        }
+        .map_err(|e| e.to_string())??;
+
+        if header.channels == 0 || header.dsd_rate == 0 {
+            return Err("invalid DSD header".to_string());
+        }
+        let step = (header.dsd_rate / 8 / PCM_RATE).max(1) as usize;
+        let table = filter_table(header.dsd_rate, step * TAPS_PER_STEP / 4);
+        reader
+            .seek(SeekFrom::Start(header.data_start))
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            reader,
+            layout: header.layout,
+            channels: header.channels,
+            dsd_rate: header.dsd_rate,
+            data_start: header.data_start,
+            data_len: header.data_len,
+            read: 0,
+            step,
+            table,
+            windows: vec![Vec::new(); header.channels],
+            samples: Vec::new(),
+            position: 0,
+            skip: 0,
+        })
+    }
+
+    // Следующий блок каждого канала, биты в порядке от старшего
+    fn read_block(&mut self) -> Option<Vec<Vec<u8>>> {
+        let left = self.data_len.saturating_sub(self.read) as usize;
+        if left == 0 {
+            return None;
+        }
+        let block = match self.layout {
+            Layout::Dsf { block_size } => block_size,
+            Layout::Dff => DFF_BLOCK,
+        };
+        let mut raw = vec![0u8; block * self.channels];
+        let filled = read_full(&mut self.reader, &mut raw).ok()?;
+        let used = left.min(filled / self.channels);
+        if used == 0 {
+            return None;
+        }
+        self.read += used as u64;
+
+        let blocks = match self.layout {
+            Layout::Dsf { block_size } => (0..self.channels)
+                .map(|c| {
+                    raw[c * block_size..c * block_size + used]
+                        .iter()
+                        .map(|b| b.reverse_bits())
+                        .collect()
+                })
+                .collect(),
+            Layout::Dff => (0..self.channels)
+                .map(|c| {
+                    raw.iter()
+                        .skip(c)
+                        .step_by(self.channels)
+                        .take(used)
+                        .copied()
+                        .collect()
+                })
+                .collect(),
+        };
+        Some(blocks)
+    }
+
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let Some(blocks) = self.read_block() else {
+                return false;
+            };
+            let taps = self.table.len();
+            for (window, block) in self.windows.iter_mut().zip(blocks) {
+                window.extend_from_slice(&block);
+            }
+
+            let len = self.windows[0].len();
+            let outputs = if len < taps {
+                0
+            } else {
+                (len - taps) / self.step + 1
+            };
+            self.samples.clear();
+            for i in 0..outputs {
+                for window in &self.windows {
+                    let start = i * self.step;
+                    let sample = window[start..start + taps]
+                        .iter()
+                        .zip(&self.table)
+                        .map(|(&byte, row)| row[byte as usize])
+                        .sum();
+                    self.samples.push(sample);
+                }
+            }
+            for window in &mut self.windows {
+                window.drain(..outputs * self.step);
+            }
+
+            self.position = self.skip.min(self.samples.len());
+            self.skip -= self.position;
+            if self.position < self.samples.len() {
+                return true;
+            }
+        }
+    }
+}
+
+impl Iterator for DsdSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.samples.len() && !self.decode_next() {
+            return None;
+        }
+        let sample = self.samples[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for DsdSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.dsd_rate / 8 / self.step as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(
+            self.data_len as f64 * 8.0 / self.dsd_rate as f64,
+        ))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let target = ((pos.as_secs_f64() * self.dsd_rate as f64 / 8.0) as u64).min(self.data_len);
+        // Читать можно только с начала блока, остаток пропускается после декодирования
+        let block = match self.layout {
+            Layout::Dsf { block_size } => block_size as u64,
+            Layout::Dff => 1,
+        };
+        let start = target / block * block;
+        self.reader
+            .seek(SeekFrom::Start(
+                self.data_start + start * self.channels as u64,
+            ))
+            .map_err(|e| SeekError::Other(Box::new(e)))?;
+        self.read = start;
+        for window in &mut self.windows {
+            window.clear();
+        }
+        self.samples.clear();
+        self.position = 0;
+        self.skip = (target - start) as usize / self.step * self.channels;
+        Ok(())
+    }
+}
+
+struct Header {
+    layout: Layout,
+    channels: usize,
+    dsd_rate: u32,
+    data_start: u64,
+    data_len: u64,
+}
+
+// Чанки "DSD ", "fmt " и "data", числа little-endian
+fn parse_dsf(reader: &mut TrackReader) -> io::Result<Result<Header, String>> {
+    let dsd_size = read_u64_le(reader)?;
+    reader.seek(SeekFrom::Start(dsd_size))?;
+
+    let mut id = [0u8; 4];
+    reader.read_exact(&mut id)?;
+    if &id != b"fmt " {
+        return Ok(Err("DSF: missing fmt chunk".to_string()));
+    }
+    let fmt_size = read_u64_le(reader)?;
+    let _version = read_u32_le(reader)?;
+    let format = read_u32_le(reader)?;
+    let _channel_type = read_u32_le(reader)?;
+    let channels = read_u32_le(reader)? as usize;
+    let dsd_rate = read_u32_le(reader)?;
+    let bits = read_u32_le(reader)?;
+    let sample_count = read_u64_le(reader)?;
+    let block_size = read_u32_le(reader)? as usize;
+    if format != 0 || bits != 1 || block_size == 0 {
+        return Ok(Err(format!(
+            "DSF: unsupported format {} with {} bits per sample",
+            format, bits
+        )));
+    }
+
+    reader.seek(SeekFrom::Start(dsd_size + fmt_size))?;
+    reader.read_exact(&mut id)?;
+    if &id != b"data" {
+        return Ok(Err("DSF: missing data chunk".to_string()));
+    }
+    let _data_size = read_u64_le(reader)?;
+    Ok(Ok(Header {
+        layout: Layout::Dsf { block_size },
+        channels,
+        dsd_rate,
+        data_start: reader.stream_position()?,
+        // Последний блок дополнен нулями; настоящая длина — в числе отсчётов
+        data_len: sample_count / 8,
+    }))
+}
+
+// Контейнер FRM8 с чанками PROP/SND и DSD, числа big-endian
+fn parse_dff(reader: &mut TrackReader) -> io::Result<Result<Header, String>> {
+    let _form_size = read_u64_be(reader)?;
+    let mut kind = [0u8; 4];
+    reader.read_exact(&mut kind)?;
+    if &kind != b"DSD " {
+        return Ok(Err("DSDIFF: not a DSD form".to_string()));
+    }
+
+    let mut channels = 0;
+    let mut dsd_rate = 0;
+    loop {
+        let mut id = [0u8; 4];
+        if reader.read_exact(&mut id).is_err() {
+            return Ok(Err("DSDIFF: missing DSD chunk".to_string()));
+        }
+        let size = read_u64_be(reader)?;
+        let start = reader.stream_position()?;
+        match &id {
+            b"PROP" => {
+                reader.read_exact(&mut kind)?;
+                let end = start + size;
+                while reader.stream_position()? < end {
+                    reader.read_exact(&mut id)?;
+                    let sub_size = read_u64_be(reader)?;
+                    let sub_start = reader.stream_position()?;
+                    match &id {
+                        b"FS  " => dsd_rate = read_u32_be(reader)?,
+                        b"CHNL" => {
+                            let mut count = [0u8; 2];
+                            reader.read_exact(&mut count)?;
+                            channels = u16::from_be_bytes(count) as usize;
+                        }
+                        b"CMPR" => {
+                            reader.read_exact(&mut kind)?;
+                            if &kind != b"DSD " {
+                                return Ok(Err(
+                                    "DSDIFF: compressed (DST) audio is not supported".to_string()
+                                ));
+                            }
+                        }
+                        _ => {}
+                    }
+                    reader.seek(SeekFrom::Start(sub_start + sub_size + sub_size % 2))?;
+                }
+            }
+            b"DSD " => {
+                return Ok(Ok(Header {
+                    layout: Layout::Dff,
+                    channels,
+                    dsd_rate,
+                    data_start: start,
+                    data_len: size / channels.max(1) as u64,
+                }));
+            }
+            b"DST " => {
+                return Ok(Err(
+                    "DSDIFF: compressed (DST) audio is not supported".to_string()
+                ))
+            }
+            _ => {}
+        }
+        reader.seek(SeekFrom::Start(start + size + size % 2))?;
+    }
+}
+
+// Окно Блэкмана поверх sinc; бит 1 — +1, бит 0 — -1
+fn filter_table(dsd_rate: u32, taps_bytes: usize) -> Vec<[f32; 256]> {
+    let taps = taps_bytes * 8;
+    let cutoff = CUTOFF_HZ / dsd_rate as f64;
+    let center = (taps - 1) as f64 / 2.0;
+    let mut coefficients: Vec<f64> = (0..taps)
+        .map(|n| {
+            let x = n as f64 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+            };
+            let phase = 2.0 * std::f64::consts::PI * n as f64 / (taps - 1) as f64;
+            sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+        })
+        .collect();
+    let sum: f64 = coefficients.iter().sum();
+    for c in &mut coefficients {
+        *c /= sum;
+    }
+
+    coefficients
+        .chunks(8)
+        .map(|bits| {
+            let mut row = [0f32; 256];
+            for (byte, value) in row.iter_mut().enumerate() {
+                *value = bits
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| if byte & (0x80 >> i) != 0 { *c } else { -*c })
+                    .sum::<f64>() as f32;
+            }
+            row
+        })
+        .collect()
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn read_u32_le(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64_le(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_u32_be(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64_be(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
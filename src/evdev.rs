@@ -0,0 +1,129 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+pub const EV_KEY: u16 = 0x01;
+#[cfg(feature = "rotary")]
+pub const EV_REL: u16 = 0x02;
+pub const EV_SW: u16 = 0x05;
+
+#[cfg(feature = "rotary")]
+pub const REL_X: u16 = 0x00;
+
+pub const SW_HEADPHONE_INSERT: u16 = 0x02;
+
+#[cfg(feature = "rfid")]
+pub const KEY_ENTER: u16 = 28;
+#[cfg(feature = "rfid")]
+pub const KEY_KPENTER: u16 = 96;
+
+// EVIOCGRAB = _IOW('E', 0x90, int)
+#[cfg(feature = "rfid")]
+const EVIOCGRAB: libc::c_ulong = 0x40044590;
+// EVIOCGNAME(256) = _IOC(_IOC_READ, 'E', 0x06, 256)
+const EVIOCGNAME_256: libc::c_ulong = 0x81004506;
+
+#[derive(Debug, Clone, Copy)]
+pub struct InputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+pub struct Device {
+    file: File,
+}
+
+impl Device {
+    pub fn open(path: &Path) -> Result<Self, io::Error> {
+        Ok(Self {
+            file: File::open(path)?,
+        })
+    }
+
+    // Эксклюзивный захват, чтобы ввод не попадал в другие приложения
+    #[cfg(feature = "rfid")]
+    pub fn grab(&self) -> Result<(), io::Error> {
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGRAB, 1 as libc::c_int) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> Result<String, io::Error> {
+        let mut buf = [0u8; 256];
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGNAME_256, buf.as_mut_ptr()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+    }
+
+    // Блокирующее чтение одного события struct input_event
+    pub fn next_event(&mut self) -> Result<InputEvent, io::Error> {
+        let mut buf = [0u8; mem::size_of::<libc::input_event>()];
+        self.file.read_exact(&mut buf)?;
+
+        let offset = mem::size_of::<libc::timeval>();
+        Ok(InputEvent {
+            kind: u16::from_ne_bytes([buf[offset], buf[offset + 1]]),
+            code: u16::from_ne_bytes([buf[offset + 2], buf[offset + 3]]),
+            value: i32::from_ne_bytes([
+                buf[offset + 4],
+                buf[offset + 5],
+                buf[offset + 6],
+                buf[offset + 7],
+            ]),
+        })
+    }
+}
+
+// /dev/input/event* по порядку номеров
+pub fn event_devices() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev/input")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .collect();
+    paths.sort_by_key(|path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name["event".len()..].parse::<u32>().ok())
+    });
+    paths
+}
+
+// Символ для цифровых и шестнадцатеричных клавиш клавиатурных считывателей
+#[cfg(feature = "rfid")]
+pub fn key_char(code: u16) -> Option<char> {
+    match code {
+        2..=10 => char::from_digit((code - 1) as u32, 10),
+        11 => Some('0'),
+        71 => Some('7'),
+        72 => Some('8'),
+        73 => Some('9'),
+        75 => Some('4'),
+        76 => Some('5'),
+        77 => Some('6'),
+        79 => Some('1'),
+        80 => Some('2'),
+        81 => Some('3'),
+        82 => Some('0'),
+        30 => Some('A'),
+        48 => Some('B'),
+        46 => Some('C'),
+        32 => Some('D'),
+        18 => Some('E'),
+        33 => Some('F'),
+        _ => None,
+    }
+}
@@ -0,0 +1,156 @@
+use crate::plugin::{ControlSurface, PlaybackError, TrackMetadata};
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Клиент, который не читает события дольше этого, отключается, а не тормозит плеер
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const TOPICS: [&str; 6] = ["track", "pause", "volume", "queue", "error", "position"];
+// Позиция приходит раз в секунду, пока трек играет, поэтому только по явной подписке
+const POSITION_INTERVAL: Duration = Duration::from_secs(1);
+
+// Соединение, которое остаётся открытым после subscribe
+pub trait Connection: Write + Send {
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for UnixStream {
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl Connection for TcpStream {
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+struct Subscriber {
+    connection: Box<dyn Connection>,
+    topics: Vec<&'static str>,
+}
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static POSITION_SENT: Mutex<Option<Instant>> = Mutex::new(None);
+
+// "subscribe" — все события, "subscribe track,volume" — только выбранные
+pub fn parse_topics(arg: &str) -> Result<Vec<&'static str>, String> {
+    if arg.is_empty() {
+        return Ok(TOPICS
+            .into_iter()
+            .filter(|&topic| topic != "position")
+            .collect());
+    }
+    arg.split([',', ' '])
+        .filter(|topic| !topic.is_empty())
+        .map(|topic| {
+            TOPICS
+                .iter()
+                .find(|&&known| known == topic)
+                .copied()
+                .ok_or_else(|| format!("unknown event: {}", topic))
+        })
+        .collect()
+}
+
+// Первая строка подтверждает подписку, дальше по строке JSON на событие
+pub fn subscribe(mut connection: Box<dyn Connection>, topics: Vec<&'static str>) {
+    let reply = serde_json::json!({ "subscribed": topics }).to_string() + "\n";
+    if connection.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+        || connection.write_all(reply.as_bytes()).is_err()
+    {
+        return;
+    }
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .push(Subscriber { connection, topics });
+}
+
+// Отключившиеся клиенты убираются при первой неудачной записи
+fn publish(topic: &str, event: serde_json::Value) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let line = event.to_string() + "\n";
+    subscribers.retain_mut(|subscriber| {
+        !subscriber.topics.contains(&topic)
+            || subscriber.connection.write_all(line.as_bytes()).is_ok()
+    });
+}
+
+// Событие position собирается, только если на него кто-то подписан и прошла секунда
+pub fn position(event: impl FnOnce() -> serde_json::Value) {
+    let wanted = SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|subscriber| subscriber.topics.contains(&"position"));
+    if !wanted {
+        return;
+    }
+    let mut sent = POSITION_SENT.lock().unwrap();
+    if sent.is_some_and(|sent| sent.elapsed() < POSITION_INTERVAL) {
+        return;
+    }
+    *sent = Some(Instant::now());
+    drop(sent);
+    publish("position", event());
+}
+
+// Рассылает изменения состояния плеера подписчикам сокета
+pub struct EventFeed;
+
+impl ControlSurface for EventFeed {
+    fn name(&self) -> &str {
+        "events"
+    }
+
+    fn track_changed(&mut self, track: &TrackMetadata, position: Duration) {
+        publish(
+            "track",
+            serde_json::json!({
+                "event": "track",
+                "path": track.path,
+                "title": track.title,
+                "artist": track.artist,
+                "album": track.album,
+                "track_number": track.track_number,
+                "position": position.as_secs_f32(),
+            }),
+        );
+    }
+
+    fn playback_changed(&mut self, paused: bool) {
+        publish(
+            "pause",
+            serde_json::json!({ "event": "pause", "paused": paused }),
+        );
+    }
+
+    fn volume_changed(&mut self, volume: f32) {
+        publish(
+            "volume",
+            serde_json::json!({ "event": "volume", "volume": volume }),
+        );
+    }
+
+    fn queue_changed(&mut self, length: usize, revision: u64) {
+        publish(
+            "queue",
+            serde_json::json!({ "event": "queue", "length": length, "revision": revision }),
+        );
+    }
+
+    fn playback_error(&mut self, error: &PlaybackError) {
+        publish(
+            "error",
+            serde_json::json!({ "event": "error", "error": error }),
+        );
+    }
+}
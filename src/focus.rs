@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+// Когда горячая клавиша действует
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct HotkeyContext {
+    // Классы окон (подстрока без учёта регистра), в фокусе которых клавиша отключена
+    pub disabled_windows: Vec<String>,
+    pub only_when_playing: bool,
+}
+
+impl HotkeyContext {
+    pub fn allows(&self, playing: impl FnOnce() -> bool) -> bool {
+        if self.only_when_playing && !playing() {
+            return false;
+        }
+        if self.disabled_windows.is_empty() {
+            return true;
+        }
+
+        // Если фокус узнать не удалось, клавиша работает
+        let Some(class) = focused_window_class() else {
+            return true;
+        };
+        let class = class.to_lowercase();
+        !self
+            .disabled_windows
+            .iter()
+            .any(|pattern| class.contains(&pattern.to_lowercase()))
+    }
+}
+
+// Класс (app_id) окна в фокусе: Hyprland, Sway, затем X11 через xprop
+pub fn focused_window_class() -> Option<String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        if let Some(class) = hyprland_class().or_else(sway_class) {
+            return Some(class);
+        }
+    }
+    x11_class()
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn hyprland_class() -> Option<String> {
+    let json: serde_json::Value =
+        serde_json::from_str(&output("hyprctl", &["activewindow", "-j"])?).ok()?;
+    json["class"].as_str().map(str::to_string)
+}
+
+fn sway_class() -> Option<String> {
+    let tree: serde_json::Value =
+        serde_json::from_str(&output("swaymsg", &["-t", "get_tree"])?).ok()?;
+    focused_node(&tree).and_then(|node| {
+        node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .map(str::to_string)
+    })
+}
+
+fn focused_node(node: &serde_json::Value) -> Option<&serde_json::Value> {
+    if node["focused"].as_bool() == Some(true) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(focused_node)
+}
+
+// _NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007
+// WM_CLASS(STRING) = "Navigator", "firefox"
+fn x11_class() -> Option<String> {
+    let active = output("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+    let id = active.split_whitespace().last()?;
+    let class = output("xprop", &["-id", id, "WM_CLASS"])?;
+    let (_, values) = class.split_once('=')?;
+    Some(values.replace('"', "").trim().to_string())
+}
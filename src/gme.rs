@@ -0,0 +1,338 @@
+use crate::plugin::{Library, TrackMetadata};
+use crate::tracker;
+use rodio::source::SeekError;
+use rodio::Source;
+use std::ffi::{c_char, c_int, c_long, c_void, CStr};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// libgme загружается при первом файле игровой музыки, как libopenmpt для модулей
+const LIBRARY: &str = "libgme.so.0";
+const RATE: u32 = 48000;
+const CHUNK_FRAMES: usize = 1024;
+// Столько libgme затухает после заданной длины трека
+const FADE: Duration = Duration::from_secs(8);
+// Длина трека, у которого нет ни длины, ни петли
+const DEFAULT_LENGTH: Duration = Duration::from_secs(150);
+
+const EXTENSIONS: [&str; 11] = [
+    "vgm", "vgz", "nsf", "nsfe", "spc", "gbs", "ay", "gym", "hes", "kss", "sap",
+];
+
+// Сколько раз проигрывать петлю трека перед затуханием; задаётся из настроек
+static LOOPS: AtomicU32 = AtomicU32::new(2);
+
+pub fn set_loops(loops: u32) {
+    LOOPS.store(loops.max(1), Ordering::Relaxed);
+}
+
+pub fn is_game_music(ext: &str) -> bool {
+    EXTENSIONS.contains(&ext)
+}
+
+pub fn extensions() -> &'static [&'static str] {
+    &EXTENSIONS
+}
+
+// Файл игровой музыки или отдельный трек из него
+pub fn is_game_file(path: &Path) -> bool {
+    tracker::split_subsong(path)
+        .0
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| is_game_music(&ext.to_lowercase()))
+}
+
+// gme_info_t: 16 чисел и 16 строк, из которых нужны не все
+#[repr(C)]
+struct Info {
+    length: c_int,
+    intro_length: c_int,
+    loop_length: c_int,
+    play_length: c_int,
+    _ints: [c_int; 12],
+    system: *const c_char,
+    game: *const c_char,
+    song: *const c_char,
+    author: *const c_char,
+    _strings: [*const c_char; 12],
+}
+
+struct Api {
+    open_data:
+        unsafe extern "C" fn(*const c_void, c_long, *mut *mut c_void, c_int) -> *const c_char,
+    delete: unsafe extern "C" fn(*mut c_void),
+    track_count: unsafe extern "C" fn(*const c_void) -> c_int,
+    start_track: unsafe extern "C" fn(*mut c_void, c_int) -> *const c_char,
+    play: unsafe extern "C" fn(*mut c_void, c_int, *mut i16) -> *const c_char,
+    track_ended: unsafe extern "C" fn(*const c_void) -> c_int,
+    set_fade: unsafe extern "C" fn(*mut c_void, c_int),
+    seek: unsafe extern "C" fn(*mut c_void, c_int) -> *const c_char,
+    track_info: unsafe extern "C" fn(*const c_void, *mut *mut Info, c_int) -> *const c_char,
+    free_info: unsafe extern "C" fn(*mut Info),
+    _library: Library,
+}
+
+impl Api {
+    fn load() -> Result<Self, String> {
+        let library = Library::open(Path::new(LIBRARY))?;
+        unsafe {
+            Ok(Self {
+                open_data: library.function("gme_open_data")?,
+                delete: library.function("gme_delete")?,
+                track_count: library.function("gme_track_count")?,
+                start_track: library.function("gme_start_track")?,
+                play: library.function("gme_play")?,
+                track_ended: library.function("gme_track_ended")?,
+                set_fade: library.function("gme_set_fade")?,
+                seek: library.function("gme_seek")?,
+                track_info: library.function("gme_track_info")?,
+                free_info: library.function("gme_free_info")?,
+                _library: library,
+            })
+        }
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(Api::load).as_ref().map_err(Clone::clone)
+}
+
+// Ошибки libgme — статические строки, освобождать их не нужно
+fn check(error: *const c_char) -> Result<(), String> {
+    if error.is_null() {
+        return Ok(());
+    }
+    let message = unsafe { CStr::from_ptr(error) }.to_string_lossy();
+    Err(format!("libgme: {}", message))
+}
+
+// Файлы игровой музыки попадают в библиотеку, только если libgme установлена
+pub fn available() -> bool {
+    api().is_ok()
+}
+
+// Файл с несколькими треками становится несколькими записями очереди
+pub fn expand(path: &Path) -> Vec<PathBuf> {
+    let count = std::fs::read(path)
+        .ok()
+        .and_then(|data| Emulator::load(&data).ok())
+        .map(|emu| emu.tracks())
+        .unwrap_or(1);
+    if count <= 1 {
+        return vec![path.to_path_buf()];
+    }
+    (0..count)
+        .map(|index| tracker::subsong_path(path, index))
+        .collect()
+}
+
+// Название трека и игры вместо имени файла и длительность с затуханием
+pub fn probe(path: &Path) -> Option<(Option<TrackMetadata>, f32)> {
+    let (file, track) = tracker::split_subsong(path);
+    let emu = Emulator::load(&std::fs::read(&file).ok()?).ok()?;
+    let info = emu.info(track.unwrap_or(0)).ok()?;
+    let duration = info.duration.as_secs_f32();
+    let title = match (info.song, &info.game) {
+        (Some(song), _) => song,
+        (None, Some(game)) => match track {
+            Some(index) => format!("{} #{}", game, index + 1),
+            None => game.clone(),
+        },
+        (None, None) => return Some((None, duration)),
+    };
+    let tags = TrackMetadata {
+        path: path.to_path_buf(),
+        title,
+        artist: info.author,
+        album: info.game.or(info.system),
+        track_number: track.map(|index| index as u32 + 1),
+    };
+    Some((Some(tags), duration))
+}
+
+struct TrackInfo {
+    // Длина до начала затухания
+    length: Duration,
+    // Вместе с затуханием
+    duration: Duration,
+    system: Option<String>,
+    game: Option<String>,
+    song: Option<String>,
+    author: Option<String>,
+}
+
+struct Emulator {
+    api: &'static Api,
+    raw: *mut c_void,
+}
+
+// Эмулятором пользуется один поток за раз
+unsafe impl Send for Emulator {}
+
+impl Emulator {
+    fn load(data: &[u8]) -> Result<Self, String> {
+        let api = api()?;
+        let mut raw = ptr::null_mut();
+        check(unsafe {
+            (api.open_data)(
+                data.as_ptr() as *const c_void,
+                data.len() as c_long,
+                &mut raw,
+                RATE as c_int,
+            )
+        })?;
+        if raw.is_null() {
+            return Err("libgme: failed to open file".to_string());
+        }
+        Ok(Self { api, raw })
+    }
+
+    fn tracks(&self) -> i32 {
+        unsafe { (self.api.track_count)(self.raw) }
+    }
+
+    // Петля повторяется LOOPS раз; без петли берётся длина из файла
+    fn info(&self, track: i32) -> Result<TrackInfo, String> {
+        let mut raw = ptr::null_mut();
+        check(unsafe { (self.api.track_info)(self.raw, &mut raw, track) })?;
+        if raw.is_null() {
+            return Err("libgme: no track info".to_string());
+        }
+        let info = unsafe { &*raw };
+        let loops = LOOPS.load(Ordering::Relaxed) as i64;
+        let millis = if info.loop_length > 0 {
+            info.intro_length.max(0) as i64 + info.loop_length as i64 * loops
+        } else if info.length > 0 {
+            info.length as i64
+        } else {
+            DEFAULT_LENGTH.as_millis() as i64
+        };
+        let length = Duration::from_millis(millis as u64);
+        let string = |raw: *const c_char| {
+            if raw.is_null() {
+                return None;
+            }
+            let value = unsafe { CStr::from_ptr(raw) }
+                .to_string_lossy()
+                .trim()
+                .to_string();
+            Some(value).filter(|value| !value.is_empty())
+        };
+        let result = TrackInfo {
+            length,
+            duration: length + FADE,
+            system: string(info.system),
+            game: string(info.game),
+            song: string(info.song),
+            author: string(info.author),
+        };
+        unsafe { (self.api.free_info)(raw) };
+        Ok(result)
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        unsafe { (self.api.delete)(self.raw) };
+    }
+}
+
+pub struct GameMusicSource {
+    emu: Emulator,
+    buffer: Vec<i16>,
+    position: usize,
+    length: Duration,
+    duration: Duration,
+}
+
+impl GameMusicSource {
+    pub fn new(mut reader: impl Read, track: Option<i32>) -> Result<Self, String> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        let emu = Emulator::load(&data)?;
+        let track = track.unwrap_or(0);
+        if track >= emu.tracks() {
+            return Err(format!("no track {}", track + 1));
+        }
+        let info = emu.info(track)?;
+        check(unsafe { (emu.api.start_track)(emu.raw, track) })?;
+        let mut source = Self {
+            emu,
+            buffer: Vec::new(),
+            position: 0,
+            length: info.length,
+            duration: info.duration,
+        };
+        source.fade();
+        Ok(source)
+    }
+
+    // Зацикленные треки иначе играли бы бесконечно
+    fn fade(&mut self) {
+        let start = self.length.as_millis().min(c_int::MAX as u128) as c_int;
+        unsafe { (self.emu.api.set_fade)(self.emu.raw, start) };
+    }
+
+    fn render(&mut self) -> bool {
+        if unsafe { (self.emu.api.track_ended)(self.emu.raw) } != 0 {
+            return false;
+        }
+        self.buffer.resize(CHUNK_FRAMES * 2, 0);
+        let error = unsafe {
+            (self.emu.api.play)(
+                self.emu.raw,
+                self.buffer.len() as c_int,
+                self.buffer.as_mut_ptr(),
+            )
+        };
+        self.position = 0;
+        check(error).is_ok()
+    }
+}
+
+impl Iterator for GameMusicSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.buffer.len() && !self.render() {
+            return None;
+        }
+        let sample = self.buffer[self.position] as f32 / 32768.0;
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for GameMusicSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let millis = pos.as_millis().min(c_int::MAX as u128) as c_int;
+        let result = check(unsafe { (self.emu.api.seek)(self.emu.raw, millis) });
+        // Перемотка назад перезапускает трек, а вместе с ним и затухание
+        self.fade();
+        self.buffer.clear();
+        self.position = 0;
+        result.map_err(|e| SeekError::Other(Box::new(std::io::Error::other(e))))
+    }
+}
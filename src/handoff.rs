@@ -0,0 +1,49 @@
+use crate::player::Snapshot;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 6601;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn send_snapshot(host: &str, token: Option<&str>, snapshot: &Snapshot) -> Result<(), String> {
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, DEFAULT_PORT)
+    };
+    let addr = addr
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Cannot resolve {}", host))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    stream
+        .set_write_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+
+    let snapshot = serde_json::to_string(snapshot).map_err(|e| e.to_string())?;
+    let request = match token {
+        Some(token) => format!("auth {}\nrestore {}", token, snapshot),
+        None => format!("restore {}", snapshot),
+    };
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+    stream
+        .shutdown(Shutdown::Write)
+        .map_err(|e| e.to_string())?;
+
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|e| e.to_string())?;
+    match reply.strip_prefix("ERR ") {
+        Some(err) => Err(err.trim().to_string()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,405 @@
+use crate::clock::Timers;
+use crate::config::Config;
+use crate::focus::HotkeyContext;
+use crate::keylog::{self, KeyRecord};
+use crate::listeners::send_command;
+use crate::plugin::{Control, InputSource};
+use crate::press::{self, PressActions, PressDispatcher, PressTiming};
+use crate::{keyboard, keystate};
+use rdev::{listen, Event as KbdEvent, EventType, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct ModifierState {
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+    meta: bool,
+}
+
+impl ModifierState {
+    pub fn update(&mut self, key: &Key, is_press: bool) {
+        match key {
+            Key::ShiftLeft | Key::ShiftRight => self.shift = is_press,
+            Key::ControlLeft | Key::ControlRight => self.ctrl = is_press,
+            Key::Alt | Key::AltGr => self.alt = is_press,
+            Key::MetaLeft | Key::MetaRight => self.meta = is_press,
+            _ => {}
+        }
+    }
+
+    pub fn matches(&self, required_mods: &HashSet<&str>) -> bool {
+        (required_mods.contains("shift") == self.shift)
+            && (required_mods.contains("ctrl") == self.ctrl)
+            && (required_mods.contains("alt") == self.alt)
+            && (required_mods.contains("meta") == self.meta)
+    }
+}
+
+// Команда hotkeys off выключает глобальные привязки, например на время игры
+pub static HOTKEYS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+const HOTKEYS_TOGGLE: &str = "hotkeys toggle";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HotkeyBackend {
+    #[default]
+    Rdev,
+    Evdev,
+}
+
+pub struct HotkeyInput {
+    pub hotkeys: HashMap<String, String>,
+    pub contexts: HashMap<String, HotkeyContext>,
+    pub timing: PressTiming,
+    pub toggle: Option<String>,
+    pub backend: HotkeyBackend,
+    pub devices: Vec<PathBuf>,
+}
+
+impl InputSource for HotkeyInput {
+    fn name(&self) -> &str {
+        "hotkeys"
+    }
+
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        let mut hotkeys = self.hotkeys;
+        if let Some(toggle) = self.toggle {
+            hotkeys.insert(HOTKEYS_TOGGLE.to_string(), toggle);
+        }
+        let matcher = hotkey_matcher(hotkeys, self.contexts, self.timing, control);
+        match self.backend {
+            HotkeyBackend::Rdev => {
+                let mut matcher = matcher;
+                listen(move |event: KbdEvent| matcher.handle(&event.event_type))
+                    .map_err(|e| format!("{:?}", e))
+            }
+            // X-сервера может не быть, сверять модификаторы не с чем
+            HotkeyBackend::Evdev => {
+                let mut matcher = matcher.query_keyboard(false);
+                keyboard::listen(&self.devices, |event| matcher.handle(&event))
+            }
+        }
+    }
+}
+
+fn hotkey_matcher(
+    hotkeys: HashMap<String, String>,
+    contexts: HashMap<String, HotkeyContext>,
+    timing: PressTiming,
+    control: Control,
+) -> HotkeyMatcher {
+    HotkeyMatcher::new(hotkeys, timing, Timers::live(), move |cmd| {
+        // Переключатель действует и тогда, когда остальные привязки выключены
+        if cmd != HOTKEYS_TOGGLE && !HOTKEYS_ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+        if contexts
+            .get(cmd)
+            .is_none_or(|context| context.allows(is_playing))
+        {
+            let _ = control.send(cmd);
+        }
+    })
+}
+
+// Сопоставляет поток нажатий с привязками; общий для rdev и воспроизведения записей
+pub struct HotkeyMatcher {
+    pressed_keys: HashSet<Key>,
+    modifiers: ModifierState,
+    dispatcher: PressDispatcher,
+    // Отпускание могло потеряться (захват клавиатуры другим окном, смена VT),
+    // поэтому после долгой тишины нажатые клавиши забываются
+    stale_after: Duration,
+    timers: Timers,
+    last_event: Duration,
+    // Сверять модификаторы с X-сервером; при воспроизведении записи не нужно
+    query_keyboard: bool,
+}
+
+impl HotkeyMatcher {
+    pub fn new(
+        hotkeys: HashMap<String, String>,
+        timing: PressTiming,
+        timers: Timers,
+        fire: impl Fn(&str) + Send + Sync + 'static,
+    ) -> Self {
+        let mut actions: HashMap<String, PressActions> = HashMap::new();
+        for (cmd, binding) in hotkeys {
+            let (combo, kind) = press::split_binding(&binding);
+            actions.entry(combo.to_string()).or_default().set(kind, cmd);
+        }
+        Self {
+            pressed_keys: HashSet::new(),
+            modifiers: ModifierState::default(),
+            stale_after: Duration::from_millis(timing.stale_key_ms),
+            last_event: timers.elapsed(),
+            dispatcher: PressDispatcher::new(actions, timing, timers.clone(), fire),
+            timers,
+            query_keyboard: true,
+        }
+    }
+
+    // Записанные нажатия и evdev не сверяются с состоянием клавиатуры X-сервера
+    pub fn query_keyboard(mut self, enabled: bool) -> Self {
+        self.query_keyboard = enabled;
+        self
+    }
+
+    pub fn handle(&mut self, event: &EventType) {
+        match *event {
+            EventType::KeyPress(key) => {
+                self.resync();
+                self.pressed_keys.insert(key);
+                self.modifiers.update(&key, true);
+
+                for combo in self.dispatcher.combos() {
+                    if check_hotkey(&self.pressed_keys, &self.modifiers, combo) {
+                        self.dispatcher.press(combo);
+                    }
+                }
+            }
+            EventType::KeyRelease(key) => {
+                self.pressed_keys.remove(&key);
+                self.modifiers.update(&key, false);
+
+                for combo in self.dispatcher.combos() {
+                    if self.dispatcher.is_held(combo) && combo_key(combo) == Some(key) {
+                        self.dispatcher.release(combo);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.last_event = self.timers.elapsed();
+    }
+
+    // Зависшие клавиши сбрасываются перед нажатием, иначе комбинации перестают совпадать
+    fn resync(&mut self) {
+        let quiet = self.timers.elapsed().saturating_sub(self.last_event);
+        if !self.stale_after.is_zero() && quiet >= self.stale_after {
+            self.pressed_keys.clear();
+        }
+        if self.query_keyboard {
+            if let Some(held) = keystate::held_modifiers() {
+                self.pressed_keys.retain(|key| !keystate::is_modifier(key));
+                self.pressed_keys.extend(held);
+            }
+        }
+        let mut modifiers = ModifierState::default();
+        for key in &self.pressed_keys {
+            modifiers.update(key, true);
+        }
+        self.modifiers = modifiers;
+    }
+}
+
+// Печатает сработавшие на записанных нажатиях команды с моментом срабатывания:
+// вывод можно сравнить с ожидаемым
+pub fn replay_hotkeys(config: &Config, path: &Path) -> Result<(), String> {
+    let records = keylog::load(path)?;
+    for (at_ms, cmd) in replay_events(config.hotkeys.clone(), &config.hotkey_timing, records) {
+        println!("{} {}", at_ms, cmd);
+    }
+    Ok(())
+}
+
+// Проигрывает нажатия через сопоставление горячих клавиш на виртуальных часах,
+// поэтому результат не зависит от загрузки машины
+pub fn replay_events(
+    hotkeys: HashMap<String, String>,
+    timing: &PressTiming,
+    records: Vec<KeyRecord>,
+) -> Vec<(u64, String)> {
+    let timers = Timers::simulated();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut matcher = HotkeyMatcher::new(hotkeys, timing.clone(), timers.clone(), {
+        let fired = fired.clone();
+        let timers = timers.clone();
+        move |cmd| {
+            let at_ms = timers.elapsed().as_millis() as u64;
+            fired.lock().unwrap().push((at_ms, cmd.to_string()));
+        }
+    })
+    .query_keyboard(false);
+    for record in records {
+        timers.advance_to(Duration::from_millis(record.at_ms));
+        matcher.handle(&record.event);
+    }
+    // Долгие и одиночные нажатия срабатывают по таймеру после последнего события
+    let wait = Duration::from_millis(timing.long_press_ms.max(timing.double_press_ms));
+    timers.advance_to(timers.elapsed() + wait);
+    let fired = std::mem::take(&mut *fired.lock().unwrap());
+    fired
+}
+
+// Основная (не модификатор) клавиша комбинации
+pub fn combo_key(hotkey_str: &str) -> Option<Key> {
+    hotkey_str
+        .split('+')
+        .filter(|part| {
+            !matches!(
+                part.to_lowercase().as_str(),
+                "shift" | "ctrl" | "alt" | "meta" | "super" | "win"
+            )
+        })
+        .find_map(str_to_key)
+}
+
+fn is_playing() -> bool {
+    send_command("status")
+        .ok()
+        .and_then(|reply| serde_json::from_str::<serde_json::Value>(&reply).ok())
+        .is_some_and(|status| status["state"] == "playing")
+}
+
+pub fn check_hotkey(
+    pressed_keys: &HashSet<Key>,
+    modifiers: &ModifierState,
+    hotkey_str: &str,
+) -> bool {
+    let parts: Vec<&str> = hotkey_str.split('+').collect();
+    let mut required_mods = HashSet::new();
+    let mut required_key = None;
+
+    for part in parts {
+        match part.to_lowercase().as_str() {
+            "shift" => required_mods.insert("shift"),
+            "ctrl" => required_mods.insert("ctrl"),
+            "alt" => required_mods.insert("alt"),
+            "meta" | "super" | "win" => required_mods.insert("meta"),
+            key_str => {
+                required_key = str_to_key(key_str);
+                false
+            }
+        };
+    }
+
+    modifiers.matches(&required_mods) && required_key.is_some_and(|k| pressed_keys.contains(&k))
+}
+
+pub fn str_to_key(key_str: &str) -> Option<Key> {
+    match key_str.to_lowercase().as_str() {
+        // Медиа-клавиши
+        "nextsong" | "audionext" => Some(Key::Unknown(0x1008ff17)),
+        "previoussong" | "audioprev" => Some(Key::Unknown(0x1008ff16)),
+        "playpause" | "audioplay" => Some(Key::Unknown(0x1008ff14)),
+        "stopcd" | "audiostop" => Some(Key::Unknown(0x1008ff15)),
+        "volumedown" => Some(Key::Unknown(0x1008ff11)),
+        "volumeup" => Some(Key::Unknown(0x1008ff13)),
+        "volumemute" => Some(Key::Unknown(0x1008ff12)),
+
+        // Буквы
+        "a" => Some(Key::KeyA),
+        "b" => Some(Key::KeyB),
+        "c" => Some(Key::KeyC),
+        "d" => Some(Key::KeyD),
+        "e" => Some(Key::KeyE),
+        "f" => Some(Key::KeyF),
+        "g" => Some(Key::KeyG),
+        "h" => Some(Key::KeyH),
+        "i" => Some(Key::KeyI),
+        "j" => Some(Key::KeyJ),
+        "k" => Some(Key::KeyK),
+        "l" => Some(Key::KeyL),
+        "m" => Some(Key::KeyM),
+        "n" => Some(Key::KeyN),
+        "o" => Some(Key::KeyO),
+        "p" => Some(Key::KeyP),
+        "q" => Some(Key::KeyQ),
+        "r" => Some(Key::KeyR),
+        "s" => Some(Key::KeyS),
+        "t" => Some(Key::KeyT),
+        "u" => Some(Key::KeyU),
+        "v" => Some(Key::KeyV),
+        "w" => Some(Key::KeyW),
+        "x" => Some(Key::KeyX),
+        "y" => Some(Key::KeyY),
+        "z" => Some(Key::KeyZ),
+
+        // Цифры
+        "0" => Some(Key::Num0),
+        "1" => Some(Key::Num1),
+        "2" => Some(Key::Num2),
+        "3" => Some(Key::Num3),
+        "4" => Some(Key::Num4),
+        "5" => Some(Key::Num5),
+        "6" => Some(Key::Num6),
+        "7" => Some(Key::Num7),
+        "8" => Some(Key::Num8),
+        "9" => Some(Key::Num9),
+
+        // Функциональные клавиши
+        "f1" => Some(Key::F1),
+        "f2" => Some(Key::F2),
+        "f3" => Some(Key::F3),
+        "f4" => Some(Key::F4),
+        "f5" => Some(Key::F5),
+        "f6" => Some(Key::F6),
+        "f7" => Some(Key::F7),
+        "f8" => Some(Key::F8),
+        "f9" => Some(Key::F9),
+        "f10" => Some(Key::F10),
+        "f11" => Some(Key::F11),
+        "f12" => Some(Key::F12),
+
+        // Специальные клавиши
+        "space" => Some(Key::Space),
+        "enter" => Some(Key::Return),
+        "tab" => Some(Key::Tab),
+        "backspace" => Some(Key::Backspace),
+        "escape" => Some(Key::Escape),
+        "insert" => Some(Key::Insert),
+        "delete" => Some(Key::Delete),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "up" => Some(Key::UpArrow),
+        "down" => Some(Key::DownArrow),
+        "left" => Some(Key::LeftArrow),
+        "right" => Some(Key::RightArrow),
+
+        // Модификаторы
+        "shift" => Some(Key::ShiftLeft),
+        "ctrl" => Some(Key::ControlLeft),
+        "alt" => Some(Key::Alt),
+        "meta" | "super" | "win" => Some(Key::MetaLeft),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shift зажат с начала записи, его отпускание потерялось. Пока клавиша не признана
+    // зависшей, AudioNext совпадает только с Shift+AudioNext; после тишины в stale_key_ms
+    // она забывается, и одиночное нажатие ждёт double_press_ms, не будет ли второго
+    #[test]
+    fn stuck_shift_is_forgotten_after_silence() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/keys/stuck-shift.jsonl");
+        let records = keylog::load(&path).unwrap();
+        let hotkeys = HashMap::from([
+            ("next".to_string(), "AudioNext".to_string()),
+            ("prev".to_string(), "AudioNext:double".to_string()),
+            ("seek +30".to_string(), "Shift+AudioNext".to_string()),
+        ]);
+        let timing = PressTiming {
+            double_press_ms: 300,
+            long_press_ms: 500,
+            stale_key_ms: 500,
+        };
+        assert_eq!(
+            replay_events(hotkeys, &timing, records),
+            vec![(400, "seek +30".to_string()), (1580, "next".to_string())]
+        );
+    }
+}
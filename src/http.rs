@@ -0,0 +1,503 @@
+use crate::analysis;
+use crate::scan::percent_decode;
+use crate::{art, events, handle_command, openapi, websocket, MusicPlayer};
+use rodio::Sink;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// Самая большая сторона обложки, которую можно запросить
+const MAX_ART_SIZE: u32 = 2048;
+// Размер страницы /api/library по умолчанию и наибольший
+const PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+const LIBRARY_FIELDS: [&str; 7] = [
+    "path", "title", "artist", "album", "duration", "bpm", "mood",
+];
+// Веб-интерфейс вшит в бинарник
+const INDEX_HTML: &str = include_str!("../web/index.html");
+
+type ArtCache = Arc<Mutex<Option<((PathBuf, Option<u32>), Vec<u8>)>>>;
+
+struct Context {
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+    users: HashMap<String, String>,
+    cache: ArtCache,
+}
+
+pub fn http_server(
+    addr: String,
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+    users: HashMap<String, String>,
+) {
+    let server = match Server::http(&addr) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to start HTTP server on {}: {}", addr, e);
+            return;
+        }
+    };
+
+    let context = Arc::new(Context {
+        player,
+        sink,
+        users,
+        cache: Arc::new(Mutex::new(None)),
+    });
+    for request in server.incoming_requests() {
+        let context = Arc::clone(&context);
+        thread::spawn(move || handle_request(request, &context));
+    }
+}
+
+fn handle_request(mut request: Request, context: &Context) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let query = parse_query(query);
+
+    let response = match (request.method(), path) {
+        (Method::Get, "/") => Response::from_string(INDEX_HTML)
+            .with_header(header("Content-Type", "text/html; charset=utf-8")),
+        (Method::Get, "/art/current.jpg") => match authorize(&request, &query, context) {
+            Ok(_) => current_art(&context.player, &context.cache, &query),
+            Err(response) => response,
+        },
+        (Method::Get, "/api/events") => match authorize(&request, &query, context) {
+            Ok(_) => {
+                let topics = query.get("topics").map(String::as_str).unwrap_or("");
+                match events::parse_topics(topics) {
+                    Ok(topics) => return websocket::serve(request, topics),
+                    Err(e) => text_response(400, &e),
+                }
+            }
+            Err(response) => response,
+        },
+        // Чужая страница не должна управлять плеером из браузера, даже без токенов
+        (method, _) if *method != Method::Get && !same_origin(&request) => {
+            text_response(403, "Cross-origin request refused")
+        }
+        (_, api) if api.starts_with("/api/") => match authorize(&request, &query, context) {
+            Ok(user) => api_request(&mut request, api, &query, context, user.as_deref()),
+            Err(response) => response,
+        },
+        _ => text_response(404, "Not found"),
+    };
+    let _ = request.respond(response);
+}
+
+// Origin браузер ставит сам и подделать его страница не может; у curl и
+// других клиентов его нет
+fn same_origin(request: &Request) -> bool {
+    let Some(origin) = header_value(request, "Origin") else {
+        return true;
+    };
+    let origin = origin.split_once("://").map_or(origin, |(_, host)| host);
+    header_value(request, "Host").is_some_and(|host| host.eq_ignore_ascii_case(origin))
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+// Токен в заголовке Authorization: Bearer; без пользователей в конфиге доступ открыт.
+// Браузер не даёт задать заголовки WebSocket и картинке, поэтому годится и параметр ?token=
+fn authorize(
+    request: &Request,
+    query: &HashMap<String, String>,
+    context: &Context,
+) -> Result<Option<String>, Response<std::io::Cursor<Vec<u8>>>> {
+    if context.users.is_empty() {
+        return Ok(None);
+    }
+    header_value(request, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(query.get("token").map(String::as_str))
+        .and_then(|token| context.users.get(token.trim()))
+        .map(|user| Some(user.clone()))
+        .ok_or_else(|| text_response(401, "Authentication required"))
+}
+
+// REST поверх команд протокола: ответы те же, что и через сокет.
+// Каждый маршрут описан в схеме OpenAPI (/api/openapi.json)
+fn api_request(
+    request: &mut Request,
+    path: &str,
+    query: &HashMap<String, String>,
+    context: &Context,
+    user: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    match (request.method(), path) {
+        (Method::Get, "/api/openapi.json") => json_response(openapi::ApiDoc::json()),
+        (Method::Get, "/api/status") => status(context, user),
+        (Method::Get, "/api/queue") => queue(context, user),
+        (Method::Get, "/api/library") => library(query, context),
+        (Method::Get, "/api/search") => search(query, context, user),
+        (Method::Post, "/api/next") => next(context, user),
+        (Method::Post, "/api/prev") => prev(context, user),
+        (Method::Post, "/api/pause") => pause(context, user),
+        (Method::Get, "/api/volume") => volume(context, user),
+        (Method::Put, "/api/volume") => {
+            let mut body = String::new();
+            if request.as_reader().read_to_string(&mut body).is_err() {
+                return text_response(400, "Invalid body");
+            }
+            set_volume(&body, context, user)
+        }
+        (Method::Post, "/api/command") => {
+            // Простой POST с text/plain страница шлёт без спроса, JSON — только после preflight
+            let json = header_value(request, "Content-Type")
+                .is_some_and(|value| value.starts_with("application/json"));
+            if !json {
+                return text_response(415, "Expected application/json");
+            }
+            let mut body = String::new();
+            if request.as_reader().read_to_string(&mut body).is_err() {
+                return text_response(400, "Invalid body");
+            }
+            command(&body, context, user)
+        }
+        _ => text_response(404, "Not found"),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    responses((status = 200, description = "Playback state", body = openapi::Status))
+)]
+fn status(context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    run_command("status", context, user)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/queue",
+    responses((status = 200, description = "Queue with metadata", body = openapi::Queue))
+)]
+fn queue(context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    run_command("queue list", context, user)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(("q" = String, Query, description = "Words that must all appear in the path")),
+    responses((status = 200, description = "First matching tracks", body = openapi::SearchResults))
+)]
+fn search(
+    query: &HashMap<String, String>,
+    context: &Context,
+    user: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let q = query.get("q").map(String::as_str).unwrap_or("");
+    run_command(&format!("search {}", q), context, user)
+}
+
+// Кнопки пульта: команда, а в ответ — новое состояние, чтобы не запрашивать его отдельно
+#[utoipa::path(
+    post,
+    path = "/api/next",
+    responses(
+        (status = 200, description = "Playback state after the switch", body = openapi::Status),
+        (status = 400, description = "Command error", body = String, content_type = "text/plain")
+    )
+)]
+fn next(context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    control("next", context, user)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/prev",
+    responses(
+        (status = 200, description = "Playback state after the switch", body = openapi::Status),
+        (status = 400, description = "Command error", body = String, content_type = "text/plain")
+    )
+)]
+fn prev(context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    control("prev", context, user)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/pause",
+    responses(
+        (status = 200, description = "Playback state after toggling pause", body = openapi::Status),
+        (status = 400, description = "Command error", body = String, content_type = "text/plain")
+    )
+)]
+fn pause(context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    control("pause", context, user)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/volume",
+    responses((status = 200, description = "Current volume", body = openapi::Volume))
+)]
+fn volume(context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    run_command("volume", context, user)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/volume",
+    request_body(content = String, content_type = "text/plain", description = "Percentage like `60`, or a change like `+5` or `-10`"),
+    responses(
+        (status = 200, description = "New volume", body = openapi::Volume),
+        (status = 400, description = "Invalid volume", body = String, content_type = "text/plain")
+    )
+)]
+fn set_volume(
+    body: &str,
+    context: &Context,
+    user: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let level = body.trim();
+    if level.is_empty() {
+        return text_response(400, "Missing volume");
+    }
+    run_command(&format!("volume {}", level), context, user)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/command",
+    request_body(content = openapi::Command, content_type = "application/json"),
+    responses(
+        (status = 200, description = "Command reply: JSON for commands that report data, plain text otherwise"),
+        (status = 400, description = "Command error", body = String, content_type = "text/plain"),
+        (status = 415, description = "Body is not JSON", body = String, content_type = "text/plain")
+    )
+)]
+fn command(
+    body: &str,
+    context: &Context,
+    user: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let body: serde_json::Value = match serde_json::from_str(body) {
+        Ok(body) => body,
+        Err(e) => return text_response(400, &format!("Invalid body: {}", e)),
+    };
+    match body["command"].as_str() {
+        Some(cmd) => run_command(cmd, context, user),
+        None => text_response(400, "Missing command"),
+    }
+}
+
+fn control(cmd: &str, context: &Context, user: Option<&str>) -> Response<std::io::Cursor<Vec<u8>>> {
+    let reply = handle_command(&context.player, &context.sink, cmd, user);
+    match reply.strip_prefix("ERR ") {
+        Some(error) => text_response(400, error.trim_end()),
+        None => run_command("status", context, user),
+    }
+}
+
+fn run_command(
+    cmd: &str,
+    context: &Context,
+    user: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let reply = handle_command(&context.player, &context.sink, cmd, user);
+    match reply.strip_prefix("ERR ") {
+        Some(error) => text_response(400, error.trim_end()),
+        None => {
+            let reply = reply.trim_end();
+            if serde_json::from_str::<serde_json::Value>(reply).is_ok() {
+                json_response(reply.to_string())
+            } else {
+                text_response(200, reply)
+            }
+        }
+    }
+}
+
+fn current_art(
+    player: &Arc<Mutex<MusicPlayer>>,
+    cache: &ArtCache,
+    query: &HashMap<String, String>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let size = match query.get("size").map(|size| size.parse::<u32>()) {
+        Some(Ok(size)) if size > 0 => Some(size.min(MAX_ART_SIZE)),
+        Some(_) => return text_response(400, "Invalid size"),
+        None => None,
+    };
+    let track = {
+        let player = player.lock().unwrap();
+        player.files[player.current_index].clone()
+    };
+
+    let key = (track, size);
+    if let Some((ref cached_key, ref data)) = *cache.lock().unwrap() {
+        if *cached_key == key {
+            return jpeg_response(data.clone());
+        }
+    }
+
+    let Some(cover) = art::find_cover(&key.0) else {
+        return text_response(404, "No cover art");
+    };
+    match art::to_jpeg(&cover, size) {
+        Ok(data) => {
+            *cache.lock().unwrap() = Some((key, data.clone()));
+            jpeg_response(data)
+        }
+        Err(e) => text_response(500, &e),
+    }
+}
+
+// Страница библиотеки. Курсор — последний выданный путь: библиотека отсортирована,
+// поэтому страницы не съезжают при пересканировании
+#[utoipa::path(
+    get,
+    path = "/api/library",
+    params(
+        ("cursor" = Option<String>, Query, description = "next_cursor of the previous page"),
+        ("limit" = Option<usize>, Query, description = "Page size, 100 by default, at most 1000"),
+        ("fields" = Option<String>, Query, description = "Comma-separated: path, title, artist, album, duration, bpm, mood"),
+        ("q" = Option<String>, Query, description = "Words that must all appear in the path"),
+        ("dir" = Option<String>, Query, description = "Folder relative to the music directory"),
+        ("mood" = Option<String>, Query, description = "Analyzed mood"),
+        ("bpm" = Option<String>, Query, description = "Tempo range such as 120-130")
+    ),
+    responses(
+        (status = 200, description = "One page of tracks", body = openapi::LibraryPage),
+        (status = 400, description = "Invalid parameter", body = String, content_type = "text/plain")
+    )
+)]
+fn library(
+    query: &HashMap<String, String>,
+    context: &Context,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let limit = match query.get("limit").map(|limit| limit.parse::<usize>()) {
+        Some(Ok(limit)) if limit > 0 => limit.min(MAX_PAGE_SIZE),
+        Some(_) => return text_response(400, "Invalid limit"),
+        None => PAGE_SIZE,
+    };
+    let fields: Vec<&str> = match query.get("fields") {
+        Some(fields) => fields.split(',').map(str::trim).collect(),
+        None => vec!["path", "title", "artist"],
+    };
+    if let Some(field) = fields.iter().find(|f| !LIBRARY_FIELDS.contains(f)) {
+        return text_response(400, &format!("Unknown field: {}", field));
+    }
+    let bpm = match query.get("bpm").map(|bpm| analysis::parse_bpm_range(bpm)) {
+        Some(Some(range)) => Some(range),
+        Some(None) => return text_response(400, "Invalid bpm range"),
+        None => None,
+    };
+    let mood = query.get("mood");
+    let terms: Vec<String> = query
+        .get("q")
+        .map(|q| q.split_whitespace().map(str::to_lowercase).collect())
+        .unwrap_or_default();
+
+    let player = context.player.lock().unwrap();
+    let db = player.db.lock().unwrap();
+    let start = match query.get("cursor") {
+        Some(cursor) => player
+            .library
+            .partition_point(|path| path.as_path() <= std::path::Path::new(cursor)),
+        None => 0,
+    };
+    let dir = query.get("dir").map(|dir| player.music_dir.join(dir));
+
+    let mut matches = player.library[start..].iter().filter(|path| {
+        if dir.as_ref().is_some_and(|dir| !path.starts_with(dir)) {
+            return false;
+        }
+        if !terms.is_empty() {
+            let haystack = path
+                .strip_prefix(&player.music_dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .to_lowercase();
+            if !terms.iter().all(|term| haystack.contains(term)) {
+                return false;
+            }
+        }
+        if mood.is_none() && bpm.is_none() {
+            return true;
+        }
+        db.analysis(path).is_some_and(|a| {
+            mood.is_none_or(|mood| a.mood.as_ref() == Some(mood))
+                && bpm.is_none_or(|(low, high)| a.bpm.is_some_and(|b| b >= low && b <= high))
+        })
+    });
+
+    let page: Vec<&PathBuf> = matches.by_ref().take(limit).collect();
+    let next_cursor = match (page.last(), matches.next()) {
+        (Some(last), Some(_)) => Some(last.to_string_lossy().into_owned()),
+        _ => None,
+    };
+
+    // Метаданные запрашиваются у плагинов, только если нужны
+    let wants_tags = fields
+        .iter()
+        .any(|f| ["title", "artist", "album"].contains(f));
+    let tracks: Vec<serde_json::Value> = page
+        .into_iter()
+        .map(|path| {
+            let tags = wants_tags.then(|| player.plugins.metadata(path));
+            let analysis = db.analysis(path);
+            let mut track = serde_json::Map::new();
+            for field in &fields {
+                let value = match *field {
+                    "path" => serde_json::json!(path),
+                    "title" => serde_json::json!(tags.as_ref().map(|t| &t.title)),
+                    "artist" => serde_json::json!(tags.as_ref().and_then(|t| t.artist.as_ref())),
+                    "album" => serde_json::json!(tags.as_ref().and_then(|t| t.album.as_ref())),
+                    "duration" => serde_json::json!(analysis.map(|a| a.duration)),
+                    "bpm" => serde_json::json!(analysis.and_then(|a| a.bpm)),
+                    _ => serde_json::json!(analysis.and_then(|a| a.mood.as_ref())),
+                };
+                track.insert(field.to_string(), value);
+            }
+            serde_json::Value::Object(track)
+        })
+        .collect();
+
+    let body = serde_json::json!({ "tracks": tracks, "next_cursor": next_cursor });
+    json_response(body.to_string())
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| {
+            (
+                percent_decode(&key.replace('+', " ")),
+                percent_decode(&value.replace('+', " ")),
+            )
+        })
+        .collect()
+}
+
+fn json_response(body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body).with_header(header("Content-Type", "application/json"))
+}
+
+fn jpeg_response(data: Vec<u8>) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_data(data)
+        .with_header(header("Content-Type", "image/jpeg"))
+        .with_header(header("Cache-Control", "no-cache"))
+}
+
+fn text_response(status: u16, text: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(text)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("static header is valid")
+}
@@ -0,0 +1,74 @@
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::env;
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("ru", include_str!("../locales/ru.ftl")),
+];
+
+struct Translations {
+    bundle: FluentBundle<FluentResource>,
+    fallback: FluentBundle<FluentResource>,
+}
+
+static TRANSLATIONS: OnceLock<Translations> = OnceLock::new();
+
+pub fn tr(id: &str, args: &[(&str, &str)]) -> String {
+    let translations = TRANSLATIONS.get_or_init(|| Translations {
+        bundle: load_bundle(&system_language()),
+        fallback: load_bundle("en"),
+    });
+
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, *value);
+    }
+
+    [&translations.bundle, &translations.fallback]
+        .into_iter()
+        .find_map(|bundle| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let mut errors = Vec::new();
+            Some(
+                bundle
+                    .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                    .into_owned(),
+            )
+        })
+        .unwrap_or_else(|| id.to_string())
+}
+
+// Язык из LC_ALL / LC_MESSAGES / LANG, например "ru_RU.UTF-8" -> "ru"
+fn system_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| {
+            let lang = value.split(['_', '.', '@']).next()?.to_lowercase();
+            LOCALES
+                .iter()
+                .any(|(code, _)| *code == lang)
+                .then_some(lang)
+        })
+        .unwrap_or_else(|| "en".to_string())
+}
+
+fn load_bundle(lang: &str) -> FluentBundle<FluentResource> {
+    let source = LOCALES
+        .iter()
+        .find(|(code, _)| *code == lang)
+        .map(|(_, source)| *source)
+        .unwrap_or(LOCALES[0].1);
+    let langid: LanguageIdentifier = lang.parse().unwrap_or_default();
+
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // Без символов изоляции направления текста: вывод идёт в терминал
+    bundle.set_use_isolating(false);
+    let resource = FluentResource::try_new(source.to_string()).unwrap_or_else(|(res, _)| res);
+    let _ = bundle.add_resource(resource);
+    bundle
+}
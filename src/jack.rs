@@ -0,0 +1,54 @@
+use crate::evdev::{self, Device};
+use crate::plugin::{Control, InputSource};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const UNPLUG_REASON: &str = "headphones-unplugged";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct HeadphonesConfig {
+    pub auto_pause: bool,
+    pub resume_on_plug: bool,
+    // По умолчанию ищется устройство ALSA с "Headphone" в названии
+    pub device: Option<String>,
+}
+
+impl InputSource for HeadphonesConfig {
+    fn name(&self) -> &str {
+        "headphones"
+    }
+
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        let path = match self.device {
+            Some(ref device) => PathBuf::from(device),
+            None => find_jack_device().ok_or("No headphone jack input device found")?,
+        };
+        let mut device = Device::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        loop {
+            let event = device.next_event().map_err(|e| e.to_string())?;
+            if event.kind != evdev::EV_SW || event.code != evdev::SW_HEADPHONE_INSERT {
+                continue;
+            }
+
+            if event.value == 0 {
+                let _ = control.send(&format!("auto_pause {}", UNPLUG_REASON));
+            } else if self.resume_on_plug {
+                let _ = control.send(&format!("auto_resume {}", UNPLUG_REASON));
+            }
+        }
+    }
+}
+
+fn find_jack_device() -> Option<PathBuf> {
+    evdev::event_devices()
+        .into_iter()
+        .find(|path| is_jack_device(path))
+}
+
+fn is_jack_device(path: &Path) -> bool {
+    Device::open(path)
+        .and_then(|device| device.name())
+        .is_ok_and(|name| name.contains("Headphone"))
+}
@@ -0,0 +1,127 @@
+use crate::evdev::{self, Device};
+use crate::str_to_key;
+use rdev::{EventType, Key};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+// Горячие клавиши прямо из /dev/input: работают и под Wayland, где rdev ничего не слышит.
+// Нужен доступ к устройствам ввода (обычно группа input)
+pub fn listen(devices: &[PathBuf], mut callback: impl FnMut(EventType)) -> Result<(), String> {
+    let paths = if devices.is_empty() {
+        evdev::event_devices()
+    } else {
+        devices.to_vec()
+    };
+
+    // Все устройства сливаются в один поток событий, как у rdev
+    let (sender, receiver) = mpsc::channel();
+    for path in paths {
+        let mut device = match Device::open(&path) {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("Skipping hotkey device {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let sender = sender.clone();
+        thread::spawn(move || loop {
+            let event = match device.next_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Hotkey device {} stopped: {}", path.display(), e);
+                    return;
+                }
+            };
+            if event.kind != evdev::EV_KEY {
+                continue;
+            }
+            let Some(key) = key_from_code(event.code) else {
+                continue;
+            };
+            // 2 — автоповтор, для привязок это снова нажатие
+            let event = match event.value {
+                0 => EventType::KeyRelease(key),
+                _ => EventType::KeyPress(key),
+            };
+            if sender.send(event).is_err() {
+                return;
+            }
+        });
+    }
+    drop(sender);
+
+    for event in receiver {
+        callback(event);
+    }
+    Err("no readable input devices for hotkeys".to_string())
+}
+
+// Код клавиши ядра (linux/input-event-codes.h) -> клавиша так, как её называет конфиг
+fn key_from_code(code: u16) -> Option<Key> {
+    let name = match code {
+        1 => "escape",
+        2..=10 => return str_to_key(&(code - 1).to_string()),
+        11 => "0",
+        14 => "backspace",
+        15 => "tab",
+        16 => "q",
+        17 => "w",
+        18 => "e",
+        19 => "r",
+        20 => "t",
+        21 => "y",
+        22 => "u",
+        23 => "i",
+        24 => "o",
+        25 => "p",
+        28 => "enter",
+        29 => return Some(Key::ControlLeft),
+        30 => "a",
+        31 => "s",
+        32 => "d",
+        33 => "f",
+        34 => "g",
+        35 => "h",
+        36 => "j",
+        37 => "k",
+        38 => "l",
+        42 => return Some(Key::ShiftLeft),
+        44 => "z",
+        45 => "x",
+        46 => "c",
+        47 => "v",
+        48 => "b",
+        49 => "n",
+        50 => "m",
+        54 => return Some(Key::ShiftRight),
+        56 => return Some(Key::Alt),
+        57 => "space",
+        59..=68 => return str_to_key(&format!("f{}", code - 58)),
+        87 => "f11",
+        88 => "f12",
+        97 => return Some(Key::ControlRight),
+        100 => return Some(Key::AltGr),
+        102 => "home",
+        103 => "up",
+        104 => "pageup",
+        105 => "left",
+        106 => "right",
+        107 => "end",
+        108 => "down",
+        109 => "pagedown",
+        110 => "insert",
+        111 => "delete",
+        113 => "volumemute",
+        114 => "volumedown",
+        115 => "volumeup",
+        125 => return Some(Key::MetaLeft),
+        126 => return Some(Key::MetaRight),
+        163 => "nextsong",
+        164 => "playpause",
+        165 => "previoussong",
+        166 => "stopcd",
+        _ => return None,
+    };
+    str_to_key(name)
+}
@@ -0,0 +1,56 @@
+use rdev::{listen, EventType};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+// Запись нажатий для воспроизведения: строка JSON на событие,
+// например {"at_ms":120,"event":{"KeyPress":"ShiftLeft"}}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyRecord {
+    // От начала записи
+    pub at_ms: u64,
+    pub event: EventType,
+}
+
+// Пишет нажатия и отпускания клавиш, пока процесс не прервут (Ctrl+C)
+pub fn record(path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut out = BufWriter::new(file);
+    let start = Instant::now();
+    println!("Recording keys to {}; press Ctrl+C to stop", path.display());
+    listen(move |event| {
+        if !matches!(
+            event.event_type,
+            EventType::KeyPress(_) | EventType::KeyRelease(_)
+        ) {
+            return;
+        }
+        let record = KeyRecord {
+            at_ms: start.elapsed().as_millis() as u64,
+            event: event.event_type,
+        };
+        // Каждая строка сразу на диск: запись обрывается сигналом
+        let written = serde_json::to_string(&record)
+            .map_err(std::io::Error::other)
+            .and_then(|line| writeln!(out, "{}", line))
+            .and_then(|()| out.flush());
+        if let Err(e) = written {
+            eprintln!("Failed to record key event: {}", e);
+        }
+    })
+    .map_err(|e| format!("{:?}", e))
+}
+
+pub fn load(path: &Path) -> Result<Vec<KeyRecord>, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))
+        })
+        .collect()
+}
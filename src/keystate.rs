@@ -0,0 +1,84 @@
+use crate::plugin::Library;
+use rdev::Key;
+use std::ffi::{c_char, c_int, c_ulong, c_void};
+use std::path::Path;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+
+// Настоящее состояние клавиатуры берётся у X-сервера, с которым работает и rdev
+const LIBRARY: &str = "libX11.so.6";
+
+// keysym модификатора и клавиша rdev, которой он приходит в событиях
+const MODIFIERS: [(c_ulong, Key); 8] = [
+    (0xffe1, Key::ShiftLeft),
+    (0xffe2, Key::ShiftRight),
+    (0xffe3, Key::ControlLeft),
+    (0xffe4, Key::ControlRight),
+    (0xffe9, Key::Alt),
+    (0xfe03, Key::AltGr),
+    (0xffeb, Key::MetaLeft),
+    (0xffec, Key::MetaRight),
+];
+
+struct Api {
+    display: *mut c_void,
+    query_keymap: unsafe extern "C" fn(*mut c_void, *mut c_char) -> c_int,
+    // Коды клавиш модификаторов на этом сервере
+    keycodes: Vec<(u8, Key)>,
+    _library: Library,
+}
+
+// Соединение с X-сервером используется только под мьютексом
+unsafe impl Send for Api {}
+
+impl Api {
+    fn load() -> Result<Self, String> {
+        let library = Library::open(Path::new(LIBRARY))?;
+        unsafe {
+            let open_display: unsafe extern "C" fn(*const c_char) -> *mut c_void =
+                library.function("XOpenDisplay")?;
+            let keysym_to_keycode: unsafe extern "C" fn(*mut c_void, c_ulong) -> u8 =
+                library.function("XKeysymToKeycode")?;
+            let display = open_display(ptr::null());
+            if display.is_null() {
+                return Err("cannot open X display".to_string());
+            }
+            let keycodes = MODIFIERS
+                .iter()
+                .map(|&(keysym, key)| (keysym_to_keycode(display, keysym), key))
+                .filter(|&(keycode, _)| keycode != 0)
+                .collect();
+            Ok(Self {
+                display,
+                query_keymap: library.function("XQueryKeymap")?,
+                keycodes,
+                _library: library,
+            })
+        }
+    }
+}
+
+fn api() -> Result<&'static Mutex<Api>, String> {
+    static API: OnceLock<Result<Mutex<Api>, String>> = OnceLock::new();
+    API.get_or_init(|| Api::load().map(Mutex::new))
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+// Модификаторы, которые сейчас действительно зажаты; None без X-сервера
+pub fn held_modifiers() -> Option<Vec<Key>> {
+    let api = api().ok()?.lock().unwrap();
+    let mut keymap = [0 as c_char; 32];
+    unsafe { (api.query_keymap)(api.display, keymap.as_mut_ptr()) };
+    let held = api
+        .keycodes
+        .iter()
+        .filter(|&&(keycode, _)| keymap[keycode as usize / 8] as u8 & (1 << (keycode % 8)) != 0)
+        .map(|&(_, key)| key)
+        .collect();
+    Some(held)
+}
+
+pub fn is_modifier(key: &Key) -> bool {
+    MODIFIERS.iter().any(|(_, modifier)| modifier == key)
+}
@@ -0,0 +1,198 @@
+use crate::digest::md5_hex;
+use crate::scrobble::{Listen, Service};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const API: &str = "https://ws.audioscrobbler.com/2.0/";
+
+// Ключ и секрет приложения — https://www.last.fm/api/account/create.
+// Сессию можно указать готовой или получить по логину и паролю при запуске
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LastfmConfig {
+    pub api_key: String,
+    pub api_secret: String,
+    pub session_key: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Прослушивания, которые не удалось отправить
+    pub queue_file: String,
+    pub curl_path: PathBuf,
+}
+
+impl Default for LastfmConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            api_secret: String::new(),
+            session_key: None,
+            username: None,
+            password: None,
+            queue_file: "lastfm_queue.jsonl".to_string(),
+            curl_path: PathBuf::from("curl"),
+        }
+    }
+}
+
+pub struct Lastfm {
+    config: LastfmConfig,
+}
+
+impl Lastfm {
+    pub fn new(config: LastfmConfig) -> Self {
+        Self { config }
+    }
+
+    // Ключ сессии запрашивается один раз и дальше хранится в памяти
+    fn session(&mut self) -> Result<String, String> {
+        if let Some(ref key) = self.config.session_key {
+            return Ok(key.clone());
+        }
+        let (Some(username), Some(password)) =
+            (self.config.username.clone(), self.config.password.clone())
+        else {
+            return Err("no session_key, username or password in the config".to_string());
+        };
+        let reply = self.call(vec![
+            ("method", "auth.getMobileSession".to_string()),
+            ("username", username),
+            ("password", password),
+        ])?;
+        let key = reply["session"]["key"]
+            .as_str()
+            .ok_or("no session key in the reply")?
+            .to_string();
+        self.config.session_key = Some(key.clone());
+        Ok(key)
+    }
+
+    // Подписанный POST: api_sig — md5 от отсортированных параметров и секрета
+    fn call(&self, mut params: Vec<(&str, String)>) -> Result<serde_json::Value, String> {
+        params.push(("api_key", self.config.api_key.clone()));
+        params.sort();
+        let mut signed: String = params
+            .iter()
+            .map(|(key, value)| format!("{}{}", key, value))
+            .collect();
+        signed.push_str(&self.config.api_secret);
+        params.push(("api_sig", md5_hex(signed.as_bytes())));
+        params.push(("format", "json".to_string()));
+        let body = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, form_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        // Тело идёт через stdin, чтобы пароль и ключи не светились в списке процессов
+        let mut child = Command::new(&self.config.curl_path)
+            .args([
+                "-s",
+                "--max-time",
+                "15",
+                "-A",
+                "NSmp/0.1",
+                "--data-binary",
+                "@-",
+                API,
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("curl: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(body.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(format!("curl exited with {}", output.status));
+        }
+        let reply: serde_json::Value =
+            serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+        if let Some(code) = reply["error"].as_u64() {
+            return Err(format!(
+                "Last.fm error {}: {}",
+                code,
+                reply["message"].as_str().unwrap_or_default()
+            ));
+        }
+        Ok(reply)
+    }
+}
+
+impl Service for Lastfm {
+    fn name(&self) -> &str {
+        "lastfm"
+    }
+
+    fn now_playing(&mut self, listen: &Listen) -> Result<(), String> {
+        let mut params = vec![
+            ("method", "track.updateNowPlaying".to_string()),
+            ("sk", self.session()?),
+            ("artist", listen.artist.clone()),
+            ("track", listen.title.clone()),
+        ];
+        if let Some(ref album) = listen.album {
+            params.push(("album", album.clone()));
+        }
+        if let Some(number) = listen.track_number {
+            params.push(("trackNumber", number.to_string()));
+        }
+        if let Some(duration) = listen.duration {
+            params.push(("duration", duration.to_string()));
+        }
+        if let Some(ref mbid) = listen.musicbrainz.recording {
+            params.push(("mbid", mbid.clone()));
+        }
+        self.call(params).map(|_| ())
+    }
+
+    fn scrobble(&mut self, listens: &[Listen]) -> Result<(), String> {
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        for (i, listen) in listens.iter().enumerate() {
+            let mut field = |name: &str, value: String| {
+                names.push(format!("{}[{}]", name, i));
+                values.push(value);
+            };
+            field("artist", listen.artist.clone());
+            field("track", listen.title.clone());
+            field("timestamp", listen.timestamp.to_string());
+            if let Some(ref album) = listen.album {
+                field("album", album.clone());
+            }
+            if let Some(number) = listen.track_number {
+                field("trackNumber", number.to_string());
+            }
+            if let Some(duration) = listen.duration {
+                field("duration", duration.to_string());
+            }
+            if let Some(ref mbid) = listen.musicbrainz.recording {
+                field("mbid", mbid.clone());
+            }
+        }
+        let mut params = vec![
+            ("method", "track.scrobble".to_string()),
+            ("sk", self.session()?),
+        ];
+        params.extend(names.iter().map(String::as_str).zip(values));
+        self.call(params).map(|_| ())
+    }
+}
+
+// application/x-www-form-urlencoded
+fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
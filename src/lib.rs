@@ -0,0 +1,93 @@
+// Движок плеера: очередь, протокол команд, горячие клавиши и конфиг; main.rs — только CLI
+mod analysis;
+#[cfg(feature = "http")]
+mod art;
+mod atomic;
+mod automix;
+mod buffer;
+mod cd;
+mod changes;
+pub mod clock;
+pub mod commands;
+mod config;
+mod confirm;
+mod context;
+mod daemon;
+mod db;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod decode;
+mod digest;
+mod dispatch;
+mod dsd;
+mod evdev;
+mod events;
+mod focus;
+mod gme;
+mod handoff;
+mod hotkeys;
+#[cfg(feature = "http")]
+mod http;
+mod i18n;
+mod jack;
+mod keyboard;
+pub mod keylog;
+mod keystate;
+mod lastfm;
+mod library;
+mod limiter;
+mod listenbrainz;
+mod listeners;
+mod midi;
+mod mix;
+#[cfg(feature = "dbus")]
+mod mpris;
+mod noise;
+#[cfg(feature = "http")]
+mod openapi;
+#[cfg(feature = "opus")]
+mod opus;
+pub mod paths;
+mod player;
+mod playlist;
+pub mod plugin;
+mod preload;
+mod press;
+mod preview;
+mod recovery;
+mod removable;
+mod replaygain;
+pub mod report;
+#[cfg(feature = "rfid")]
+mod rfid;
+#[cfg(feature = "rotary")]
+mod rotary;
+mod scan;
+mod schedule;
+mod scrobble;
+mod seamless;
+mod skip;
+mod state;
+mod stream;
+mod tags;
+mod tracker;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod units;
+pub mod update;
+mod watch;
+#[cfg(feature = "http")]
+mod websocket;
+
+pub use config::{load_config, read_config, save_config, Config};
+pub use daemon::{headless, run, PlayerHandles};
+pub use dispatch::handle_command;
+pub use hotkeys::{
+    check_hotkey, combo_key, replay_events, replay_hotkeys, str_to_key, HotkeyMatcher,
+    ModifierState,
+};
+pub use listeners::{send_command, send_command_as, stream_command_as, SendError};
+pub use player::MusicPlayer;
+
+// Версия протокола управления; увеличивается при несовместимых изменениях
+pub const PROTOCOL_VERSION: u32 = 2;
@@ -0,0 +1,494 @@
+use crate::analysis;
+use crate::plugin::TrackMetadata;
+use crate::replaygain::ReplayGain;
+use crate::skip::PlayCounts;
+use crate::tags::MusicBrainzIds;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Постоянная библиотека: пути, время изменения, теги, длительность, число
+// прослушиваний и пропусков. Пути хранятся байтами, чтобы не терять имена не в UTF-8
+pub struct LibraryDb {
+    conn: Connection,
+}
+
+// Трек с числом прослушиваний и временем последнего (секунды Unix)
+#[derive(Debug)]
+pub struct PlayedTrack {
+    pub path: PathBuf,
+    pub plays: u64,
+    pub last_played: Option<u64>,
+}
+
+// Теги, прочитанные для файла; None — тегов нет, читать заново не нужно
+pub enum CachedTags {
+    Fresh(Option<TrackMetadata>),
+    Stale,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+}
+
+impl LibraryDb {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS tracks (
+                 path BLOB PRIMARY KEY,
+                 mtime INTEGER NOT NULL,
+                 tags_mtime INTEGER,
+                 title TEXT,
+                 artist TEXT,
+                 album TEXT,
+                 track_number INTEGER,
+                 duration REAL,
+                 plays INTEGER NOT NULL DEFAULT 0,
+                 skips INTEGER NOT NULL DEFAULT 0,
+                 rg_track_gain REAL,
+                 rg_track_peak REAL,
+                 rg_album_gain REAL,
+                 rg_album_peak REAL,
+                 mb_recording TEXT,
+                 mb_release TEXT,
+                 mb_artists TEXT,
+                 last_played INTEGER,
+                 rating INTEGER,
+                 favorite INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE TABLE IF NOT EXISTS plays (
+                 path BLOB NOT NULL,
+                 time INTEGER NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS plays_time ON plays (time);",
+        )
+        .map_err(|e| e.to_string())?;
+        migrate(&conn).map_err(|e| e.to_string())?;
+        Ok(Self { conn })
+    }
+
+    // Известные треки внутри папки, в порядке сортировки путей
+    pub fn tracks(&self, root: &Path) -> Result<Vec<PathBuf>, String> {
+        let mut files: Vec<PathBuf> = self
+            .paths()?
+            .into_keys()
+            .filter(|path| path.starts_with(root))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    fn paths(&self) -> Result<HashMap<PathBuf, u64>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT path, mtime FROM tracks")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((to_path(row.get(0)?), row.get::<_, i64>(1)? as u64))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    // Сверяет папку с результатом сканирования: новые и изменённые файлы
+    // получают новое время изменения, теги перечитаются при первом запросе
+    pub fn sync(&mut self, root: &Path, files: &[PathBuf]) -> Result<SyncReport, String> {
+        let known = self.paths()?;
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        let mut report = SyncReport::default();
+
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+        for path in files {
+            let mtime = analysis::file_mtime(path);
+            match known.get(path) {
+                Some(&old) if old == mtime => continue,
+                Some(_) => report.changed += 1,
+                None => report.added += 1,
+            }
+            tx.execute(
+                "INSERT INTO tracks (path, mtime) VALUES (?1, ?2)
+                 ON CONFLICT(path) DO UPDATE SET mtime = excluded.mtime",
+                params![path.as_os_str().as_bytes(), mtime as i64],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        for path in known.keys() {
+            if path.starts_with(root) && !present.contains(path) {
+                tx.execute(
+                    "DELETE FROM tracks WHERE path = ?1",
+                    params![path.as_os_str().as_bytes()],
+                )
+                .map_err(|e| e.to_string())?;
+                report.removed += 1;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(report)
+    }
+
+    // Теги актуальны, если прочитаны при том же времени изменения файла
+    pub fn cached_tags(&self, path: &Path) -> CachedTags {
+        let row = self
+            .conn
+            .prepare_cached(
+                "SELECT tags_mtime, title, artist, album, track_number FROM tracks WHERE path = ?1",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_row(params![path.as_os_str().as_bytes()], |row| {
+                    Ok((
+                        row.get::<_, Option<i64>>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<u32>>(4)?,
+                    ))
+                })
+                .optional()
+            });
+
+        match row {
+            Ok(Some((Some(tags_mtime), title, artist, album, track_number)))
+                if tags_mtime as u64 == analysis::file_mtime(path) =>
+            {
+                CachedTags::Fresh(title.map(|title| TrackMetadata {
+                    path: path.to_path_buf(),
+                    title,
+                    artist,
+                    album,
+                    track_number,
+                }))
+            }
+            _ => CachedTags::Stale,
+        }
+    }
+
+    // Файлы вне библиотеки (из плейлистов) тоже кэшируются
+    pub fn store_tags(
+        &self,
+        path: &Path,
+        tags: Option<&TrackMetadata>,
+        duration: Option<f32>,
+        gain: &ReplayGain,
+        ids: &MusicBrainzIds,
+    ) {
+        let mtime = analysis::file_mtime(path) as i64;
+        let result = self.conn.execute(
+            "INSERT INTO tracks (path, mtime, tags_mtime, title, artist, album, track_number, duration,
+                                 rg_track_gain, rg_track_peak, rg_album_gain, rg_album_peak,
+                                 mb_recording, mb_release, mb_artists)
+             VALUES (?1, ?2, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(path) DO UPDATE SET
+                 mtime = excluded.mtime,
+                 tags_mtime = excluded.tags_mtime,
+                 title = excluded.title,
+                 artist = excluded.artist,
+                 album = excluded.album,
+                 track_number = excluded.track_number,
+                 duration = excluded.duration,
+                 rg_track_gain = excluded.rg_track_gain,
+                 rg_track_peak = excluded.rg_track_peak,
+                 rg_album_gain = excluded.rg_album_gain,
+                 rg_album_peak = excluded.rg_album_peak,
+                 mb_recording = excluded.mb_recording,
+                 mb_release = excluded.mb_release,
+                 mb_artists = excluded.mb_artists",
+            params![
+                path.as_os_str().as_bytes(),
+                mtime,
+                tags.map(|t| &t.title),
+                tags.and_then(|t| t.artist.as_ref()),
+                tags.and_then(|t| t.album.as_ref()),
+                tags.and_then(|t| t.track_number),
+                duration,
+                gain.track_gain,
+                gain.track_peak,
+                gain.album_gain,
+                gain.album_peak,
+                ids.recording,
+                ids.release,
+                Some(ids.artists.join(";")).filter(|artists| !artists.is_empty()),
+            ],
+        );
+        if let Err(e) = result {
+            eprintln!("Failed to store tags of {}: {}", path.display(), e);
+        }
+    }
+
+    // Длительность по заголовку файла, известная без анализа
+    pub fn duration(&self, path: &Path) -> Option<f32> {
+        self.conn
+            .prepare_cached("SELECT duration FROM tracks WHERE path = ?1")
+            .and_then(|mut stmt| {
+                stmt.query_row(params![path.as_os_str().as_bytes()], |row| {
+                    row.get::<_, Option<f32>>(0)
+                })
+                .optional()
+            })
+            .ok()
+            .flatten()
+            .flatten()
+    }
+
+    // Как и теги, актуален только при том же времени изменения файла
+    pub fn cached_replay_gain(&self, path: &Path) -> Option<ReplayGain> {
+        let (tags_mtime, gain) = self
+            .conn
+            .prepare_cached(
+                "SELECT tags_mtime, rg_track_gain, rg_track_peak, rg_album_gain, rg_album_peak
+                 FROM tracks WHERE path = ?1",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_row(params![path.as_os_str().as_bytes()], |row| {
+                    Ok((
+                        row.get::<_, Option<i64>>(0)?,
+                        ReplayGain {
+                            track_gain: row.get(1)?,
+                            track_peak: row.get(2)?,
+                            album_gain: row.get(3)?,
+                            album_peak: row.get(4)?,
+                        },
+                    ))
+                })
+                .optional()
+            })
+            .ok()
+            .flatten()?;
+        (tags_mtime? as u64 == analysis::file_mtime(path)).then_some(gain)
+    }
+
+    pub fn cached_musicbrainz(&self, path: &Path) -> Option<MusicBrainzIds> {
+        let (tags_mtime, ids) = self
+            .conn
+            .prepare_cached(
+                "SELECT tags_mtime, mb_recording, mb_release, mb_artists FROM tracks WHERE path = ?1",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_row(params![path.as_os_str().as_bytes()], |row| {
+                    Ok((
+                        row.get::<_, Option<i64>>(0)?,
+                        MusicBrainzIds {
+                            recording: row.get(1)?,
+                            release: row.get(2)?,
+                            artists: row
+                                .get::<_, Option<String>>(3)?
+                                .map(|artists| artists.split(';').map(String::from).collect())
+                                .unwrap_or_default(),
+                        },
+                    ))
+                })
+                .optional()
+            })
+            .ok()
+            .flatten()?;
+        (tags_mtime? as u64 == analysis::file_mtime(path)).then_some(ids)
+    }
+
+    pub fn record_play(&self, path: &Path) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let bytes = path.as_os_str().as_bytes();
+        let result = self
+            .conn
+            .execute(
+                "UPDATE tracks SET plays = plays + 1, last_played = ?2 WHERE path = ?1",
+                params![bytes, time],
+            )
+            .and_then(|_| {
+                self.conn.execute(
+                    "INSERT INTO plays (path, time) VALUES (?1, ?2)",
+                    params![bytes, time],
+                )
+            });
+        if let Err(e) = result {
+            eprintln!("Failed to count play of {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn record_skip(&self, path: &Path) {
+        let result = self.conn.execute(
+            "UPDATE tracks SET skips = skips + 1 WHERE path = ?1",
+            params![path.as_os_str().as_bytes()],
+        );
+        if let Err(e) = result {
+            eprintln!("Failed to count skip of {}: {}", path.display(), e);
+        }
+    }
+
+    // Треки, которые хоть раз пропускали; у остальных вес в перемешивании обычный
+    pub fn skipped(&self) -> Result<HashMap<PathBuf, PlayCounts>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT path, plays, skips FROM tracks WHERE skips > 0")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    to_path(row.get(0)?),
+                    PlayCounts {
+                        plays: row.get::<_, i64>(1)? as u64,
+                        skips: row.get::<_, i64>(2)? as u64,
+                    },
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    // Оценка 1..5 и отметка избранного; None — трека нет в библиотеке
+    pub fn rating(&self, path: &Path) -> Option<(Option<u8>, bool)> {
+        self.conn
+            .prepare_cached("SELECT rating, favorite FROM tracks WHERE path = ?1")
+            .and_then(|mut stmt| {
+                stmt.query_row(params![path.as_os_str().as_bytes()], |row| {
+                    Ok((
+                        row.get::<_, Option<i64>>(0)?.map(|rating| rating as u8),
+                        row.get::<_, i64>(1)? != 0,
+                    ))
+                })
+                .optional()
+            })
+            .ok()
+            .flatten()
+    }
+
+    // None снимает оценку
+    pub fn set_rating(&self, path: &Path, rating: Option<u8>) -> Result<(), String> {
+        self.update_track(
+            path,
+            "UPDATE tracks SET rating = ?2 WHERE path = ?1",
+            rating.map(i64::from),
+        )
+    }
+
+    pub fn set_favorite(&self, path: &Path, favorite: bool) -> Result<(), String> {
+        self.update_track(
+            path,
+            "UPDATE tracks SET favorite = ?2 WHERE path = ?1",
+            Some(favorite as i64),
+        )
+    }
+
+    fn update_track(&self, path: &Path, sql: &str, value: Option<i64>) -> Result<(), String> {
+        let changed = self
+            .conn
+            .execute(sql, params![path.as_os_str().as_bytes(), value])
+            .map_err(|e| e.to_string())?;
+        if changed == 0 {
+            return Err(format!("{} is not in the library", path.display()));
+        }
+        Ok(())
+    }
+
+    // Избранные треки в порядке путей
+    pub fn favorites(&self) -> Result<Vec<PathBuf>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT path FROM tracks WHERE favorite != 0 ORDER BY path")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok(to_path(row.get(0)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    // Последние прослушивания, новые первыми: путь и время
+    pub fn history(&self, limit: usize) -> Result<Vec<(PathBuf, u64)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT path, time FROM plays ORDER BY time DESC, rowid DESC LIMIT ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok((to_path(row.get(0)?), row.get::<_, i64>(1)? as u64))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    // Самые слушаемые треки; при равенстве выше тот, что звучал позже
+    pub fn most_played(&self, limit: usize) -> Result<Vec<PlayedTrack>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT path, plays, last_played FROM tracks WHERE plays > 0
+                 ORDER BY plays DESC, last_played DESC, path LIMIT ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok(PlayedTrack {
+                    path: to_path(row.get(0)?),
+                    plays: row.get::<_, i64>(1)? as u64,
+                    last_played: row.get::<_, Option<i64>>(2)?.map(|time| time as u64),
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+}
+
+// Базы прежних версий получают новые столбцы; теги перечитываются, чтобы их заполнить
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let has_gain = conn
+        .prepare("SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'rg_track_gain'")?
+        .exists([])?;
+    if !has_gain {
+        conn.execute_batch(
+            "ALTER TABLE tracks ADD COLUMN rg_track_gain REAL;
+             ALTER TABLE tracks ADD COLUMN rg_track_peak REAL;
+             ALTER TABLE tracks ADD COLUMN rg_album_gain REAL;
+             ALTER TABLE tracks ADD COLUMN rg_album_peak REAL;
+             UPDATE tracks SET tags_mtime = NULL;",
+        )?;
+    }
+    let has_musicbrainz = conn
+        .prepare("SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'mb_recording'")?
+        .exists([])?;
+    if !has_musicbrainz {
+        conn.execute_batch(
+            "ALTER TABLE tracks ADD COLUMN mb_recording TEXT;
+             ALTER TABLE tracks ADD COLUMN mb_release TEXT;
+             ALTER TABLE tracks ADD COLUMN mb_artists TEXT;
+             UPDATE tracks SET tags_mtime = NULL;",
+        )?;
+    }
+    let has_skips = conn
+        .prepare("SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'skips'")?
+        .exists([])?;
+    if !has_skips {
+        conn.execute(
+            "ALTER TABLE tracks ADD COLUMN skips INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+    let has_last_played = conn
+        .prepare("SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'last_played'")?
+        .exists([])?;
+    if !has_last_played {
+        conn.execute("ALTER TABLE tracks ADD COLUMN last_played INTEGER", [])?;
+    }
+    let has_rating = conn
+        .prepare("SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'rating'")?
+        .exists([])?;
+    if !has_rating {
+        conn.execute_batch(
+            "ALTER TABLE tracks ADD COLUMN rating INTEGER;
+             ALTER TABLE tracks ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;",
+        )?;
+    }
+    Ok(())
+}
+
+fn to_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
@@ -0,0 +1,85 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// 0 дБFS: всё, что выше, при выводе обрежется
+const CEILING: f32 = 1.0;
+const RELEASE_SECS: f32 = 0.2;
+
+// Сколько отсчётов пришлось ограничить с момента запуска
+static CLIPPED: AtomicU64 = AtomicU64::new(0);
+
+pub fn clipped() -> u64 {
+    CLIPPED.load(Ordering::Relaxed)
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// Пиковый лимитер без упреждения: мгновенная атака, плавное восстановление
+pub struct Limiter<S> {
+    input: S,
+    reduction: f32,
+    release: f32,
+}
+
+impl<S> Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S) -> Self {
+        let rate = (input.sample_rate() * input.channels() as u32).max(1) as f32;
+        Self {
+            input,
+            reduction: 1.0,
+            release: (-1.0 / (RELEASE_SECS * rate)).exp(),
+        }
+    }
+}
+
+impl<S> Iterator for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        self.reduction = 1.0 - (1.0 - self.reduction) * self.release;
+
+        let level = sample.abs() * self.reduction;
+        if level > CEILING {
+            self.reduction = CEILING / sample.abs();
+            CLIPPED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Some(sample * self.reduction)
+    }
+}
+
+impl<S> Source for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
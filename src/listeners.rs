@@ -0,0 +1,257 @@
+use crate::dispatch::handle_command;
+use crate::player::MusicPlayer;
+use crate::{events, paths};
+use rodio::Sink;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::net::{Shutdown, TcpListener, ToSocketAddrs};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Сколько ждать запрос от подключившегося клиента
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// Запрос длиннее не читается до конца; с запасом вмещает снимок очереди для restore
+const MAX_REQUEST: usize = 4 << 20;
+
+// Почему команда не выполнена: до плеера не достучаться или он ответил ERR
+#[derive(Debug)]
+pub enum SendError {
+    Unavailable(String),
+    Rejected(String),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendError::Unavailable(e) => write!(
+                f,
+                "cannot reach the player at {}: {}",
+                paths::socket().display(),
+                e
+            ),
+            SendError::Rejected(reason) => f.write_str(reason),
+        }
+    }
+}
+
+pub fn send_command(cmd: &str) -> Result<String, SendError> {
+    send_command_as(cmd, None)
+}
+
+// Ответ — OK или данные команды; ERR превращается в SendError::Rejected
+pub fn send_command_as(cmd: &str, token: Option<&str>) -> Result<String, SendError> {
+    let mut stream = connect(cmd, token).map_err(SendError::Unavailable)?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|e| SendError::Unavailable(e.to_string()))?;
+    match reply.strip_prefix("ERR ") {
+        Some(reason) => Err(SendError::Rejected(reason.trim_end().to_string())),
+        None => Ok(reply),
+    }
+}
+
+// Для subscribe: строки ответа отдаются по мере прихода, пока демон не закроет соединение
+pub fn stream_command_as(
+    cmd: &str,
+    token: Option<&str>,
+    mut on_line: impl FnMut(&str),
+) -> Result<(), SendError> {
+    let stream = connect(cmd, token).map_err(SendError::Unavailable)?;
+    for line in io::BufReader::new(stream).lines() {
+        let line = line.map_err(|e| SendError::Unavailable(e.to_string()))?;
+        if let Some(reason) = line.strip_prefix("ERR ") {
+            return Err(SendError::Rejected(reason.to_string()));
+        }
+        on_line(&line);
+    }
+    Ok(())
+}
+
+fn connect(cmd: &str, token: Option<&str>) -> Result<UnixStream, String> {
+    let mut stream = UnixStream::connect(paths::socket()).map_err(|e| e.to_string())?;
+    let request = match token {
+        Some(token) => format!("auth {}\n{}", token, cmd),
+        None => cmd.to_string(),
+    };
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+    stream
+        .shutdown(Shutdown::Write)
+        .map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+pub fn command_server(
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+    users: HashMap<String, String>,
+) {
+    let listener = UnixListener::bind(paths::socket()).unwrap();
+    let users = Arc::new(users);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+                    eprintln!("Connection error: {}", e);
+                    continue;
+                }
+                let (player, sink, users) = (player.clone(), sink.clone(), users.clone());
+                thread::spawn(move || serve_request(stream, &player, &sink, &users, false));
+            }
+            Err(e) => eprintln!("Connection error: {}", e),
+        }
+    }
+}
+
+// Тот же протокол по TCP; при заданных пользователях требуется токен. Без пользователей
+// команды принимаются от кого угодно, поэтому слушать можно только локальный адрес
+pub fn tcp_server(
+    addr: String,
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+    users: HashMap<String, String>,
+) {
+    if users.is_empty() && !is_loopback(&addr) {
+        eprintln!(
+            "Refusing to listen on {} without users: set tokens in users or use 127.0.0.1",
+            addr
+        );
+        return;
+    }
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", addr, e);
+            return;
+        }
+    };
+
+    let require_auth = !users.is_empty();
+    let users = Arc::new(users);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                // Молчащий клиент не держит поток вечно
+                if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+                    eprintln!("Connection error: {}", e);
+                    continue;
+                }
+                let (player, sink, users) = (player.clone(), sink.clone(), users.clone());
+                thread::spawn(move || serve_request(stream, &player, &sink, &users, require_auth));
+            }
+            Err(e) => eprintln!("Connection error: {}", e),
+        }
+    }
+}
+
+// Все адреса, в которые разрешается имя, локальные; неразрешимое имя не локальное
+fn is_loopback(addr: &str) -> bool {
+    addr.to_socket_addrs()
+        .is_ok_and(|mut addrs| addrs.all(|addr| addr.ip().is_loopback()))
+}
+
+fn serve_request(
+    mut stream: impl Read + events::Connection + 'static,
+    player: &Arc<Mutex<MusicPlayer>>,
+    sink: &Arc<Mutex<Sink>>,
+    users: &HashMap<String, String>,
+    require_auth: bool,
+) {
+    let mut request = String::new();
+    let limit = MAX_REQUEST as u64 + 1;
+    if (&mut stream)
+        .take(limit)
+        .read_to_string(&mut request)
+        .is_err()
+    {
+        return;
+    }
+    if request.len() > MAX_REQUEST {
+        let reply = format!("ERR request is longer than {} bytes\n", MAX_REQUEST);
+        let _ = stream.write_all(reply.as_bytes());
+        return;
+    }
+
+    let reply = match authenticate(&request, users) {
+        Ok((None, _)) if require_auth => "ERR authentication required\n".to_string(),
+        Ok((user, cmd)) => {
+            // Подписка забирает соединение: события пишутся в него, пока клиент не уйдёт
+            let (name, arg) = cmd.trim().split_once(' ').unwrap_or((cmd.trim(), ""));
+            if name == "subscribe" {
+                match events::parse_topics(arg.trim()) {
+                    Ok(topics) => return events::subscribe(Box::new(stream), topics),
+                    Err(e) => format!("ERR {}\n", e),
+                }
+            } else {
+                handle_command(player, sink, cmd, user.as_deref())
+            }
+        }
+        Err(e) => format!("ERR {}\n", e),
+    };
+    // Команда без данных в ответе подтверждается, чтобы клиент отличал успех от обрыва
+    let reply = if reply.is_empty() {
+        "OK\n".to_string()
+    } else {
+        reply
+    };
+    let _ = stream.write_all(reply.as_bytes());
+}
+
+// Необязательная первая строка запроса "auth <token>" определяет пользователя
+fn authenticate<'a>(
+    request: &'a str,
+    users: &HashMap<String, String>,
+) -> Result<(Option<String>, &'a str), String> {
+    match request.split_once('\n') {
+        Some((first, cmd)) if first.starts_with("auth ") => {
+            let token = first["auth ".len()..].trim();
+            users
+                .get(token)
+                .map(|user| (Some(user.clone()), cmd))
+                .ok_or_else(|| "unknown token".to_string())
+        }
+        _ => Ok((None, request)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_local_addresses_count_as_loopback() {
+        assert!(is_loopback("127.0.0.1:6601"));
+        assert!(is_loopback("[::1]:6601"));
+        assert!(is_loopback("localhost:6601"));
+        assert!(!is_loopback("0.0.0.0:6601"));
+        assert!(!is_loopback("192.168.1.5:6601"));
+        assert!(!is_loopback("not an address"));
+    }
+
+    #[test]
+    fn auth_line_names_the_user() {
+        let users = HashMap::from([("s3cret".to_string(), "alice".to_string())]);
+        assert_eq!(
+            authenticate("auth s3cret\nnext", &users),
+            Ok((Some("alice".to_string()), "next"))
+        );
+        assert_eq!(
+            authenticate("auth  s3cret \nvolume 40", &users),
+            Ok((Some("alice".to_string()), "volume 40"))
+        );
+        assert_eq!(authenticate("status", &users), Ok((None, "status")));
+        assert!(authenticate("auth guess\nquit --force", &users).is_err());
+        assert!(authenticate("auth \nquit", &users).is_err());
+        // Без перевода строки это команда, а не строка авторизации
+        assert_eq!(
+            authenticate("auth s3cret", &users),
+            Ok((None, "auth s3cret"))
+        );
+    }
+}
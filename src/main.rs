@@ -1,21 +1,13 @@
-use clap::Parser;
-use libc;
-use rdev::{listen, Event as KbdEvent, EventType, Key, ListenError};
-use rodio::{Decoder, OutputStream, Sink};
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::io::{self, Read, Write};
-use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::{Path, PathBuf};
-use std::process;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
-
-const SOCKET_PATH: &str = "/tmp/music_player.sock";
-const PID_FILE: &str = "/tmp/music_player.pid";
-const DEFAULT_CONFIG: &str = "music_player.json";
+use clap::{Parser, Subcommand, ValueEnum};
+use nsmp::{clock, keylog, paths, report, units, update, Config, SendError};
+use std::path::{self, PathBuf};
+use std::process::ExitCode;
+
+// Коды выхода клиентских команд; 2 — ошибка в аргументах, её возвращает clap
+const EXIT_REJECTED: u8 = 1;
+const EXIT_UNAVAILABLE: u8 = 3;
+// Демон ждёт подтверждения: команда не выполнена, токен в ответе
+const EXIT_UNCONFIRMED: u8 = 4;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -23,429 +15,459 @@ struct Args {
     #[arg(short, long)]
     path: Option<PathBuf>,
 
-    #[arg(short = 'm', long)]
-    cmd: Option<String>,
-
-    #[arg(short, long)]
+    // По умолчанию $XDG_CONFIG_HOME/nsmp/config.json
+    #[arg(short, long, env = "NSMP_CONFIG")]
     config: Option<PathBuf>,
 
+    // По умолчанию $XDG_RUNTIME_DIR/nsmp/socket
+    #[arg(long, env = "NSMP_SOCKET")]
+    socket: Option<PathBuf>,
+
+    #[arg(long, env = "NSMP_PID_FILE")]
+    pid_file: Option<PathBuf>,
+
     #[arg(short, long, default_value_t = false)]
     daemon: bool,
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Config {
-    hotkeys: HashMap<String, String>,
-    music_dir: Option<String>,
-    volume: f32,
-}
+    #[arg(long, env = "NSMP_TOKEN")]
+    token: Option<String>,
 
-impl Default for Config {
-    fn default() -> Self {
-        let mut hotkeys = HashMap::new();
-        hotkeys.insert("next".to_string(), "XF86AudioNext".to_string());
-        hotkeys.insert("prev".to_string(), "XF86AudioPrev".to_string());
-        hotkeys.insert("pause".to_string(), "XF86AudioPlay".to_string());
-        hotkeys.insert("stop".to_string(), "XF86AudioStop".to_string());
-
-        Config {
-            hotkeys,
-            music_dir: None,
-            volume: 0.7,
-        }
-    }
-}
+    // Записать нажатия клавиш в файл для воспроизведения
+    #[arg(long, value_name = "FILE")]
+    record_keys: Option<PathBuf>,
 
-#[derive(Debug, Default)]
-struct ModifierState {
-    shift: bool,
-    ctrl: bool,
-    alt: bool,
-    meta: bool,
-}
+    // Прогнать записанные нажатия через горячие клавиши из конфига и напечатать команды
+    #[arg(long, value_name = "FILE", conflicts_with = "record_keys")]
+    replay_keys: Option<PathBuf>,
 
-impl ModifierState {
-    fn update(&mut self, key: &Key, is_press: bool) {
-        match key {
-            Key::ShiftLeft | Key::ShiftRight => self.shift = is_press,
-            Key::ControlLeft | Key::ControlRight => self.ctrl = is_press,
-            Key::Alt | Key::AltGr => self.alt = is_press,
-            Key::MetaLeft | Key::MetaRight => self.meta = is_press,
-            _ => {}
-        }
-    }
+    // Таймеры идут в SPEED раз быстрее; "clock advance" переводит их вперёд
+    #[arg(long, value_name = "SPEED")]
+    simulate_time: Option<f64>,
 
-    fn matches(&self, required_mods: &HashSet<&str>) -> bool {
-        (required_mods.contains("shift") == self.shift)
-            && (required_mods.contains("ctrl") == self.ctrl)
-            && (required_mods.contains("alt") == self.alt)
-            && (required_mods.contains("meta") == self.meta)
-    }
+    // Без подкоманды запускается сам плеер
+    #[command(subcommand)]
+    command: Option<Cmd>,
 }
 
-fn main() -> Result<(), String> {
-    let args = Args::parse();
-
-    if let Some(cmd) = args.cmd {
-        return send_command(&cmd);
-    }
-
-    let config_path = args.config.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG));
-    let mut config = load_config(&config_path)?;
-
-    if let Some(path) = args.path {
-        config.music_dir = Some(path.to_string_lossy().into_owned());
-        save_config(&config_path, &config)?;
-    }
-
-    let music_dir = match config.music_dir {
-        Some(ref dir) => PathBuf::from(dir),
-        None => PathBuf::from("."),
-    };
-
-    if args.daemon {
-        daemonize()?;
-    }
-
-    let (_stream, handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
-    let sink = Arc::new(Mutex::new(
-        Sink::try_new(&handle).map_err(|e| e.to_string())?,
-    ));
-    sink.lock().unwrap().set_volume(config.volume);
-
-    let mut player = MusicPlayer::new(music_dir).map_err(|e| e.to_string())?;
+// Команды запущенному плееру; всё, чего здесь нет, уходит в сокет как есть
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Resume playback or build the queue from a filter
+    Play {
+        /// Tracks within a BPM range, e.g. 120-130
+        #[arg(long, group = "filter")]
+        bpm: Option<String>,
+        /// Tracks with the given mood
+        #[arg(long, group = "filter")]
+        mood: Option<String>,
+        /// Tracks similar to the current one
+        #[arg(long, group = "filter", value_name = "COUNT")]
+        similar: Option<usize>,
+        /// Background noise instead of music
+        #[arg(long, group = "filter")]
+        noise: Option<Noise>,
+        /// The favorite tracks
+        #[arg(long, group = "filter")]
+        favorites: bool,
+    },
+    /// Pause playback
+    Pause,
+    /// Stop playback; the daemon keeps running (use quit to exit it)
+    Stop,
+    /// Skip to the next track
+    Next,
+    /// Go back to the previous track
+    Prev,
+    /// Show the current track and player state
+    Status,
+    /// Show library statistics
+    Stats,
+    /// List the last played tracks, newest first
+    History {
+        /// How many plays to list
+        #[arg(default_value_t = 20)]
+        count: usize,
+        /// List recent commands sent to the daemon instead
+        #[arg(long, conflicts_with = "count")]
+        commands: bool,
+    },
+    /// List the most played tracks with their play counts
+    MostPlayed {
+        /// How many tracks to list
+        #[arg(default_value_t = 20)]
+        count: usize,
+    },
+    /// Show the volume, set it (40, 40%) or change it (+5, -10%)
+    Volume {
+        #[arg(allow_negative_numbers = true, value_parser = volume_arg)]
+        volume: Option<String>,
+    },
+    /// Seek to a position (1:30) or by an offset (+10s, -1m)
+    Seek {
+        #[arg(allow_negative_numbers = true, allow_hyphen_values = true, value_parser = seek_arg)]
+        position: String,
+    },
+    /// Pause after a duration (30, 1h, 45m); off cancels, no argument shows the time left
+    Sleep {
+        #[arg(value_parser = sleep_arg)]
+        after: Option<String>,
+        /// Quit instead of pausing
+        #[arg(long)]
+        quit: bool,
+    },
+    /// Turn shuffle on or off
+    Shuffle { mode: Option<ShuffleMode> },
+    /// Set the repeat mode
+    Repeat { mode: RepeatMode },
+    /// Rate the current track from 1 to 5; no argument shows the rating
+    Rate {
+        #[arg(value_parser = clap::value_parser!(u8).range(1..=5))]
+        rating: Option<u8>,
+        /// Remove the rating
+        #[arg(long, conflicts_with = "rating")]
+        clear: bool,
+    },
+    /// Mark or unmark the current track as a favorite
+    Favorite { state: Option<FavoriteState> },
+    /// Search the library
+    Search {
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+    /// Show or edit the queue
+    Queue {
+        #[command(subcommand)]
+        action: QueueCmd,
+    },
+    /// Print player events as JSON lines until interrupted
+    Subscribe {
+        /// track, pause, volume, queue or error; all by default
+        topics: Vec<String>,
+    },
+    /// Save state and exit the daemon
+    Quit {
+        /// Skip the confirmation
+        #[arg(long)]
+        force: bool,
+        /// Token from the daemon's CONFIRM reply
+        #[arg(long)]
+        confirm: Option<String>,
+    },
+    /// Bundle version, audio setup, scrubbed config, recent log and errors for a bug report
+    Report {
+        /// Archive path; nsmp-report-<time>.tar.gz in the current directory by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Full-screen terminal interface to the running player: queue, progress and volume
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Update a release binary to the latest GitHub release, verifying its checksum and signature
+    SelfUpdate {
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+        /// minisign public key of the releases, if the binary was built without one
+        #[arg(long, env = "NSMP_UPDATE_KEY")]
+        public_key: Option<String>,
+        /// Install without a signing key, trusting only the checksum from the same server
+        #[arg(long)]
+        insecure: bool,
+    },
+    #[command(external_subcommand)]
+    Other(Vec<String>),
+}
 
-    let _ = fs::remove_file(SOCKET_PATH);
-    save_pid()?;
+#[derive(Subcommand, Debug)]
+enum QueueCmd {
+    /// Print the queue
+    List,
+    /// Append a file or directory
+    Add { path: PathBuf },
+    /// Remove the entry at INDEX
+    Remove { index: usize },
+    /// Move an entry to another position
+    Move { from: usize, to: usize },
+    /// Empty the queue
+    Clear {
+        /// Skip the confirmation
+        #[arg(long)]
+        force: bool,
+        /// Token from the daemon's CONFIRM reply
+        #[arg(long)]
+        confirm: Option<String>,
+    },
+    /// Print the edits made to the queue after a revision
+    Changes {
+        /// Revision the client already has, from queue list or a queue event
+        #[arg(long)]
+        since: u64,
+    },
+}
 
-    let player_clone = player.clone();
-    let sink_clone = Arc::clone(&sink);
-    thread::spawn(move || {
-        command_server(player_clone, sink_clone);
-    });
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Noise {
+    White,
+    Pink,
+    Brown,
+    Off,
+}
 
-    let config_clone = config.clone();
-    thread::spawn(move || {
-        if let Err(e) = hotkey_listener(config_clone) {
-            eprintln!("Hotkey listener error: {:?}", e);
-        }
-    });
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FavoriteState {
+    On,
+    Off,
+    Toggle,
+}
 
-    player.main_loop(Arc::clone(&sink));
-    Ok(())
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ShuffleMode {
+    On,
+    Off,
+    Toggle,
+    /// Whole folders in order, the next folder at random
+    Folders,
 }
 
-fn daemonize() -> Result<(), String> {
-    unsafe {
-        match libc::fork() {
-            -1 => Err("Failed to fork".to_string()),
-            0 => Ok(()),
-            _ => process::exit(0),
-        }
-    }
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RepeatMode {
+    Off,
+    One,
+    All,
 }
 
-fn save_pid() -> Result<(), String> {
-    fs::write(PID_FILE, process::id().to_string()).map_err(|e| e.to_string())
+// Аргументы проверяются теми же разборщиками, что и в плеере, и уходят как есть
+fn volume_arg(text: &str) -> Result<String, String> {
+    units::parse_volume(text).map(|_| text.to_string())
 }
 
-fn send_command(cmd: &str) -> Result<(), String> {
-    let mut stream = UnixStream::connect(SOCKET_PATH).map_err(|e| e.to_string())?;
-    stream
-        .write_all(cmd.as_bytes())
-        .map_err(|e| e.to_string())?;
-    Ok(())
+fn seek_arg(text: &str) -> Result<String, String> {
+    units::parse_seek(text).map(|_| text.to_string())
 }
 
-fn load_config(path: &Path) -> Result<Config, String> {
-    if path.exists() {
-        let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
-        serde_json::from_str(&data).map_err(|e| e.to_string())
-    } else {
-        let config = Config::default();
-        save_config(path, &config)?;
-        Ok(config)
+fn sleep_arg(text: &str) -> Result<String, String> {
+    match text {
+        "off" => Ok(text.to_string()),
+        _ => units::parse_duration(text, units::MINUTE).map(|_| text.to_string()),
     }
 }
 
-fn save_config(path: &Path, config: &Config) -> Result<(), String> {
-    let data = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
-    fs::write(path, data).map_err(|e| e.to_string())
+// Имя значения так, как его пишут в командной строке и в протоколе
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
 }
 
-fn hotkey_listener(config: Config) -> Result<(), ListenError> {
-    let mut pressed_keys = HashSet::new();
-    let mut modifiers = ModifierState::default();
-
-    let callback = move |event: KbdEvent| match event.event_type {
-        EventType::KeyPress(key) => {
-            pressed_keys.insert(key.clone());
-            modifiers.update(&key, true);
-
-            for (cmd, key_combination) in &config.hotkeys {
-                if check_hotkey(&pressed_keys, &modifiers, key_combination) {
-                    let _ = send_command(cmd);
-                }
+impl Cmd {
+    // Строка протокола сокета
+    fn request(self) -> Result<String, String> {
+        let request = match self {
+            Cmd::Play {
+                bpm: Some(range), ..
+            } => format!("play --bpm {}", range),
+            Cmd::Play {
+                mood: Some(mood), ..
+            } => format!("play --mood {}", mood),
+            Cmd::Play {
+                similar: Some(count),
+                ..
+            } => format!("play --similar {}", count),
+            Cmd::Play {
+                noise: Some(noise), ..
+            } => format!("play noise:{}", value_name(noise)),
+            Cmd::Play {
+                favorites: true, ..
+            } => "play favorites".to_string(),
+            Cmd::Play { .. } => "play".to_string(),
+            Cmd::Pause => "pause".to_string(),
+            Cmd::Stop => "stop".to_string(),
+            Cmd::Next => "next".to_string(),
+            Cmd::Prev => "prev".to_string(),
+            Cmd::Status => "status".to_string(),
+            Cmd::Stats => "stats".to_string(),
+            Cmd::History { commands: true, .. } => "history commands".to_string(),
+            Cmd::History { count, .. } => format!("history {}", count),
+            Cmd::MostPlayed { count } => format!("most_played {}", count),
+            Cmd::Volume { volume } => format!("volume {}", volume.unwrap_or_default()),
+            Cmd::Seek { position } => format!("seek {}", position),
+            Cmd::Sleep {
+                after: Some(after),
+                quit: true,
+            } => format!("sleep {} quit", after),
+            Cmd::Sleep { after, .. } => format!("sleep {}", after.unwrap_or_default()),
+            Cmd::Rate { clear: true, .. } => "rate clear".to_string(),
+            Cmd::Rate { rating, .. } => format!(
+                "rate {}",
+                rating.map(|rating| rating.to_string()).unwrap_or_default()
+            ),
+            Cmd::Favorite { state } => {
+                format!(
+                    "favorite {}",
+                    value_name(state.unwrap_or(FavoriteState::Toggle))
+                )
             }
-        }
-        EventType::KeyRelease(key) => {
-            pressed_keys.remove(&key);
-            modifiers.update(&key, false);
-        }
-        _ => {}
-    };
-
-    listen(callback)
-}
-
-fn check_hotkey(pressed_keys: &HashSet<Key>, modifiers: &ModifierState, hotkey_str: &str) -> bool {
-    let parts: Vec<&str> = hotkey_str.split('+').collect();
-    let mut required_mods = HashSet::new();
-    let mut required_key = None;
-
-    for part in parts {
-        match part.to_lowercase().as_str() {
-            "shift" => required_mods.insert("shift"),
-            "ctrl" => required_mods.insert("ctrl"),
-            "alt" => required_mods.insert("alt"),
-            "meta" | "super" | "win" => required_mods.insert("meta"),
-            key_str => {
-                required_key = str_to_key(key_str);
-                false
+            Cmd::Shuffle { mode } => {
+                format!(
+                    "shuffle {}",
+                    value_name(mode.unwrap_or(ShuffleMode::Toggle))
+                )
             }
+            Cmd::Repeat { mode } => format!("repeat_{}", value_name(mode)),
+            Cmd::Search { query } => format!("search {}", query.join(" ")),
+            Cmd::Queue { action } => match action {
+                QueueCmd::List => "queue list".to_string(),
+                // Адрес потока уходит как есть
+                QueueCmd::Add { path } if path.to_str().is_some_and(|p| p.contains("://")) => {
+                    format!("add {}", path.display())
+                }
+                // Демон работает из "/", относительный путь считается отсюда
+                QueueCmd::Add { path } => {
+                    let path =
+                        path::absolute(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                    format!("add {}", path.display())
+                }
+                QueueCmd::Remove { index } => format!("remove {}", index),
+                QueueCmd::Move { from, to } => format!("move {} {}", from, to),
+                QueueCmd::Clear { force, confirm } => confirmed("queue clear", force, confirm),
+                QueueCmd::Changes { since } => format!("queue changes --since {}", since),
+            },
+            Cmd::Subscribe { topics } => format!("subscribe {}", topics.join(",")),
+            Cmd::Quit { force, confirm } => confirmed("quit", force, confirm),
+            Cmd::Report { .. } => return Err("report runs without the player".to_string()),
+            #[cfg(feature = "tui")]
+            Cmd::Tui => return Err("tui is an interactive client".to_string()),
+            Cmd::SelfUpdate { .. } => return Err("self-update runs without the player".to_string()),
+            Cmd::Other(words) => words.join(" "),
         };
+        Ok(request.trim_end().to_string())
     }
-
-    modifiers.matches(&required_mods) && required_key.map_or(false, |k| pressed_keys.contains(&k))
 }
 
-fn str_to_key(key_str: &str) -> Option<Key> {
-    match key_str.to_lowercase().as_str() {
-        // Медиа-клавиши
-        "nextsong" | "audionext" => Some(Key::Unknown(0x1008ff17)),
-        "previoussong" | "audioprev" => Some(Key::Unknown(0x1008ff16)),
-        "playpause" | "audioplay" => Some(Key::Unknown(0x1008ff14)),
-        "stopcd" | "audiostop" => Some(Key::Unknown(0x1008ff15)),
-        "volumedown" => Some(Key::Unknown(0x1008ff11)),
-        "volumeup" => Some(Key::Unknown(0x1008ff13)),
-        "volumemute" => Some(Key::Unknown(0x1008ff12)),
-
-        // Буквы
-        "a" => Some(Key::KeyA),
-        "b" => Some(Key::KeyB),
-        "c" => Some(Key::KeyC),
-        "d" => Some(Key::KeyD),
-        "e" => Some(Key::KeyE),
-        "f" => Some(Key::KeyF),
-        "g" => Some(Key::KeyG),
-        "h" => Some(Key::KeyH),
-        "i" => Some(Key::KeyI),
-        "j" => Some(Key::KeyJ),
-        "k" => Some(Key::KeyK),
-        "l" => Some(Key::KeyL),
-        "m" => Some(Key::KeyM),
-        "n" => Some(Key::KeyN),
-        "o" => Some(Key::KeyO),
-        "p" => Some(Key::KeyP),
-        "q" => Some(Key::KeyQ),
-        "r" => Some(Key::KeyR),
-        "s" => Some(Key::KeyS),
-        "t" => Some(Key::KeyT),
-        "u" => Some(Key::KeyU),
-        "v" => Some(Key::KeyV),
-        "w" => Some(Key::KeyW),
-        "x" => Some(Key::KeyX),
-        "y" => Some(Key::KeyY),
-        "z" => Some(Key::KeyZ),
-
-        // Цифры
-        "0" => Some(Key::Num0),
-        "1" => Some(Key::Num1),
-        "2" => Some(Key::Num2),
-        "3" => Some(Key::Num3),
-        "4" => Some(Key::Num4),
-        "5" => Some(Key::Num5),
-        "6" => Some(Key::Num6),
-        "7" => Some(Key::Num7),
-        "8" => Some(Key::Num8),
-        "9" => Some(Key::Num9),
-
-        // Функциональные клавиши
-        "f1" => Some(Key::F1),
-        "f2" => Some(Key::F2),
-        "f3" => Some(Key::F3),
-        "f4" => Some(Key::F4),
-        "f5" => Some(Key::F5),
-        "f6" => Some(Key::F6),
-        "f7" => Some(Key::F7),
-        "f8" => Some(Key::F8),
-        "f9" => Some(Key::F9),
-        "f10" => Some(Key::F10),
-        "f11" => Some(Key::F11),
-        "f12" => Some(Key::F12),
-
-        // Специальные клавиши
-        "space" => Some(Key::Space),
-        "enter" => Some(Key::Return),
-        "tab" => Some(Key::Tab),
-        "backspace" => Some(Key::Backspace),
-        "escape" => Some(Key::Escape),
-        "insert" => Some(Key::Insert),
-        "delete" => Some(Key::Delete),
-        "home" => Some(Key::Home),
-        "end" => Some(Key::End),
-        "pageup" => Some(Key::PageUp),
-        "pagedown" => Some(Key::PageDown),
-        "up" => Some(Key::UpArrow),
-        "down" => Some(Key::DownArrow),
-        "left" => Some(Key::LeftArrow),
-        "right" => Some(Key::RightArrow),
-
-        // Модификаторы
-        "shift" => Some(Key::ShiftLeft),
-        "ctrl" => Some(Key::ControlLeft),
-        "alt" => Some(Key::Alt),
-        "meta" | "super" | "win" => Some(Key::MetaLeft),
-
-        _ => None,
+// Флаги подтверждения дописываются в конец, где их ищет демон
+fn confirmed(command: &str, force: bool, confirm: Option<String>) -> String {
+    let mut request = command.to_string();
+    if force {
+        request.push_str(" --force");
     }
+    if let Some(token) = confirm {
+        request.push_str(" --confirm ");
+        request.push_str(&token);
+    }
+    request
 }
 
-fn command_server(mut player: MusicPlayer, sink: Arc<Mutex<Sink>>) {
-    let listener = UnixListener::bind(SOCKET_PATH).unwrap();
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                let mut cmd = String::new();
-                if stream.read_to_string(&mut cmd).is_ok() {
-                    match cmd.as_str() {
-                        "next" => {
-                            let sink = sink.lock().unwrap();
-                            let _ = player.next(&sink);
-                        }
-                        "prev" => {
-                            let sink = sink.lock().unwrap();
-                            let _ = player.prev(&sink);
-                        }
-                        "pause" => {
-                            let sink = sink.lock().unwrap();
-                            if sink.is_paused() {
-                                sink.play();
-                            } else {
-                                sink.pause();
-                            }
-                        }
-                        "stop" => process::exit(0),
-                        "volume_up" => {
-                            let sink = sink.lock().unwrap();
-                            let vol = (sink.volume() + 0.1).min(1.0);
-                            sink.set_volume(vol);
-                        }
-                        "volume_down" => {
-                            let sink = sink.lock().unwrap();
-                            let vol = (sink.volume() - 0.1).max(0.0);
-                            sink.set_volume(vol);
-                        }
-                        _ => {}
-                    }
-                }
+// Данные из ответа печатаются в stdout, ERR — в stderr с ненулевым кодом выхода
+fn send(command: Cmd, token: Option<&str>) -> ExitCode {
+    let request = match command.request() {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let sent = if request.split_whitespace().next() == Some("subscribe") {
+        nsmp::stream_command_as(&request, token, |line| println!("{}", line)).map(|()| false)
+    } else {
+        nsmp::send_command_as(&request, token).map(|reply| {
+            let reply = reply.trim_end();
+            if !reply.is_empty() && reply != "OK" {
+                println!("{}", reply);
             }
-            Err(e) => eprintln!("Connection error: {}", e),
+            reply.starts_with("CONFIRM ")
+        })
+    };
+    match sent {
+        Ok(true) => ExitCode::from(EXIT_UNCONFIRMED),
+        Ok(false) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(match e {
+                SendError::Rejected(_) => EXIT_REJECTED,
+                SendError::Unavailable(_) => EXIT_UNAVAILABLE,
+            })
         }
     }
 }
 
-#[derive(Clone)]
-struct MusicPlayer {
-    files: Vec<PathBuf>,
-    current_index: usize,
-}
-
-impl MusicPlayer {
-    fn new(path: PathBuf) -> Result<Self, io::Error> {
-        if !path.is_dir() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Path is not a directory",
-            ));
+fn main() -> ExitCode {
+    let mut args = Args::parse();
+    paths::init(args.socket.clone(), args.pid_file.clone());
+
+    match args.command.take() {
+        Some(Cmd::Report { output }) => {
+            let config_path = args.config.unwrap_or_else(paths::config_file);
+            return match report::create(&config_path, output) {
+                Ok(path) => {
+                    println!("Report written to {}", path.display());
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            };
         }
-
-        let supported = ["mp3", "wav", "flac", "ogg", "aac", "m4a"];
-        let mut files = Vec::new();
-
-        for entry in fs::read_dir(path)? {
-            let path = entry?.path();
-            if path.is_file() && has_supported_extension(&path, &supported) {
-                files.push(path);
-            }
+        #[cfg(feature = "tui")]
+        Some(Cmd::Tui) => {
+            return match nsmp::tui::run(args.token) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            };
         }
-
-        if files.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "No supported audio files found",
-            ));
+        Some(Cmd::SelfUpdate {
+            check,
+            public_key,
+            insecure,
+        }) => {
+            return match update::self_update(check, public_key, insecure) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            };
         }
-
-        Ok(Self {
-            files,
-            current_index: 0,
-        })
+        Some(command) => return send(command, args.token.as_deref()),
+        None => {}
     }
-
-    fn main_loop(&mut self, sink: Arc<Mutex<Sink>>) {
-        {
-            let sink = sink.lock().unwrap();
-            self.play(&sink).unwrap();
-        }
-
-        loop {
-            {
-                let sink = sink.lock().unwrap();
-                if sink.empty() {
-                    self.next(&sink).unwrap();
-                }
-            }
-            thread::sleep(Duration::from_millis(100));
+    match start(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
         }
     }
+}
 
-    fn play(&self, sink: &Sink) -> Result<(), io::Error> {
-        sink.stop();
-        let file = fs::File::open(&self.files[self.current_index])?;
-        let source = Decoder::new(file).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        sink.append(source);
-        println!("Now playing: {}", self.current_track());
-        Ok(())
+fn start(args: Args) -> Result<(), String> {
+    if let Some(speed) = args.simulate_time {
+        clock::simulate(speed)?;
+        println!("Simulating time at {}x", speed);
     }
 
-    fn next(&mut self, sink: &Sink) -> Result<(), io::Error> {
-        self.current_index = (self.current_index + 1) % self.files.len();
-        self.play(sink)
+    let config_path = args.config.unwrap_or_else(paths::config_file);
+    let mut config: Config = nsmp::load_config(&config_path)?;
+
+    if let Some(path) = args.record_keys {
+        return keylog::record(&path);
+    }
+    if let Some(path) = args.replay_keys {
+        return nsmp::replay_hotkeys(&config, &path);
     }
 
-    fn prev(&mut self, sink: &Sink) -> Result<(), io::Error> {
-        self.current_index = if self.current_index == 0 {
-            self.files.len() - 1
-        } else {
-            self.current_index - 1
-        };
-        self.play(sink)
+    if let Some(path) = args.path {
+        config.set_music_dir(&path);
+        nsmp::save_config(&config_path, &config)?;
     }
 
-    fn current_track(&self) -> String {
-        self.files[self.current_index]
-            .file_name()
-            .unwrap()
-            .to_string_lossy()
-            .into_owned()
+    if args.daemon {
+        config.daemonize()?;
     }
-}
 
-fn has_supported_extension(path: &Path, extensions: &[&str]) -> bool {
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| extensions.contains(&ext.to_lowercase().as_str()))
-        .unwrap_or(false)
+    nsmp::run(config)
 }
@@ -0,0 +1,431 @@
+use crate::plugin::Library;
+use rodio::source::SeekError;
+use rodio::Source;
+use std::ffi::{c_char, c_double, c_int, c_void, CString};
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// libfluidsynth загружается при первом MIDI-файле, как libopenmpt для модулей
+const LIBRARIES: [&str; 2] = ["libfluidsynth.so.3", "libfluidsynth.so.2"];
+const RATE: u32 = 48000;
+const CHUNK_FRAMES: usize = 1024;
+// Сколько доигрывать после последнего события, чтобы не обрезать затухание нот
+const TAIL: Duration = Duration::from_secs(2);
+const PLAYER_PLAYING: c_int = 1;
+// Темп по умолчанию до первого события Set Tempo, мкс на четверть
+const DEFAULT_TEMPO: u32 = 500_000;
+
+const EXTENSIONS: [&str; 4] = ["mid", "midi", "kar", "rmi"];
+
+pub fn is_midi(ext: &str) -> bool {
+    EXTENSIONS.contains(&ext)
+}
+
+pub fn extensions() -> &'static [&'static str] {
+    &EXTENSIONS
+}
+
+struct Api {
+    new_settings: unsafe extern "C" fn() -> *mut c_void,
+    delete_settings: unsafe extern "C" fn(*mut c_void),
+    settings_setnum: unsafe extern "C" fn(*mut c_void, *const c_char, c_double) -> c_int,
+    settings_setstr: unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char) -> c_int,
+    new_synth: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+    delete_synth: unsafe extern "C" fn(*mut c_void),
+    sfload: unsafe extern "C" fn(*mut c_void, *const c_char, c_int) -> c_int,
+    system_reset: unsafe extern "C" fn(*mut c_void) -> c_int,
+    write_float: unsafe extern "C" fn(
+        *mut c_void,
+        c_int,
+        *mut c_void,
+        c_int,
+        c_int,
+        *mut c_void,
+        c_int,
+        c_int,
+    ) -> c_int,
+    new_player: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+    delete_player: unsafe extern "C" fn(*mut c_void),
+    player_add_mem: unsafe extern "C" fn(*mut c_void, *const c_void, usize) -> c_int,
+    player_play: unsafe extern "C" fn(*mut c_void) -> c_int,
+    player_stop: unsafe extern "C" fn(*mut c_void) -> c_int,
+    player_status: unsafe extern "C" fn(*mut c_void) -> c_int,
+    player_seek: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+    _library: Library,
+}
+
+impl Api {
+    fn load() -> Result<Self, String> {
+        let mut errors = Vec::new();
+        let library = LIBRARIES
+            .iter()
+            .find_map(|name| {
+                Library::open(Path::new(name))
+                    .map_err(|e| errors.push(e))
+                    .ok()
+            })
+            .ok_or_else(|| errors.join("; "))?;
+        unsafe {
+            Ok(Self {
+                new_settings: library.function("new_fluid_settings")?,
+                delete_settings: library.function("delete_fluid_settings")?,
+                settings_setnum: library.function("fluid_settings_setnum")?,
+                settings_setstr: library.function("fluid_settings_setstr")?,
+                new_synth: library.function("new_fluid_synth")?,
+                delete_synth: library.function("delete_fluid_synth")?,
+                sfload: library.function("fluid_synth_sfload")?,
+                system_reset: library.function("fluid_synth_system_reset")?,
+                write_float: library.function("fluid_synth_write_float")?,
+                new_player: library.function("new_fluid_player")?,
+                delete_player: library.function("delete_fluid_player")?,
+                player_add_mem: library.function("fluid_player_add_mem")?,
+                player_play: library.function("fluid_player_play")?,
+                player_stop: library.function("fluid_player_stop")?,
+                player_status: library.function("fluid_player_get_status")?,
+                player_seek: library.function("fluid_player_seek")?,
+                _library: library,
+            })
+        }
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(Api::load).as_ref().map_err(Clone::clone)
+}
+
+// MIDI попадает в библиотеку, только если есть и fluidsynth, и SoundFont
+pub fn available() -> bool {
+    api().is_ok()
+}
+
+// Синтезатор с загруженным SoundFont и проигрывателем одного файла
+pub struct MidiSource {
+    api: &'static Api,
+    settings: *mut c_void,
+    synth: *mut c_void,
+    player: *mut c_void,
+    data: Vec<u8>,
+    tempo: TempoMap,
+    samples: Vec<f32>,
+    position: usize,
+    // Кадров тишины, оставшихся после конца файла
+    tail: usize,
+}
+
+// Синтезатором пользуется один поток за раз
+unsafe impl Send for MidiSource {}
+
+impl MidiSource {
+    pub fn new(mut reader: impl Read, soundfont: &Path) -> Result<Self, String> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        let tempo = TempoMap::parse(&data)?;
+        let api = api()?;
+        let soundfont =
+            CString::new(soundfont.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+
+        unsafe {
+            let settings = (api.new_settings)();
+            if settings.is_null() {
+                return Err("fluidsynth: failed to create settings".to_string());
+            }
+            let key = |name: &str| CString::new(name).unwrap();
+            (api.settings_setnum)(settings, key("synth.sample-rate").as_ptr(), RATE as f64);
+            // Проигрыватель идёт по числу отрисованных отсчётов, а не по часам
+            (api.settings_setstr)(
+                settings,
+                key("player.timing-source").as_ptr(),
+                key("sample").as_ptr(),
+            );
+            let mut source = Self {
+                api,
+                settings,
+                synth: std::ptr::null_mut(),
+                player: std::ptr::null_mut(),
+                data,
+                tempo,
+                samples: Vec::new(),
+                position: 0,
+                tail: 0,
+            };
+            source.synth = (api.new_synth)(settings);
+            if source.synth.is_null() {
+                return Err("fluidsynth: failed to create synthesizer".to_string());
+            }
+            if (api.sfload)(source.synth, soundfont.as_ptr(), 1) < 0 {
+                return Err(format!(
+                    "fluidsynth: failed to load SoundFont {}",
+                    soundfont.to_string_lossy()
+                ));
+            }
+            source.start(0)?;
+            Ok(source)
+        }
+    }
+
+    // Новый проигрыватель с позиции в тиках
+    fn start(&mut self, tick: u32) -> Result<(), String> {
+        unsafe {
+            if !self.player.is_null() {
+                (self.api.player_stop)(self.player);
+                (self.api.delete_player)(self.player);
+                // Звучащие ноты и контроллеры старой позиции сбрасываются
+                (self.api.system_reset)(self.synth);
+            }
+            self.player = (self.api.new_player)(self.synth);
+            if self.player.is_null() {
+                return Err("fluidsynth: failed to create player".to_string());
+            }
+            let added = (self.api.player_add_mem)(
+                self.player,
+                self.data.as_ptr() as *const c_void,
+                self.data.len(),
+            );
+            if added < 0 || (self.api.player_play)(self.player) < 0 {
+                return Err("fluidsynth: failed to play MIDI file".to_string());
+            }
+            if tick > 0 {
+                (self.api.player_seek)(self.player, tick as c_int);
+            }
+        }
+        self.tail = (TAIL.as_secs_f32() * RATE as f32) as usize;
+        self.samples.clear();
+        self.position = 0;
+        Ok(())
+    }
+
+    fn render(&mut self) -> bool {
+        let playing = unsafe { (self.api.player_status)(self.player) } == PLAYER_PLAYING;
+        let frames = if playing {
+            CHUNK_FRAMES
+        } else {
+            CHUNK_FRAMES.min(self.tail)
+        };
+        if frames == 0 {
+            return false;
+        }
+        if !playing {
+            self.tail -= frames;
+        }
+        self.samples.resize(frames * 2, 0.0);
+        let buffer = self.samples.as_mut_ptr() as *mut c_void;
+        unsafe {
+            (self.api.write_float)(self.synth, frames as c_int, buffer, 0, 2, buffer, 1, 2);
+        }
+        self.position = 0;
+        true
+    }
+}
+
+impl Drop for MidiSource {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.player.is_null() {
+                (self.api.player_stop)(self.player);
+                (self.api.delete_player)(self.player);
+            }
+            if !self.synth.is_null() {
+                (self.api.delete_synth)(self.synth);
+            }
+            (self.api.delete_settings)(self.settings);
+        }
+    }
+}
+
+impl Iterator for MidiSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.samples.len() && !self.render() {
+            return None;
+        }
+        let sample = self.samples[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for MidiSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.tempo.duration())
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let tick = self.tempo.tick_at(pos);
+        self.start(tick)
+            .map_err(|e| SeekError::Other(Box::new(std::io::Error::other(e))))
+    }
+}
+
+// Смены темпа из всех дорожек: по ним тики переводятся во время и обратно
+struct TempoMap {
+    division: u32,
+    // (тик, мкс на четверть) по возрастанию тиков
+    changes: Vec<(u32, u32)>,
+    last_tick: u32,
+}
+
+impl TempoMap {
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        // RIFF-обёртка .rmi: сам SMF лежит в чанке "data"
+        let data = match data.get(..4) {
+            Some(b"RIFF") => data
+                .windows(4)
+                .position(|w| w == b"MThd")
+                .map(|start| &data[start..])
+                .ok_or("RMI without MIDI data")?,
+            _ => data,
+        };
+        if data.get(..4) != Some(b"MThd") || data.len() < 14 {
+            return Err("not a standard MIDI file".to_string());
+        }
+        let division = u16::from_be_bytes([data[12], data[13]]) as u32;
+        if division == 0 || division & 0x8000 != 0 {
+            return Err("SMPTE time division is not supported".to_string());
+        }
+
+        let mut changes = Vec::new();
+        let mut last_tick = 0;
+        let mut offset = 8 + u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        while offset + 8 <= data.len() {
+            let len = u32::from_be_bytes([
+                data[offset + 4],
+                data[offset + 5],
+                data[offset + 6],
+                data[offset + 7],
+            ]) as usize;
+            let body = &data[offset + 8..(offset + 8 + len).min(data.len())];
+            if &data[offset..offset + 4] == b"MTrk" {
+                last_tick = last_tick.max(scan_track(body, &mut changes));
+            }
+            offset += 8 + len;
+        }
+        changes.sort_by_key(|&(tick, _)| tick);
+        Ok(Self {
+            division,
+            changes,
+            last_tick,
+        })
+    }
+
+    // Пары (тик, мкс на тик) отрезков постоянного темпа
+    fn segments(&self) -> impl Iterator<Item = (u32, u32, f64)> + '_ {
+        let mut starts = vec![(0, DEFAULT_TEMPO)];
+        starts.extend(self.changes.iter().copied());
+        let ends: Vec<u32> = starts
+            .iter()
+            .skip(1)
+            .map(|&(tick, _)| tick)
+            .chain([u32::MAX])
+            .collect();
+        starts
+            .into_iter()
+            .zip(ends)
+            .map(move |((start, tempo), end)| (start, end, tempo as f64 / self.division as f64))
+    }
+
+    fn duration(&self) -> Duration {
+        let mut micros = 0.0;
+        for (start, end, per_tick) in self.segments() {
+            if start >= self.last_tick {
+                break;
+            }
+            micros += (end.min(self.last_tick) - start) as f64 * per_tick;
+        }
+        Duration::from_secs_f64(micros / 1e6) + TAIL
+    }
+
+    fn tick_at(&self, pos: Duration) -> u32 {
+        let mut left = pos.as_secs_f64() * 1e6;
+        for (start, end, per_tick) in self.segments() {
+            let span = (end - start) as f64 * per_tick;
+            if left < span {
+                return start + (left / per_tick) as u32;
+            }
+            left -= span;
+        }
+        self.last_tick
+    }
+}
+
+// Тик последнего события дорожки; темпы добавляются в changes
+fn scan_track(body: &[u8], changes: &mut Vec<(u32, u32)>) -> u32 {
+    let mut i = 0;
+    let mut tick: u32 = 0;
+    let mut running = 0u8;
+    while i < body.len() {
+        let Some(delta) = read_var(body, &mut i) else {
+            break;
+        };
+        tick = tick.saturating_add(delta);
+        let Some(&first) = body.get(i) else {
+            break;
+        };
+        let status = if first & 0x80 != 0 {
+            i += 1;
+            first
+        } else {
+            running
+        };
+        match status {
+            0xFF => {
+                let kind = body.get(i).copied().unwrap_or(0);
+                i += 1;
+                let Some(len) = read_var(body, &mut i) else {
+                    break;
+                };
+                let len = len as usize;
+                if kind == 0x51 && len == 3 && i + 3 <= body.len() {
+                    let tempo = u32::from_be_bytes([0, body[i], body[i + 1], body[i + 2]]);
+                    changes.push((tick, tempo.max(1)));
+                }
+                i += len;
+            }
+            0xF0 | 0xF7 => {
+                let Some(len) = read_var(body, &mut i) else {
+                    break;
+                };
+                i += len as usize;
+            }
+            0x80..=0xEF => {
+                running = status;
+                i += if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    1
+                } else {
+                    2
+                };
+            }
+            _ => break,
+        }
+    }
+    tick
+}
+
+// Число переменной длины: по 7 бит, старший бит — продолжение
+fn read_var(body: &[u8], i: &mut usize) -> Option<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let byte = *body.get(*i)?;
+        *i += 1;
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
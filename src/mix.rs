@@ -0,0 +1,82 @@
+use crate::playlist;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Плейлист с долей в миксе; треки берутся из перемешанной колоды без повторов,
+// пока колода не кончится
+struct Source {
+    name: String,
+    weight: f32,
+    tracks: Vec<PathBuf>,
+    bag: Vec<usize>,
+    // Накопленная очередь на выбор: растёт на weight за каждый трек микса
+    credit: f32,
+}
+
+// Несколько плейлистов с весами: "chill:0.7 upbeat:0.3".
+// Источники чередуются так, что доли выдерживаются и на коротком отрезке
+pub struct Mix {
+    sources: Vec<Source>,
+}
+
+impl Mix {
+    // Вес без двоеточия — 1; веса не обязаны давать в сумме единицу
+    pub fn parse(spec: &str, dir: &Path) -> Result<Self, String> {
+        let mut sources = Vec::new();
+        for item in spec.split_whitespace() {
+            let (name, weight) = match item.rsplit_once(':') {
+                Some((name, weight)) => {
+                    let weight = weight
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|w| w.is_finite() && *w > 0.0)
+                        .ok_or_else(|| format!("invalid weight in '{}'", item))?;
+                    (name, weight)
+                }
+                None => (item, 1.0),
+            };
+            let tracks = playlist::find(name, dir).and_then(|path| playlist::load(&path))?;
+            sources.push(Source {
+                name: name.to_string(),
+                weight,
+                tracks,
+                bag: Vec::new(),
+                credit: 0.0,
+            });
+        }
+        if sources.is_empty() {
+            return Err("usage: mix <playlist>[:weight]...".to_string());
+        }
+        Ok(Self { sources })
+    }
+
+    pub fn next_track(&mut self) -> Option<PathBuf> {
+        let total: f32 = self.sources.iter().map(|source| source.weight).sum();
+        for source in &mut self.sources {
+            source.credit += source.weight;
+        }
+        let source = self
+            .sources
+            .iter_mut()
+            .max_by(|a, b| a.credit.total_cmp(&b.credit))?;
+        source.credit -= total;
+        if source.bag.is_empty() {
+            source.bag = (0..source.tracks.len()).collect();
+            fastrand::shuffle(&mut source.bag);
+        }
+        source.bag.pop().map(|i| source.tracks[i].clone())
+    }
+}
+
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: f32 = self.sources.iter().map(|source| source.weight).sum();
+        for (i, source) in self.sources.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}:{:.2}", source.name, source.weight / total)?;
+        }
+        Ok(())
+    }
+}
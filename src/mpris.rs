@@ -0,0 +1,668 @@
+use crate::analysis;
+use crate::plugin::{Control, ControlSurface, TrackMetadata};
+use crate::scan::percent_decode;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use zbus::blocking::connection;
+use zbus::blocking::Connection;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.nsmp";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const TRACK_PREFIX: &str = "/org/nsmp/track/";
+const PLAYLIST_PREFIX: &str = "/org/nsmp/playlist/";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+// Сервис org.mpris.MediaPlayer2.nsmp: корневой объект, Player, TrackList и Playlists.
+// Состояние берётся у плеера командами через сокет
+pub struct Mpris {
+    connection: Option<Connection>,
+    control: Option<Control>,
+    // Очередь, о которой клиенты уже знают; при замене шлётся TrackListReplaced
+    known_tracks: Arc<Mutex<Vec<String>>>,
+}
+
+impl Mpris {
+    pub fn new() -> Self {
+        Self {
+            connection: None,
+            control: None,
+            known_tracks: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl ControlSurface for Mpris {
+    fn name(&self) -> &str {
+        "mpris"
+    }
+
+    fn start(&mut self, control: Control) -> Result<(), String> {
+        let active = Arc::new(Mutex::new(None));
+        let connection = connection::Builder::session()
+            .and_then(|b| b.name(BUS_NAME))
+            .and_then(|b| b.serve_at(OBJECT_PATH, Root::new(control.clone())))
+            .and_then(|b| b.serve_at(OBJECT_PATH, Player::new(control.clone())))
+            .and_then(|b| b.serve_at(OBJECT_PATH, TrackList::new(control.clone())))
+            .and_then(|b| b.serve_at(OBJECT_PATH, Playlists::new(control.clone(), active)))
+            .and_then(|b| b.build())
+            .map_err(|e| e.to_string())?;
+
+        self.connection = Some(connection);
+        self.control = Some(control);
+        Ok(())
+    }
+
+    fn track_changed(&mut self, _track: &TrackMetadata, _position: Duration) {
+        let (Some(connection), Some(control)) = (self.connection.clone(), self.control.clone())
+        else {
+            return;
+        };
+        let known_tracks = Arc::clone(&self.known_tracks);
+
+        // Плеер сейчас заблокирован, очередь запрашивается после выхода из обработчика
+        thread::spawn(move || {
+            let Some(queue) = Queue::fetch(&control) else {
+                return;
+            };
+            player_changed(&connection, &control, Some(&queue));
+
+            let paths: Vec<String> = queue.tracks.iter().map(|t| t.path.clone()).collect();
+            let mut known = known_tracks.lock().unwrap();
+            if *known == paths {
+                return;
+            }
+            *known = paths;
+
+            let body = (queue.track_ids(), track_id(queue.current));
+            if let Err(e) = connection.emit_signal(
+                None::<&str>,
+                OBJECT_PATH,
+                "org.mpris.MediaPlayer2.TrackList",
+                "TrackListReplaced",
+                &body,
+            ) {
+                eprintln!("Failed to emit TrackListReplaced: {}", e);
+            }
+        });
+    }
+
+    fn playback_changed(&mut self, _paused: bool) {
+        self.notify_player();
+    }
+
+    fn volume_changed(&mut self, _volume: f32) {
+        self.notify_player();
+    }
+}
+
+impl Mpris {
+    fn notify_player(&self) {
+        let (Some(connection), Some(control)) = (self.connection.clone(), self.control.clone())
+        else {
+            return;
+        };
+        thread::spawn(move || player_changed(&connection, &control, None));
+    }
+}
+
+// PropertiesChanged для Player по свежему состоянию плеера
+fn player_changed(connection: &Connection, control: &Control, queue: Option<&Queue>) {
+    let Some(status) = Status::fetch(control) else {
+        return;
+    };
+    let mut changed = HashMap::new();
+    changed.insert("PlaybackStatus", owned(status.playback_status()));
+    changed.insert("LoopStatus", owned(status.loop_status()));
+    changed.insert("Shuffle", owned(status.shuffle));
+    changed.insert("Volume", owned(status.volume));
+    if let Some(track) = queue.and_then(|q| q.tracks.get(q.current).map(|t| (q.current, t))) {
+        changed.insert("Metadata", owned(track_metadata(track.0, track.1)));
+    }
+
+    let body = (PLAYER_INTERFACE, changed, Vec::<String>::new());
+    if let Err(e) = connection.emit_signal(
+        None::<&str>,
+        OBJECT_PATH,
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+        &body,
+    ) {
+        eprintln!("Failed to emit PropertiesChanged: {}", e);
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct Status {
+    state: String,
+    index: usize,
+    position: f64,
+    volume: f64,
+    repeat: String,
+    shuffle: bool,
+}
+
+impl Status {
+    fn fetch(control: &Control) -> Option<Self> {
+        let reply = control.request("status").ok()?;
+        serde_json::from_str(&reply).ok()
+    }
+
+    fn playback_status(&self) -> String {
+        match self.state.as_str() {
+            "playing" => "Playing",
+            "paused" => "Paused",
+            _ => "Stopped",
+        }
+        .to_string()
+    }
+
+    fn loop_status(&self) -> String {
+        match self.repeat.as_str() {
+            "one" => "Track",
+            "all" => "Playlist",
+            _ => "None",
+        }
+        .to_string()
+    }
+
+    fn position_us(&self) -> i64 {
+        (self.position * 1_000_000.0) as i64
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct QueueTrack {
+    path: String,
+    title: String,
+    artist: Option<String>,
+    album: Option<String>,
+    track_number: Option<u32>,
+    duration: Option<f32>,
+}
+
+#[derive(serde::Deserialize)]
+struct Queue {
+    current: usize,
+    tracks: Vec<QueueTrack>,
+}
+
+impl Queue {
+    fn fetch(control: &Control) -> Option<Self> {
+        let reply = control.request("queue list").ok()?;
+        serde_json::from_str(&reply).ok()
+    }
+
+    fn track_ids(&self) -> Vec<OwnedObjectPath> {
+        (0..self.tracks.len()).map(track_id).collect()
+    }
+}
+
+fn track_id(index: usize) -> OwnedObjectPath {
+    object_path(&format!("{}{}", TRACK_PREFIX, index))
+}
+
+fn object_path(path: &str) -> OwnedObjectPath {
+    ObjectPath::try_from(path.to_string())
+        .map(OwnedObjectPath::from)
+        .unwrap_or_else(|_| OwnedObjectPath::from(ObjectPath::from_static_str_unchecked(NO_TRACK)))
+}
+
+fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    value
+        .into()
+        .try_to_owned()
+        .expect("metadata values contain no file descriptors")
+}
+
+fn track_metadata(index: usize, track: &QueueTrack) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    metadata.insert("mpris:trackid".to_string(), owned(track_id(index)));
+    metadata.insert(
+        "xesam:url".to_string(),
+        owned(format!("file://{}", track.path)),
+    );
+    metadata.insert("xesam:title".to_string(), owned(track.title.clone()));
+    if let Some(ref artist) = track.artist {
+        metadata.insert("xesam:artist".to_string(), owned(vec![artist.clone()]));
+    }
+    if let Some(ref album) = track.album {
+        metadata.insert("xesam:album".to_string(), owned(album.clone()));
+    }
+    if let Some(number) = track.track_number {
+        metadata.insert("xesam:trackNumber".to_string(), owned(number as i32));
+    }
+    if let Some(duration) = track.duration {
+        metadata.insert(
+            "mpris:length".to_string(),
+            owned((duration as f64 * 1_000_000.0) as i64),
+        );
+    }
+    metadata
+}
+
+fn failed(e: String) -> fdo::Error {
+    fdo::Error::Failed(e)
+}
+
+struct Root {
+    control: Control,
+}
+
+impl Root {
+    fn new(control: Control) -> Self {
+        Self { control }
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) -> fdo::Result<()> {
+        self.control.send("quit --force").map_err(failed)
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "NSmp".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["file".to_string()]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        [
+            "audio/mpeg",
+            "audio/flac",
+            "audio/ogg",
+            "audio/wav",
+            "audio/aac",
+            "audio/mp4",
+        ]
+        .iter()
+        .map(|mime| mime.to_string())
+        .collect()
+    }
+}
+
+struct Player {
+    control: Control,
+}
+
+impl Player {
+    fn new(control: Control) -> Self {
+        Self { control }
+    }
+
+    fn status(&self) -> fdo::Result<Status> {
+        Status::fetch(&self.control).ok_or_else(|| failed("Player is not responding".to_string()))
+    }
+
+    fn send(&self, cmd: &str) -> fdo::Result<()> {
+        self.control.send(cmd).map_err(failed)
+    }
+
+    // Перемотка на смещение в микросекундах относительно текущей позиции
+    fn seek_by(&self, offset: i64) -> fdo::Result<()> {
+        let secs = offset.unsigned_abs() as f64 / 1_000_000.0;
+        if offset >= 0 {
+            self.send(&format!("seek_forward {}", secs))
+        } else {
+            self.send(&format!("seek_backward {}", secs))
+        }
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.send("next")
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.send("prev")
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        if self.status()?.state == "playing" {
+            self.send("pause")?;
+        }
+        Ok(())
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        match self.status()?.state.as_str() {
+            "stopped" => self.send("play"),
+            _ => self.send("pause"),
+        }
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.send("stop")
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        self.send("play")
+    }
+
+    async fn seek(
+        &self,
+        offset: i64,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.seek_by(offset)?;
+        let position = self.status()?.position_us();
+        Self::seeked(&emitter, position).await?;
+        Ok(())
+    }
+
+    async fn set_position(
+        &self,
+        track: OwnedObjectPath,
+        position: i64,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        let status = self.status()?;
+        // Запрос для уже сменившегося трека игнорируется, как требует спецификация
+        if track != track_id(status.index) || position < 0 {
+            return Ok(());
+        }
+        self.seek_by(position - status.position_us())?;
+        Self::seeked(&emitter, position).await?;
+        Ok(())
+    }
+
+    fn open_uri(&self, uri: String) -> fdo::Result<()> {
+        let path = uri
+            .strip_prefix("file://")
+            .ok_or_else(|| fdo::Error::NotSupported(format!("Unsupported URI: {}", uri)))?;
+        self.send(&format!("load {}", percent_decode(path)))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> fdo::Result<String> {
+        Ok(self.status()?.playback_status())
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> fdo::Result<String> {
+        Ok(self.status()?.loop_status())
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&mut self, value: String) -> fdo::Result<()> {
+        match value.as_str() {
+            "None" => self.send("repeat_off"),
+            "Track" => self.send("repeat_one"),
+            "Playlist" => self.send("repeat_all"),
+            _ => Err(fdo::Error::InvalidArgs(format!(
+                "Unknown loop status: {}",
+                value
+            ))),
+        }
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&mut self, _value: f64) {}
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> fdo::Result<bool> {
+        Ok(self.status()?.shuffle)
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&mut self, value: bool) -> fdo::Result<()> {
+        self.send(if value { "shuffle on" } else { "shuffle off" })
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> fdo::Result<HashMap<String, OwnedValue>> {
+        let queue = Queue::fetch(&self.control)
+            .ok_or_else(|| failed("Player is not responding".to_string()))?;
+        Ok(queue
+            .tracks
+            .get(queue.current)
+            .map(|track| track_metadata(queue.current, track))
+            .unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> fdo::Result<f64> {
+        Ok(self.status()?.volume)
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, value: f64) -> fdo::Result<()> {
+        self.send(&format!("volume {}", value.clamp(0.0, 1.0) * 100.0))
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> fdo::Result<i64> {
+        Ok(self.status()?.position_us())
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+struct TrackList {
+    control: Control,
+}
+
+impl TrackList {
+    fn new(control: Control) -> Self {
+        Self { control }
+    }
+
+    fn queue(&self) -> fdo::Result<Queue> {
+        Queue::fetch(&self.control).ok_or_else(|| failed("Player is not responding".to_string()))
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackList {
+    fn get_tracks_metadata(
+        &self,
+        track_ids: Vec<OwnedObjectPath>,
+    ) -> fdo::Result<Vec<HashMap<String, OwnedValue>>> {
+        let queue = self.queue()?;
+        Ok(track_ids
+            .iter()
+            .filter_map(|id| id.as_str().strip_prefix(TRACK_PREFIX)?.parse().ok())
+            .filter_map(|index: usize| Some(track_metadata(index, queue.tracks.get(index)?)))
+            .collect())
+    }
+
+    // Очередь редактируется командами плеера, а не через MPRIS
+    fn add_track(&self, _uri: String, _after_track: OwnedObjectPath, _set_as_current: bool) {}
+
+    fn remove_track(&self, _track_id: OwnedObjectPath) {}
+
+    fn go_to(&self, track_id: OwnedObjectPath) -> fdo::Result<()> {
+        let index = track_id
+            .as_str()
+            .strip_prefix(TRACK_PREFIX)
+            .and_then(|index| index.parse::<usize>().ok())
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown track: {}", track_id)))?;
+        self.control
+            .send(&format!("goto {}", index))
+            .map_err(failed)
+    }
+
+    #[zbus(property)]
+    fn tracks(&self) -> fdo::Result<Vec<OwnedObjectPath>> {
+        Ok(self.queue()?.track_ids())
+    }
+
+    #[zbus(property)]
+    fn can_edit_tracks(&self) -> bool {
+        false
+    }
+}
+
+// Плейлисты NSmp — генераторы очереди: история прослушиваний и настроения
+fn playlists() -> Vec<(String, String, String)> {
+    let mut playlists = vec![
+        (
+            "rediscover".to_string(),
+            "Rediscover".to_string(),
+            "generate rediscover".to_string(),
+        ),
+        (
+            "on_this_day".to_string(),
+            "On this day".to_string(),
+            "generate on-this-day".to_string(),
+        ),
+    ];
+    for mood in analysis::MOODS {
+        playlists.push((
+            format!("mood_{}", mood),
+            format!("Mood: {}", mood),
+            format!("play --mood {}", mood),
+        ));
+    }
+    playlists
+}
+
+type PlaylistEntry = (OwnedObjectPath, String, String);
+
+struct Playlists {
+    control: Control,
+    active: Arc<Mutex<Option<PlaylistEntry>>>,
+}
+
+impl Playlists {
+    fn new(control: Control, active: Arc<Mutex<Option<PlaylistEntry>>>) -> Self {
+        Self { control, active }
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Playlists")]
+impl Playlists {
+    fn activate_playlist(&self, playlist_id: OwnedObjectPath) -> fdo::Result<()> {
+        let id = playlist_id.as_str().strip_prefix(PLAYLIST_PREFIX);
+        let (name, cmd) = playlists()
+            .into_iter()
+            .find(|(key, _, _)| Some(key.as_str()) == id)
+            .map(|(_, name, cmd)| (name, cmd))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown playlist: {}", playlist_id)))?;
+
+        self.control.send(&cmd).map_err(failed)?;
+        *self.active.lock().unwrap() = Some((playlist_id, name, String::new()));
+        Ok(())
+    }
+
+    fn get_playlists(
+        &self,
+        index: u32,
+        max_count: u32,
+        _order: String,
+        reverse_order: bool,
+    ) -> Vec<PlaylistEntry> {
+        let mut entries: Vec<PlaylistEntry> = playlists()
+            .into_iter()
+            .map(|(key, name, _)| {
+                (
+                    object_path(&format!("{}{}", PLAYLIST_PREFIX, key)),
+                    name,
+                    String::new(),
+                )
+            })
+            .collect();
+        if reverse_order {
+            entries.reverse();
+        }
+        entries
+            .into_iter()
+            .skip(index as usize)
+            .take(max_count as usize)
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn playlist_count(&self) -> u32 {
+        playlists().len() as u32
+    }
+
+    #[zbus(property)]
+    fn orderings(&self) -> Vec<String> {
+        vec!["UserDefined".to_string()]
+    }
+
+    #[zbus(property)]
+    fn active_playlist(&self) -> (bool, PlaylistEntry) {
+        match self.active.lock().unwrap().clone() {
+            Some(entry) => (true, entry),
+            None => (false, (object_path("/"), String::new(), String::new())),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseKind {
+    White,
+    Pink,
+    Brown,
+}
+
+impl NoiseKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "white" => Some(Self::White),
+            "pink" => Some(Self::Pink),
+            "brown" => Some(Self::Brown),
+            _ => None,
+        }
+    }
+}
+
+// Бесконечный генератор шума, моно
+pub struct Noise {
+    kind: NoiseKind,
+    seed: u32,
+    pink: [f32; 7],
+    brown: f32,
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind) -> Self {
+        Self {
+            kind,
+            seed: 0x9E37_79B9,
+            pink: [0.0; 7],
+            brown: 0.0,
+        }
+    }
+
+    // xorshift32: качества хватает для шума, зависимость не нужна
+    fn white(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+impl Iterator for Noise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let white = self.white();
+        let sample = match self.kind {
+            NoiseKind::White => white * 0.5,
+            // Фильтр Пола Келлета
+            NoiseKind::Pink => {
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b.iter().sum::<f32>() + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            // Интегрированный белый шум с утечкой, чтобы не уходить от нуля
+            NoiseKind::Brown => {
+                self.brown = (self.brown + white * 0.02) / 1.02;
+                self.brown * 3.5
+            }
+        };
+        Some(sample)
+    }
+}
+
+impl Source for Noise {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// Записанный звук окружения из папки ambience: rain.ogg для "noise:rain"
+pub fn find_sample(dir: &Path, name: &str) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| path.is_file() && path.file_stem().is_some_and(|stem| stem == name))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NoiseConfig {
+    // Громкость шума не зависит от громкости музыки
+    pub volume: f32,
+    pub ambience_dir: Option<String>,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        NoiseConfig {
+            volume: 0.3,
+            ambience_dir: None,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// Схема REST API. Ответы собираются командами протокола, поэтому
+// структуры здесь только описывают их форму и нигде не создаются
+#![allow(dead_code)]
+
+use serde::Serialize;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "NSmp", description = "Control API of the NSmp music daemon"),
+    paths(
+        crate::http::status,
+        crate::http::queue,
+        crate::http::library,
+        crate::http::search,
+        crate::http::next,
+        crate::http::prev,
+        crate::http::pause,
+        crate::http::volume,
+        crate::http::set_volume,
+        crate::http::command,
+    ),
+    components(schemas(
+        Command,
+        Status,
+        Recovery,
+        Volume,
+        Queue,
+        QueueTrack,
+        SearchResults,
+        LibraryTrack,
+        LibraryPage
+    )),
+    modifiers(&BearerToken),
+    security(("token" = []))
+)]
+pub struct ApiDoc;
+
+impl ApiDoc {
+    pub fn json() -> String {
+        Self::openapi()
+            .to_json()
+            .expect("OpenAPI document serializes")
+    }
+}
+
+// Токены из "users" конфига; без пользователей авторизация не нужна
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+// Тело POST /api/command
+#[derive(Serialize, ToSchema)]
+pub struct Command {
+    // Команда протокола, например "next" или "volume_up 5"
+    command: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Status {
+    // stopped, paused или playing
+    state: String,
+    pause_reason: Option<String>,
+    track: String,
+    // Из тегов, иначе имя файла
+    title: String,
+    artist: Option<String>,
+    album: Option<String>,
+    track_number: Option<u32>,
+    // "Исполнитель - Название" для вывода
+    display: String,
+    index: usize,
+    // Секунды от начала трека
+    position: f32,
+    // 0.0..1.0
+    volume: f32,
+    // off, one или all
+    repeat: String,
+    shuffle: bool,
+    queue_length: usize,
+    // Растёт с каждой правкой очереди
+    queue_revision: u64,
+    queue_remaining: u64,
+    queue_remaining_text: String,
+    queue_unknown_durations: usize,
+    // Трек, чей отрывок сейчас звучит по preview-start
+    preview: Option<String>,
+    // Оценка текущего трека 1..5
+    rating: Option<u8>,
+    favorite: bool,
+    // Файлы, испорченные к запуску и заменённые; пусто, если всё прочиталось
+    recovered: Vec<Recovery>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Recovery {
+    file: String,
+    error: String,
+    // Куда отложен испорченный файл
+    broken: Option<String>,
+    // backup или defaults
+    fallback: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Volume {
+    // 0.0..1.0
+    volume: f32,
+    percent: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct QueueTrack {
+    path: String,
+    title: String,
+    artist: Option<String>,
+    album: Option<String>,
+    track_number: Option<u32>,
+    duration: Option<f32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Queue {
+    revision: u64,
+    current: usize,
+    tracks: Vec<QueueTrack>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResults {
+    results: Vec<QueueTrack>,
+}
+
+// Есть только поля, перечисленные в fields
+#[derive(Serialize, ToSchema)]
+pub struct LibraryTrack {
+    path: Option<String>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration: Option<f32>,
+    bpm: Option<f32>,
+    mood: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LibraryPage {
+    tracks: Vec<LibraryTrack>,
+    // null на последней странице
+    next_cursor: Option<String>,
+}
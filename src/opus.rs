@@ -0,0 +1,166 @@
+use crate::plugin::Library;
+use std::ffi::{c_int, c_uchar, c_void};
+use std::path::Path;
+use std::ptr;
+use std::sync::OnceLock;
+use symphonia::core::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec};
+use symphonia::core::codecs::{
+    CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS,
+};
+use symphonia::core::errors::{decode_error, unsupported_error, Error, Result};
+use symphonia::core::formats::Packet;
+
+// Контейнер Ogg или Matroska разбирает symphonia, сами пакеты декодирует libopus,
+// загружаемая при первом файле .opus
+const LIBRARY: &str = "libopus.so.0";
+const RATE: u32 = 48000;
+// Самый длинный пакет Opus — 120 мс
+const MAX_FRAMES: usize = 5760;
+
+struct Api {
+    create: unsafe extern "C" fn(i32, c_int, *mut c_int) -> *mut c_void,
+    decode_float:
+        unsafe extern "C" fn(*mut c_void, *const c_uchar, i32, *mut f32, c_int, c_int) -> c_int,
+    destroy: unsafe extern "C" fn(*mut c_void),
+    _library: Library,
+}
+
+impl Api {
+    fn load() -> std::result::Result<Self, String> {
+        let library = Library::open(Path::new(LIBRARY))?;
+        unsafe {
+            Ok(Self {
+                create: library.function("opus_decoder_create")?,
+                decode_float: library.function("opus_decode_float")?,
+                destroy: library.function("opus_decoder_destroy")?,
+                _library: library,
+            })
+        }
+    }
+}
+
+fn api() -> std::result::Result<&'static Api, String> {
+    static API: OnceLock<std::result::Result<Api, String>> = OnceLock::new();
+    API.get_or_init(Api::load).as_ref().map_err(Clone::clone)
+}
+
+// Файлы .opus попадают в библиотеку, только если libopus установлена
+pub fn available() -> bool {
+    api().is_ok()
+}
+
+// Только моно и стерео: многоканальный Opus требует multistream-декодера
+pub struct OpusDecoder {
+    api: &'static Api,
+    raw: *mut c_void,
+    params: CodecParameters,
+    channels: usize,
+    pcm: Vec<f32>,
+    buffer: AudioBuffer<f32>,
+}
+
+// Декодером пользуется один поток за раз
+unsafe impl Send for OpusDecoder {}
+unsafe impl Sync for OpusDecoder {}
+
+impl OpusDecoder {
+    fn create(api: &Api, channels: usize) -> Result<*mut c_void> {
+        let mut error = 0;
+        let raw = unsafe { (api.create)(RATE as i32, channels as c_int, &mut error) };
+        if raw.is_null() || error != 0 {
+            return decode_error("opus: failed to create decoder");
+        }
+        Ok(raw)
+    }
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+        let Ok(api) = api() else {
+            return unsupported_error("opus: libopus not found");
+        };
+        let layout = params
+            .channels
+            .ok_or(Error::Unsupported("opus: no channel layout"))?;
+        let channels = layout.count();
+        if !(1..=2).contains(&channels) {
+            return unsupported_error("opus: only mono and stereo are supported");
+        }
+        Ok(Self {
+            api,
+            raw: Self::create(api, channels)?,
+            params: params.clone(),
+            channels,
+            pcm: vec![0.0; MAX_FRAMES * channels],
+            buffer: AudioBuffer::new(MAX_FRAMES as u64, SignalSpec::new(RATE, layout)),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[CodecDescriptor {
+            codec: CODEC_TYPE_OPUS,
+            short_name: "opus",
+            long_name: "Opus (libopus)",
+            inst_func: |params, options| Ok(Box::new(OpusDecoder::try_new(params, options)?)),
+        }]
+    }
+
+    // После перемотки состояние декодера не относится к новому месту
+    fn reset(&mut self) {
+        if let Ok(raw) = Self::create(self.api, self.channels) {
+            unsafe { (self.api.destroy)(self.raw) };
+            self.raw = raw;
+        }
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        let data = packet.buf();
+        let frames = unsafe {
+            (self.api.decode_float)(
+                self.raw,
+                data.as_ptr(),
+                data.len() as i32,
+                self.pcm.as_mut_ptr(),
+                MAX_FRAMES as c_int,
+                0,
+            )
+        };
+        if frames < 0 {
+            return decode_error("opus: corrupt packet");
+        }
+        let frames = frames as usize;
+        self.buffer.clear();
+        self.buffer.render_reserved(Some(frames));
+        for channel in 0..self.channels {
+            let plane = self.buffer.chan_mut(channel);
+            for (frame, sample) in plane.iter_mut().enumerate() {
+                *sample = self.pcm[frame * self.channels + channel];
+            }
+        }
+        // Задержка кодера в начале и добивка в конце, если контейнер их сообщил
+        self.buffer
+            .trim(packet.trim_start() as usize, packet.trim_end() as usize);
+        Ok(self.buffer.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        FinalizeResult::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buffer.as_audio_buffer_ref()
+    }
+}
+
+impl Drop for OpusDecoder {
+    fn drop(&mut self) {
+        if !self.raw.is_null() {
+            unsafe { (self.api.destroy)(self.raw) };
+            self.raw = ptr::null_mut();
+        }
+    }
+}
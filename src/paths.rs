@@ -0,0 +1,84 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const APP_DIR: &str = "nsmp";
+// Где конфиг лежал раньше: в текущей папке
+const LEGACY_CONFIG: &str = "music_player.json";
+
+struct RuntimePaths {
+    socket: PathBuf,
+    pid: PathBuf,
+}
+
+static RUNTIME: OnceLock<RuntimePaths> = OnceLock::new();
+
+// $XDG_CONFIG_HOME/nsmp/config.json или ~/.config/nsmp/config.json.
+// Старый music_player.json в текущей папке читается, пока нового нет
+pub fn config_file() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    let path = base.join(APP_DIR).join("config.json");
+    let legacy = Path::new(LEGACY_CONFIG);
+    if !path.exists() && legacy.exists() {
+        return legacy.to_path_buf();
+    }
+    path
+}
+
+// $XDG_RUNTIME_DIR/nsmp; без него личная папка в /tmp
+fn runtime_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+    {
+        Some(dir) => dir.join(APP_DIR),
+        None => env::temp_dir().join(format!("{}-{}", APP_DIR, unsafe { libc::getuid() })),
+    }
+}
+
+// Пути из --socket и --pid-file (или NSMP_SOCKET и NSMP_PID_FILE) главнее умолчаний
+// Относительные пути считаются от папки запуска: демон потом уходит в "/"
+pub fn init(socket: Option<PathBuf>, pid: Option<PathBuf>) {
+    let absolute = |path: PathBuf| std::path::absolute(&path).unwrap_or(path);
+    let _ = RUNTIME.set(resolve(socket.map(absolute), pid.map(absolute)));
+}
+
+fn resolve(socket: Option<PathBuf>, pid: Option<PathBuf>) -> RuntimePaths {
+    let dir = runtime_dir();
+    RuntimePaths {
+        socket: socket.unwrap_or_else(|| dir.join("socket")),
+        pid: pid.unwrap_or_else(|| dir.join("pid")),
+    }
+}
+
+fn runtime() -> &'static RuntimePaths {
+    RUNTIME.get_or_init(|| resolve(None, None))
+}
+
+pub fn socket() -> &'static Path {
+    &runtime().socket
+}
+
+pub fn pid_file() -> &'static Path {
+    &runtime().pid
+}
+
+// Папки сокета и PID-файла, доступные только владельцу
+pub fn create_runtime_dirs() -> Result<(), String> {
+    for path in [socket(), pid_file()] {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1877 @@
+use crate::analysis::{self, TrackAnalysis};
+use crate::automix::AutomixConfig;
+use crate::buffer::Monitor;
+use crate::cd::CdConfig;
+use crate::changes::{Change, QueueLog};
+use crate::config::{
+    default_output, default_playlists_dir, default_volume_step, DEFAULT_CONTEXTS_DIR,
+};
+use crate::context::{self, ContextConfig, ContextState};
+use crate::db::{self, Database};
+use crate::decode::{DecodedSource, DecoderConfig};
+use crate::dispatch::CommandRecord;
+use crate::library::LibraryDb;
+use crate::limiter::{self, Limiter};
+use crate::mix::Mix;
+use crate::noise::{self, Noise, NoiseConfig, NoiseKind};
+use crate::plugin::{ErrorKind, PlaybackError, PlayerPlugins};
+use crate::preload::{Prebuffer, PreloadConfig, Preloader};
+use crate::preview::{PreviewConfig, PreviewSource};
+use crate::replaygain::{ReplayGain, ReplayGainConfig, ReplayGainMode};
+use crate::scan::{scan_music, track_exists, ScanOptions};
+use crate::skip::{self, SkipConfig};
+use crate::state::{self, PlaybackState};
+use crate::{clock, i18n, paths, playlist, stream, tags};
+use rodio::source::Amplify;
+use rodio::{Decoder, OutputStreamHandle, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Контекст, в котором плеер работает, пока не переключён
+const DEFAULT_CONTEXT: &str = "default";
+// Перемотка с удержанием разгоняется от 4x до 32x за 3 секунды
+const SCAN_MIN_RATE: f32 = 4.0;
+const SCAN_MAX_RATE: f32 = 32.0;
+const SCAN_ACCEL_SECS: f32 = 3.0;
+const SCAN_PREVIEW_VOLUME: f32 = 0.3;
+// За сколько до конца трека следующий открывается и ставится в sink
+const GAPLESS_LEAD: Duration = Duration::from_secs(5);
+// За сколько до срабатывания таймера сна начинает стихать звук
+const SLEEP_FADE: Duration = Duration::from_secs(30);
+// Сколько последних ошибок воспроизведения отдаёт команда errors
+const ERROR_HISTORY: usize = 50;
+
+type TrackSource = Monitor<Limiter<Prebuffer<Amplify<DecodedSource>>>>;
+
+// Громкость для OSD: как в status (0..1) и в процентах
+pub fn volume_json(volume: f32) -> String {
+    serde_json::json!({
+        "volume": volume,
+        "percent": (volume * 100.0).round() as u32,
+    })
+    .to_string()
+        + "\n"
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Autofill {
+    #[default]
+    Off,
+    // В конце очереди добавляется ближайший по звучанию трек
+    Similar,
+    // Или следующий трек микса плейлистов
+    Mix,
+}
+
+// Что делать, когда доиграл последний трек очереди
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueEnd {
+    #[default]
+    Repeat,
+    Stop,
+    // Завершить демон
+    Quit,
+    // Остановиться и усыпить машину
+    Suspend,
+    // Остановиться и выполнить команду через sh -c
+    Hook(String),
+}
+
+// Повтор: all замыкает очередь, one крутит текущий трек
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Repeat {
+    Off,
+    One,
+    All,
+}
+
+impl QueueEnd {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "repeat" => Some(Self::Repeat),
+            "stop" => Some(Self::Stop),
+            "quit" => Some(Self::Quit),
+            "suspend" => Some(Self::Suspend),
+            _ => None,
+        }
+    }
+}
+
+// Состояние воспроизведения для передачи на другую машину
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    music_dir: PathBuf,
+    files: Vec<PathBuf>,
+    current_index: usize,
+    position: f32,
+    volume: f32,
+}
+
+impl Snapshot {
+    // Снимок приходит по сети или из файла состояния, поэтому позиция и громкость
+    // проверяются до того, как что-то в плеере поменяется; отрицательная позиция — начало трека
+    fn start(&self) -> Result<Duration, io::Error> {
+        let invalid = |what: &str, value: f32| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid snapshot {}: {}", what, value),
+            )
+        };
+        if !self.volume.is_finite() {
+            return Err(invalid("volume", self.volume));
+        }
+        if !self.position.is_finite() {
+            return Err(invalid("position", self.position));
+        }
+        Duration::try_from_secs_f32(self.position.max(0.0))
+            .map_err(|_| invalid("position", self.position))
+    }
+}
+
+pub struct MusicPlayer {
+    pub(crate) music_dir: PathBuf,
+    pub(crate) library: Vec<PathBuf>,
+    pub(crate) files: Vec<PathBuf>,
+    pub(crate) current_index: usize,
+    pub(crate) plugins: PlayerPlugins,
+    pub(crate) db: Arc<Mutex<Database>>,
+    pub(crate) library_db: Arc<Mutex<LibraryDb>>,
+    pub(crate) preloader: Preloader,
+    pub(crate) decoders: DecoderConfig,
+    pub(crate) gapless: bool,
+    // Трек, уже добавленный в sink вслед за текущим: индекс в очереди и путь
+    pub(crate) queued: Option<(usize, PathBuf)>,
+    // Следующий трек не открылся заранее; до смены трека не пробовать снова
+    queue_failed: bool,
+    // Файлы очереди, которых не оказалось на месте; пропускаются, пока не вернутся
+    missing: HashSet<PathBuf>,
+    // Последние ошибки воспроизведения, старые в начале
+    pub(crate) errors: VecDeque<PlaybackError>,
+    // Выполненные команды для "history commands" и repeat-last, старые в начале
+    pub(crate) command_history: VecDeque<CommandRecord>,
+    pub(crate) automix: AutomixConfig,
+    pub(crate) autofill: Autofill,
+    pub(crate) mix: Option<Mix>,
+    // Кто запустил текущее воспроизведение: ему записываются прослушивания
+    pub(crate) listener: Option<String>,
+    // Почему воспроизведение на паузе: "user", "headphones-unplugged"...
+    pub(crate) pause_reason: Option<String>,
+    pub(crate) output: String,
+    pub(crate) preamp_db: f32,
+    pub(crate) replaygain: ReplayGainConfig,
+    pub(crate) playlists_dir: PathBuf,
+    pub(crate) state_file: Option<PathBuf>,
+    pub(crate) volume_step: f32,
+    // Вывод, который главный цикл должен переоткрыть
+    pub(crate) pending_output: Option<String>,
+    // Очередь, прерванная командой play-dir
+    detour: Option<Snapshot>,
+    pub(crate) pending_interrupt: Option<PathBuf>,
+    interruption: Option<Interruption>,
+    pub(crate) noise: NoiseConfig,
+    pub(crate) cd: CdConfig,
+    // Подключённые съёмные носители, чьи треки временно в библиотеке
+    drives: Vec<PathBuf>,
+    // Сколько смен песен в потоках уже передано плагинам
+    stream_songs: u64,
+    pub(crate) pending_noise: Option<String>,
+    // Шум или звук окружения, играющий независимо от музыки
+    pub(crate) noise_sink: Option<Sink>,
+    pub(crate) preview: PreviewConfig,
+    // Отрывок, для которого главный цикл должен открыть свой sink
+    pub(crate) pending_preview: Option<PreviewSource>,
+    pub(crate) preview_sink: Option<Sink>,
+    pub(crate) preview_track: Option<PathBuf>,
+    // В sink бесконечная петля, а не трек из очереди
+    pub(crate) looping: bool,
+    on_queue_end: QueueEnd,
+    pub(crate) repeat: Repeat,
+    // Очередь доиграла и не повторяется; play начнёт её сначала
+    pub(crate) stopped: bool,
+    // quit, queue clear и playlist delete требуют подтверждения токеном
+    pub(crate) confirm_destructive: bool,
+    scan: Option<Scan>,
+    pub(crate) scan_options: ScanOptions,
+    sleep: Option<SleepTimer>,
+    pub(crate) shuffle: bool,
+    // Папка играет целиком по порядку, затем случайно выбирается следующая
+    pub(crate) shuffle_folders: bool,
+    // Ещё не сыгранные в этом круге индексы; следующий — последний
+    pub(crate) shuffle_bag: Vec<usize>,
+    pub(crate) skips: SkipConfig,
+    // Ревизии очереди для "queue changes"
+    pub(crate) queue_log: QueueLog,
+    // Активный контекст и заготовки остальных
+    pub(crate) context: String,
+    pub(crate) contexts: HashMap<String, ContextConfig>,
+    pub(crate) contexts_dir: PathBuf,
+}
+
+// Непрерывная перемотка, пока удерживается клавиша
+struct Scan {
+    forward: bool,
+    started: Instant,
+    // Громкость до перемотки; во время неё звук тише
+    volume: f32,
+}
+
+// Таймер сна: звук стихает к сроку, потом пауза или выход
+struct SleepTimer {
+    deadline: Instant,
+    quit: bool,
+    // Громкость до начала затухания
+    volume: Option<f32>,
+}
+
+enum SleepStep {
+    Wait,
+    Fade(f32),
+    // Срок наступил; volume — громкость до затухания
+    Expired { volume: f32, quit: bool },
+}
+
+impl SleepTimer {
+    fn new(after: Duration, quit: bool) -> Self {
+        SleepTimer {
+            deadline: clock::now() + after,
+            quit,
+            volume: None,
+        }
+    }
+
+    fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(clock::now())
+    }
+
+    // Громкость линейно уходит в ноль за SLEEP_FADE до срока; current — громкость сейчас
+    fn step(&mut self, current: f32) -> SleepStep {
+        let left = self.remaining();
+        if left > SLEEP_FADE {
+            return SleepStep::Wait;
+        }
+        let volume = *self.volume.get_or_insert(current);
+        if left.is_zero() {
+            return SleepStep::Expired {
+                volume,
+                quit: self.quit,
+            };
+        }
+        SleepStep::Fade(volume * left.as_secs_f32() / SLEEP_FADE.as_secs_f32())
+    }
+}
+
+// Файл, играющий поверх приостановленной музыки
+struct Interruption {
+    sink: Sink,
+    // Музыка играла до прерывания и должна продолжиться после
+    resume: bool,
+}
+
+impl MusicPlayer {
+    // cached — треки папки из базы библиотеки; пустой список значит первый запуск
+    pub(crate) fn new(
+        path: PathBuf,
+        cached: Vec<PathBuf>,
+        plugins: PlayerPlugins,
+        db: Arc<Mutex<Database>>,
+        library_db: Arc<Mutex<LibraryDb>>,
+        automix: AutomixConfig,
+        scan_options: ScanOptions,
+    ) -> Result<Self, io::Error> {
+        let library = if cached.is_empty() {
+            let files = scan_music(&path, &scan_options)?;
+            if path.is_dir() {
+                if let Err(e) = library_db.lock().unwrap().sync(&path, &files) {
+                    eprintln!("Failed to update library database: {}", e);
+                }
+            }
+            files
+        } else {
+            cached
+        };
+        let preloader = Preloader::new(PreloadConfig::default().resolve(&path));
+        Ok(Self {
+            music_dir: path,
+            queue_log: QueueLog::new(&library),
+            files: library.clone(),
+            library,
+            current_index: 0,
+            plugins,
+            db,
+            library_db,
+            preloader,
+            decoders: DecoderConfig::default(),
+            gapless: true,
+            queued: None,
+            queue_failed: false,
+            missing: HashSet::new(),
+            errors: VecDeque::new(),
+            command_history: VecDeque::new(),
+            automix,
+            autofill: Autofill::Off,
+            mix: None,
+            listener: None,
+            pause_reason: None,
+            output: default_output(),
+            preamp_db: 0.0,
+            replaygain: ReplayGainConfig::default(),
+            playlists_dir: PathBuf::from(default_playlists_dir()),
+            state_file: None,
+            volume_step: default_volume_step(),
+            pending_output: None,
+            detour: None,
+            pending_interrupt: None,
+            interruption: None,
+            noise: NoiseConfig::default(),
+            drives: Vec::new(),
+            stream_songs: 0,
+            cd: CdConfig::default(),
+            pending_noise: None,
+            noise_sink: None,
+            preview: PreviewConfig::default(),
+            pending_preview: None,
+            preview_sink: None,
+            preview_track: None,
+            looping: false,
+            on_queue_end: QueueEnd::Repeat,
+            repeat: Repeat::All,
+            stopped: false,
+            confirm_destructive: false,
+            scan: None,
+            scan_options,
+            sleep: None,
+            shuffle: false,
+            shuffle_folders: false,
+            shuffle_bag: Vec::new(),
+            skips: SkipConfig::default(),
+            context: DEFAULT_CONTEXT.to_string(),
+            contexts: HashMap::new(),
+            contexts_dir: PathBuf::from(DEFAULT_CONTEXTS_DIR),
+        })
+    }
+
+    pub(crate) fn load(&mut self, path: &Path, sink: &Sink) -> Result<(), io::Error> {
+        let files = scan_music(path, &self.scan_options)?;
+        self.set_queue(files, 0);
+        self.play(sink)
+    }
+
+    // Временная очередь из папки; прежняя вернётся, когда папка доиграет
+    pub(crate) fn play_dir(&mut self, path: &Path, sink: &Sink) -> Result<(), io::Error> {
+        let files = scan_music(path, &self.scan_options)?;
+        if self.detour.is_none() {
+            self.detour = Some(self.snapshot(sink));
+        }
+        self.files = files;
+        self.current_index = 0;
+        self.refill_shuffle();
+        self.queue_changed();
+        self.play(sink)
+    }
+
+    // Новая очередь отменяет play-dir и перемешивается заново
+    pub(crate) fn set_queue(&mut self, files: Vec<PathBuf>, index: usize) {
+        self.files = files;
+        self.current_index = index;
+        self.detour = None;
+        self.refill_shuffle();
+        self.queue_changed();
+    }
+
+    // Новая ревизия очереди и событие для плагинов, если очередь действительно изменилась
+    fn queue_changed(&mut self) {
+        if self.queue_log.update(&self.files) {
+            let revision = self.queue_log.revision();
+            self.plugins.queue_changed(self.files.len(), revision);
+        }
+    }
+
+    // Непроигранные треки очереди в случайном порядке, кроме текущего
+    pub(crate) fn refill_shuffle(&mut self) {
+        if !self.shuffle {
+            self.shuffle_bag.clear();
+            return;
+        }
+        let current = self.current_index;
+        if self.shuffle_folders {
+            self.shuffle_bag = self.folder_order(current);
+            return;
+        }
+        self.shuffle_bag = (0..self.files.len()).filter(|&i| i != current).collect();
+        if !self.skips.smart_shuffle {
+            fastrand::shuffle(&mut self.shuffle_bag);
+            return;
+        }
+        match self.library_db.lock().unwrap().skipped() {
+            Ok(counts) => skip::weighted_shuffle(&mut self.shuffle_bag, &self.files, &counts),
+            Err(e) => {
+                eprintln!("Failed to read skip counts: {}", e);
+                fastrand::shuffle(&mut self.shuffle_bag);
+            }
+        }
+    }
+
+    // Папки в случайном порядке, внутри папки — порядок очереди. Сначала доигрывается
+    // папка текущего трека; мешок разбирается с конца, поэтому порядок обратный
+    fn folder_order(&self, current: usize) -> Vec<usize> {
+        let current_folder = self.files.get(current).and_then(|path| path.parent());
+        let mut rest_of_current = Vec::new();
+        let mut folders: Vec<Vec<usize>> = Vec::new();
+        let mut slots: HashMap<Option<&Path>, usize> = HashMap::new();
+        for (index, path) in self.files.iter().enumerate() {
+            if index == current {
+                continue;
+            }
+            let folder = path.parent();
+            if index > current && folder == current_folder {
+                rest_of_current.push(index);
+                continue;
+            }
+            let slot = *slots.entry(folder).or_insert_with(|| {
+                folders.push(Vec::new());
+                folders.len() - 1
+            });
+            folders[slot].push(index);
+        }
+        fastrand::shuffle(&mut folders);
+        let mut order = rest_of_current;
+        order.extend(folders.into_iter().flatten());
+        order.reverse();
+        order
+    }
+
+    // Треки, дописанные в конец очереди, попадают в ещё не сыгранные
+    fn bag_new_tracks(&mut self, added: std::ops::Range<usize>) {
+        if !self.shuffle {
+            return;
+        }
+        if self.shuffle_folders {
+            // Целыми папками после уже выбранных
+            self.shuffle_bag.splice(0..0, added.rev());
+            return;
+        }
+        for index in added {
+            let at = fastrand::usize(..=self.shuffle_bag.len());
+            self.shuffle_bag.insert(at, index);
+        }
+    }
+
+    fn next_index(&self) -> usize {
+        match self.shuffle_bag.last() {
+            Some(&index) if self.shuffle => index,
+            _ => (self.current_index + 1) % self.files.len(),
+        }
+    }
+
+    fn detour_finished(&self) -> bool {
+        self.detour.is_some() && self.last_in_queue()
+    }
+
+    fn last_in_queue(&self) -> bool {
+        if self.shuffle {
+            self.shuffle_bag.is_empty()
+        } else {
+            self.current_index + 1 == self.files.len()
+        }
+    }
+
+    // Очередь из треков библиотеки, чей анализ подходит под фильтр
+    pub(crate) fn play_filtered(
+        &mut self,
+        sink: &Sink,
+        filter: impl Fn(&TrackAnalysis) -> bool,
+    ) -> Result<(), io::Error> {
+        let files: Vec<PathBuf> = {
+            let db = self.db.lock().unwrap();
+            self.library
+                .iter()
+                .filter(|path| db.analysis(path).is_some_and(&filter))
+                .cloned()
+                .collect()
+        };
+
+        if files.is_empty() {
+            db::spawn_analyzer(Arc::clone(&self.db), self.library.clone());
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                i18n::tr("no-matching-tracks", &[]),
+            ));
+        }
+
+        self.set_queue(files, 0);
+        self.play(sink)
+    }
+
+    // Очередь из избранных треков библиотеки; перемешивается, как любая другая
+    pub(crate) fn play_favorites(&mut self, sink: &Sink) -> Result<(), String> {
+        let favorites: HashSet<PathBuf> = self
+            .library_db
+            .lock()
+            .unwrap()
+            .favorites()?
+            .into_iter()
+            .collect();
+        let files: Vec<PathBuf> = self
+            .library
+            .iter()
+            .filter(|path| favorites.contains(*path))
+            .cloned()
+            .collect();
+        if files.is_empty() {
+            return Err("no favorite tracks".to_string());
+        }
+        self.set_queue(files, 0);
+        self.play(sink).map_err(|e| e.to_string())
+    }
+
+    pub(crate) fn snapshot(&self, sink: &Sink) -> Snapshot {
+        Snapshot {
+            music_dir: self.music_dir.clone(),
+            files: self.files.clone(),
+            current_index: self.current_index,
+            position: sink.get_pos().as_secs_f32(),
+            volume: self.user_volume(sink),
+        }
+    }
+
+    pub(crate) fn position_json(&self, sink: &Sink) -> serde_json::Value {
+        let duration = {
+            let db = self.db.lock().unwrap();
+            self.duration_of(&db, &self.files[self.current_index])
+        };
+        serde_json::json!({
+            "event": "position",
+            "position": sink.get_pos().as_secs_f32(),
+            "duration": duration,
+        })
+    }
+
+    // Ответ клиенту — получившаяся громкость, чтобы её можно было показать
+    pub(crate) fn set_volume(&mut self, sink: &Sink, volume: f32) -> String {
+        let volume = volume.clamp(0.0, 1.0);
+        sink.set_volume(volume);
+        self.plugins.volume_changed(volume);
+        volume_json(volume)
+    }
+
+    // Громкость без временного приглушения перемоткой или таймером сна
+    pub(crate) fn user_volume(&self, sink: &Sink) -> f32 {
+        self.scan
+            .as_ref()
+            .map(|scan| scan.volume)
+            .or_else(|| self.sleep.as_ref().and_then(|timer| timer.volume))
+            .unwrap_or_else(|| sink.volume())
+    }
+
+    fn playback_state(&self, sink: &Sink) -> PlaybackState {
+        PlaybackState {
+            snapshot: self.snapshot(sink),
+            shuffle: self.shuffle,
+            shuffle_folders: self.shuffle_folders,
+            repeat: self.repeat,
+            paused: self.stopped || sink.is_paused(),
+            context: Some(self.context.clone()),
+        }
+    }
+
+    // Вместе с общим состоянием сохраняется и активный контекст
+    pub(crate) fn save_state(&self, sink: &Sink) {
+        if let Err(e) = self.save_context(&self.context, sink) {
+            eprintln!("Failed to save context {}: {}", self.context, e);
+        }
+        let Some(ref path) = self.state_file else {
+            return;
+        };
+        if let Err(e) = state::save(path, &self.playback_state(sink)) {
+            eprintln!("Failed to save playback state: {}", e);
+        }
+    }
+
+    pub(crate) fn save_context(&self, name: &str, sink: &Sink) -> Result<(), String> {
+        let state = ContextState {
+            playback: self.playback_state(sink),
+            output: self.output.clone(),
+            autofill: self.autofill,
+            mix: self.mix.as_ref().map(ToString::to_string),
+        };
+        context::save(&self.contexts_dir, name, &state)
+    }
+
+    // Текущий контекст запоминается, а новый продолжает с того места, где его оставили,
+    // или, при первом переключении, начинается с заготовки из конфига
+    pub(crate) fn switch_context(&mut self, name: &str, sink: &Sink) -> Result<(), String> {
+        if name == self.context {
+            return Ok(());
+        }
+        let saved = context::load(&self.contexts_dir, name)?;
+        let preset = self.contexts.get(name).cloned();
+        if saved.is_none() && preset.is_none() {
+            return Err(format!("unknown context: {}", name));
+        }
+        self.save_context(&self.context, sink)?;
+        self.context = name.to_string();
+        match saved {
+            Some(saved) => {
+                self.autofill = saved.autofill;
+                self.mix = saved
+                    .mix
+                    .and_then(|spec| Mix::parse(&spec, &self.playlists_dir).ok());
+                if self.autofill == Autofill::Mix && self.mix.is_none() {
+                    self.autofill = Autofill::Off;
+                }
+                if saved.output != self.output {
+                    self.pending_output = Some(saved.output);
+                }
+                self.resume(saved.playback, sink).map_err(|e| e.to_string())
+            }
+            None => self.apply_preset(preset.unwrap_or_default(), sink),
+        }
+    }
+
+    fn apply_preset(&mut self, preset: ContextConfig, sink: &Sink) -> Result<(), String> {
+        if let Some(shuffle) = preset.shuffle {
+            self.shuffle = shuffle;
+            self.shuffle_folders = false;
+        }
+        if let Some(repeat) = preset.repeat {
+            self.set_repeat(repeat);
+        }
+        if let Some(volume) = preset.volume {
+            self.set_volume(sink, volume);
+        }
+        if let Some(output) = preset.output.filter(|output| *output != self.output) {
+            self.pending_output = Some(output);
+        }
+        if let Some(ref name) = preset.playlist {
+            let path = playlist::find(name, &self.playlists_dir)?;
+            let (files, current) = playlist::load_with_current(&path)?;
+            self.set_queue(files, current);
+        } else if let Some(ref spec) = preset.mix {
+            let mut mix = Mix::parse(spec, &self.playlists_dir)?;
+            if let Some(first) = mix.next_track() {
+                self.set_queue(vec![first], 0);
+            }
+            self.mix = Some(mix);
+            self.autofill = Autofill::Mix;
+        } else {
+            self.refill_shuffle();
+            return Ok(());
+        }
+        self.play(sink).map_err(|e| e.to_string())
+    }
+
+    // Сохранённая очередь продолжает играть с той же позиции, пауза остаётся паузой.
+    // Имя контекста не трогается: его задаёт тот, кто переключает
+    pub(crate) fn resume(&mut self, state: PlaybackState, sink: &Sink) -> Result<(), io::Error> {
+        self.shuffle = state.shuffle;
+        self.shuffle_folders = state.shuffle_folders;
+        self.set_repeat(state.repeat);
+        self.restore(state.snapshot, sink)?;
+        if state.paused {
+            sink.pause();
+            self.pause_reason = Some("user".to_string());
+            self.plugins.playback_changed(true);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn shutdown(&self, sink: &Sink) -> ! {
+        if let Err(e) = self.db.lock().unwrap().save_if_dirty() {
+            eprintln!("Failed to save database: {}", e);
+        }
+        self.save_state(sink);
+        sink.stop();
+        // Иначе следующий запуск наткнётся на устаревшие сокет и PID
+        let _ = fs::remove_file(paths::socket());
+        let _ = fs::remove_file(paths::pid_file());
+        process::exit(0);
+    }
+
+    // Пути из чужой библиотеки переносятся в свою, если файла нет по исходному пути
+    pub(crate) fn restore(&mut self, snapshot: Snapshot, sink: &Sink) -> Result<(), io::Error> {
+        let start = snapshot.start()?;
+        let files: Vec<PathBuf> = snapshot
+            .files
+            .into_iter()
+            .map(|path| match path.strip_prefix(&snapshot.music_dir) {
+                Ok(relative) if !path.exists() => self.music_dir.join(relative),
+                _ => path,
+            })
+            .collect();
+
+        if files.is_empty() || snapshot.current_index >= files.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Snapshot has no current track",
+            ));
+        }
+
+        self.set_queue(files, snapshot.current_index);
+        sink.set_volume(snapshot.volume.clamp(0.0, 1.0));
+        self.play_from(sink, start)?;
+        self.plugins.volume_changed(sink.volume());
+        Ok(())
+    }
+
+    // Усиление ограничено запасом до истинного пика трека; без анализа выручает лимитер
+    fn open_source(&self, path: &Path) -> Result<TrackSource, io::Error> {
+        let decoder = self
+            .decoders
+            .decode(path, |file| self.preloader.open(file))?;
+        let gain_db = self.track_gain(path);
+        let source = decoder.amplify(limiter::db_to_gain(gain_db));
+        Ok(Monitor::new(Limiter::new(Prebuffer::new(
+            source,
+            self.preloader.policy().buffer_secs,
+        ))))
+    }
+
+    // preamp плюс ReplayGain из тегов или по громкости из анализа,
+    // но не выше запаса до пика трека
+    fn track_gain(&self, path: &Path) -> f32 {
+        let (loudness, analysis_peak) = match self.db.lock().unwrap().analysis(path) {
+            Some(analysis) => (analysis.loudness, Some(analysis.peak)),
+            None => (None, None),
+        };
+        let tags = match self.replaygain.mode {
+            ReplayGainMode::Off => ReplayGain::default(),
+            _ => tags::replay_gain(&self.library_db, path),
+        };
+        let (tag_gain, tag_peak) = self.replaygain.select(&tags);
+        let gain = tag_gain.or_else(|| self.replaygain.loudness_gain(loudness));
+        let total = self.preamp_db + gain.unwrap_or(0.0);
+        match tag_peak.or(analysis_peak) {
+            Some(peak) => total.min(-peak),
+            None => total,
+        }
+    }
+
+    // Треки, которые не открылись, пропускаются; ошибка — только если не открылся ни один
+    pub(crate) fn play(&mut self, sink: &Sink) -> Result<(), io::Error> {
+        let mut attempts = self.files.len().max(1);
+        loop {
+            let Err(e) = self.play_from(sink, Duration::ZERO) else {
+                return Ok(());
+            };
+            let path = self.files[self.current_index].clone();
+            self.track_failed(&path, &e.to_string());
+            attempts -= 1;
+            if attempts == 0 {
+                return Err(e);
+            }
+            if self.shuffle && self.shuffle_bag.is_empty() {
+                self.refill_shuffle();
+            }
+            self.current_index = self.next_index();
+            self.shuffle_bag.pop();
+        }
+    }
+
+    // Трек не открылся: его файла нет или он не декодируется
+    fn track_failed(&mut self, path: &Path, message: &str) {
+        if track_exists(path) {
+            self.report_error(ErrorKind::Decode, Some(path), message);
+        } else if self.missing.insert(path.to_path_buf()) {
+            self.report_error(ErrorKind::Missing, Some(path), message);
+        }
+    }
+
+    // Ошибка попадает в лог, в историю для команды errors и к плагинам
+    pub(crate) fn report_error(&mut self, kind: ErrorKind, path: Option<&Path>, message: &str) {
+        let error = PlaybackError::new(kind, path, message);
+        match path {
+            Some(path) => eprintln!("Skipping {}: {}", path.display(), message),
+            None => eprintln!("Audio {}", message),
+        }
+        self.plugins.playback_error(&error);
+        if self.errors.len() == ERROR_HISTORY {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(error);
+    }
+
+    // Демон продолжает работать; play начнёт текущий трек сначала
+    pub(crate) fn stop(&mut self, sink: &Sink) {
+        self.queued = None;
+        sink.stop();
+        self.stopped = true;
+        self.pause_reason = None;
+        self.plugins.playback_changed(true);
+    }
+
+    // Ошибка воспроизведения из главного цикла останавливает плеер, а не роняет его
+    pub(crate) fn stop_on_error(&mut self, result: Result<(), io::Error>) {
+        if let Err(e) = result {
+            eprintln!("Playback stopped: {}", e);
+            self.stopped = true;
+            self.plugins.playback_changed(true);
+        }
+    }
+
+    fn play_from(&mut self, sink: &Sink, position: Duration) -> Result<(), io::Error> {
+        self.looping = false;
+        self.stopped = false;
+        self.queued = None;
+        sink.stop();
+        sink.set_speed(1.0);
+        sink.append(self.open_source(&self.files[self.current_index])?);
+        if !position.is_zero() {
+            let _ = sink.try_seek(position);
+        }
+        self.track_started(position);
+        Ok(())
+    }
+
+    // Новый sink продолжает текущий трек с той же позиции и в том же состоянии
+    pub(crate) fn rebuild_sink(
+        &mut self,
+        sink: &mut Sink,
+        handle: &OutputStreamHandle,
+    ) -> Result<(), String> {
+        let position = sink.get_pos();
+        // Поставленный заранее трек остаётся в старом sink и будет поставлен снова
+        self.queued = None;
+        let rebuilt = Sink::try_new(handle).map_err(|e| e.to_string())?;
+        rebuilt.set_volume(sink.volume());
+        rebuilt.set_speed(sink.speed());
+        if sink.is_paused() {
+            rebuilt.pause();
+        }
+
+        let path = &self.files[self.current_index];
+        rebuilt.append(self.open_source(path).map_err(|e| e.to_string())?);
+        if rebuilt.try_seek(position).is_err() {
+            // Не все декодеры умеют перематывать; тогда пропускаем начало вручную
+            rebuilt.clear();
+            let source = self.open_source(path).map_err(|e| e.to_string())?;
+            rebuilt.append(source.skip_duration(position));
+            if sink.is_paused() {
+                rebuilt.pause();
+            } else {
+                rebuilt.play();
+            }
+        }
+
+        std::mem::replace(sink, rebuilt).stop();
+        Ok(())
+    }
+
+    pub(crate) fn start_interrupt(
+        &mut self,
+        path: &Path,
+        sink: &Sink,
+        handle: &OutputStreamHandle,
+    ) -> Result<(), io::Error> {
+        let source = self.open_source(path)?;
+        let interrupt = Sink::try_new(handle).map_err(io::Error::other)?;
+        interrupt.set_volume(sink.volume());
+        interrupt.append(source);
+
+        // Повторное прерывание не должно забыть, играла ли музыка изначально
+        let resume = match self.interruption.take() {
+            Some(previous) => previous.resume,
+            None => !sink.is_paused(),
+        };
+        if !sink.is_paused() {
+            sink.pause();
+            self.pause_reason = Some("interrupt".to_string());
+            self.plugins.playback_changed(true);
+        }
+
+        self.interruption = Some(Interruption {
+            sink: interrupt,
+            resume,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn finish_interrupt(&mut self, sink: &Sink) {
+        if !self.interruption.as_ref().is_some_and(|i| i.sink.empty()) {
+            return;
+        }
+        let interruption = self.interruption.take().unwrap();
+        if interruption.resume && self.pause_reason.as_deref() == Some("interrupt") {
+            sink.play();
+            self.pause_reason = None;
+            self.plugins.playback_changed(false);
+        }
+    }
+
+    pub(crate) fn start_noise(
+        &mut self,
+        name: &str,
+        handle: &OutputStreamHandle,
+    ) -> Result<(), io::Error> {
+        let noise = Sink::try_new(handle).map_err(io::Error::other)?;
+        noise.set_volume(self.noise.volume);
+
+        match NoiseKind::parse(name) {
+            Some(kind) => noise.append(Noise::new(kind)),
+            None => {
+                let path = self
+                    .noise
+                    .ambience_dir
+                    .as_deref()
+                    .and_then(|dir| noise::find_sample(Path::new(dir), name))
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, format!("Unknown noise: {}", name))
+                    })?;
+                let source = Decoder::new(fs::File::open(path)?).map_err(io::Error::other)?;
+                noise.append(source.repeat_infinite());
+            }
+        }
+
+        if let Some(previous) = self.noise_sink.replace(noise) {
+            previous.stop();
+        }
+        Ok(())
+    }
+
+    // Трек по номеру в очереди или по пути, относительному к папке музыки.
+    // Декодируется сразу, чтобы ошибка дошла до клиента; sink откроет главный цикл
+    pub(crate) fn start_preview(&mut self, id: &str) -> Result<(), String> {
+        let path = match id.parse::<usize>() {
+            Ok(index) => self
+                .files
+                .get(index)
+                .cloned()
+                .ok_or_else(|| format!("no track {} in the queue", index))?,
+            Err(_) if id.is_empty() => return Err("usage: preview-start <index|path>".to_string()),
+            Err(_) => self.music_dir.join(id),
+        };
+        let source = self
+            .decoders
+            .decode(&path, |file| self.preloader.open(file))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let source = source.amplify(limiter::db_to_gain(self.track_gain(&path)));
+        self.pending_preview = Some(self.preview.clip(source));
+        self.preview_track = Some(path);
+        Ok(())
+    }
+
+    pub(crate) fn stop_preview(&mut self) {
+        self.pending_preview = None;
+        self.preview_track = None;
+        if let Some(preview) = self.preview_sink.take() {
+            preview.stop();
+        }
+    }
+
+    fn track_started(&mut self, position: Duration) {
+        self.queue_failed = false;
+        self.missing.remove(&self.files[self.current_index]);
+        self.db
+            .lock()
+            .unwrap()
+            .record_play(&self.files[self.current_index], self.listener.as_deref());
+        self.library_db
+            .lock()
+            .unwrap()
+            .record_play(&self.files[self.current_index]);
+        println!(
+            "{}",
+            i18n::tr("now-playing", &[("track", &self.current_track())])
+        );
+        let track = self.plugins.metadata(&self.files[self.current_index]);
+        self.plugins.track_changed(&track, position);
+        let upcoming = self.upcoming(self.preloader.policy().tracks_ahead);
+        self.preloader.prefetch(upcoming);
+    }
+
+    // Следующие треки в порядке воспроизведения, с учётом перемешивания
+    fn upcoming(&self, count: usize) -> Vec<PathBuf> {
+        let indices: Vec<usize> = if self.shuffle {
+            self.shuffle_bag.iter().rev().copied().collect()
+        } else {
+            let wrap = if self.repeat == Repeat::All {
+                self.current_index
+            } else {
+                0
+            };
+            (self.current_index + 1..self.files.len())
+                .chain(0..wrap)
+                .collect()
+        };
+        indices
+            .into_iter()
+            .take(count)
+            .map(|i| self.files[i].clone())
+            .collect()
+    }
+
+    // Ручной переход дальше раньше порога — пропуск текущего трека
+    pub(crate) fn count_skip(&self, played: Duration) {
+        let path = &self.files[self.current_index];
+        let duration = {
+            let db = self.db.lock().unwrap();
+            self.duration_of(&db, path)
+        };
+        if self.skips.is_skip(played, duration) {
+            self.library_db.lock().unwrap().record_skip(path);
+        }
+    }
+
+    // Длительность из анализа, иначе из заголовка файла
+    fn duration_of(&self, db: &Database, path: &Path) -> Option<f32> {
+        db.analysis(path)
+            .map(|analysis| analysis.duration)
+            .or_else(|| self.library_db.lock().unwrap().duration(path))
+    }
+
+    // Время до конца текущего трека по известной длительности
+    fn remaining(&self, sink: &Sink) -> Option<Duration> {
+        let db = self.db.lock().unwrap();
+        let duration = self.duration_of(&db, &self.files[self.current_index])?;
+        let left = (duration - sink.get_pos().as_secs_f32()).max(0.0) / sink.speed();
+        Some(Duration::from_secs_f32(left))
+    }
+
+    // Сколько осталось играть очереди по длительностям из базы
+    // и сколько треков без известной длительности не учтено
+    pub(crate) fn queue_remaining(&self, sink: &Sink) -> (Duration, usize) {
+        let mut unknown = 0;
+        let mut left = self.remaining(sink).unwrap_or_else(|| {
+            unknown += 1;
+            Duration::ZERO
+        });
+
+        let upcoming: Vec<&PathBuf> = if self.shuffle {
+            self.shuffle_bag.iter().map(|&i| &self.files[i]).collect()
+        } else {
+            self.files[self.current_index + 1..].iter().collect()
+        };
+
+        let db = self.db.lock().unwrap();
+        for path in upcoming {
+            match self.duration_of(&db, path) {
+                Some(duration) => left += Duration::from_secs_f32(duration.max(0.0)),
+                None => unknown += 1,
+            }
+        }
+        (left, unknown)
+    }
+
+    // Ставит следующий трек в sink перед концом текущего, а когда тот доиграл,
+    // переключает на него очередь без перезапуска воспроизведения
+    pub(crate) fn gapless_step(&mut self, sink: &Sink) {
+        if let Some((index, path)) = self.queued.clone() {
+            if sink.len() <= 1 {
+                self.queued = None;
+                // Очередь могла измениться, пока трек ждал в sink
+                self.current_index = match self.files.get(index) {
+                    Some(queued) if *queued == path => index,
+                    _ => match self.files.iter().position(|p| *p == path) {
+                        Some(index) => index,
+                        None => return,
+                    },
+                };
+                if self.shuffle_bag.last() == Some(&self.current_index) {
+                    self.shuffle_bag.pop();
+                }
+                self.track_started(Duration::ZERO);
+            }
+            return;
+        }
+
+        let due = self.gapless
+            && !self.automix.enabled
+            && !self.stopped
+            && !self.looping
+            && !self.queue_failed
+            && self.scan.is_none()
+            && self.detour.is_none()
+            && sink.len() == 1
+            && !sink.is_paused()
+            && (self.repeat == Repeat::One || self.has_next())
+            && !(self.autofill != Autofill::Off && self.last_in_queue())
+            && self.remaining(sink).is_some_and(|r| r <= GAPLESS_LEAD);
+        if !due {
+            return;
+        }
+
+        let index = if self.repeat == Repeat::One {
+            self.current_index
+        } else {
+            if self.shuffle && self.shuffle_bag.is_empty() {
+                self.refill_shuffle();
+            }
+            self.next_index()
+        };
+        let path = self.files[index].clone();
+        match self.open_source(&path) {
+            Ok(source) => {
+                sink.append(source);
+                self.queued = Some((index, path));
+            }
+            // Следующий трек откроется обычным путём, когда sink опустеет,
+            // и попадёт в ошибки, если не откроется и тогда
+            Err(e) => {
+                eprintln!("Failed to queue {}: {}", path.display(), e);
+                self.queue_failed = true;
+            }
+        }
+    }
+
+    pub(crate) fn automix_due(&self, sink: &Sink) -> bool {
+        let crossfade = self.automix.crossfade();
+        self.automix.enabled
+            && !self.looping
+            && self.queued.is_none()
+            && self.repeat != Repeat::One
+            && self.has_next()
+            && self.files.len() > 1
+            && !self.detour_finished()
+            && !sink.is_paused()
+            && sink.get_pos() >= crossfade
+            && self.remaining(sink).is_some_and(|r| r <= crossfade)
+    }
+
+    // Ожидание до ближайшей доли уходящего трека
+    pub(crate) fn beat_wait(&self, sink: &Sink) -> Duration {
+        let db = self.db.lock().unwrap();
+        let Some(analysis) = db.analysis(&self.files[self.current_index]) else {
+            return Duration::ZERO;
+        };
+        let Some(period) = analysis::beat_period(analysis) else {
+            return Duration::ZERO;
+        };
+
+        let since_first = sink.get_pos().as_secs_f32() - analysis.first_beat;
+        let phase = since_first.rem_euclid(period.as_secs_f32());
+        Duration::from_secs_f32((period.as_secs_f32() - phase) / sink.speed())
+    }
+
+    // Запускает следующий трек на новом выходе и возвращает уходящий
+    pub(crate) fn automix_transition(
+        &mut self,
+        sink: &mut Sink,
+        handle: &OutputStreamHandle,
+    ) -> Result<Sink, io::Error> {
+        if self.shuffle && self.shuffle_bag.is_empty() {
+            self.refill_shuffle();
+        }
+        let next_index = self.next_index();
+        let (ratio, first_beat) = {
+            let db = self.db.lock().unwrap();
+            let outgoing = db.analysis(&self.files[self.current_index]);
+            let incoming = db.analysis(&self.files[next_index]);
+            let outgoing_bpm = outgoing.and_then(|a| a.bpm).map(|bpm| bpm * sink.speed());
+            (
+                self.automix
+                    .tempo_ratio(outgoing_bpm, incoming.and_then(|a| a.bpm)),
+                incoming.map(|a| a.first_beat).unwrap_or(0.0),
+            )
+        };
+
+        let source = self.open_source(&self.files[next_index])?;
+        let new_sink = Sink::try_new(handle).map_err(io::Error::other)?;
+        new_sink.set_volume(0.0);
+        new_sink.set_speed(ratio);
+        new_sink.append(source.skip_duration(Duration::from_secs_f32(first_beat)));
+
+        self.current_index = next_index;
+        if self.shuffle {
+            self.shuffle_bag.pop();
+        }
+        self.track_started(Duration::from_secs_f32(first_beat));
+        Ok(std::mem::replace(sink, new_sink))
+    }
+
+    pub(crate) fn start_scan(&mut self, sink: &Sink, forward: bool) {
+        if let Some(ref mut scan) = self.scan {
+            scan.forward = forward;
+            return;
+        }
+        self.scan = Some(Scan {
+            forward,
+            started: Instant::now(),
+            volume: sink.volume(),
+        });
+        sink.set_volume(sink.volume() * SCAN_PREVIEW_VOLUME);
+    }
+
+    pub(crate) fn stop_scan(&mut self, sink: &Sink) {
+        if let Some(scan) = self.scan.take() {
+            sink.set_volume(scan.volume);
+        }
+    }
+
+    // Сдвигает позицию на столько, сколько проиграло бы за `elapsed` на текущей скорости
+    pub(crate) fn scan_step(&mut self, sink: &Sink, elapsed: Duration) {
+        let Some(ref scan) = self.scan else {
+            return;
+        };
+
+        let t = (scan.started.elapsed().as_secs_f32() / SCAN_ACCEL_SECS).min(1.0);
+        let rate = SCAN_MIN_RATE + (SCAN_MAX_RATE - SCAN_MIN_RATE) * t * t;
+        let step = elapsed.mul_f32(rate);
+        let position = sink.get_pos();
+        let target = if scan.forward {
+            position + step
+        } else {
+            position.saturating_sub(step)
+        };
+
+        if sink.try_seek(target).is_err() {
+            self.stop_scan(sink);
+        }
+    }
+
+    pub(crate) fn set_sleep(&mut self, sink: &Sink, after: Duration, quit: bool) {
+        self.cancel_sleep(sink);
+        self.sleep = Some(SleepTimer::new(after, quit));
+    }
+
+    pub(crate) fn cancel_sleep(&mut self, sink: &Sink) {
+        if let Some(volume) = self.sleep.take().and_then(|timer| timer.volume) {
+            sink.set_volume(volume);
+        }
+    }
+
+    pub(crate) fn sleep_remaining(&self) -> Option<Duration> {
+        self.sleep.as_ref().map(SleepTimer::remaining)
+    }
+
+    pub(crate) fn sleep_step(&mut self, sink: &Sink) {
+        let Some(ref mut timer) = self.sleep else {
+            return;
+        };
+        let (volume, quit) = match timer.step(sink.volume()) {
+            SleepStep::Wait => return,
+            SleepStep::Fade(volume) => {
+                sink.set_volume(volume);
+                return;
+            }
+            SleepStep::Expired { volume, quit } => (volume, quit),
+        };
+
+        self.sleep = None;
+        // Утром музыка продолжится с прежней громкостью
+        sink.set_volume(volume);
+        if quit {
+            self.shutdown(sink);
+        }
+        sink.pause();
+        self.pause_reason = Some("sleep".to_string());
+        self.plugins.playback_changed(true);
+    }
+
+    // Очередь с метаданными и длительностями из базы
+    pub(crate) fn queue_json(&self) -> serde_json::Value {
+        let db = self.db.lock().unwrap();
+        let tracks: Vec<serde_json::Value> = self
+            .files
+            .iter()
+            .map(|path| self.track_json(&db, path))
+            .collect();
+        serde_json::json!({
+            "revision": self.queue_log.revision(),
+            "current": self.current_index,
+            "tracks": tracks,
+        })
+    }
+
+    fn track_json(&self, db: &Database, path: &Path) -> serde_json::Value {
+        let track = self.plugins.metadata(path);
+        serde_json::json!({
+            "path": path,
+            "title": track.title,
+            "artist": track.artist,
+            "album": track.album,
+            "track_number": track.track_number,
+            "duration": self.duration_of(db, path),
+            "missing": self.missing.contains(path),
+        })
+    }
+
+    // Правки очереди после ревизии since по порядку; если журнал их уже
+    // не помнит, вместо них вся очередь с "reset": true
+    pub(crate) fn queue_changes_json(&self, since: u64) -> serde_json::Value {
+        let Some(changes) = self.queue_log.since(since) else {
+            let mut queue = self.queue_json();
+            queue["reset"] = serde_json::json!(true);
+            return queue;
+        };
+        let db = self.db.lock().unwrap();
+        let changes: Vec<serde_json::Value> = changes
+            .into_iter()
+            .map(|(revision, change)| match change {
+                Change::Splice {
+                    index,
+                    remove,
+                    insert,
+                } => serde_json::json!({
+                    "revision": revision,
+                    "op": "splice",
+                    "index": index,
+                    "remove": remove,
+                    "insert": insert
+                        .iter()
+                        .map(|path| self.track_json(&db, path))
+                        .collect::<Vec<_>>(),
+                }),
+                Change::Move { from, to } => serde_json::json!({
+                    "revision": revision,
+                    "op": "move",
+                    "from": from,
+                    "to": to,
+                }),
+            })
+            .collect();
+        serde_json::json!({
+            "revision": self.queue_log.revision(),
+            "current": self.current_index,
+            "reset": false,
+            "changes": changes,
+        })
+    }
+
+    // Треки библиотеки, в пути которых есть все слова запроса
+    pub(crate) fn search_json(&self, query: &str, limit: usize) -> serde_json::Value {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let results: Vec<serde_json::Value> = self
+            .library
+            .iter()
+            .filter(|path| {
+                let haystack = path
+                    .strip_prefix(&self.music_dir)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .to_lowercase();
+                !terms.is_empty() && terms.iter().all(|term| haystack.contains(term))
+            })
+            .take(limit)
+            .map(|path| {
+                let track = self.plugins.metadata(path);
+                serde_json::json!({
+                    "path": path,
+                    "title": track.title,
+                    "artist": track.artist,
+                    "album": track.album,
+                })
+            })
+            .collect();
+        serde_json::json!({ "results": results })
+    }
+
+    // Очередь пустой не бывает, поэтому в ней остаётся текущий трек
+    pub(crate) fn clear_queue(&mut self) {
+        let current = self.files[self.current_index].clone();
+        self.set_queue(vec![current], 0);
+    }
+
+    // Треки файла, папки или плейлиста в конец очереди
+    pub(crate) fn add_to_queue(&mut self, path: &Path, sink: &Sink) -> Result<usize, io::Error> {
+        let files = scan_music(path, &self.scan_options)?;
+        let start = self.files.len();
+        self.files.extend(files);
+        self.bag_new_tracks(start..self.files.len());
+        self.unqueue(sink);
+        self.queue_changed();
+        Ok(self.files.len() - start)
+    }
+
+    // Удаление текущего трека сразу включает тот, что встал на его место
+    pub(crate) fn remove_from_queue(&mut self, index: usize, sink: &Sink) -> Result<(), String> {
+        if index >= self.files.len() {
+            return Err(format!("invalid queue index: {}", index));
+        }
+        if self.files.len() == 1 {
+            return Err("cannot remove the only track in the queue".to_string());
+        }
+        self.files.remove(index);
+        let current = index == self.current_index;
+        self.shuffle_bag.retain(|&i| i != index);
+        self.remap(|i| if i > index { i - 1 } else { i });
+        self.queue_changed();
+        if !current {
+            self.unqueue(sink);
+            return Ok(());
+        }
+
+        self.current_index = index % self.files.len();
+        let next = self.current_index;
+        self.shuffle_bag.retain(|&i| i != next);
+        if self.stopped {
+            return Ok(());
+        }
+        self.play(sink).map_err(|e| e.to_string())
+    }
+
+    // Текущий трек продолжает играть, где бы он ни оказался
+    pub(crate) fn move_in_queue(
+        &mut self,
+        from: usize,
+        to: usize,
+        sink: &Sink,
+    ) -> Result<(), String> {
+        let len = self.files.len();
+        if from >= len || to >= len {
+            return Err(format!("invalid queue index: {} {}", from, to));
+        }
+        let path = self.files.remove(from);
+        self.files.insert(to, path);
+        self.remap(|i| {
+            if i == from {
+                return to;
+            }
+            let shifted = if i > from { i - 1 } else { i };
+            if shifted >= to {
+                shifted + 1
+            } else {
+                shifted
+            }
+        });
+        self.unqueue(sink);
+        self.queue_changed();
+        Ok(())
+    }
+
+    pub(crate) fn add_drive(&mut self, mount: &Path, files: Vec<PathBuf>) {
+        println!("Indexed {} tracks on {}", files.len(), mount.display());
+        self.library.retain(|path| !path.starts_with(mount));
+        self.extend_library(files);
+        if !self.drives.iter().any(|drive| drive == mount) {
+            self.drives.push(mount.to_path_buf());
+        }
+    }
+
+    // Библиотека держится отсортированной: на этом порядке стоит курсор /api/library
+    fn extend_library(&mut self, added: impl IntoIterator<Item = PathBuf>) {
+        self.library.extend(added);
+        self.library.sort();
+        self.library.dedup();
+    }
+
+    // Часть библиотеки под root изменилась на диске; files — то, что там есть теперь.
+    // Если играет вся библиотека, новые треки встают и в очередь, на свои места по порядку
+    pub(crate) fn update_library(&mut self, root: &Path, files: Vec<PathBuf>, sink: &Sink) {
+        let whole_library = self.files == self.library;
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        let gone: HashSet<PathBuf> = self
+            .library
+            .iter()
+            .filter(|path| path.starts_with(root) && !present.contains(path))
+            .cloned()
+            .collect();
+        let known: HashSet<&PathBuf> = self.library.iter().collect();
+        let mut added: Vec<PathBuf> = files
+            .iter()
+            .filter(|path| !known.contains(path))
+            .cloned()
+            .collect();
+        added.sort();
+        if gone.is_empty() && added.is_empty() {
+            return;
+        }
+        println!(
+            "Library: {} added, {} removed under {}",
+            added.len(),
+            gone.len(),
+            root.display()
+        );
+
+        if !gone.is_empty() {
+            self.forget(sink, |path| gone.contains(path));
+        }
+        if whole_library && !self.files.is_empty() {
+            for path in &added {
+                let at = self.files.partition_point(|queued| queued < path);
+                self.files.insert(at, path.clone());
+                self.remap(|i| if i >= at { i + 1 } else { i });
+                self.bag_new_tracks(at..at + 1);
+            }
+            self.unqueue(sink);
+            self.queue_changed();
+        }
+        self.extend_library(added);
+    }
+
+    // Треки отключённого носителя уходят из библиотеки и очереди,
+    // чтобы плеер не пытался открыть несуществующие пути
+    pub(crate) fn remove_drive(&mut self, mount: &Path, sink: &Sink) {
+        self.drives.retain(|drive| drive != mount);
+        self.forget(sink, |path| path.starts_with(mount));
+    }
+
+    // Убирает пропавшие треки отовсюду; если играл один из них, играет следующий оставшийся
+    fn forget(&mut self, sink: &Sink, gone: impl Fn(&Path) -> bool) {
+        self.library.retain(|path| !gone(path));
+        if let Some(ref mut detour) = self.detour {
+            detour.files.retain(|path| !gone(path));
+            if detour.files.is_empty() {
+                self.detour = None;
+            } else {
+                detour.current_index = detour.current_index.min(detour.files.len() - 1);
+            }
+        }
+        if !self.files.iter().any(|path| gone(path)) {
+            return;
+        }
+
+        if self.files.iter().all(|path| gone(path)) {
+            // Пустой очереди не бывает: играет библиотека, а если и она пуста — тишина
+            self.queued = None;
+            sink.stop();
+            if self.library.is_empty() {
+                self.stopped = true;
+                self.plugins.playback_changed(true);
+                return;
+            }
+            self.set_queue(self.library.clone(), 0);
+            if let Err(e) = self.play(sink) {
+                eprintln!("Failed to play {}: {}", self.current_track(), e);
+            }
+            return;
+        }
+
+        // Новый индекс каждого трека; на место удалённого встаёт следующий оставшийся
+        let current_gone = gone(&self.files[self.current_index]);
+        let mut map = Vec::with_capacity(self.files.len());
+        let mut kept = 0;
+        for path in &self.files {
+            map.push(kept);
+            if !gone(path) {
+                kept += 1;
+            }
+        }
+        let files = &self.files;
+        self.shuffle_bag.retain(|&i| !gone(&files[i]));
+        self.files.retain(|path| !gone(path));
+        self.remap(|i| map[i].min(kept - 1));
+        self.queue_changed();
+        if !current_gone {
+            self.unqueue(sink);
+            return;
+        }
+        let current = self.current_index;
+        self.shuffle_bag.retain(|&i| i != current);
+        if self.stopped {
+            self.queued = None;
+            sink.stop();
+        } else if let Err(e) = self.play(sink) {
+            eprintln!("Failed to play {}: {}", self.current_track(), e);
+        }
+    }
+
+    // Индексы текущего трека, перемешанного остатка и поставленного в sink
+    // после изменения порядка очереди
+    fn remap(&mut self, map: impl Fn(usize) -> usize) {
+        self.current_index = map(self.current_index);
+        for index in &mut self.shuffle_bag {
+            *index = map(*index);
+        }
+        if let Some((index, _)) = &mut self.queued {
+            *index = map(*index);
+        }
+    }
+
+    // Трек, заранее поставленный в sink для gapless, убирается,
+    // если после правки очереди следующим должен играть другой
+    pub(crate) fn unqueue(&mut self, sink: &Sink) {
+        let Some((_, path)) = self.queued.clone() else {
+            return;
+        };
+        let next = if self.repeat == Repeat::One {
+            self.current_index
+        } else {
+            self.next_index()
+        };
+        if self.files.get(next) == Some(&path) {
+            return;
+        }
+        // Текущий трек открывается заново с той же позиции, без нового track_started
+        self.queued = None;
+        let position = sink.get_pos();
+        sink.stop();
+        match self.open_source(&self.files[self.current_index]) {
+            Ok(source) => {
+                sink.append(source);
+                let _ = sink.try_seek(position);
+            }
+            Err(e) => eprintln!("Failed to reopen current track: {}", e),
+        }
+    }
+
+    // Последний трек доигрывает сам, и очередь ничем не продлится
+    pub(crate) fn at_queue_end(&self) -> bool {
+        self.last_in_queue() && self.detour.is_none() && self.autofill == Autofill::Off
+    }
+
+    fn has_next(&self) -> bool {
+        !self.at_queue_end() || self.repeat == Repeat::All
+    }
+
+    // queue_end repeat и repeat_all — одно и то же состояние
+    pub(crate) fn set_queue_end(&mut self, action: QueueEnd) {
+        if action == QueueEnd::Repeat {
+            self.repeat = Repeat::All;
+        } else if self.repeat == Repeat::All {
+            self.repeat = Repeat::Off;
+        }
+        self.on_queue_end = action;
+    }
+
+    pub(crate) fn set_repeat(&mut self, repeat: Repeat) {
+        if repeat == Repeat::All {
+            self.on_queue_end = QueueEnd::Repeat;
+        } else if self.on_queue_end == QueueEnd::Repeat {
+            self.on_queue_end = QueueEnd::Stop;
+        }
+        self.repeat = repeat;
+    }
+
+    pub(crate) fn queue_finished(&mut self, sink: &Sink) -> Result<(), io::Error> {
+        let finished = self.files[self.current_index].clone();
+        match self.on_queue_end.clone() {
+            QueueEnd::Repeat => return self.next(sink),
+            QueueEnd::Stop => {}
+            QueueEnd::Quit => self.shutdown(sink),
+            QueueEnd::Suspend => {
+                if let Err(e) = process::Command::new("systemctl").arg("suspend").status() {
+                    eprintln!("Failed to suspend: {}", e);
+                }
+            }
+            QueueEnd::Hook(command) => {
+                let result = process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&command)
+                    .env("NSMP_LAST_TRACK", &finished)
+                    .spawn();
+                if let Err(e) = result {
+                    eprintln!("Failed to run queue end hook: {}", e);
+                }
+            }
+        }
+
+        self.current_index = 0;
+        self.stopped = true;
+        self.plugins.playback_changed(true);
+        Ok(())
+    }
+
+    // Для плагинов новая песня в потоке радио — такая же смена трека
+    pub(crate) fn stream_song_step(&mut self, sink: &Sink) {
+        let changes = stream::song_changes();
+        if changes == self.stream_songs {
+            return;
+        }
+        self.stream_songs = changes;
+        let path = &self.files[self.current_index];
+        if self.stopped || !stream::is_stream(path) {
+            return;
+        }
+        let track = self.plugins.metadata(path);
+        println!(
+            "{}",
+            i18n::tr("now-playing", &[("track", &track.display())])
+        );
+        self.plugins.track_changed(&track, sink.get_pos());
+    }
+
+    pub(crate) fn next(&mut self, sink: &Sink) -> Result<(), io::Error> {
+        if self.detour_finished() {
+            let snapshot = self.detour.take().unwrap();
+            return self.restore(snapshot, sink);
+        }
+        if self.last_in_queue() {
+            let fill = match self.autofill {
+                Autofill::Off => None,
+                Autofill::Similar => self.similar(1).pop(),
+                Autofill::Mix => self.mix.as_mut().and_then(Mix::next_track),
+            };
+            if let Some(path) = fill {
+                self.files.push(path);
+                self.shuffle_bag.push(self.files.len() - 1);
+                self.queue_changed();
+            }
+        }
+        if self.shuffle && self.shuffle_bag.is_empty() {
+            self.refill_shuffle();
+        }
+        self.current_index = self.next_index();
+        self.shuffle_bag.pop();
+        self.play(sink)
+    }
+
+    // Ближайшие по признакам треки библиотеки, которых ещё нет в очереди
+    pub(crate) fn similar(&self, k: usize) -> Vec<PathBuf> {
+        let queued: HashSet<&PathBuf> = self.files.iter().collect();
+        let candidates: Vec<&PathBuf> = self
+            .library
+            .iter()
+            .filter(|path| !queued.contains(path))
+            .collect();
+        self.db
+            .lock()
+            .unwrap()
+            .nearest(&self.files[self.current_index], &candidates, k)
+    }
+
+    // Текущий трек продолжает играть, за ним в очереди похожие
+    pub(crate) fn play_similar(&mut self, k: usize) -> Result<(), io::Error> {
+        let current = self.files[self.current_index].clone();
+        let similar = self.similar(k);
+        if similar.is_empty() {
+            db::spawn_analyzer(Arc::clone(&self.db), self.library.clone());
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                i18n::tr("track-not-analyzed", &[]),
+            ));
+        }
+
+        self.set_queue(std::iter::once(current).chain(similar).collect(), 0);
+        Ok(())
+    }
+
+    pub(crate) fn prev(&mut self, sink: &Sink) -> Result<(), io::Error> {
+        self.current_index = if self.current_index == 0 {
+            self.files.len() - 1
+        } else {
+            self.current_index - 1
+        };
+        self.play(sink)
+    }
+
+    fn current_track(&self) -> String {
+        self.plugins
+            .metadata(&self.files[self.current_index])
+            .display()
+    }
+}
+
+// Пересканирование папки при запуске: в базу попадают только изменения,
+// и если очередь — вся библиотека, она обновляется с тем же текущим треком
+pub fn spawn_rescan(player: Arc<Mutex<MusicPlayer>>) {
+    let (music_dir, scan_options, library_db) = {
+        let player = player.lock().unwrap();
+        (
+            player.music_dir.clone(),
+            player.scan_options.clone(),
+            Arc::clone(&player.library_db),
+        )
+    };
+
+    thread::spawn(move || {
+        let files = match scan_music(&music_dir, &scan_options) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Failed to rescan {}: {}", music_dir.display(), e);
+                return;
+            }
+        };
+        let report = match library_db.lock().unwrap().sync(&music_dir, &files) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Failed to update library database: {}", e);
+                return;
+            }
+        };
+        if report.added + report.removed == 0 {
+            return;
+        }
+        println!(
+            "Library: {} added, {} changed, {} removed",
+            report.added, report.changed, report.removed
+        );
+
+        let mut player = player.lock().unwrap();
+        if player.files == player.library {
+            let current = player.files[player.current_index].clone();
+            let index = files.iter().position(|path| *path == current);
+            if let Some(index) = index {
+                player.files = files.clone();
+                player.current_index = index;
+                player.refill_shuffle();
+                player.queue_changed();
+            }
+        }
+        // Треки подключённых носителей остаются в библиотеке
+        let drives = player.drives.clone();
+        let on_drives: Vec<PathBuf> = player
+            .library
+            .iter()
+            .filter(|path| drives.iter().any(|drive| path.starts_with(drive)))
+            .cloned()
+            .collect();
+        player.library = files;
+        player.extend_library(on_drives);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(position: f32, volume: f32) -> Snapshot {
+        Snapshot {
+            music_dir: PathBuf::from("/music"),
+            files: vec![PathBuf::from("/music/a.flac")],
+            current_index: 0,
+            position,
+            volume,
+        }
+    }
+
+    #[test]
+    fn snapshot_rejects_unplayable_position() {
+        assert_eq!(
+            snapshot(12.5, 0.5).start().unwrap(),
+            Duration::from_secs_f32(12.5)
+        );
+        assert_eq!(snapshot(-3.0, 0.5).start().unwrap(), Duration::ZERO);
+        for position in [1e30, f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            let e = snapshot(position, 0.5).start().unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", position);
+        }
+        assert!(snapshot(0.0, f32::NAN).start().is_err());
+    }
+
+    #[test]
+    fn snapshot_survives_a_round_trip() {
+        let json = serde_json::to_string(&snapshot(42.0, 0.7)).unwrap();
+        let back: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.files, [PathBuf::from("/music/a.flac")]);
+        assert_eq!(
+            (back.current_index, back.position, back.volume),
+            (0, 42.0, 0.7)
+        );
+        assert_eq!(back.start().unwrap(), Duration::from_secs(42));
+        // Число вне f32 в присланном снимке не роняет разбор и не доходит до плеера
+        assert!(serde_json::from_str::<Snapshot>(
+            r#"{"music_dir":"/m","files":["/m/a"],"current_index":0,"position":1e30,"volume":1}"#
+        )
+        .unwrap()
+        .start()
+        .is_err());
+    }
+
+    #[test]
+    fn sleep_timer_fades_then_expires() {
+        let _clock = clock::simulated_for_test();
+        let mut timer = SleepTimer::new(Duration::from_secs(60), true);
+        assert!(matches!(timer.step(0.8), SleepStep::Wait));
+
+        clock::advance(Duration::from_secs(45)).unwrap();
+        match timer.step(0.8) {
+            SleepStep::Fade(volume) => assert!((volume - 0.4).abs() < 0.01, "{}", volume),
+            _ => panic!("timer should fade halfway through SLEEP_FADE"),
+        }
+        // Затухание считается от громкости в его начале, а не от уже убавленной
+        clock::advance(Duration::from_secs(15)).unwrap();
+        assert!(matches!(
+            timer.step(0.4),
+            SleepStep::Expired { volume, quit: true } if volume == 0.8
+        ));
+    }
+}
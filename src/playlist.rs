@@ -0,0 +1,347 @@
+use crate::atomic;
+use crate::stream;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Номер текущего трека в сохранённой очереди
+const CURRENT_TAG: &str = "#NSMP-CURRENT:";
+
+// Доля совпадения имён, начиная с которой файл считается тем же треком
+const FUZZY_THRESHOLD: f32 = 0.8;
+
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub kept: usize,
+    pub mapped: usize,
+    pub matched: usize,
+    pub missing: Vec<String>,
+}
+
+// Переписывает битые пути плейлиста: сначала по префиксам old=new,
+// затем по похожему имени файла в библиотеке. Комментарии и #EXTINF сохраняются
+pub fn repair(
+    playlist: &Path,
+    maps: &[(PathBuf, PathBuf)],
+    library: &[PathBuf],
+) -> Result<RepairReport, String> {
+    if !is_playlist(playlist) {
+        return Err(format!("{}: not an m3u playlist", playlist.display()));
+    }
+    let text = fs::read_to_string(playlist).map_err(|e| e.to_string())?;
+    let base = playlist.parent().unwrap_or(Path::new("."));
+    let mut report = RepairReport::default();
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        let entry = line.trim();
+        if entry.is_empty() || entry.starts_with('#') || entry.contains("://") {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let relative = Path::new(entry).is_relative();
+        let path = base.join(entry);
+        if path.exists() {
+            report.kept += 1;
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let repaired = if let Some(mapped) = apply_maps(&path, maps).filter(|p| p.exists()) {
+            report.mapped += 1;
+            mapped
+        } else if let Some(found) = fuzzy_find(&path, library) {
+            report.matched += 1;
+            found
+        } else {
+            report.missing.push(entry.to_string());
+            lines.push(line.to_string());
+            continue;
+        };
+
+        // Относительные записи остаются относительными, если это возможно
+        let written = match repaired.strip_prefix(base) {
+            Ok(stripped) if relative => stripped.to_path_buf(),
+            _ => repaired,
+        };
+        lines.push(written.to_string_lossy().into_owned());
+    }
+
+    if report.mapped + report.matched > 0 {
+        let mut text = lines.join("\n");
+        text.push('\n');
+        atomic::write(playlist, text).map_err(|e| e.to_string())?;
+    }
+    Ok(report)
+}
+
+// Пути из плейлиста; относительные записи считаются от папки плейлиста.
+// Адреса http(s) остаются как есть, прочие потоки и отсутствующие файлы пропускаются
+pub fn load(playlist: &Path) -> Result<Vec<PathBuf>, String> {
+    load_with_current(playlist).map(|(files, _)| files)
+}
+
+// То же, плюс номер трека, на котором очередь была сохранена
+pub fn load_with_current(playlist: &Path) -> Result<(Vec<PathBuf>, usize), String> {
+    let bytes = fs::read(playlist).map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&bytes);
+    let base = playlist.parent().unwrap_or(Path::new("."));
+
+    let mut files = Vec::new();
+    let mut skipped = 0;
+    let mut saved_current = None;
+    let mut current = 0;
+    for line in text.lines() {
+        let entry = line.trim().trim_start_matches('\u{feff}');
+        if let Some(index) = entry.strip_prefix(CURRENT_TAG) {
+            saved_current = index.trim().parse::<usize>().ok();
+            continue;
+        }
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+        let path = match entry.strip_prefix("file://") {
+            Some(uri) => PathBuf::from(crate::scan::percent_decode(uri)),
+            None if stream::is_stream(Path::new(entry)) => PathBuf::from(entry),
+            None if entry.contains("://") => {
+                skipped += 1;
+                continue;
+            }
+            None => base.join(entry),
+        };
+        if path.is_file() || stream::is_stream(&path) {
+            // Пропущенные записи сдвигают номер текущего трека
+            if saved_current == Some(files.len() + skipped) {
+                current = files.len();
+            }
+            files.push(path);
+        } else {
+            skipped += 1;
+        }
+    }
+
+    if skipped > 0 {
+        eprintln!(
+            "{}: skipped {} missing entries",
+            playlist.display(),
+            skipped
+        );
+    }
+    if files.is_empty() {
+        return Err(format!("{}: no playable entries", playlist.display()));
+    }
+    Ok((files, current))
+}
+
+// Очередь в порядке воспроизведения с номером текущего трека
+pub fn save(playlist: &Path, files: &[PathBuf], current: usize) -> Result<(), String> {
+    if !is_playlist(playlist) {
+        return Err(format!("{}: not an m3u playlist", playlist.display()));
+    }
+    let mut text = format!("#EXTM3U\n{}{}\n", CURRENT_TAG, current);
+    for path in files {
+        text.push_str(&path.to_string_lossy());
+        text.push('\n');
+    }
+    if let Some(dir) = playlist.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    atomic::write(playlist, text).map_err(|e| e.to_string())
+}
+
+// Плейлист для чтения: по пути к файлу или по имени в папке плейлистов
+pub fn find(name: &str, dir: &Path) -> Result<PathBuf, String> {
+    let path = Path::new(name);
+    if path.components().count() > 1 || path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    resolve(name, dir)
+}
+
+// Плейлист по имени строго внутри папки плейлистов: сюда пишут команды,
+// пришедшие и по сети, поэтому ни "..", ни абсолютных путей, ни выхода по ссылкам
+pub fn resolve(name: &str, dir: &Path) -> Result<PathBuf, String> {
+    let invalid = || format!("Invalid playlist name: {}", name);
+    let path = Path::new(name);
+    let plain = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    let hidden = path
+        .file_name()
+        .is_none_or(|file| file.to_string_lossy().starts_with('.'));
+    if name.is_empty() || !plain || hidden {
+        return Err(invalid());
+    }
+    let path = if is_playlist(path) {
+        dir.join(name)
+    } else {
+        dir.join(format!("{}.m3u8", name))
+    };
+    if !inside(&path, dir) {
+        return Err(invalid());
+    }
+    Ok(path)
+}
+
+// Ближайший существующий предок пути после раскрытия ссылок лежит в dir
+fn inside(path: &Path, dir: &Path) -> bool {
+    let Ok(dir) = fs::canonicalize(dir) else {
+        // Папки ещё нет, значит и ссылок в ней нет
+        return true;
+    };
+    // Ссылка на месте самого файла тоже не должна вести наружу
+    let mut existing = path;
+    while fs::symlink_metadata(existing).is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return false,
+        }
+    }
+    fs::canonicalize(existing).is_ok_and(|real| real.starts_with(&dir))
+}
+
+pub fn delete(playlist: &Path) -> Result<(), String> {
+    if !is_playlist(playlist) {
+        return Err(format!("{}: not an m3u playlist", playlist.display()));
+    }
+    fs::remove_file(playlist).map_err(|e| e.to_string())
+}
+
+pub fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+}
+
+// Аргумент вида "/old/root=/new/root"
+pub fn parse_map(value: &str) -> Option<(PathBuf, PathBuf)> {
+    let (old, new) = value.split_once('=')?;
+    (!old.is_empty() && !new.is_empty()).then(|| (PathBuf::from(old), PathBuf::from(new)))
+}
+
+fn apply_maps(path: &Path, maps: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+    maps.iter()
+        .find_map(|(old, new)| path.strip_prefix(old).ok().map(|rest| new.join(rest)))
+}
+
+fn fuzzy_find(path: &Path, library: &[PathBuf]) -> Option<PathBuf> {
+    let wanted = normalized_stem(path);
+    if wanted.is_empty() {
+        return None;
+    }
+
+    library
+        .iter()
+        .map(|candidate| (similarity(&wanted, &normalized_stem(candidate)), candidate))
+        .filter(|(score, _)| *score >= FUZZY_THRESHOLD)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate.clone())
+}
+
+// Имя без расширения, регистра и знаков: "01 - Song (Remastered).mp3" -> "01songremastered"
+fn normalized_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| {
+            stem.to_string_lossy()
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 1 - расстояние Левенштейна, нормированное на длину большей строки
+fn similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    1.0 - previous[b.len()] as f32 / longest as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("nsmp-playlist-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn resolve_stays_inside_the_folder() {
+        let dir = temp_dir("resolve");
+        assert_eq!(resolve("road", &dir).unwrap(), dir.join("road.m3u8"));
+        assert_eq!(resolve("old.m3u", &dir).unwrap(), dir.join("old.m3u"));
+        assert_eq!(
+            resolve("notes.txt", &dir).unwrap(),
+            dir.join("notes.txt.m3u8")
+        );
+        for name in ["", "../road", "/etc/passwd.m3u", ".hidden", "a/../../b"] {
+            assert!(resolve(name, &dir).is_err(), "{}", name);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/tmp", dir.join("out")).unwrap();
+            assert!(resolve("out/road", &dir).is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repair_maps_prefixes_and_matches_similar_names() {
+        let dir = temp_dir("repair");
+        let music = dir.join("music");
+        fs::create_dir_all(music.join("Album")).unwrap();
+        for track in [
+            "kept.flac",
+            "Album/moved.flac",
+            "01 - Song (Remastered).mp3",
+        ] {
+            fs::write(music.join(track), "").unwrap();
+        }
+        let playlist = dir.join("mix.m3u8");
+        let text = "#EXTM3U\n\
+                    music/kept.flac\n\
+                    /old/music/Album/moved.flac\n\
+                    /gone/01 Song Remastered.flac\n\
+                    /gone/nothing like it.flac\n";
+        fs::write(&playlist, text).unwrap();
+
+        let maps = [parse_map(&format!("/old/music={}", music.display())).unwrap()];
+        let library = [music.join("01 - Song (Remastered).mp3")];
+        let report = repair(&playlist, &maps, &library).unwrap();
+        assert_eq!((report.kept, report.mapped, report.matched), (1, 1, 1));
+        assert_eq!(report.missing, ["/gone/nothing like it.flac"]);
+        let repaired = fs::read_to_string(&playlist).unwrap();
+        let lines: Vec<&str> = repaired.lines().collect();
+        assert_eq!(lines[0], "#EXTM3U");
+        assert_eq!(lines[1], "music/kept.flac");
+        assert_eq!(Path::new(lines[2]), music.join("Album/moved.flac"));
+        assert_eq!(Path::new(lines[3]), library[0]);
+        assert_eq!(lines[4], "/gone/nothing like it.flac");
+
+        fs::write(dir.join("notes.txt"), "music/kept.flac\n").unwrap();
+        assert!(repair(&dir.join("notes.txt"), &maps, &library).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
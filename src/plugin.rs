@@ -0,0 +1,428 @@
+use rodio::{OutputStream, OutputStreamHandle};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Версия интерфейса плагинов; динамические плагины собираются тем же компилятором
+pub const PLUGIN_API_VERSION: u32 = 5;
+
+// События одного вида уходят подписчикам не чаще раза в этот интервал
+const EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
+const REGISTER_SYMBOL: &str = "nsmp_plugin_register";
+const VERSION_SYMBOL: &str = "NSMP_PLUGIN_API_VERSION";
+
+pub type RegisterFn = fn(&mut PluginRegistry, &serde_json::Value) -> Result<(), String>;
+
+type SendFn = dyn Fn(&str) -> Result<String, String> + Send + Sync;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackMetadata {
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+}
+
+impl TrackMetadata {
+    pub fn display(&self) -> String {
+        match self.artist {
+            Some(ref artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+}
+
+// Отправка команд плееру тем же путём, что и через сокет
+#[derive(Clone)]
+pub struct Control {
+    send: Arc<SendFn>,
+}
+
+impl Control {
+    pub fn new(send: impl Fn(&str) -> Result<String, String> + Send + Sync + 'static) -> Self {
+        Self {
+            send: Arc::new(send),
+        }
+    }
+
+    pub fn send(&self, cmd: &str) -> Result<(), String> {
+        (self.send)(cmd).map(|_| ())
+    }
+
+    // Команда с ответом плеера, например JSON от "status".
+    // Нельзя вызывать из обработчиков ControlSurface: плеер в это время заблокирован
+    pub fn request(&self, cmd: &str) -> Result<String, String> {
+        (self.send)(cmd)
+    }
+}
+
+// Источник команд: клавиши, энкодеры, считыватели меток, MQTT...
+pub trait InputSource: Send {
+    fn name(&self) -> &str;
+    fn run(self: Box<Self>, control: Control) -> Result<(), String>;
+}
+
+// Куда выводится звук
+pub trait OutputTarget: Send {
+    fn name(&self) -> &str;
+    fn open(&self) -> Result<(OutputStream, OutputStreamHandle), String>;
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    // Файл есть, но не открылся или не декодируется
+    Decode,
+    // Устройство вывода пропало или перестало играть
+    Device,
+    // Файла из очереди больше нет
+    Missing,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Decode => "decode",
+            ErrorKind::Device => "device",
+            ErrorKind::Missing => "missing",
+        }
+    }
+}
+
+// Почему трек пропущен или вывод переоткрыт; time — секунды Unix
+#[derive(Serialize, Debug, Clone)]
+pub struct PlaybackError {
+    pub time: u64,
+    pub kind: ErrorKind,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl PlaybackError {
+    pub fn new(kind: ErrorKind, path: Option<&Path>, message: impl Into<String>) -> Self {
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            kind,
+            path: path.map(Path::to_path_buf),
+            message: message.into(),
+        }
+    }
+}
+
+// Сведения о треке по пути к файлу
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &str;
+    fn metadata(&self, path: &Path) -> Option<TrackMetadata>;
+}
+
+// Двусторонняя интеграция: получает состояние плеера и может им управлять
+pub trait ControlSurface: Send {
+    fn name(&self) -> &str;
+    fn start(&mut self, _control: Control) -> Result<(), String> {
+        Ok(())
+    }
+    // position — откуда начинается воспроизведение (после перехода automix или restore)
+    fn track_changed(&mut self, _track: &TrackMetadata, _position: Duration) {}
+    fn playback_changed(&mut self, _paused: bool) {}
+    fn volume_changed(&mut self, _volume: f32) {}
+    // Треки добавлены, удалены или переставлены; length — новая длина очереди,
+    // revision — её номер для "queue changes"
+    fn queue_changed(&mut self, _length: usize, _revision: u64) {}
+    // Трек пропущен из-за ошибки или пропало устройство вывода
+    fn playback_error(&mut self, _error: &PlaybackError) {}
+}
+
+#[derive(Default)]
+pub struct PluginRegistry {
+    inputs: Vec<Box<dyn InputSource>>,
+    outputs: Vec<Box<dyn OutputTarget>>,
+    metadata: Vec<Box<dyn MetadataProvider>>,
+    surfaces: Vec<Box<dyn ControlSurface>>,
+    libraries: Vec<Library>,
+}
+
+impl PluginRegistry {
+    pub fn register_input(&mut self, input: Box<dyn InputSource>) {
+        self.inputs.push(input);
+    }
+
+    pub fn register_output(&mut self, output: Box<dyn OutputTarget>) {
+        self.outputs.push(output);
+    }
+
+    pub fn register_metadata(&mut self, provider: Box<dyn MetadataProvider>) {
+        self.metadata.push(provider);
+    }
+
+    // Вызывается из динамических плагинов
+    pub fn register_surface(&mut self, surface: Box<dyn ControlSurface>) {
+        self.surfaces.push(surface);
+    }
+
+    pub fn load_library(&mut self, plugin: &PluginConfig) -> Result<(), String> {
+        let library = Library::open(&plugin.path)?;
+
+        let version = library.symbol(VERSION_SYMBOL)? as *const u32;
+        let version = unsafe { *version };
+        if version != PLUGIN_API_VERSION {
+            return Err(format!(
+                "{}: plugin API version {} is not supported (expected {})",
+                plugin.path.display(),
+                version,
+                PLUGIN_API_VERSION
+            ));
+        }
+
+        let register: RegisterFn = unsafe { std::mem::transmute(library.symbol(REGISTER_SYMBOL)?) };
+        // Библиотека должна жить, пока живут зарегистрированные ею объекты
+        self.libraries.push(library);
+        register(self, &plugin.config)
+    }
+
+    pub fn start_inputs(&mut self, control: &Control) {
+        for input in self.inputs.drain(..) {
+            let control = control.clone();
+            thread::spawn(move || {
+                let name = input.name().to_string();
+                if let Err(e) = input.run(control) {
+                    eprintln!("Input '{}' error: {}", name, e);
+                }
+            });
+        }
+    }
+
+    pub fn into_player_plugins(self, control: &Control) -> PlayerPlugins {
+        let mut surfaces = self.surfaces;
+        surfaces.retain_mut(|surface| match surface.start(control.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Control surface '{}' error: {}", surface.name(), e);
+                false
+            }
+        });
+
+        PlayerPlugins {
+            outputs: self.outputs,
+            metadata: self.metadata,
+            surfaces,
+            _libraries: self.libraries,
+            ..Default::default()
+        }
+    }
+}
+
+// Плагины, которыми пользуется сам плеер во время воспроизведения
+#[derive(Default)]
+pub struct PlayerPlugins {
+    outputs: Vec<Box<dyn OutputTarget>>,
+    metadata: Vec<Box<dyn MetadataProvider>>,
+    surfaces: Vec<Box<dyn ControlSurface>>,
+    _libraries: Vec<Library>,
+    track: Throttle<(TrackMetadata, Duration)>,
+    playback: Throttle<bool>,
+    volume: Throttle<f32>,
+    queue: Throttle<(usize, u64)>,
+}
+
+// Затухание или перемотка меняют состояние десятки раз в секунду: первое изменение
+// уходит сразу, следующие в пределах интервала схлопываются до последнего
+struct Throttle<T> {
+    sent: Option<Instant>,
+    pending: Option<T>,
+}
+
+impl<T> Default for Throttle<T> {
+    fn default() -> Self {
+        Self {
+            sent: None,
+            pending: None,
+        }
+    }
+}
+
+impl<T> Throttle<T> {
+    // Значение, которое можно отправить сейчас; иначе оно ждёт flush
+    fn offer(&mut self, value: T) -> Option<T> {
+        self.pending = Some(value);
+        self.due()
+    }
+
+    fn due(&mut self) -> Option<T> {
+        if self
+            .sent
+            .is_some_and(|sent| sent.elapsed() < EVENT_INTERVAL)
+        {
+            return None;
+        }
+        let value = self.pending.take()?;
+        self.sent = Some(Instant::now());
+        Some(value)
+    }
+}
+
+impl PlayerPlugins {
+    // Вывод можно переоткрыть во время работы, поэтому цели вывода живут у плеера
+    pub fn open_output(&self, name: &str) -> Result<(OutputStream, OutputStreamHandle), String> {
+        self.outputs
+            .iter()
+            .find(|output| output.name() == name)
+            .ok_or_else(|| format!("Unknown output: {}", name))?
+            .open()
+    }
+
+    pub fn metadata(&self, path: &Path) -> TrackMetadata {
+        self.metadata
+            .iter()
+            .find_map(|provider| provider.metadata(path))
+            .unwrap_or_else(|| TrackMetadata {
+                path: path.to_path_buf(),
+                title: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                ..Default::default()
+            })
+    }
+
+    pub fn track_changed(&mut self, track: &TrackMetadata, position: Duration) {
+        if let Some(track) = self.track.offer((track.clone(), position)) {
+            self.send_track(track);
+        }
+    }
+
+    pub fn playback_changed(&mut self, paused: bool) {
+        if let Some(paused) = self.playback.offer(paused) {
+            self.send_playback(paused);
+        }
+    }
+
+    pub fn volume_changed(&mut self, volume: f32) {
+        if let Some(volume) = self.volume.offer(volume) {
+            self.send_volume(volume);
+        }
+    }
+
+    pub fn queue_changed(&mut self, length: usize, revision: u64) {
+        if let Some(queue) = self.queue.offer((length, revision)) {
+            self.send_queue(queue);
+        }
+    }
+
+    // Досылает последние отложенные изменения; вызывается из основного цикла плеера
+    pub fn flush_events(&mut self) {
+        if let Some(track) = self.track.due() {
+            self.send_track(track);
+        }
+        if let Some(paused) = self.playback.due() {
+            self.send_playback(paused);
+        }
+        if let Some(volume) = self.volume.due() {
+            self.send_volume(volume);
+        }
+        if let Some(queue) = self.queue.due() {
+            self.send_queue(queue);
+        }
+    }
+
+    fn send_track(&mut self, (track, position): (TrackMetadata, Duration)) {
+        for surface in &mut self.surfaces {
+            surface.track_changed(&track, position);
+        }
+    }
+
+    fn send_playback(&mut self, paused: bool) {
+        for surface in &mut self.surfaces {
+            surface.playback_changed(paused);
+        }
+    }
+
+    fn send_volume(&mut self, volume: f32) {
+        for surface in &mut self.surfaces {
+            surface.volume_changed(volume);
+        }
+    }
+
+    fn send_queue(&mut self, (length, revision): (usize, u64)) {
+        for surface in &mut self.surfaces {
+            surface.queue_changed(length, revision);
+        }
+    }
+
+    pub fn playback_error(&mut self, error: &PlaybackError) {
+        for surface in &mut self.surfaces {
+            surface.playback_error(error);
+        }
+    }
+}
+
+pub struct DefaultOutput;
+
+impl OutputTarget for DefaultOutput {
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    fn open(&self) -> Result<(OutputStream, OutputStreamHandle), String> {
+        OutputStream::try_default().map_err(|e| e.to_string())
+    }
+}
+
+// Разделяемая библиотека, открытая через dlopen
+pub(crate) struct Library {
+    handle: *mut libc::c_void,
+}
+
+// Дескриптор dlopen можно использовать из любого потока
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let c_path = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(format!("{}: {}", path.display(), dl_error()));
+        }
+        Ok(Self { handle })
+    }
+
+    // Указатель на функцию нужного типа; сигнатуру F проверяет вызывающий
+    pub unsafe fn function<F: Copy>(&self, name: &str) -> Result<F, String> {
+        let symbol = self.symbol(name)?;
+        Ok(std::mem::transmute_copy::<*mut libc::c_void, F>(&symbol))
+    }
+
+    pub fn symbol(&self, name: &str) -> Result<*mut libc::c_void, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        let symbol = unsafe { libc::dlsym(self.handle, c_name.as_ptr()) };
+        if symbol.is_null() {
+            return Err(format!("{}: {}", name, dl_error()));
+        }
+        Ok(symbol)
+    }
+}
+
+fn dl_error() -> String {
+    let err = unsafe { libc::dlerror() };
+    if err.is_null() {
+        return "unknown error".to_string();
+    }
+    unsafe { std::ffi::CStr::from_ptr(err) }
+        .to_string_lossy()
+        .into_owned()
+}
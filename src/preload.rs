@@ -0,0 +1,338 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Отсчётов в одном блоке предварительного декодирования
+const CHUNK_SAMPLES: usize = 4096;
+// Файловые системы, на которых чтение может подвисать
+const NETWORK_FS: [&str; 8] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "fuse.sshfs",
+    "9p",
+    "fuse.rclone",
+];
+
+// Сколько следующих треков держать в памяти и на сколько секунд декодировать
+// вперёд. Не заданные поля выбираются по файловой системе папки с музыкой
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PreloadConfig {
+    pub tracks_ahead: Option<usize>,
+    pub buffer_secs: Option<f32>,
+    // Файлы крупнее не загружаются в память целиком
+    pub max_file_mb: u64,
+}
+
+impl Default for PreloadConfig {
+    fn default() -> Self {
+        Self {
+            tracks_ahead: None,
+            buffer_secs: None,
+            max_file_mb: 200,
+        }
+    }
+}
+
+impl PreloadConfig {
+    // Локальный диск: кэша страниц достаточно. Сеть: запас на задержки
+    pub fn resolve(&self, music_dir: &Path) -> PreloadPolicy {
+        let network = is_network_fs(music_dir);
+        PreloadPolicy {
+            tracks_ahead: self.tracks_ahead.unwrap_or(if network { 2 } else { 0 }),
+            buffer_secs: self.buffer_secs.unwrap_or(if network { 10.0 } else { 0.0 }),
+            max_file_bytes: self.max_file_mb * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PreloadPolicy {
+    pub tracks_ahead: usize,
+    pub buffer_secs: f32,
+    pub max_file_bytes: u64,
+}
+
+// Самая длинная точка монтирования из /proc/mounts, под которой лежит папка
+fn is_network_fs(path: &Path) -> bool {
+    let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+        return false;
+    };
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .is_some_and(|(_, fs_type)| NETWORK_FS.contains(&fs_type.as_str()))
+}
+
+// Файлы следующих треков, прочитанные в память заранее
+pub struct Preloader {
+    policy: PreloadPolicy,
+    cache: Arc<Mutex<HashMap<PathBuf, Arc<[u8]>>>>,
+}
+
+impl Preloader {
+    pub fn new(policy: PreloadPolicy) -> Self {
+        Self {
+            policy,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn policy(&self) -> PreloadPolicy {
+        self.policy
+    }
+
+    // Оставляет в кэше только upcoming и дочитывает недостающие в фоне
+    pub fn prefetch(&self, upcoming: Vec<PathBuf>) {
+        let upcoming: Vec<PathBuf> = upcoming
+            .into_iter()
+            .take(self.policy.tracks_ahead)
+            .collect();
+        let missing: Vec<PathBuf> = {
+            let mut cache = self.cache.lock().unwrap();
+            cache.retain(|path, _| upcoming.contains(path));
+            upcoming
+                .into_iter()
+                .filter(|path| !cache.contains_key(path))
+                .collect()
+        };
+        if missing.is_empty() {
+            return;
+        }
+
+        let cache = Arc::clone(&self.cache);
+        let max_bytes = self.policy.max_file_bytes;
+        thread::spawn(move || {
+            for path in missing {
+                let too_big = path.metadata().map(|m| m.len() > max_bytes).unwrap_or(true);
+                if too_big {
+                    continue;
+                }
+                match fs::read(&path) {
+                    Ok(data) => {
+                        cache.lock().unwrap().insert(path, data.into());
+                    }
+                    Err(e) => eprintln!("Failed to preload {}: {}", path.display(), e),
+                }
+            }
+        });
+    }
+
+    // Файл из памяти, если он уже загружен, иначе с диска
+    pub fn open(&self, path: &Path) -> io::Result<TrackReader> {
+        if let Some(data) = self.cache.lock().unwrap().get(path) {
+            return Ok(TrackReader::Memory(Cursor::new(Arc::clone(data))));
+        }
+        Ok(TrackReader::File(BufReader::new(File::open(path)?)))
+    }
+}
+
+pub enum TrackReader {
+    File(BufReader<File>),
+    Memory(Cursor<Arc<[u8]>>),
+}
+
+impl Read for TrackReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            TrackReader::File(file) => file.read(buf),
+            TrackReader::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for TrackReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            TrackReader::File(file) => file.seek(pos),
+            TrackReader::Memory(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+// Декодирование вперёд в отдельном потоке; без буфера источник читается напрямую
+pub struct Prebuffer<S> {
+    inner: Inner<S>,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+enum Inner<S> {
+    Direct(S),
+    Buffered(Buffered<S>),
+}
+
+struct Buffered<S> {
+    shared: Arc<Shared<S>>,
+    receiver: Receiver<(u64, Option<Vec<f32>>)>,
+    generation: u64,
+    chunk: std::vec::IntoIter<f32>,
+}
+
+struct Shared<S> {
+    source: Mutex<S>,
+    // Увеличивается при перемотке, чтобы отбросить старые блоки
+    generation: AtomicU64,
+    seeked: Condvar,
+}
+
+impl<S> Prebuffer<S>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    pub fn new(source: S, buffer_secs: f32) -> Self {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let total_duration = source.total_duration();
+        let samples = buffer_secs.max(0.0) * sample_rate as f32 * channels as f32;
+        let capacity = (samples as usize).div_ceil(CHUNK_SAMPLES);
+
+        let inner = if capacity == 0 {
+            Inner::Direct(source)
+        } else {
+            let shared = Arc::new(Shared {
+                source: Mutex::new(source),
+                generation: AtomicU64::new(0),
+                seeked: Condvar::new(),
+            });
+            let receiver = spawn_decoder(&shared, capacity);
+            Inner::Buffered(Buffered {
+                shared,
+                receiver,
+                generation: 0,
+                chunk: Vec::new().into_iter(),
+            })
+        };
+
+        Self {
+            inner,
+            channels,
+            sample_rate,
+            total_duration,
+        }
+    }
+}
+
+fn spawn_decoder<S>(shared: &Arc<Shared<S>>, capacity: usize) -> Receiver<(u64, Option<Vec<f32>>)>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let shared = Arc::clone(shared);
+    thread::spawn(move || loop {
+        let (generation, chunk) = {
+            let mut source = shared.source.lock().unwrap();
+            let generation = shared.generation.load(Ordering::SeqCst);
+            let chunk: Vec<f32> = source.by_ref().take(CHUNK_SAMPLES).collect();
+            (generation, chunk)
+        };
+        let finished = chunk.len() < CHUNK_SAMPLES;
+        if !chunk.is_empty() && sender.send((generation, Some(chunk))).is_err() {
+            return;
+        }
+        if !finished {
+            continue;
+        }
+        if sender.send((generation, None)).is_err() {
+            return;
+        }
+
+        // Конец файла: ждём перемотки назад, пока источник жив
+        let mut source = shared.source.lock().unwrap();
+        while shared.generation.load(Ordering::SeqCst) == generation {
+            if Arc::strong_count(&shared) == 1 {
+                return;
+            }
+            source = shared
+                .seeked
+                .wait_timeout(source, Duration::from_secs(1))
+                .unwrap()
+                .0;
+        }
+    });
+    receiver
+}
+
+impl<S> Iterator for Prebuffer<S>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let buffered = match &mut self.inner {
+            Inner::Direct(source) => return source.next(),
+            Inner::Buffered(buffered) => buffered,
+        };
+        loop {
+            if let Some(sample) = buffered.chunk.next() {
+                return Some(sample);
+            }
+            match buffered.receiver.recv().ok()? {
+                (generation, _) if generation != buffered.generation => continue,
+                (_, Some(chunk)) => buffered.chunk = chunk.into_iter(),
+                (_, None) => return None,
+            }
+        }
+    }
+}
+
+impl<S> Source for Prebuffer<S>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        match &self.inner {
+            Inner::Direct(source) => source.current_frame_len(),
+            Inner::Buffered(_) => None,
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let buffered = match &mut self.inner {
+            Inner::Direct(source) => return source.try_seek(pos),
+            Inner::Buffered(buffered) => buffered,
+        };
+        {
+            let mut source = buffered.shared.source.lock().unwrap();
+            source.try_seek(pos)?;
+            buffered.generation = buffered.shared.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        }
+        buffered.shared.seeked.notify_all();
+        buffered.chunk = Vec::new().into_iter();
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+use crate::clock::Timers;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PressTiming {
+    // Вторая короткая пауза не дольше этого считается двойным нажатием
+    pub double_press_ms: u64,
+    // Удержание дольше этого считается долгим нажатием
+    pub long_press_ms: u64,
+    // После стольких мс без событий клавиатуры зажатые клавиши считаются отпущенными; 0 — никогда
+    pub stale_key_ms: u64,
+}
+
+impl Default for PressTiming {
+    fn default() -> Self {
+        PressTiming {
+            double_press_ms: 300,
+            long_press_ms: 500,
+            stale_key_ms: 10000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PressKind {
+    Single,
+    Double,
+    Long,
+}
+
+// "AudioNext:double" -> ("AudioNext", Double); без суффикса — одиночное нажатие
+pub fn split_binding(binding: &str) -> (&str, PressKind) {
+    match binding.rsplit_once(':') {
+        Some((combo, "double")) => (combo, PressKind::Double),
+        Some((combo, "long" | "hold")) => (combo, PressKind::Long),
+        _ => (binding, PressKind::Single),
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct PressActions {
+    pub single: Option<String>,
+    pub double: Option<String>,
+    pub long: Option<String>,
+    // Что отправить при отпускании после долгого нажатия
+    pub long_release: Option<String>,
+}
+
+impl PressActions {
+    pub fn set(&mut self, kind: PressKind, cmd: String) {
+        match kind {
+            PressKind::Single => self.single = Some(cmd),
+            PressKind::Double => self.double = Some(cmd),
+            PressKind::Long => {
+                // Перемотка идёт, пока клавиша удерживается
+                if cmd.starts_with("scan ") {
+                    self.long_release = Some("scan stop".to_string());
+                }
+                self.long = Some(cmd)
+            }
+        }
+    }
+
+    // Одиночное нажатие без других вариантов срабатывает сразу, как раньше
+    fn immediate(&self) -> bool {
+        self.double.is_none() && self.long.is_none()
+    }
+}
+
+#[derive(Default)]
+struct ComboState {
+    // Меняется при каждом событии, чтобы таймеры узнавали устаревшие ожидания
+    generation: u64,
+    held: bool,
+    long_fired: bool,
+    pending_tap: bool,
+}
+
+type FireFn = dyn Fn(&str) + Send + Sync;
+
+// Различает одиночные, двойные и долгие нажатия одной комбинации
+#[derive(Clone)]
+pub struct PressDispatcher {
+    actions: Arc<HashMap<String, PressActions>>,
+    timing: PressTiming,
+    state: Arc<Mutex<HashMap<String, ComboState>>>,
+    timers: Timers,
+    fire: Arc<FireFn>,
+}
+
+impl PressDispatcher {
+    pub fn new(
+        actions: HashMap<String, PressActions>,
+        timing: PressTiming,
+        timers: Timers,
+        fire: impl Fn(&str) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            actions: Arc::new(actions),
+            timing,
+            state: Arc::new(Mutex::new(HashMap::new())),
+            timers,
+            fire: Arc::new(fire),
+        }
+    }
+
+    pub fn combos(&self) -> impl Iterator<Item = &String> {
+        self.actions.keys()
+    }
+
+    pub fn is_held(&self, combo: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .get(combo)
+            .is_some_and(|state| state.held)
+    }
+
+    pub fn press(&self, combo: &str) {
+        let Some(actions) = self.actions.get(combo) else {
+            return;
+        };
+
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            let state = state.entry(combo.to_string()).or_default();
+            // Автоповтор клавиатуры присылает нажатия, пока клавиша удерживается;
+            // повторяются только простые привязки, как громкость
+            if state.held && !actions.immediate() {
+                return;
+            }
+            state.held = true;
+            state.long_fired = false;
+            state.generation += 1;
+            state.generation
+        };
+
+        if actions.immediate() {
+            if let Some(ref cmd) = actions.single {
+                (self.fire)(cmd);
+            }
+            return;
+        }
+
+        if let Some(ref cmd) = actions.long {
+            let this = self.clone();
+            let combo = combo.to_string();
+            let cmd = cmd.clone();
+            let delay = Duration::from_millis(this.timing.long_press_ms);
+            self.timers.after(delay, move || {
+                let still_held = {
+                    let mut state = this.state.lock().unwrap();
+                    let state = state.entry(combo).or_default();
+                    let still_held = state.held && state.generation == generation;
+                    if still_held {
+                        state.long_fired = true;
+                        state.pending_tap = false;
+                    }
+                    still_held
+                };
+                if still_held {
+                    (this.fire)(&cmd);
+                }
+            });
+        }
+    }
+
+    pub fn release(&self, combo: &str) {
+        let Some(actions) = self.actions.get(combo) else {
+            return;
+        };
+
+        let (tap, double, generation) = {
+            let mut state = self.state.lock().unwrap();
+            let state = state.entry(combo.to_string()).or_default();
+            if !state.held {
+                return;
+            }
+            state.held = false;
+            state.generation += 1;
+            if state.long_fired {
+                if let Some(ref cmd) = actions.long_release {
+                    (self.fire)(cmd);
+                }
+            }
+            let tap = !state.long_fired && !actions.immediate();
+            let double = tap && state.pending_tap && actions.double.is_some();
+            state.pending_tap = tap && !double && actions.double.is_some();
+            (tap, double, state.generation)
+        };
+
+        if double {
+            if let Some(ref cmd) = actions.double {
+                (self.fire)(cmd);
+            }
+        } else if tap && actions.double.is_none() {
+            if let Some(ref cmd) = actions.single {
+                (self.fire)(cmd);
+            }
+        } else if tap {
+            // Одиночное нажатие ждёт, не будет ли второго
+            let Some(cmd) = actions.single.clone() else {
+                return;
+            };
+            let this = self.clone();
+            let combo = combo.to_string();
+            let delay = Duration::from_millis(this.timing.double_press_ms);
+            self.timers.after(delay, move || {
+                let single = {
+                    let mut state = this.state.lock().unwrap();
+                    let state = state.entry(combo).or_default();
+                    let single = state.pending_tap && state.generation == generation;
+                    if single {
+                        state.pending_tap = false;
+                    }
+                    single
+                };
+                if single {
+                    (this.fire)(&cmd);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Fired = Arc<Mutex<Vec<(u128, String)>>>;
+
+    fn dispatcher(bindings: &[(&str, &str)]) -> (PressDispatcher, Timers, Fired) {
+        let mut actions: HashMap<String, PressActions> = HashMap::new();
+        for (cmd, binding) in bindings {
+            let (combo, kind) = split_binding(binding);
+            actions
+                .entry(combo.to_string())
+                .or_default()
+                .set(kind, cmd.to_string());
+        }
+        let timers = Timers::simulated();
+        let fired: Fired = Arc::default();
+        let dispatcher = PressDispatcher::new(actions, PressTiming::default(), timers.clone(), {
+            let (fired, timers) = (fired.clone(), timers.clone());
+            move |cmd| {
+                let at = timers.elapsed().as_millis();
+                fired.lock().unwrap().push((at, cmd.to_string()));
+            }
+        });
+        (dispatcher, timers, fired)
+    }
+
+    fn owned(expected: &[(u128, &str)]) -> Vec<(u128, String)> {
+        expected
+            .iter()
+            .map(|(at, cmd)| (*at, cmd.to_string()))
+            .collect()
+    }
+
+    // Автоповтор клавиатуры не перезапускает ожидание долгого нажатия
+    #[test]
+    fn long_press_fires_once_through_key_repeat() {
+        let (keys, timers, log) = dispatcher(&[("next", "Right"), ("scan forward", "Right:long")]);
+        keys.press("Right");
+        for ms in (100..=700).step_by(100) {
+            timers.advance_to(Duration::from_millis(ms));
+            keys.press("Right");
+        }
+        timers.advance_to(Duration::from_millis(800));
+        keys.release("Right");
+        timers.advance_to(Duration::from_millis(2000));
+        assert_eq!(
+            *log.lock().unwrap(),
+            owned(&[(500, "scan forward"), (800, "scan stop")])
+        );
+    }
+
+    // Простая привязка повторяется вместе с клавиатурой
+    #[test]
+    fn immediate_binding_repeats() {
+        let (keys, timers, log) = dispatcher(&[("volume +5", "Up")]);
+        for ms in [0, 30, 60] {
+            timers.advance_to(Duration::from_millis(ms));
+            keys.press("Up");
+        }
+        keys.release("Up");
+        assert_eq!(
+            *log.lock().unwrap(),
+            owned(&[(0, "volume +5"), (30, "volume +5"), (60, "volume +5")])
+        );
+    }
+
+    #[test]
+    fn single_waits_for_double_press_window() {
+        let (keys, timers, log) = dispatcher(&[("next", "Right"), ("prev", "Right:double")]);
+        keys.press("Right");
+        timers.advance_to(Duration::from_millis(50));
+        keys.release("Right");
+        timers.advance_to(Duration::from_millis(200));
+        keys.press("Right");
+        timers.advance_to(Duration::from_millis(250));
+        keys.release("Right");
+        timers.advance_to(Duration::from_millis(1000));
+        keys.press("Right");
+        timers.advance_to(Duration::from_millis(1050));
+        keys.release("Right");
+        timers.advance_to(Duration::from_millis(2000));
+        assert_eq!(
+            *log.lock().unwrap(),
+            owned(&[(250, "prev"), (1350, "next")])
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use crate::decode::DecodedSource;
+use rodio::source::{Amplify, SkipDuration, TakeDuration};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Отрывок для прослушивания при выборе трека; играет в отдельном sink,
+// очередь и основное воспроизведение не трогаются
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PreviewConfig {
+    // С какой секунды трека начинать
+    pub offset: f32,
+    pub seconds: f32,
+    // Доля громкости музыки
+    pub volume: f32,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            offset: 0.0,
+            seconds: 10.0,
+            volume: 0.5,
+        }
+    }
+}
+
+pub type PreviewSource = TakeDuration<SkipDuration<Amplify<DecodedSource>>>;
+
+impl PreviewConfig {
+    // Отрывок затухает к концу, а не обрывается
+    pub fn clip(&self, source: Amplify<DecodedSource>) -> PreviewSource {
+        let mut clip = source
+            .skip_duration(Duration::from_secs_f32(self.offset.max(0.0)))
+            .take_duration(Duration::from_secs_f32(self.seconds.max(0.1)));
+        clip.set_filter_fadeout();
+        clip
+    }
+}
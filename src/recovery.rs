@@ -0,0 +1,68 @@
+use crate::atomic;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Файлы, которые при запуске не прочитались и были заменены: их видно в status,
+// пока демон не перезапущен
+static RECOVERED: Mutex<Vec<Recovery>> = Mutex::new(Vec::new());
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Recovery {
+    pub file: PathBuf,
+    pub error: String,
+    // Куда отложен испорченный файл; None — отложить не удалось, он не тронут
+    pub broken: Option<PathBuf>,
+    pub fallback: Fallback,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Fallback {
+    // Последняя рабочая копия файла
+    Backup,
+    Defaults,
+}
+
+// Испорченный файл откладывается рядом с отметкой времени, чтобы повторный сбой
+// не затёр прошлую копию
+pub fn set_aside(path: &Path) -> Option<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let broken = atomic::sibling(path, &format!(".broken-{}", time));
+    match fs::rename(path, &broken) {
+        Ok(()) => Some(broken),
+        Err(e) => {
+            eprintln!("Failed to move {} aside: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn record(recovery: Recovery) {
+    let kept = match &recovery.broken {
+        Some(broken) => format!("the broken file is kept as {}", broken.display()),
+        None => "the broken file was left in place".to_string(),
+    };
+    let fallback = match recovery.fallback {
+        Fallback::Backup => "restored the last good copy",
+        Fallback::Defaults => "started with defaults",
+    };
+    eprintln!("==============================================================");
+    eprintln!(
+        "WARNING: {} could not be read: {}",
+        recovery.file.display(),
+        recovery.error
+    );
+    eprintln!("WARNING: {}; {}", fallback, kept);
+    eprintln!("==============================================================");
+    RECOVERED.lock().unwrap().push(recovery);
+}
+
+pub fn recovered() -> Vec<Recovery> {
+    RECOVERED.lock().unwrap().clone()
+}
@@ -0,0 +1,137 @@
+use crate::plugin::{Control, InputSource};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Съёмные носители: udisks и udev монтируют их в эти папки
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RemovableConfig {
+    pub enabled: bool,
+    pub mount_prefixes: Vec<PathBuf>,
+    // Музыка с носителя временно добавляется в библиотеку, пока он подключён
+    pub index: bool,
+    // sh -c с NSMP_DRIVE=<точка монтирования>
+    pub on_mount: Option<String>,
+    pub on_unmount: Option<String>,
+}
+
+impl Default for RemovableConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mount_prefixes: vec![
+                PathBuf::from("/media"),
+                PathBuf::from("/run/media"),
+                PathBuf::from("/mnt"),
+            ],
+            index: true,
+            on_mount: None,
+            on_unmount: None,
+        }
+    }
+}
+
+impl InputSource for RemovableConfig {
+    fn name(&self) -> &str {
+        "removable"
+    }
+
+    // Ядро будит poll на /proc/self/mounts при каждом изменении таблицы монтирования,
+    // то есть уже после того, как udev и udisks подключили носитель
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        let mounts = File::open("/proc/self/mounts").map_err(|e| e.to_string())?;
+        let mut known = self.drives();
+        loop {
+            let mut fd = libc::pollfd {
+                fd: mounts.as_raw_fd(),
+                events: libc::POLLPRI,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut fd, 1, -1) } < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error.to_string());
+            }
+
+            let current = self.drives();
+            for drive in current.difference(&known) {
+                println!("Removable drive mounted at {}", drive.display());
+                self.run_hook(self.on_mount.as_deref(), drive);
+                if self.index {
+                    let _ = control.send(&format!("drive added {}", drive.display()));
+                }
+            }
+            for drive in known.difference(&current) {
+                println!("Removable drive at {} removed", drive.display());
+                let _ = control.send(&format!("drive removed {}", drive.display()));
+                self.run_hook(self.on_unmount.as_deref(), drive);
+            }
+            known = current;
+        }
+    }
+}
+
+impl RemovableConfig {
+    // Точки монтирования блочных устройств внутри mount_prefixes
+    fn drives(&self) -> HashSet<PathBuf> {
+        let table = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        table
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let device = fields.next()?;
+                let mount = PathBuf::from(unescape(fields.next()?));
+                let removable = device.starts_with("/dev/")
+                    && self
+                        .mount_prefixes
+                        .iter()
+                        .any(|prefix| mount.starts_with(prefix) && mount != *prefix);
+                removable.then_some(mount)
+            })
+            .collect()
+    }
+
+    fn run_hook(&self, hook: Option<&str>, drive: &PathBuf) {
+        let Some(hook) = hook else {
+            return;
+        };
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(hook)
+            .env("NSMP_DRIVE", drive)
+            .spawn();
+        if let Err(e) = result {
+            eprintln!("Failed to run drive hook: {}", e);
+        }
+    }
+}
+
+// Пробелы и прочее в /proc/mounts записаны как \040
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let code = bytes
+            .get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match code {
+            Some(byte) => {
+                out.push(byte);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    // Альбомное усиление сохраняет разницу громкости между треками альбома
+    Album,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReplayGainConfig {
+    pub mode: ReplayGainMode,
+    // Для файлов без тегов усиление считается по громкости EBU R128 из анализа
+    pub analyze_untagged: bool,
+    // Опорная громкость ReplayGain 2.0, LUFS
+    pub target_lufs: f32,
+}
+
+impl Default for ReplayGainConfig {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::Off,
+            analyze_untagged: false,
+            target_lufs: -18.0,
+        }
+    }
+}
+
+impl ReplayGainConfig {
+    // Усиление, дБ, и пик, дБFS, по режиму; альбомных нет — берутся трековые
+    pub fn select(&self, tags: &ReplayGain) -> (Option<f32>, Option<f32>) {
+        let (gain, peak) = match self.mode {
+            ReplayGainMode::Off => return (None, None),
+            ReplayGainMode::Track => (tags.track_gain, tags.track_peak),
+            ReplayGainMode::Album => (
+                tags.album_gain.or(tags.track_gain),
+                tags.album_peak.or(tags.track_peak),
+            ),
+        };
+        let peak_db = peak
+            .filter(|peak| *peak > 0.0)
+            .map(|peak| 20.0 * peak.log10());
+        (gain, peak_db)
+    }
+
+    // Громкость треков без тегов берётся из фонового анализа
+    pub fn needs_analysis(&self) -> bool {
+        self.mode != ReplayGainMode::Off && self.analyze_untagged
+    }
+
+    // Усиление по измеренной громкости трека
+    pub fn loudness_gain(&self, loudness: Option<f32>) -> Option<f32> {
+        if !self.needs_analysis() {
+            return None;
+        }
+        loudness.map(|lufs| self.target_lufs - lufs)
+    }
+}
+
+// Теги REPLAYGAIN_*; усиление в дБ, пик линейный
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    // Стандартный ключ или имя вида "TXXX:replaygain_track_gain",
+    // "----:com.apple.iTunes:replaygain_track_gain"
+    pub fn read_tag(&mut self, tag: &Tag) {
+        let key = tag.key.to_lowercase();
+        let slot = match tag.std_key {
+            Some(StandardTagKey::ReplayGainTrackGain) => &mut self.track_gain,
+            Some(StandardTagKey::ReplayGainTrackPeak) => &mut self.track_peak,
+            Some(StandardTagKey::ReplayGainAlbumGain) => &mut self.album_gain,
+            Some(StandardTagKey::ReplayGainAlbumPeak) => &mut self.album_peak,
+            _ if key.ends_with("replaygain_track_gain") => &mut self.track_gain,
+            _ if key.ends_with("replaygain_track_peak") => &mut self.track_peak,
+            _ if key.ends_with("replaygain_album_gain") => &mut self.album_gain,
+            _ if key.ends_with("replaygain_album_peak") => &mut self.album_peak,
+            _ => return,
+        };
+        if slot.is_none() {
+            *slot = parse_number(&tag.value.to_string());
+        }
+    }
+}
+
+// "-6.54 dB", "+1.2 dB", "0.988547"
+fn parse_number(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value)
+        .trim();
+    number.parse::<f32>().ok().filter(|n| n.is_finite())
+}
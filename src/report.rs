@@ -0,0 +1,205 @@
+use crate::dispatch::enabled_features;
+use crate::{send_command, Config};
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Из лога берётся только хвост: последние события перед сбоем
+const LOG_TAIL_BYTES: u64 = 256 * 1024;
+// Значения с такими именами ключей в отчёт не попадают; в users ключи — сами токены
+const SECRET_KEYS: [&str; 6] = [
+    "token",
+    "secret",
+    "password",
+    "session_key",
+    "api_key",
+    "users",
+];
+
+// Архив для отчёта об ошибке: версия, система, звук, конфиг без секретов,
+// хвост лога и последние ошибки воспроизведения. Никуда не отправляется —
+// пользователь сам прикладывает его к issue
+pub fn create(config_path: &Path, output: Option<PathBuf>) -> Result<PathBuf, String> {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let name = format!("nsmp-report-{}", stamp);
+    let output = match output {
+        Some(path) => path,
+        None => PathBuf::from(format!("{}.tar.gz", name)),
+    };
+    let output = std::path::absolute(&output).map_err(|e| e.to_string())?;
+
+    let staging = env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let dir = staging.join(&name);
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let result = collect(&dir, config_path).and_then(|_| pack(&staging, &name, &output));
+    let _ = fs::remove_dir_all(&staging);
+    result.map(|_| output)
+}
+
+fn collect(dir: &Path, config_path: &Path) -> Result<(), String> {
+    let write = |file: &str, text: String| {
+        let path = dir.join(file);
+        fs::write(&path, anonymize(&text)).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+
+    write("version.txt", system_info())?;
+    write("audio.txt", audio_info())?;
+
+    // load_config создал бы недостающий конфиг или восстановил копию, а отчёт ничего не меняет
+    let loaded = if config_path.exists() {
+        crate::read_config(config_path)
+    } else {
+        Err("no config file, defaults are used".to_string())
+    };
+    let config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            write("config.txt", format!("{}: {}\n", config_path.display(), e))?;
+            Config::default()
+        }
+    };
+    let mut value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+    scrub(&mut value);
+    write(
+        "config.json",
+        serde_json::to_string_pretty(&value).unwrap_or_default(),
+    )?;
+    write("log.txt", log_tail(Path::new(&config.log_file)))?;
+
+    // Запущенный плеер добавляет своё состояние; без него отчёт всё равно полезен
+    let mut player = String::new();
+    for command in ["hello", "status", "errors"] {
+        let reply = match send_command(command) {
+            Ok(reply) => reply,
+            Err(e) => format!("{}\n", e),
+        };
+        player.push_str(&format!("> {}\n{}\n", command, reply.trim_end()));
+    }
+    write("player.txt", player)?;
+    Ok(())
+}
+
+fn pack(staging: &Path, name: &str, output: &Path) -> Result<(), String> {
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(output)
+        .arg("-C")
+        .arg(staging)
+        .arg(name)
+        .status()
+        .map_err(|e| format!("tar: {}", e))?;
+    if !status.success() {
+        return Err(format!("tar exited with {}", status));
+    }
+    Ok(())
+}
+
+fn system_info() -> String {
+    let mut text = format!(
+        "nsmp {}\nfeatures: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        enabled_features().join(", ")
+    );
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } == 0 {
+        let field = |raw: &[libc::c_char]| {
+            unsafe { CStr::from_ptr(raw.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        text.push_str(&format!(
+            "system: {} {} {}\n",
+            field(&uts.sysname),
+            field(&uts.release),
+            field(&uts.machine)
+        ));
+    }
+    if let Ok(release) = fs::read_to_string("/etc/os-release") {
+        if let Some(name) = release
+            .lines()
+            .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        {
+            text.push_str(&format!("distribution: {}\n", name.trim_matches('"')));
+        }
+    }
+    text
+}
+
+// Звуковые карты ALSA и то, какой звуковой сервер запущен
+fn audio_info() -> String {
+    let mut text = String::new();
+    for file in [
+        "/proc/asound/version",
+        "/proc/asound/cards",
+        "/proc/asound/pcm",
+    ] {
+        let content = fs::read_to_string(file).unwrap_or_else(|e| format!("{}\n", e));
+        text.push_str(&format!("== {}\n{}\n", file, content.trim_end()));
+    }
+    let runtime = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    for (server, socket) in [("pipewire", "pipewire-0"), ("pulseaudio", "pulse/native")] {
+        let running = runtime
+            .as_ref()
+            .is_some_and(|dir| dir.join(socket).exists());
+        text.push_str(&format!(
+            "{}: {}\n",
+            server,
+            if running { "yes" } else { "no" }
+        ));
+    }
+    text
+}
+
+fn log_tail(path: &Path) -> String {
+    let Ok(mut file) = fs::File::open(path) else {
+        return format!(
+            "{}: no log (the player logs to a file only with --daemon)\n",
+            path.display()
+        );
+    };
+    let length = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    let _ = file.seek(SeekFrom::Start(length.saturating_sub(LOG_TAIL_BYTES)));
+    let mut data = Vec::new();
+    let _ = file.read_to_end(&mut data);
+    String::from_utf8_lossy(&data).into_owned()
+}
+
+fn scrub(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let secret = SECRET_KEYS.iter().any(|name| key.contains(name));
+                if secret && !value.is_null() {
+                    *value = serde_json::Value::String("<removed>".to_string());
+                } else {
+                    scrub(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(scrub),
+        _ => {}
+    }
+}
+
+// Домашняя папка и имя пользователя выдают человека, а для отладки не нужны
+fn anonymize(text: &str) -> String {
+    let mut text = text.to_string();
+    if let Some(home) = env::var_os("HOME").and_then(|home| home.into_string().ok()) {
+        if home.len() > 1 {
+            text = text.replace(&home, "~");
+        }
+    }
+    if let Some(user) = env::var_os("USER").and_then(|user| user.into_string().ok()) {
+        if user.len() > 2 {
+            text = text.replace(&user, "<user>");
+        }
+    }
+    text
+}
@@ -0,0 +1,346 @@
+use crate::evdev::{self, Device};
+use crate::i18n;
+use crate::plugin::{Control, InputSource};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RfidReader {
+    // USB-считыватель, который "печатает" номер метки как клавиатура
+    Hid,
+    // Считыватель, присылающий номер метки строкой по UART (RDM6300 и т.п.)
+    Serial,
+    // PN532 по UART (HSU) или I2C (/dev/i2c-N)
+    Pn532,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RfidConfig {
+    pub reader: RfidReader,
+    pub device: String,
+    pub baud: u32,
+    pub i2c_address: u16,
+    pub tags: HashMap<String, String>,
+    pub remove_command: Option<String>,
+}
+
+impl Default for RfidConfig {
+    fn default() -> Self {
+        RfidConfig {
+            reader: RfidReader::Hid,
+            device: String::new(),
+            baud: 115200,
+            i2c_address: 0x24,
+            tags: HashMap::new(),
+            remove_command: None,
+        }
+    }
+}
+
+impl InputSource for RfidConfig {
+    fn name(&self) -> &str {
+        "rfid"
+    }
+
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        rfid_listener(*self, control)
+    }
+}
+
+fn rfid_listener(config: RfidConfig, control: Control) -> Result<(), String> {
+    let on_tag = |tag: Option<String>| match tag {
+        Some(id) => match config.tags.get(&id) {
+            Some(path) => {
+                let _ = control.send(&format!("load {}", path));
+            }
+            None => eprintln!("{}", i18n::tr("unknown-rfid-tag", &[("id", &id)])),
+        },
+        None => {
+            if let Some(ref cmd) = config.remove_command {
+                let _ = control.send(cmd);
+            }
+        }
+    };
+
+    match config.reader {
+        RfidReader::Hid => hid_reader(&config.device, on_tag),
+        RfidReader::Serial => serial_reader(&config.device, config.baud, on_tag),
+        RfidReader::Pn532 => pn532_reader(&config, on_tag),
+    }
+    .map_err(|e| format!("{}: {}", config.device, e))
+}
+
+fn hid_reader(path: &str, mut on_tag: impl FnMut(Option<String>)) -> Result<(), io::Error> {
+    let mut device = Device::open(Path::new(path))?;
+    device.grab()?;
+    let mut id = String::new();
+
+    loop {
+        let event = device.next_event()?;
+        if event.kind != evdev::EV_KEY || event.value != 1 {
+            continue;
+        }
+
+        match event.code {
+            evdev::KEY_ENTER | evdev::KEY_KPENTER if !id.is_empty() => {
+                on_tag(Some(std::mem::take(&mut id)));
+            }
+            code => {
+                if let Some(c) = evdev::key_char(code) {
+                    id.push(c);
+                }
+            }
+        }
+    }
+}
+
+fn serial_reader(
+    path: &str,
+    baud: u32,
+    mut on_tag: impl FnMut(Option<String>),
+) -> Result<(), io::Error> {
+    let mut port = open_serial(path, baud)?;
+    let mut id = String::new();
+    let mut byte = [0u8; 1];
+
+    loop {
+        if port.read(&mut byte)? == 0 {
+            continue;
+        }
+
+        match byte[0] {
+            // Разделители: перевод строки, STX/ETX
+            b'\r' | b'\n' | 0x02 | 0x03 if !id.is_empty() => {
+                on_tag(Some(std::mem::take(&mut id)));
+            }
+            b if b.is_ascii_alphanumeric() => id.push(b.to_ascii_uppercase() as char),
+            _ => {}
+        }
+    }
+}
+
+fn pn532_reader(
+    config: &RfidConfig,
+    mut on_tag: impl FnMut(Option<String>),
+) -> Result<(), io::Error> {
+    let mut pn532 = if config.device.starts_with("/dev/i2c") {
+        Pn532::i2c(&config.device, config.i2c_address)?
+    } else {
+        Pn532::serial(&config.device, config.baud)?
+    };
+
+    // SAMConfiguration: обычный режим
+    pn532.command(0x14, &[0x01, 0x14, 0x01])?;
+    // RFConfiguration: ограничиваем число попыток, чтобы опрос не блокировался
+    pn532.command(0x32, &[0x05, 0xFF, 0x01, 0x02])?;
+
+    let mut current: Option<String> = None;
+
+    loop {
+        // InListPassiveTarget: одна метка ISO14443A
+        let response = pn532.command(0x4A, &[0x01, 0x00])?;
+        let tag = parse_target_uid(&response);
+
+        if tag != current {
+            if tag.is_some() || current.is_some() {
+                on_tag(tag.clone());
+            }
+            current = tag;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+// Ответ InListPassiveTarget: NbTg, Tg, SENS_RES(2), SEL_RES, NFCIDLength, NFCID...
+fn parse_target_uid(data: &[u8]) -> Option<String> {
+    if data.first().copied().unwrap_or(0) == 0 || data.len() < 6 {
+        return None;
+    }
+    let len = data[5] as usize;
+    let uid = data.get(6..6 + len)?;
+    Some(uid.iter().map(|b| format!("{:02X}", b)).collect())
+}
+
+enum Pn532Transport {
+    Serial,
+    I2c,
+}
+
+struct Pn532 {
+    file: File,
+    transport: Pn532Transport,
+}
+
+impl Pn532 {
+    fn serial(path: &str, baud: u32) -> Result<Self, io::Error> {
+        let mut file = open_serial(path, baud)?;
+        // Пробуждение из режима низкого потребления для HSU
+        file.write_all(&[0x55, 0x55, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])?;
+        Ok(Self {
+            file,
+            transport: Pn532Transport::Serial,
+        })
+    }
+
+    fn i2c(path: &str, address: u16) -> Result<Self, io::Error> {
+        const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let res = unsafe { libc::ioctl(file.as_raw_fd(), I2C_SLAVE, address as libc::c_ulong) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            file,
+            transport: Pn532Transport::I2c,
+        })
+    }
+
+    fn command(&mut self, cmd: u8, params: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut body = vec![0xD4, cmd];
+        body.extend_from_slice(params);
+
+        let len = body.len() as u8;
+        let checksum = body.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+        let mut frame = vec![0x00, 0x00, 0xFF, len, len.wrapping_neg()];
+        frame.extend_from_slice(&body);
+        frame.extend_from_slice(&[checksum.wrapping_neg(), 0x00]);
+        self.file.write_all(&frame)?;
+
+        // Сначала приходит ACK (пустой кадр), затем ответ
+        let ack = self.read_frame()?;
+        if !ack.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "PN532: expected ACK",
+            ));
+        }
+
+        let response = self.read_frame()?;
+        match response.as_slice() {
+            [0xD5, code, data @ ..] if *code == cmd + 1 => Ok(data.to_vec()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "PN532: unexpected response",
+            )),
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Vec<u8>, io::Error> {
+        match self.transport {
+            Pn532Transport::Serial => {
+                let file = &mut self.file;
+                parse_frame(|| {
+                    let mut byte = [0u8; 1];
+                    match file.read(&mut byte)? {
+                        0 => Err(io::Error::new(io::ErrorKind::TimedOut, "PN532: timeout")),
+                        _ => Ok(byte[0]),
+                    }
+                })
+            }
+            Pn532Transport::I2c => {
+                // По I2C каждый ответ начинается с байта готовности
+                let mut buf = [0u8; 64];
+                for _ in 0..100 {
+                    self.file.read_exact(&mut buf)?;
+                    if buf[0] & 0x01 != 0 {
+                        let mut bytes = buf[1..].iter().copied();
+                        return parse_frame(|| {
+                            bytes.next().ok_or_else(|| {
+                                io::Error::new(io::ErrorKind::UnexpectedEof, "PN532: short frame")
+                            })
+                        });
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(io::Error::new(io::ErrorKind::TimedOut, "PN532: not ready"))
+            }
+        }
+    }
+}
+
+fn parse_frame(mut next: impl FnMut() -> Result<u8, io::Error>) -> Result<Vec<u8>, io::Error> {
+    // Ищем начало кадра 00 FF
+    let mut prev = next()?;
+    loop {
+        let byte = next()?;
+        if prev == 0x00 && byte == 0xFF {
+            break;
+        }
+        prev = byte;
+    }
+
+    let len = next()?;
+    let lcs = next()?;
+    if len.wrapping_add(lcs) != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "PN532: bad length",
+        ));
+    }
+
+    let mut data = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        data.push(next()?);
+    }
+    if len > 0 {
+        let dcs = next()?;
+        if data.iter().fold(dcs, |acc, b| acc.wrapping_add(*b)) != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "PN532: bad checksum",
+            ));
+        }
+    }
+    next()?;
+    Ok(data)
+}
+
+fn open_serial(path: &str, baud: u32) -> Result<File, io::Error> {
+    let speed = match baud {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported baud rate: {}", baud),
+            ))
+        }
+    };
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+
+    unsafe {
+        let mut tty: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(file.as_raw_fd(), &mut tty) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut tty);
+        libc::cfsetispeed(&mut tty, speed);
+        libc::cfsetospeed(&mut tty, speed);
+        // Чтение с таймаутом 1 с, чтобы не зависать на молчащем устройстве
+        tty.c_cc[libc::VMIN] = 0;
+        tty.c_cc[libc::VTIME] = 10;
+        if libc::tcsetattr(file.as_raw_fd(), libc::TCSANOW, &tty) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(file)
+}
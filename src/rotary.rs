@@ -0,0 +1,97 @@
+use crate::evdev::{self, Device};
+use crate::plugin::{Control, InputSource};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RotaryAction {
+    Volume,
+    Seek,
+}
+
+// Энкодер на GPIO подключается через оверлей rotary-encoder и виден как evdev-устройство
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RotaryConfig {
+    pub device: String,
+    pub action: RotaryAction,
+    pub axis: u16,
+    pub invert: bool,
+    // Проценты громкости или секунды перемотки на один щелчок
+    pub step: f32,
+    pub acceleration: f32,
+    pub max_multiplier: f32,
+    pub accel_window_ms: u64,
+    pub button_command: Option<String>,
+}
+
+impl Default for RotaryConfig {
+    fn default() -> Self {
+        RotaryConfig {
+            device: String::new(),
+            action: RotaryAction::Volume,
+            axis: evdev::REL_X,
+            invert: false,
+            step: 2.0,
+            acceleration: 0.5,
+            max_multiplier: 5.0,
+            accel_window_ms: 80,
+            button_command: Some("pause".to_string()),
+        }
+    }
+}
+
+impl InputSource for RotaryConfig {
+    fn name(&self) -> &str {
+        "rotary"
+    }
+
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        rotary_listener(*self, control)
+    }
+}
+
+fn rotary_listener(config: RotaryConfig, control: Control) -> Result<(), String> {
+    let mut device =
+        Device::open(Path::new(&config.device)).map_err(|e| format!("{}: {}", config.device, e))?;
+    let window = Duration::from_millis(config.accel_window_ms);
+    let mut last_turn = Instant::now() - window;
+    let mut streak = 0u32;
+
+    loop {
+        let event = device.next_event().map_err(|e| e.to_string())?;
+
+        match event.kind {
+            evdev::EV_REL if event.code == config.axis && event.value != 0 => {
+                let now = Instant::now();
+                streak = if now.duration_since(last_turn) < window {
+                    streak + 1
+                } else {
+                    0
+                };
+                last_turn = now;
+
+                let multiplier =
+                    (1.0 + streak as f32 * config.acceleration).min(config.max_multiplier);
+                let clockwise = (event.value > 0) != config.invert;
+                let amount = config.step * multiplier * event.value.unsigned_abs() as f32;
+
+                let cmd = match (config.action, clockwise) {
+                    (RotaryAction::Volume, true) => format!("volume_up {}", amount),
+                    (RotaryAction::Volume, false) => format!("volume_down {}", amount),
+                    (RotaryAction::Seek, true) => format!("seek_forward {}", amount),
+                    (RotaryAction::Seek, false) => format!("seek_backward {}", amount),
+                };
+                let _ = control.send(&cmd);
+            }
+            evdev::EV_KEY if event.value == 1 => {
+                if let Some(ref cmd) = config.button_command {
+                    let _ = control.send(cmd);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{cd, gme, playlist, stream, tracker};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum SymlinkPolicy {
+    // Ссылки на папки обходятся; петли и повторы отсекаются по inode
+    #[default]
+    Follow,
+    Skip,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScanOptions {
+    // 0 — только сама папка, как раньше
+    max_depth: usize,
+    symlinks: SymlinkPolicy,
+    // Расширения из настроек декодеров сверх встроенных
+    #[serde(skip)]
+    pub extensions: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            max_depth: 16,
+            symlinks: SymlinkPolicy::Follow,
+            extensions: Vec::new(),
+        }
+    }
+}
+
+// %XX из URI и строк запроса
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub fn scan_music(path: &Path, options: &ScanOptions) -> Result<Vec<PathBuf>, io::Error> {
+    let mut supported = vec!["mp3", "wav", "flac", "ogg", "aac", "m4a", "dsf", "dff"];
+    supported.extend(options.extensions.iter().map(String::as_str));
+    if tracker::available() {
+        supported.extend_from_slice(tracker::extensions());
+    }
+    if gme::available() {
+        supported.extend_from_slice(gme::extensions());
+    }
+
+    if stream::is_stream(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if path.is_file() && has_supported_extension(path, &supported) {
+        return Ok(expand_modules(vec![path.to_path_buf()]));
+    }
+    if path.is_file() && playlist::is_playlist(path) {
+        return playlist::load(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+
+    if !path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Path is not a directory",
+        ));
+    }
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(path.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        if let Ok(meta) = fs::metadata(&dir) {
+            if !visited.insert((meta.dev(), meta.ino())) {
+                continue;
+            }
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            // Корень обязан читаться, вложенные недоступные папки пропускаются
+            Err(e) if depth == 0 => return Err(e),
+            Err(_) => continue,
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_symlink() && options.symlinks == SymlinkPolicy::Skip {
+                continue;
+            }
+            if path.is_dir() {
+                if depth < options.max_depth {
+                    pending.push((path, depth + 1));
+                }
+            } else if path.is_file() && has_supported_extension(&path, &supported) {
+                files.push(path);
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No supported audio files found",
+        ));
+    }
+
+    files.sort();
+    Ok(expand_modules(dedup_inodes(files)))
+}
+
+// Подпесня модуля есть, если есть её файл; трек CD проверяется при открытии
+pub fn track_exists(path: &Path) -> bool {
+    cd::is_cd_track(path) || stream::is_stream(path) || tracker::split_subsong(path).0.exists()
+}
+
+// Подпесни трекерных модулей и треки игровой музыки — отдельные записи
+fn expand_modules(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    files
+        .into_iter()
+        .flat_map(|path| {
+            if tracker::is_module(&path) {
+                tracker::expand(&path)
+            } else if gme::is_game_file(&path) {
+                gme::expand(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+// Один файл, доступный через симлинк или жёсткую ссылку, попадает в список один раз;
+// настоящий путь предпочитается симлинку
+fn dedup_inodes(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let id = |path: &Path| fs::metadata(path).ok().map(|m| (m.dev(), m.ino()));
+
+    let mut owners: HashMap<(u64, u64), &PathBuf> = HashMap::new();
+    for path in &files {
+        if let Some(key) = id(path) {
+            owners
+                .entry(key)
+                .and_modify(|owner| {
+                    if owner.is_symlink() && !path.is_symlink() {
+                        *owner = path;
+                    }
+                })
+                .or_insert(path);
+        }
+    }
+
+    files
+        .iter()
+        .filter(|path| id(path).is_none_or(|key| owners[&key] == *path))
+        .cloned()
+        .collect()
+}
+
+fn has_supported_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| extensions.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
@@ -0,0 +1,160 @@
+use crate::clock;
+use crate::plugin::{Control, InputSource};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+const PAUSE_REASON: &str = "schedule";
+
+// Интервал расписания: в эти дни и часы играет playlist или mix, а вне всех
+// интервалов воспроизведение на паузе. Первый подходящий интервал главнее
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ScheduleEntry {
+    // "mon", "sat", "mon-fri"; пусто — каждый день
+    pub days: Vec<String>,
+    // "09:00"; конец раньше начала — интервал через полночь, день считается по началу
+    pub start: String,
+    pub end: String,
+    pub playlist: Option<String>,
+    pub mix: Option<String>,
+    pub volume: Option<String>,
+}
+
+struct Slot {
+    days: [bool; 7],
+    // Минуты от полуночи
+    start: u32,
+    end: u32,
+    entry: ScheduleEntry,
+}
+
+impl Slot {
+    fn covers(&self, weekday: usize, minute: u32) -> bool {
+        if self.start < self.end {
+            return self.days[weekday] && (self.start..self.end).contains(&minute);
+        }
+        let yesterday = (weekday + 6) % 7;
+        (self.days[weekday] && minute >= self.start) || (self.days[yesterday] && minute < self.end)
+    }
+}
+
+pub struct Schedule {
+    slots: Vec<Slot>,
+}
+
+impl Schedule {
+    pub fn new(entries: &[ScheduleEntry]) -> Result<Self, String> {
+        let slots = entries
+            .iter()
+            .map(|entry| {
+                Ok(Slot {
+                    days: parse_days(&entry.days)?,
+                    start: parse_time(&entry.start)?,
+                    end: parse_time(&entry.end)?,
+                    entry: entry.clone(),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { slots })
+    }
+
+    fn active(&self, weekday: usize, minute: u32) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.covers(weekday, minute))
+    }
+
+    fn commands(&self, active: Option<usize>) -> Vec<String> {
+        let Some(index) = active else {
+            return vec![format!("auto_pause {}", PAUSE_REASON)];
+        };
+        let entry = &self.slots[index].entry;
+        let mut commands = vec![format!("auto_resume {}", PAUSE_REASON)];
+        commands.push(match (&entry.playlist, &entry.mix) {
+            (Some(playlist), _) => format!("load_playlist {}", playlist),
+            (None, Some(mix)) => format!("mix {}", mix),
+            (None, None) => "play".to_string(),
+        });
+        if let Some(ref volume) = entry.volume {
+            commands.push(format!("volume {}", volume));
+        }
+        commands
+    }
+}
+
+impl InputSource for Schedule {
+    fn name(&self) -> &str {
+        "schedule"
+    }
+
+    // Команды уходят только на границах интервалов, так что между ними
+    // можно свободно управлять плеером вручную
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        let mut applied = None;
+        loop {
+            let (weekday, minute) = local_time();
+            let active = self.active(weekday, minute);
+            if applied != Some(active) {
+                for command in self.commands(active) {
+                    if let Err(e) = control.send(&command) {
+                        eprintln!("Schedule: {} failed: {}", command, e);
+                    }
+                }
+                applied = Some(active);
+            }
+            clock::sleep(CHECK_INTERVAL);
+        }
+    }
+}
+
+// День недели (0 — воскресенье) и минута суток по местному времени
+fn local_time() -> (usize, u32) {
+    let seconds = clock::wall()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&seconds, &mut tm) };
+    (tm.tm_wday as usize, (tm.tm_hour * 60 + tm.tm_min) as u32)
+}
+
+// "HH:MM", до "24:00" включительно
+fn parse_time(text: &str) -> Result<u32, String> {
+    text.split_once(':')
+        .and_then(|(hours, minutes)| {
+            Some((hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?))
+        })
+        .filter(|&(hours, minutes)| minutes < 60 && hours * 60 + minutes <= 24 * 60)
+        .map(|(hours, minutes)| hours * 60 + minutes)
+        .ok_or_else(|| format!("invalid schedule time '{}': expected HH:MM", text))
+}
+
+fn parse_days(days: &[String]) -> Result<[bool; 7], String> {
+    if days.is_empty() {
+        return Ok([true; 7]);
+    }
+    let day = |name: &str| {
+        DAYS.iter()
+            .position(|day| name.eq_ignore_ascii_case(day))
+            .ok_or_else(|| format!("invalid schedule day '{}': expected mon..sun", name))
+    };
+    let mut result = [false; 7];
+    for item in days {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (day(first)?, day(last)?),
+            None => (day(item)?, day(item)?),
+        };
+        // "fri-mon" переходит через выходные
+        let mut current = first;
+        loop {
+            result[current] = true;
+            if current == last {
+                break;
+            }
+            current = (current + 1) % 7;
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,221 @@
+use crate::atomic;
+use crate::library::LibraryDb;
+use crate::plugin::{Control, ControlSurface, TrackMetadata};
+use crate::tags::{self, MusicBrainzIds};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Трек засчитывается, если сыграна половина или четыре минуты, а сам он длиннее 30 секунд
+const MIN_TRACK: Duration = Duration::from_secs(30);
+const ENOUGH_PLAYED: Duration = Duration::from_secs(240);
+// Как часто повторять отправку накопившихся прослушиваний без сети
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+// Одно прослушивание; в таком виде оно и ждёт отправки в файле очереди
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Listen {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    // Длительность трека в секундах, если известна
+    pub duration: Option<u32>,
+    // Начало воспроизведения, секунды Unix
+    pub timestamp: u64,
+    #[serde(default)]
+    pub musicbrainz: MusicBrainzIds,
+}
+
+// Сервис учёта прослушиваний. Вызывается из отдельного потока, поэтому может ждать сеть
+pub trait Service: Send {
+    fn name(&self) -> &str;
+    fn now_playing(&mut self, listen: &Listen) -> Result<(), String>;
+    fn scrobble(&mut self, listens: &[Listen]) -> Result<(), String>;
+    // Сколько прослушиваний принимается за один запрос
+    fn batch_size(&self) -> usize {
+        50
+    }
+}
+
+enum Job {
+    NowPlaying(Listen),
+    Scrobble(Listen),
+}
+
+struct Playing {
+    listen: Listen,
+    played: Duration,
+    // Когда воспроизведение возобновилось; None на паузе
+    resumed: Option<Instant>,
+}
+
+impl Playing {
+    fn played(&self) -> Duration {
+        self.played + self.resumed.map_or(Duration::ZERO, |at| at.elapsed())
+    }
+
+    fn counts(&self) -> bool {
+        let played = self.played();
+        match self
+            .listen
+            .duration
+            .map(|secs| Duration::from_secs(secs.into()))
+        {
+            Some(duration) => duration > MIN_TRACK && played >= (duration / 2).min(ENOUGH_PLAYED),
+            None => played >= ENOUGH_PLAYED,
+        }
+    }
+}
+
+// Сообщает сервису, что играет, и засчитывает доигранные треки.
+// Без сети прослушивания копятся в queue_file и уходят позже
+pub struct Scrobbler {
+    service: Option<Box<dyn Service>>,
+    queue_file: PathBuf,
+    library_db: Arc<Mutex<LibraryDb>>,
+    jobs: Option<Sender<Job>>,
+    current: Option<Playing>,
+}
+
+impl Scrobbler {
+    pub fn new(
+        service: Box<dyn Service>,
+        queue_file: PathBuf,
+        library_db: Arc<Mutex<LibraryDb>>,
+    ) -> Self {
+        Self {
+            service: Some(service),
+            queue_file,
+            library_db,
+            jobs: None,
+            current: None,
+        }
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(ref jobs) = self.jobs {
+            let _ = jobs.send(job);
+        }
+    }
+}
+
+impl ControlSurface for Scrobbler {
+    fn name(&self) -> &str {
+        "scrobbler"
+    }
+
+    fn start(&mut self, _control: Control) -> Result<(), String> {
+        let service = self.service.take().ok_or("scrobbler already started")?;
+        let (sender, receiver) = mpsc::channel();
+        let queue_file = self.queue_file.clone();
+        thread::spawn(move || deliver(service, receiver, queue_file));
+        self.jobs = Some(sender);
+        Ok(())
+    }
+
+    fn track_changed(&mut self, track: &TrackMetadata, _position: Duration) {
+        if let Some(previous) = self.current.take() {
+            if previous.counts() {
+                self.send(Job::Scrobble(previous.listen));
+            }
+        }
+        // Без исполнителя сервисы трек не примут
+        let Some(ref artist) = track.artist else {
+            return;
+        };
+        let duration = self.library_db.lock().unwrap().duration(&track.path);
+        let musicbrainz = tags::musicbrainz(&self.library_db, &track.path);
+        let listen = Listen {
+            artist: artist.clone(),
+            title: track.title.clone(),
+            album: track.album.clone(),
+            track_number: track.track_number,
+            duration: duration.map(|secs| secs.round() as u32),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            musicbrainz,
+        };
+        self.send(Job::NowPlaying(listen.clone()));
+        self.current = Some(Playing {
+            listen,
+            played: Duration::ZERO,
+            resumed: Some(Instant::now()),
+        });
+    }
+
+    fn playback_changed(&mut self, paused: bool) {
+        let Some(ref mut current) = self.current else {
+            return;
+        };
+        match (paused, current.resumed) {
+            (true, Some(at)) => {
+                current.played += at.elapsed();
+                current.resumed = None;
+            }
+            (false, None) => current.resumed = Some(Instant::now()),
+            _ => {}
+        }
+    }
+}
+
+fn deliver(mut service: Box<dyn Service>, jobs: Receiver<Job>, queue_file: PathBuf) {
+    let mut pending = load_queue(&queue_file);
+    loop {
+        match jobs.recv_timeout(RETRY_INTERVAL) {
+            Ok(Job::NowPlaying(listen)) => {
+                if let Err(e) = service.now_playing(&listen) {
+                    eprintln!("{}: now playing not sent: {}", service.name(), e);
+                }
+                continue;
+            }
+            Ok(Job::Scrobble(listen)) => {
+                pending.push(listen);
+                save_queue(&queue_file, &pending);
+            }
+            Err(RecvTimeoutError::Timeout) if pending.is_empty() => continue,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        while !pending.is_empty() {
+            let batch = pending.len().min(service.batch_size());
+            if let Err(e) = service.scrobble(&pending[..batch]) {
+                eprintln!(
+                    "{}: {} scrobbles queued for later: {}",
+                    service.name(),
+                    pending.len(),
+                    e
+                );
+                break;
+            }
+            pending.drain(..batch);
+            save_queue(&queue_file, &pending);
+        }
+    }
+}
+
+fn load_queue(path: &Path) -> Vec<Listen> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn save_queue(path: &Path, pending: &[Listen]) {
+    let data: String = pending
+        .iter()
+        .filter_map(|listen| serde_json::to_string(listen).ok())
+        .map(|line| line + "\n")
+        .collect();
+    if let Err(e) = atomic::write(path, data) {
+        eprintln!("Failed to save scrobble queue {}: {}", path.display(), e);
+    }
+}
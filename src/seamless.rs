@@ -0,0 +1,136 @@
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+// Сколько отсчётов сравнивается при поиске точки склейки
+const MATCH_FRAMES: usize = 256;
+// В каком хвосте файла ищется конец петли, секунды
+const SEARCH_SECS: f32 = 0.5;
+const SILENCE: f32 = 1e-3;
+
+// Клип целиком в памяти: вступление [0, start), затем бесконечно [start, end)
+pub struct LoopSource {
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+    start: usize,
+    end: usize,
+    pos: usize,
+}
+
+impl LoopSource {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let decoder = Decoder::new(file).map_err(|e| e.to_string())?;
+        let channels = decoder.channels().max(1);
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<f32> = decoder.convert_samples().collect();
+
+        let frames = samples.len() / channels as usize;
+        if frames <= MATCH_FRAMES {
+            return Err("Clip is too short to loop".to_string());
+        }
+
+        let (start, end) = match read_loop_tags(path) {
+            Some((start, end)) => (start, end.unwrap_or(frames).min(frames)),
+            None => detect_loop(&samples, channels as usize, sample_rate),
+        };
+        if start >= end {
+            return Err(format!("Invalid loop points: {}..{}", start, end));
+        }
+
+        Ok(Self {
+            samples,
+            channels,
+            sample_rate,
+            start: start * channels as usize,
+            end: end * channels as usize,
+            pos: 0,
+        })
+    }
+}
+
+impl Iterator for LoopSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.end {
+            self.pos = self.start;
+        }
+        let sample = self.samples[self.pos];
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+impl Source for LoopSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// Теги LOOPSTART/LOOPLENGTH/LOOPEND в кадрах, как в RPG Maker и играх на Ogg
+fn read_loop_tags(path: &Path) -> Option<(usize, Option<usize>)> {
+    let file = File::open(path).ok()?;
+    let reader = lewton::inside_ogg::OggStreamReader::new(file).ok()?;
+    let tag = |name: &str| {
+        reader
+            .comment_hdr
+            .comment_list
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+    };
+
+    let start = tag("LOOPSTART")?;
+    let end = tag("LOOPEND").or_else(|| tag("LOOPLENGTH").map(|length| start + length));
+    Some((start, end))
+}
+
+// Без тегов: петля от первого звука до места в хвосте, откуда продолжение
+// сильнее всего похоже на начало петли
+fn detect_loop(samples: &[f32], channels: usize, sample_rate: u32) -> (usize, usize) {
+    let frames = samples.len() / channels;
+    let frame = |i: usize| {
+        samples[i * channels..(i + 1) * channels]
+            .iter()
+            .sum::<f32>()
+    };
+
+    let start = (0..frames - MATCH_FRAMES)
+        .find(|&i| frame(i).abs() > SILENCE)
+        .unwrap_or(0);
+    let last = (start + MATCH_FRAMES..frames)
+        .rev()
+        .find(|&i| frame(i).abs() > SILENCE)
+        .map(|i| i + 1)
+        .unwrap_or(frames);
+
+    let search = (SEARCH_SECS * sample_rate as f32) as usize;
+    let latest = last.min(frames - MATCH_FRAMES);
+    let earliest = latest.saturating_sub(search).max(start + MATCH_FRAMES);
+
+    let mismatch = |end: usize| -> f32 {
+        (0..MATCH_FRAMES)
+            .map(|i| (frame(end + i) - frame(start + i)).abs())
+            .sum()
+    };
+    let end = (earliest..=latest)
+        .min_by(|&a, &b| mismatch(a).total_cmp(&mismatch(b)))
+        .unwrap_or(last);
+
+    (start, end)
+}
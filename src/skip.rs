@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Когда ручной next считается пропуском трека: для статистики и умного перемешивания
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SkipConfig {
+    // Пропуск — next раньше этой доли трека, в процентах
+    pub percent: f32,
+    // Кто дослушал до этой секунды, уже не пропустил: для длинных треков
+    // и треков без известной длительности; null — только проценты
+    pub max_seconds: Option<f32>,
+    // Часто пропускаемые треки попадают в конец перемешанной очереди
+    pub smart_shuffle: bool,
+}
+
+impl Default for SkipConfig {
+    fn default() -> Self {
+        Self {
+            percent: 30.0,
+            max_seconds: None,
+            smart_shuffle: false,
+        }
+    }
+}
+
+impl SkipConfig {
+    pub fn is_skip(&self, played: Duration, duration: Option<f32>) -> bool {
+        let played = played.as_secs_f32();
+        let threshold = match (duration, self.max_seconds) {
+            (Some(duration), Some(seconds)) => (duration * self.percent / 100.0).min(seconds),
+            (Some(duration), None) => duration * self.percent / 100.0,
+            (None, Some(seconds)) => seconds,
+            (None, None) => return false,
+        };
+        played < threshold
+    }
+}
+
+// Число запусков и пропусков трека из библиотеки
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayCounts {
+    pub plays: u64,
+    pub skips: u64,
+}
+
+impl PlayCounts {
+    // Вес при перемешивании: никогда не пропускаемый трек — 1, всегда пропускаемый — 0.1
+    fn weight(&self) -> f64 {
+        let ratio = self.skips as f64 / self.plays.max(self.skips).max(1) as f64;
+        1.0 - 0.9 * ratio
+    }
+}
+
+// Взвешенная случайная перестановка: ключ u^(1/вес), треки с большим ключом играют
+// раньше. Мешок разбирается с конца, поэтому сортировка по возрастанию ключа
+pub fn weighted_shuffle(
+    bag: &mut [usize],
+    files: &[PathBuf],
+    counts: &HashMap<PathBuf, PlayCounts>,
+) {
+    let mut keyed: Vec<(f64, usize)> = bag
+        .iter()
+        .map(|&index| {
+            let weight = counts.get(&files[index]).map_or(1.0, PlayCounts::weight);
+            (fastrand::f64().powf(1.0 / weight), index)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (slot, (_, index)) in bag.iter_mut().zip(keyed) {
+        *slot = index;
+    }
+}
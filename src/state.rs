@@ -0,0 +1,30 @@
+use crate::atomic;
+use crate::player::{Repeat, Snapshot};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// То, что нужно, чтобы после перезапуска продолжить с того же места
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlaybackState {
+    #[serde(flatten)]
+    pub snapshot: Snapshot,
+    pub shuffle: bool,
+    #[serde(default)]
+    pub shuffle_folders: bool,
+    pub repeat: Repeat,
+    pub paused: bool,
+    // Активный контекст; null — из версий без контекстов
+    #[serde(default)]
+    pub context: Option<String>,
+}
+
+pub fn load(path: &Path) -> Result<PlaybackState, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&data).map_err(|e| e.to_string())
+}
+
+pub fn save(path: &Path, state: &PlaybackState) -> Result<(), String> {
+    let data = serde_json::to_string(state).map_err(|e| e.to_string())?;
+    atomic::write(path, data).map_err(|e| e.to_string())
+}
@@ -0,0 +1,378 @@
+use crate::plugin::TrackMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use symphonia::core::io::MediaSource;
+
+// Сколько ждать первых данных, прежде чем считать поток недоступным
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const CHUNK: usize = 16 * 1024;
+
+// Интернет-радио: записи очереди вида http(s)://...
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub curl_path: PathBuf,
+    // Сколько накопить до начала воспроизведения, КБ
+    pub prebuffer_kb: usize,
+    // Дальше буфер не растёт, и curl ждёт, пока декодер заберёт данные
+    pub buffer_kb: usize,
+    // Сколько раз подряд переподключаться после обрыва, прежде чем сдаться
+    pub reconnect_attempts: u32,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            curl_path: PathBuf::from("curl"),
+            prebuffer_kb: 64,
+            buffer_kb: 1024,
+            reconnect_attempts: 5,
+        }
+    }
+}
+
+pub fn is_stream(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|url| url.starts_with("http://") || url.starts_with("https://"))
+}
+
+// Название станции из заголовка icy-name и текущая песня из метаданных ICY
+#[derive(Default)]
+struct StationInfo {
+    station: Option<String>,
+    song: Option<String>,
+}
+
+fn stations() -> &'static Mutex<HashMap<PathBuf, StationInfo>> {
+    static STATIONS: OnceLock<Mutex<HashMap<PathBuf, StationInfo>>> = OnceLock::new();
+    STATIONS.get_or_init(Default::default)
+}
+
+// Растёт при каждой смене песни в любом потоке; главный цикл сверяет его
+static SONG_CHANGES: AtomicU64 = AtomicU64::new(0);
+
+pub fn song_changes() -> u64 {
+    SONG_CHANGES.load(Ordering::Relaxed)
+}
+
+// "Исполнитель - Название" из StreamTitle; станция идёт вместо альбома
+pub fn metadata(url: &Path) -> Option<TrackMetadata> {
+    let stations = stations().lock().unwrap();
+    let info = stations.get(url)?;
+    let (artist, title) = match info.song {
+        Some(ref song) => match song.split_once(" - ") {
+            Some((artist, title)) => (Some(artist.to_string()), title.to_string()),
+            None => (None, song.clone()),
+        },
+        None => (None, info.station.clone()?),
+    };
+    Some(TrackMetadata {
+        path: url.to_path_buf(),
+        title,
+        artist,
+        album: info.station.clone(),
+        track_number: None,
+    })
+}
+
+struct Buffer {
+    data: VecDeque<u8>,
+    // Переподключения кончились или поток закрыт
+    finished: bool,
+    closed: bool,
+    error: Option<String>,
+    child: Option<Child>,
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+}
+
+// Поток читается curl в отдельном потоке в буфер; при обрыве соединение
+// открывается заново, а декодер просто ждёт следующих байтов
+pub struct HttpStream {
+    shared: Arc<Shared>,
+}
+
+impl HttpStream {
+    pub fn open(url: &Path, config: &StreamConfig) -> Result<Self, String> {
+        let shared = Arc::new(Shared {
+            buffer: Mutex::new(Buffer {
+                data: VecDeque::new(),
+                finished: false,
+                closed: false,
+                error: None,
+                child: None,
+            }),
+            changed: Condvar::new(),
+        });
+        // Песня прошлого подключения к этому адресу уже не играет
+        stations().lock().unwrap().remove(url);
+        let pump = Arc::clone(&shared);
+        let (url, pump_config) = (url.to_path_buf(), config.clone());
+        thread::spawn(move || download(&pump, &url, &pump_config));
+
+        let prebuffer = config.prebuffer_kb * 1024;
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        let mut buffer = shared.buffer.lock().unwrap();
+        while buffer.data.len() < prebuffer && !buffer.finished {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            buffer = shared.changed.wait_timeout(buffer, left).unwrap().0;
+        }
+        if buffer.data.is_empty() {
+            let error = buffer.error.clone();
+            drop(buffer);
+            drop(Self { shared });
+            return Err(error.unwrap_or_else(|| "stream: no data received".to_string()));
+        }
+        drop(buffer);
+        Ok(Self { shared })
+    }
+}
+
+fn download(shared: &Shared, url: &Path, config: &StreamConfig) {
+    let capacity = config.buffer_kb.max(1) * 1024;
+    let mut failures = 0;
+    let mut connected = false;
+    loop {
+        let spawned = Command::new(&config.curl_path)
+            .args(["-sS", "-L", "--fail", "--no-buffer", "-i", "-A", "NSmp/0.1"])
+            .args(["-H", "Icy-MetaData: 1"])
+            .args([
+                "--connect-timeout",
+                "10",
+                "--speed-limit",
+                "1",
+                "--speed-time",
+                "15",
+            ])
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let mut buffer = shared.buffer.lock().unwrap();
+                buffer.error = Some(format!("curl: {}", e));
+                buffer.finished = true;
+                shared.changed.notify_all();
+                return;
+            }
+        };
+        let stdout = child.stdout.take().unwrap();
+        {
+            let mut buffer = shared.buffer.lock().unwrap();
+            if buffer.closed {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            buffer.child = Some(child);
+        }
+
+        let mut reader = BufReader::new(stdout);
+        if let Some(headers) = read_headers(&mut reader) {
+            if headers.station.is_some() {
+                let mut stations = stations().lock().unwrap();
+                stations.entry(url.to_path_buf()).or_default().station = headers.station;
+            }
+            let mut chunk = vec![0u8; CHUNK];
+            let mut until_metadata = headers.metaint.unwrap_or(usize::MAX);
+            loop {
+                let want = CHUNK.min(until_metadata);
+                let length = match reader.read(&mut chunk[..want]) {
+                    Ok(0) | Err(_) => break,
+                    Ok(length) => length,
+                };
+                failures = 0;
+                connected = true;
+                let mut buffer = shared.buffer.lock().unwrap();
+                while buffer.data.len() >= capacity && !buffer.closed {
+                    buffer = shared.changed.wait(buffer).unwrap();
+                }
+                if buffer.closed {
+                    break;
+                }
+                buffer.data.extend(&chunk[..length]);
+                shared.changed.notify_all();
+                drop(buffer);
+
+                // Через каждые icy-metaint байт звука вставлен блок метаданных
+                if let Some(interval) = headers.metaint {
+                    until_metadata -= length;
+                    if until_metadata == 0 {
+                        match read_song(&mut reader) {
+                            Ok(Some(song)) => set_song(url, song),
+                            Ok(None) => {}
+                            Err(_) => break,
+                        }
+                        until_metadata = interval;
+                    }
+                }
+            }
+        }
+
+        let mut buffer = shared.buffer.lock().unwrap();
+        let status = buffer.child.take().map(|mut child| {
+            let _ = child.kill();
+            child.wait()
+        });
+        if buffer.closed {
+            return;
+        }
+        // curl без ошибки — файл по ссылке кончился, а не оборвался
+        if let Some(Ok(status)) = status.as_ref().filter(|_| connected) {
+            if status.success() {
+                buffer.finished = true;
+                shared.changed.notify_all();
+                return;
+            }
+        }
+        failures += 1;
+        // Адрес, который ни разу не ответил, переподключением не исправить
+        if !connected || failures > config.reconnect_attempts {
+            buffer.error = Some(match status {
+                Some(Ok(status)) => format!("stream: curl exited with {}", status),
+                _ => "stream: connection lost".to_string(),
+            });
+            buffer.finished = true;
+            shared.changed.notify_all();
+            return;
+        }
+        drop(buffer);
+        eprintln!(
+            "Stream {} dropped, reconnecting ({}/{})",
+            url.display(),
+            failures,
+            config.reconnect_attempts
+        );
+        thread::sleep(Duration::from_secs(failures.into()));
+    }
+}
+
+struct Headers {
+    metaint: Option<usize>,
+    station: Option<String>,
+}
+
+// Заголовки ответа, которые curl с -i выводит перед телом. После
+// перенаправлений блоков несколько, нужен последний. Shoutcast отвечает "ICY 200 OK"
+fn read_headers(reader: &mut impl BufRead) -> Option<Headers> {
+    loop {
+        let mut status = String::new();
+        if reader.read_line(&mut status).ok()? == 0 {
+            return None;
+        }
+        let mut headers = Headers {
+            metaint: None,
+            station: None,
+        };
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "icy-metaint" => headers.metaint = value.parse().ok().filter(|n| *n > 0),
+                "icy-name" if !value.is_empty() => headers.station = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let code = status.split_whitespace().nth(1)?.parse::<u16>().ok()?;
+        if !(100..200).contains(&code) && !(300..400).contains(&code) {
+            return Some(headers);
+        }
+    }
+}
+
+// Байт длины в блоках по 16 байт, затем "StreamTitle='...';StreamUrl='...';"
+fn read_song(reader: &mut impl Read) -> io::Result<Option<String>> {
+    let mut length = [0u8; 1];
+    reader.read_exact(&mut length)?;
+    let mut block = vec![0u8; length[0] as usize * 16];
+    reader.read_exact(&mut block)?;
+    let text = String::from_utf8_lossy(&block);
+    let song = text
+        .split_once("StreamTitle='")
+        .and_then(|(_, rest)| rest.split_once("';").map(|(title, _)| title))
+        .map(|title| title.trim().to_string());
+    Ok(song)
+}
+
+fn set_song(url: &Path, song: String) {
+    let mut stations = stations().lock().unwrap();
+    let info = stations.entry(url.to_path_buf()).or_default();
+    let song = Some(song).filter(|song| !song.is_empty());
+    if info.song != song {
+        info.song = song;
+        SONG_CHANGES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buffer = self.shared.buffer.lock().unwrap();
+        while buffer.data.is_empty() && !buffer.finished {
+            buffer = self.shared.changed.wait(buffer).unwrap();
+        }
+        let length = buf.len().min(buffer.data.len());
+        for (to, from) in buf.iter_mut().zip(buffer.data.drain(..length)) {
+            *to = from;
+        }
+        self.shared.changed.notify_all();
+        Ok(length)
+    }
+}
+
+// Живой поток не перематывается
+impl Seek for HttpStream {
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "streams are not seekable",
+        ))
+    }
+}
+
+impl MediaSource for HttpStream {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Drop for HttpStream {
+    fn drop(&mut self) {
+        let mut buffer = self.shared.buffer.lock().unwrap();
+        buffer.closed = true;
+        buffer.finished = true;
+        if let Some(ref mut child) = buffer.child {
+            let _ = child.kill();
+        }
+        self.shared.changed.notify_all();
+    }
+}
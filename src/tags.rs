@@ -0,0 +1,251 @@
+use crate::cd;
+use crate::library::{CachedTags, LibraryDb};
+use crate::plugin::{MetadataProvider, TrackMetadata};
+use crate::replaygain::ReplayGain;
+use crate::{gme, stream, tracker};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, Value};
+use symphonia::core::probe::Hint;
+
+// Теги ID3, Vorbis comment и MP4 через symphonia с кэшем в базе библиотеки.
+// Регистрируется после динамических плагинов, поэтому их провайдеры главнее
+pub struct TagReader {
+    library: Arc<Mutex<LibraryDb>>,
+}
+
+impl TagReader {
+    pub fn new(library: Arc<Mutex<LibraryDb>>) -> Self {
+        Self { library }
+    }
+}
+
+impl MetadataProvider for TagReader {
+    fn name(&self) -> &str {
+        "tags"
+    }
+
+    fn metadata(&self, path: &Path) -> Option<TrackMetadata> {
+        // У треков CD нет файла, и кэшировать их по пути нельзя: диски меняются
+        if cd::is_cd_track(path) {
+            return cd::probe(path).map(|(tags, _)| tags);
+        }
+        if stream::is_stream(path) {
+            return stream::metadata(path);
+        }
+        if let CachedTags::Fresh(tags) = self.library.lock().unwrap().cached_tags(path) {
+            return tags;
+        }
+        refresh(&self.library, path).0
+    }
+}
+
+// Идентификаторы MusicBrainz, которые пишет Picard; нужны сервисам прослушиваний
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub release: Option<String>,
+    pub artists: Vec<String>,
+}
+
+// ReplayGain нужен до начала трека, поэтому читается отдельно от тегов
+pub fn replay_gain(library: &Mutex<LibraryDb>, path: &Path) -> ReplayGain {
+    if cd::is_cd_track(path) || stream::is_stream(path) {
+        return ReplayGain::default();
+    }
+    if let Some(gain) = library.lock().unwrap().cached_replay_gain(path) {
+        return gain;
+    }
+    refresh(library, path).1
+}
+
+pub fn musicbrainz(library: &Mutex<LibraryDb>, path: &Path) -> MusicBrainzIds {
+    if cd::is_cd_track(path) || stream::is_stream(path) {
+        return MusicBrainzIds::default();
+    }
+    if let Some(ids) = library.lock().unwrap().cached_musicbrainz(path) {
+        return ids;
+    }
+    refresh(library, path).2
+}
+
+// Читает файл без блокировки базы и обновляет кэш
+fn refresh(
+    library: &Mutex<LibraryDb>,
+    path: &Path,
+) -> (Option<TrackMetadata>, ReplayGain, MusicBrainzIds) {
+    let (tags, duration, gain, ids) = if tracker::is_module(path) || gme::is_game_file(path) {
+        let probe = if tracker::is_module(path) {
+            tracker::probe(path)
+        } else {
+            gme::probe(path)
+        };
+        match probe {
+            Some((tags, duration)) => (
+                tags,
+                Some(duration),
+                ReplayGain::default(),
+                MusicBrainzIds::default(),
+            ),
+            None => (None, None, ReplayGain::default(), MusicBrainzIds::default()),
+        }
+    } else {
+        let probe = probe(path);
+        let tags = probe.as_ref().and_then(|probe| probe.tags(path));
+        let gain = probe
+            .as_ref()
+            .map(|probe| probe.replay_gain())
+            .unwrap_or_default();
+        let ids = probe
+            .as_ref()
+            .map(|probe| probe.musicbrainz())
+            .unwrap_or_default();
+        (tags, probe.and_then(|probe| probe.duration), gain, ids)
+    };
+    library
+        .lock()
+        .unwrap()
+        .store_tags(path, tags.as_ref(), duration, &gain, &ids);
+    (tags, gain, ids)
+}
+
+impl Probe {
+    pub fn replay_gain(&self) -> ReplayGain {
+        let mut gain = ReplayGain::default();
+        for revision in &self.revisions {
+            for tag in revision.tags() {
+                gain.read_tag(tag);
+            }
+        }
+        gain
+    }
+
+    // Picard пишет id записи в musicbrainz_trackid, а id трека релиза — в releasetrackid
+    pub fn musicbrainz(&self) -> MusicBrainzIds {
+        let mut ids = MusicBrainzIds::default();
+        for revision in &self.revisions {
+            for tag in revision.tags() {
+                let value = tag.value.to_string().trim().to_string();
+                if value.is_empty() {
+                    continue;
+                }
+                match tag.std_key {
+                    Some(StandardTagKey::MusicBrainzRecordingId)
+                    | Some(StandardTagKey::MusicBrainzTrackId) => {
+                        ids.recording.get_or_insert(value);
+                    }
+                    Some(StandardTagKey::MusicBrainzAlbumId) => {
+                        ids.release.get_or_insert(value);
+                    }
+                    // Несколько исполнителей — отдельными тегами или через "/" и ";"
+                    Some(StandardTagKey::MusicBrainzArtistId) => {
+                        for id in value.split(['/', ';']).map(str::trim) {
+                            if !id.is_empty() && !ids.artists.iter().any(|known| known == id) {
+                                ids.artists.push(id.to_string());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        ids
+    }
+}
+
+pub struct Probe {
+    pub revisions: Vec<MetadataRevision>,
+    // По числу кадров в заголовке, если он его указывает
+    pub duration: Option<f32>,
+}
+
+// Ревизии метаданных файла: ID3 перед контейнером, затем теги самого контейнера
+pub fn probe(path: &Path) -> Option<Probe> {
+    let file = File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let mut revisions = Vec::new();
+    if let Some(metadata) = probed.metadata.get() {
+        revisions.extend(metadata.current().cloned());
+    }
+    revisions.extend(probed.format.metadata().current().cloned());
+
+    let duration = probed.format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        let frames = params.n_frames?;
+        let rate = params.sample_rate.filter(|rate| *rate > 0)?;
+        Some(frames as f32 / rate as f32)
+    });
+    Some(Probe {
+        revisions,
+        duration,
+    })
+}
+
+impl Probe {
+    // Без названия теги не используются: остаётся имя файла
+    fn tags(&self, path: &Path) -> Option<TrackMetadata> {
+        let mut title = None;
+        let mut artist = None;
+        let mut album_artist = None;
+        let mut album = None;
+        let mut track_number = None;
+
+        for revision in &self.revisions {
+            for tag in revision.tags() {
+                let slot = match tag.std_key {
+                    Some(StandardTagKey::TrackTitle) => &mut title,
+                    Some(StandardTagKey::Artist) => &mut artist,
+                    Some(StandardTagKey::AlbumArtist) => &mut album_artist,
+                    Some(StandardTagKey::Album) => &mut album,
+                    Some(StandardTagKey::TrackNumber) => {
+                        track_number = track_number.or_else(|| parse_track_number(&tag.value));
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if slot.is_none() {
+                    *slot =
+                        Some(tag.value.to_string().trim().to_string()).filter(|v| !v.is_empty());
+                }
+            }
+        }
+
+        Some(TrackMetadata {
+            path: path.to_path_buf(),
+            title: title?,
+            artist: artist.or(album_artist),
+            album,
+            track_number,
+        })
+    }
+}
+
+// "3", "03" или "3/12"
+fn parse_track_number(value: &Value) -> Option<u32> {
+    match value {
+        Value::UnsignedInt(n) => u32::try_from(*n).ok(),
+        Value::SignedInt(n) => u32::try_from(*n).ok(),
+        Value::String(s) => s.split('/').next()?.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|n| *n > 0)
+}
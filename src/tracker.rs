@@ -0,0 +1,310 @@
+use crate::gme;
+use crate::plugin::{Library, TrackMetadata};
+use rodio::source::SeekError;
+use rodio::Source;
+use std::ffi::{c_char, c_double, c_int, c_void, CStr, CString};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// libopenmpt загружается при первом трекерном файле; без неё такие файлы просто не играют
+const LIBRARY: &str = "libopenmpt.so.0";
+const RATE: u32 = 48000;
+// Кадров за один вызов рендера
+const CHUNK_FRAMES: usize = 1024;
+// Подпесня в очереди: "song.it#2", нумерация с единицы
+const SUBSONG_MARK: char = '#';
+
+const EXTENSIONS: [&str; 14] = [
+    "mod", "xm", "it", "s3m", "mptm", "mtm", "669", "med", "okt", "stm", "ult", "far", "umx", "amf",
+];
+
+pub fn is_tracker(ext: &str) -> bool {
+    EXTENSIONS.contains(&ext)
+}
+
+pub fn extensions() -> &'static [&'static str] {
+    &EXTENSIONS
+}
+
+// Файл модуля или подпесня из него
+pub fn is_module(path: &Path) -> bool {
+    split_subsong(path)
+        .0
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| is_tracker(&ext.to_lowercase()))
+}
+
+type CreateFn = unsafe extern "C" fn(
+    *const c_void,
+    usize,
+    *const c_void,
+    *mut c_void,
+    *const c_void,
+    *mut c_void,
+    *mut c_int,
+    *mut *const c_char,
+    *const c_void,
+) -> *mut c_void;
+
+struct Api {
+    create: CreateFn,
+    destroy: unsafe extern "C" fn(*mut c_void),
+    read_stereo: unsafe extern "C" fn(*mut c_void, i32, usize, *mut f32) -> usize,
+    duration: unsafe extern "C" fn(*mut c_void) -> c_double,
+    set_position: unsafe extern "C" fn(*mut c_void, c_double) -> c_double,
+    num_subsongs: unsafe extern "C" fn(*mut c_void) -> i32,
+    select_subsong: unsafe extern "C" fn(*mut c_void, i32) -> c_int,
+    get_metadata: unsafe extern "C" fn(*mut c_void, *const c_char) -> *const c_char,
+    subsong_name: unsafe extern "C" fn(*mut c_void, i32) -> *const c_char,
+    free_string: unsafe extern "C" fn(*const c_char),
+    _library: Library,
+}
+
+impl Api {
+    fn load() -> Result<Self, String> {
+        let library = Library::open(Path::new(LIBRARY))?;
+        unsafe {
+            Ok(Self {
+                create: library.function("openmpt_module_create_from_memory2")?,
+                destroy: library.function("openmpt_module_destroy")?,
+                read_stereo: library.function("openmpt_module_read_interleaved_float_stereo")?,
+                duration: library.function("openmpt_module_get_duration_seconds")?,
+                set_position: library.function("openmpt_module_set_position_seconds")?,
+                num_subsongs: library.function("openmpt_module_get_num_subsongs")?,
+                select_subsong: library.function("openmpt_module_select_subsong")?,
+                get_metadata: library.function("openmpt_module_get_metadata")?,
+                subsong_name: library.function("openmpt_module_get_subsong_name")?,
+                free_string: library.function("openmpt_free_string")?,
+                _library: library,
+            })
+        }
+    }
+
+    // Строки libopenmpt освобождаются её же функцией
+    fn take_string(&self, raw: *const c_char) -> Option<String> {
+        if raw.is_null() {
+            return None;
+        }
+        let value = unsafe { CStr::from_ptr(raw) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { (self.free_string)(raw) };
+        Some(value).filter(|value| !value.trim().is_empty())
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(Api::load).as_ref().map_err(Clone::clone)
+}
+
+// Трекерные файлы попадают в библиотеку, только если libopenmpt установлена
+pub fn available() -> bool {
+    api().is_ok()
+}
+
+// "song.it#2" -> ("song.it", Some(1)); обычный путь возвращается как есть.
+// Так же нумеруются треки файлов игровой музыки
+pub fn split_subsong(path: &Path) -> (PathBuf, Option<i32>) {
+    let text = path.to_string_lossy();
+    if let Some((file, number)) = text.rsplit_once(SUBSONG_MARK) {
+        let file = Path::new(file);
+        let numbered = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .is_some_and(|ext| is_tracker(&ext) || gme::is_game_music(&ext));
+        if let (true, Ok(number)) = (numbered, number.parse::<i32>()) {
+            if number >= 1 {
+                return (file.to_path_buf(), Some(number - 1));
+            }
+        }
+    }
+    (path.to_path_buf(), None)
+}
+
+pub fn subsong_path(path: &Path, index: i32) -> PathBuf {
+    let mut text = path.as_os_str().to_owned();
+    text.push(format!("{}{}", SUBSONG_MARK, index + 1));
+    PathBuf::from(text)
+}
+
+// Модуль с несколькими подпеснями становится несколькими записями очереди
+pub fn expand(path: &Path) -> Vec<PathBuf> {
+    let count = std::fs::read(path)
+        .ok()
+        .and_then(|data| Module::load(&data).ok())
+        .map(|module| module.subsongs())
+        .unwrap_or(1);
+    if count <= 1 {
+        return vec![path.to_path_buf()];
+    }
+    (0..count).map(|index| subsong_path(path, index)).collect()
+}
+
+// Название модуля и подпесни вместо имени файла и длительность подпесни
+pub fn probe(path: &Path) -> Option<(Option<TrackMetadata>, f32)> {
+    let (file, subsong) = split_subsong(path);
+    let module = Module::load(&std::fs::read(&file).ok()?).ok()?;
+    let api = module.api;
+    if let Some(index) = subsong {
+        unsafe { (api.select_subsong)(module.raw, index) };
+    }
+    let duration = unsafe { (api.duration)(module.raw) } as f32;
+    let title = module.string("title");
+    let subsong_name =
+        subsong.and_then(|index| api.take_string(unsafe { (api.subsong_name)(module.raw, index) }));
+    let title = match (title, subsong_name) {
+        (Some(title), Some(name)) => format!("{} - {}", title, name),
+        (Some(title), None) => match subsong {
+            Some(index) => format!("{} #{}", title, index + 1),
+            None => title,
+        },
+        (None, Some(name)) => name,
+        (None, None) => return Some((None, duration)),
+    };
+    let tags = TrackMetadata {
+        path: path.to_path_buf(),
+        title,
+        artist: module.string("artist"),
+        track_number: subsong.map(|index| index as u32 + 1),
+        ..Default::default()
+    };
+    Some((Some(tags), duration))
+}
+
+struct Module {
+    api: &'static Api,
+    raw: *mut c_void,
+}
+
+// Модулем пользуется один поток за раз
+unsafe impl Send for Module {}
+
+impl Module {
+    fn load(data: &[u8]) -> Result<Self, String> {
+        let api = api()?;
+        let mut error: c_int = 0;
+        let mut message: *const c_char = ptr::null();
+        let raw = unsafe {
+            (api.create)(
+                data.as_ptr() as *const c_void,
+                data.len(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null_mut(),
+                &mut error,
+                &mut message,
+                ptr::null(),
+            )
+        };
+        if raw.is_null() {
+            let message = api
+                .take_string(message)
+                .unwrap_or_else(|| format!("error {}", error));
+            return Err(format!("libopenmpt: {}", message));
+        }
+        Ok(Self { api, raw })
+    }
+
+    fn subsongs(&self) -> i32 {
+        unsafe { (self.api.num_subsongs)(self.raw) }
+    }
+
+    fn string(&self, key: &str) -> Option<String> {
+        let key = CString::new(key).ok()?;
+        self.api
+            .take_string(unsafe { (self.api.get_metadata)(self.raw, key.as_ptr()) })
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe { (self.api.destroy)(self.raw) };
+    }
+}
+
+pub struct TrackerSource {
+    module: Module,
+    samples: Vec<f32>,
+    position: usize,
+    duration: Duration,
+}
+
+impl TrackerSource {
+    pub fn new(mut reader: impl Read, subsong: Option<i32>) -> Result<Self, String> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        let module = Module::load(&data)?;
+        if let Some(index) = subsong {
+            if unsafe { (module.api.select_subsong)(module.raw, index) } == 0 {
+                return Err(format!("no subsong {}", index + 1));
+            }
+        }
+        let seconds = unsafe { (module.api.duration)(module.raw) };
+        Ok(Self {
+            module,
+            samples: Vec::new(),
+            position: 0,
+            duration: Duration::from_secs_f64(seconds.max(0.0)),
+        })
+    }
+
+    fn render(&mut self) -> bool {
+        self.samples.resize(CHUNK_FRAMES * 2, 0.0);
+        let frames = unsafe {
+            (self.module.api.read_stereo)(
+                self.module.raw,
+                RATE as i32,
+                CHUNK_FRAMES,
+                self.samples.as_mut_ptr(),
+            )
+        };
+        self.samples.truncate(frames * 2);
+        self.position = 0;
+        frames > 0
+    }
+}
+
+impl Iterator for TrackerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.samples.len() && !self.render() {
+            return None;
+        }
+        let sample = self.samples[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for TrackerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        unsafe { (self.module.api.set_position)(self.module.raw, pos.as_secs_f64()) };
+        self.samples.clear();
+        self.position = 0;
+        Ok(())
+    }
+}
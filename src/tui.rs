@@ -0,0 +1,317 @@
+use crate::{send_command_as, SendError};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+// Как часто опрашивать status; очередь догоняется по правкам, когда меняется её ревизия
+const REFRESH: Duration = Duration::from_millis(500);
+const HELP: &str =
+    "j/k move  Enter play  Space pause  n/p next/prev  h/l seek  +/- volume  g/G top/bottom  v preview  q quit";
+
+struct App {
+    token: Option<String>,
+    status: Value,
+    tracks: Vec<Value>,
+    // Ревизия очереди, которой соответствует tracks
+    revision: Option<u64>,
+    list: ListState,
+    // Последняя ошибка команды или связи с плеером, внизу экрана
+    message: Option<String>,
+    // Выбранный трек звучит отрывком, пока по очереди двигается курсор
+    previewing: bool,
+}
+
+// Терминальный интерфейс к запущенному плееру: очередь, прогресс и громкость.
+// Всё управление — обычные команды сокета, так что плеер может быть и демоном
+pub fn run(token: Option<String>) -> Result<(), String> {
+    let mut app = App {
+        token,
+        status: Value::Null,
+        tracks: Vec::new(),
+        revision: None,
+        list: ListState::default(),
+        message: None,
+        previewing: false,
+    };
+    // Без плеера нечего показывать: ошибка печатается до перехода в полноэкранный режим
+    app.refresh().map_err(|e| e.to_string())?;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    if app.previewing {
+        let _ = app.send("preview-stop");
+    }
+    result
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), String> {
+        let mut refreshed = Instant::now();
+        loop {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .map_err(|e| e.to_string())?;
+            let timeout = REFRESH.saturating_sub(refreshed.elapsed());
+            if event::poll(timeout).map_err(|e| e.to_string())? {
+                if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+                    if key.kind == KeyEventKind::Press && !self.key(key.code) {
+                        return Ok(());
+                    }
+                }
+            }
+            if refreshed.elapsed() >= REFRESH {
+                if let Err(e) = self.refresh() {
+                    self.message = Some(e.to_string());
+                }
+                refreshed = Instant::now();
+            }
+        }
+    }
+
+    // false — выйти
+    fn key(&mut self, code: KeyCode) -> bool {
+        let last = self.tracks.len().saturating_sub(1);
+        let selected = self.list.selected().unwrap_or(0);
+        let moved = match code {
+            KeyCode::Char('j') | KeyCode::Down => Some((selected + 1).min(last)),
+            KeyCode::Char('k') | KeyCode::Up => Some(selected.saturating_sub(1)),
+            KeyCode::Char('g') | KeyCode::Home => Some(0),
+            KeyCode::Char('G') | KeyCode::End => Some(last),
+            _ => None,
+        };
+        if let Some(index) = moved {
+            self.list.select(Some(index));
+            if self.previewing && index != selected {
+                self.message = self
+                    .send(&format!("preview-start {}", index))
+                    .err()
+                    .map(|e| e.to_string());
+            }
+            return true;
+        }
+        let command = match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('v') if self.previewing => {
+                self.previewing = false;
+                "preview-stop".to_string()
+            }
+            KeyCode::Char('v') if !self.tracks.is_empty() => {
+                self.previewing = true;
+                format!("preview-start {}", selected)
+            }
+            KeyCode::Enter if !self.tracks.is_empty() => {
+                if self.previewing {
+                    self.previewing = false;
+                    let _ = self.send("preview-stop");
+                }
+                format!("goto {}", selected)
+            }
+            KeyCode::Char(' ') => "pause".to_string(),
+            KeyCode::Char('n') => "next".to_string(),
+            KeyCode::Char('p') => "prev".to_string(),
+            KeyCode::Char('l') | KeyCode::Right => "seek_forward".to_string(),
+            KeyCode::Char('h') | KeyCode::Left => "seek_backward".to_string(),
+            KeyCode::Char('+') | KeyCode::Char('=') => "volume_up".to_string(),
+            KeyCode::Char('-') => "volume_down".to_string(),
+            _ => return true,
+        };
+        self.message = self.send(&command).err().map(|e| e.to_string());
+        if let Err(e) = self.refresh() {
+            self.message = Some(e.to_string());
+        }
+        true
+    }
+
+    fn send(&self, command: &str) -> Result<String, SendError> {
+        send_command_as(command, self.token.as_deref())
+    }
+
+    fn refresh(&mut self) -> Result<(), SendError> {
+        let status: Value = serde_json::from_str(&self.send("status")?).unwrap_or_default();
+        let revision = status["queue_revision"].as_u64();
+        // Выделение идёт за текущим треком, пока пользователь его не сдвинул
+        let following = self.list.selected().is_none() || self.list.selected() == self.current();
+        self.status = status;
+        if revision.is_none() || revision != self.revision {
+            self.sync_queue()?;
+        }
+        if following {
+            self.list.select(self.current());
+        }
+        let last = self.tracks.len().saturating_sub(1);
+        if let Some(selected) = self.list.selected().filter(|&selected| selected > last) {
+            self.list.select(Some(selected.min(last)));
+        }
+        Ok(())
+    }
+
+    // Правки после известной ревизии; если плеер их уже не помнит — очередь целиком
+    fn sync_queue(&mut self) -> Result<(), SendError> {
+        if let Some(revision) = self.revision {
+            let command = format!("queue changes --since {}", revision);
+            let reply: Value = serde_json::from_str(&self.send(&command)?).unwrap_or_default();
+            if reply["reset"] == false && self.apply(&reply["changes"]) {
+                self.revision = reply["revision"].as_u64();
+                return Ok(());
+            }
+        }
+        let queue: Value = serde_json::from_str(&self.send("queue list")?).unwrap_or_default();
+        self.tracks = queue["tracks"].as_array().cloned().unwrap_or_default();
+        self.revision = queue["revision"].as_u64();
+        Ok(())
+    }
+
+    // false — правка не ложится на локальную копию, её надо перечитать
+    fn apply(&mut self, changes: &Value) -> bool {
+        let Some(changes) = changes.as_array() else {
+            return false;
+        };
+        let index = |value: &Value| value.as_u64().map(|index| index as usize);
+        for change in changes {
+            match change["op"].as_str() {
+                Some("splice") => {
+                    let (Some(start), Some(remove)) =
+                        (index(&change["index"]), index(&change["remove"]))
+                    else {
+                        return false;
+                    };
+                    if start + remove > self.tracks.len() {
+                        return false;
+                    }
+                    let insert = change["insert"].as_array().cloned().unwrap_or_default();
+                    self.tracks.splice(start..start + remove, insert);
+                }
+                Some("move") => {
+                    let (Some(from), Some(to)) = (index(&change["from"]), index(&change["to"]))
+                    else {
+                        return false;
+                    };
+                    if from >= self.tracks.len() || to >= self.tracks.len() {
+                        return false;
+                    }
+                    let track = self.tracks.remove(from);
+                    self.tracks.insert(to, track);
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.status["index"].as_u64().map(|index| index as usize)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, queue, progress, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let status = &self.status;
+        let state = status["state"].as_str().unwrap_or("unknown");
+        let volume = status["volume"].as_f64().unwrap_or(0.0) * 100.0;
+        let mut modes = Vec::new();
+        if status["shuffle"] == true {
+            modes.push("shuffle".to_string());
+        }
+        if let Some(repeat) = status["repeat"].as_str() {
+            modes.push(format!("repeat {}", repeat.to_lowercase()));
+        }
+        frame.render_widget(
+            Paragraph::new(format!(
+                "[{}] {}   vol {:.0}%   {}",
+                state,
+                status["display"].as_str().unwrap_or_default(),
+                volume,
+                modes.join(", ")
+            ))
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+            header,
+        );
+
+        let current = self.current();
+        let items: Vec<ListItem> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| {
+                let marker = if Some(index) == current { "▶ " } else { "  " };
+                let duration = track["duration"]
+                    .as_f64()
+                    .map(clock_time)
+                    .unwrap_or_default();
+                let item = ListItem::new(Line::from(format!(
+                    "{}{:>4}  {}  {}",
+                    marker,
+                    index + 1,
+                    display(track),
+                    duration
+                )));
+                if Some(index) == current {
+                    item.style(Style::new().add_modifier(Modifier::BOLD))
+                } else if track["missing"] == true {
+                    item.style(Style::new().add_modifier(Modifier::DIM))
+                } else {
+                    item
+                }
+            })
+            .collect();
+        let title = format!(" Queue ({}) ", self.tracks.len());
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            queue,
+            &mut self.list,
+        );
+
+        let position = status["position"].as_f64().unwrap_or(0.0);
+        let duration = current
+            .and_then(|index| self.tracks.get(index))
+            .and_then(|track| track["duration"].as_f64());
+        let label = match duration {
+            Some(duration) => format!("{} / {}", clock_time(position), clock_time(duration)),
+            None => clock_time(position),
+        };
+        let ratio = duration
+            .filter(|duration| *duration > 0.0)
+            .map_or(0.0, |duration| (position / duration).clamp(0.0, 1.0));
+        frame.render_widget(Gauge::default().ratio(ratio).label(label), progress);
+
+        let footer_text = self.message.as_deref().unwrap_or(HELP);
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+}
+
+// "Исполнитель - Название", иначе имя файла
+fn display(track: &Value) -> String {
+    let title = track["title"].as_str().unwrap_or_default();
+    match track["artist"].as_str() {
+        Some(artist) if !title.is_empty() => format!("{} - {}", artist, title),
+        _ if !title.is_empty() => title.to_string(),
+        _ => track["path"]
+            .as_str()
+            .and_then(|path| path.rsplit('/').next())
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+// "3:07", "1:02:45"
+fn clock_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+// Единица для голого числа: "sleep 15" — минуты, "seek_forward 15" — секунды
+pub const SECOND: Duration = Duration::from_secs(1);
+pub const MINUTE: Duration = Duration::from_secs(60);
+// Дольше года не нужно ни таймеру, ни перемотке, а меньший предел не даёт
+// переполниться сложению с текущим временем или позицией
+const MAX_DURATION: Duration = Duration::from_secs(366 * 24 * 60 * 60);
+
+const DURATION_HINT: &str = "expected e.g. 90, 1m30s, 1:30, 2h or 500ms";
+const PERCENT_HINT: &str = "expected 0-100, optionally with %";
+const VOLUME_HINT: &str = "expected 0-100 or a change like +5 or -10%";
+
+// "1h2m3s", "1m30s", "500ms", "1:30", "1:02:03" или число в единицах bare
+pub fn parse_duration(text: &str, bare: Duration) -> Result<Duration, String> {
+    let duration = parse_unbounded(text, bare)?;
+    if duration > MAX_DURATION {
+        return Err(format!(
+            "duration '{}' is too long (at most 366 days)",
+            text.trim()
+        ));
+    }
+    Ok(duration)
+}
+
+fn parse_unbounded(text: &str, bare: Duration) -> Result<Duration, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid duration '{}': {}", text, DURATION_HINT);
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(value) = text.parse::<f64>() {
+        return scale(value, bare).ok_or_else(invalid);
+    }
+    if text.contains(':') {
+        return parse_clock(text).ok_or_else(invalid);
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        let (number, tail) = rest.split_at(split);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let unit = match unit {
+            "ms" => Duration::from_millis(1),
+            "s" | "sec" => SECOND,
+            "m" | "min" => MINUTE,
+            "h" => MINUTE * 60,
+            _ => return Err(invalid()),
+        };
+        let value = number.parse::<f64>().map_err(|_| invalid())?;
+        total = total
+            .checked_add(scale(value, unit).ok_or_else(invalid)?)
+            .ok_or_else(invalid)?;
+        rest = tail;
+    }
+    Ok(total)
+}
+
+// "1:30" — минуты и секунды, "1:02:03" — часы, минуты и секунды
+fn parse_clock(text: &str) -> Option<Duration> {
+    let parts: Vec<&str> = text.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let (last, whole) = parts.split_last()?;
+    let mut seconds = 0u64;
+    for part in whole {
+        seconds = seconds
+            .checked_mul(60)?
+            .checked_add(part.parse::<u64>().ok()?)?;
+    }
+    let fraction = last.parse::<f64>().ok().filter(|s| *s < 60.0)?;
+    scale(seconds as f64 * 60.0 + fraction, SECOND)
+}
+
+fn scale(value: f64, unit: Duration) -> Option<Duration> {
+    Duration::try_from_secs_f64(value * unit.as_secs_f64()).ok()
+}
+
+// Как parse_duration, но со знаком: +10s и -10s — смещение, 1:30 — позиция
+pub enum Seek {
+    To(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+
+pub fn parse_seek(text: &str) -> Result<Seek, String> {
+    let text = text.trim();
+    if let Some(offset) = text.strip_prefix('+') {
+        parse_duration(offset, SECOND).map(Seek::Forward)
+    } else if let Some(offset) = text.strip_prefix('-') {
+        parse_duration(offset, SECOND).map(Seek::Backward)
+    } else {
+        parse_duration(text, SECOND).map(Seek::To)
+    }
+}
+
+// "40" или "40%" -> 0.4
+pub fn parse_percent(text: &str) -> Result<f32, String> {
+    let text = text.trim();
+    text.strip_suffix('%')
+        .unwrap_or(text)
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .map(|percent| percent / 100.0)
+        .ok_or_else(|| format!("invalid percentage '{}': {}", text, PERCENT_HINT))
+}
+
+pub enum Volume {
+    Set(f32),
+    // Доля от полной громкости, со знаком
+    Change(f32),
+}
+
+// "40", "40%" — уровень, "+5", "-10%" — изменение
+pub fn parse_volume(text: &str) -> Result<Volume, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid volume '{}': {}", text, VOLUME_HINT);
+    if let Some(delta) = text.strip_prefix('+') {
+        parse_percent(delta)
+            .map(Volume::Change)
+            .map_err(|_| invalid())
+    } else if let Some(delta) = text.strip_prefix('-') {
+        parse_percent(delta)
+            .map(|delta| Volume::Change(-delta))
+            .map_err(|_| invalid())
+    } else {
+        parse_percent(text).map(Volume::Set).map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(text: &str) -> f64 {
+        parse_duration(text, SECOND).unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn durations_in_every_notation() {
+        assert_eq!(secs("90"), 90.0);
+        assert_eq!(secs("1m30s"), 90.0);
+        assert_eq!(secs("1h2m3s"), 3723.0);
+        assert_eq!(secs("1:30"), 90.0);
+        assert_eq!(secs("1:02:03"), 3723.0);
+        assert_eq!(secs("500ms"), 0.5);
+        assert_eq!(secs(" 2.5min "), 150.0);
+        assert_eq!(parse_duration("15", MINUTE).unwrap(), MINUTE * 15);
+    }
+
+    #[test]
+    fn durations_are_limited_and_validated() {
+        assert!(parse_duration("366:00:00:00", SECOND).is_err());
+        assert!(parse_duration("8784h", SECOND).is_ok());
+        let e = parse_duration("8785h", SECOND).unwrap_err();
+        assert!(e.contains("too long"), "{}", e);
+        for text in [
+            "1e300",
+            "99999999999999999999h",
+            "inf",
+            "NaN",
+            "-5",
+            "",
+            "1:60",
+            "5x",
+            "m",
+            "1.2.3s",
+        ] {
+            assert!(parse_duration(text, SECOND).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn seek_sign_picks_the_direction() {
+        assert!(matches!(parse_seek("+10").unwrap(), Seek::Forward(d) if d == SECOND * 10));
+        assert!(matches!(parse_seek("-1m").unwrap(), Seek::Backward(d) if d == MINUTE));
+        assert!(matches!(parse_seek("1:30").unwrap(), Seek::To(d) if d == SECOND * 90));
+    }
+
+    #[test]
+    fn percentages_and_volume_stay_in_range() {
+        assert_eq!(parse_percent("40").unwrap(), 0.4);
+        assert_eq!(parse_percent("40 %").unwrap(), 0.4);
+        for text in ["101", "-1", "NaN", "loud"] {
+            assert!(parse_percent(text).is_err(), "{}", text);
+        }
+        assert!(matches!(parse_volume("60%").unwrap(), Volume::Set(v) if v == 0.6));
+        assert!(matches!(parse_volume("+5").unwrap(), Volume::Change(v) if v == 0.05));
+        assert!(matches!(parse_volume("-10%").unwrap(), Volume::Change(v) if v == -0.1));
+        assert!(parse_volume("+200").is_err());
+    }
+}
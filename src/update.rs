@@ -0,0 +1,213 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RELEASES: &str = "https://api.github.com/repos/Vladgobelen/NSmp/releases/latest";
+const CHECKSUMS: &str = "SHA256SUMS";
+const SIGNATURE: &str = "SHA256SUMS.minisig";
+// Открытый ключ minisign, которым подписаны релизы; задаётся при сборке релиза
+const BUILT_IN_KEY: Option<&str> = option_env!("NSMP_UPDATE_KEY");
+
+// Статический бинарник релиза: nsmp-x86_64-linux, nsmp-aarch64-linux...
+fn asset_name() -> String {
+    format!("nsmp-{}-linux", env::consts::ARCH)
+}
+
+// Проверяет последний релиз на GitHub и заменяет текущий бинарник новым.
+// Файл сверяется с SHA256SUMS, а сам список — с подписью minisign. Без ключа
+// подписи замена только с insecure: список с того же сервера от подмены не защищает.
+// Замена — rename в той же папке, так что запущенный плеер доигрывает на старом файле
+pub fn self_update(
+    check_only: bool,
+    public_key: Option<String>,
+    insecure: bool,
+) -> Result<(), String> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: serde_json::Value = serde_json::from_slice(&curl(&[
+        "-H",
+        "Accept: application/vnd.github+json",
+        RELEASES,
+    ])?)
+    .map_err(|e| format!("GitHub: {}", e))?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or("GitHub: no release found")?;
+    let latest = tag.trim_start_matches('v');
+    if !is_newer(latest, current) {
+        println!("nsmp {} is up to date", current);
+        return Ok(());
+    }
+    if check_only {
+        println!("nsmp {} is available (installed: {})", latest, current);
+        return Ok(());
+    }
+
+    let asset = asset_name();
+    let url = |name: &str| {
+        release["assets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|item| item["name"] == name)
+            .and_then(|item| item["browser_download_url"].as_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("release {} has no {}", tag, name))
+    };
+    let binary_url = url(&asset)?;
+    let checksums_url = url(CHECKSUMS)?;
+
+    let exe = env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|e| format!("cannot locate the running binary: {}", e))?;
+    if let Some(home) = env::var_os("HOME") {
+        if exe.starts_with(Path::new(&home).join(".cargo")) {
+            return Err("nsmp was installed with cargo; update it with cargo install".to_string());
+        }
+    }
+    let dir = exe.parent().ok_or("the binary has no parent directory")?;
+
+    let key = public_key.as_deref().or(BUILT_IN_KEY);
+    if key.is_none() && !insecure {
+        return Err(
+            "no release signing key; pass --public-key, or --insecure to trust the checksum alone"
+                .to_string(),
+        );
+    }
+    let checksums = curl(&[&checksums_url])?;
+    match key {
+        Some(key) => verify_signature(&checksums, &url(SIGNATURE)?, key)?,
+        None => eprintln!("Warning: no release signing key, checking the SHA-256 checksum only"),
+    }
+    let expected = String::from_utf8_lossy(&checksums)
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches('*') == asset)
+        .map(|(sum, _)| sum.to_lowercase())
+        .ok_or_else(|| format!("{} does not list {}", CHECKSUMS, asset))?;
+
+    // Во временный файл рядом с бинарником: rename атомарен только в пределах одной ФС
+    let staged = dir.join(format!(".nsmp-update-{}", std::process::id()));
+    let result = download(&binary_url, &staged)
+        .and_then(|_| check_sum(&staged, &expected))
+        .and_then(|_| {
+            fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))
+                .map_err(|e| e.to_string())
+        })
+        // Иначе после сбоя питания на месте бинарника может оказаться пустой файл
+        .and_then(|_| {
+            fs::File::open(&staged)
+                .and_then(|file| file.sync_all())
+                .map_err(|e| e.to_string())
+        })
+        .and_then(|_| fs::rename(&staged, &exe).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&staged);
+        return Err(format!("{}: {}", exe.display(), e));
+    }
+    println!("Updated nsmp {} -> {} ({})", current, latest, exe.display());
+    Ok(())
+}
+
+fn curl(args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("curl")
+        .args(["-sSfL", "--max-time", "300", "-A", "NSmp/0.1"])
+        .args(args)
+        .output()
+        .map_err(|e| format!("curl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "curl: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn download(url: &str, path: &Path) -> Result<(), String> {
+    let path = path.to_str().ok_or("non-UTF-8 install path")?;
+    curl(&["-o", path, url]).map(|_| ())
+}
+
+fn check_sum(path: &Path, expected: &str) -> Result<(), String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("sha256sum: {}", e))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let actual = text.split_whitespace().next().unwrap_or_default();
+    if !output.status.success() || actual != expected {
+        return Err("checksum mismatch, the download is corrupt or tampered with".to_string());
+    }
+    Ok(())
+}
+
+fn verify_signature(checksums: &[u8], signature_url: &str, key: &str) -> Result<(), String> {
+    let dir = private_dir()?;
+    let list = dir.join(CHECKSUMS);
+    let signature = dir.join(SIGNATURE);
+    let result = fs::write(&list, checksums)
+        .map_err(|e| e.to_string())
+        .and_then(|_| download(signature_url, &signature))
+        .and_then(|_| minisign(&list, &signature, key));
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+// Своя папка только для владельца: заранее подложенную по тому же имени
+// папку или ссылку create не примет, и попытка повторится с другим именем
+fn private_dir() -> Result<PathBuf, String> {
+    let mut error = String::new();
+    for attempt in 0..16u32 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let dir = env::temp_dir().join(format!(
+            "nsmp-update-{}-{:08x}",
+            std::process::id(),
+            nanos ^ attempt.rotate_left(16)
+        ));
+        match fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) => error = e.to_string(),
+        }
+    }
+    Err(format!("cannot create a temporary directory: {}", error))
+}
+
+fn minisign(list: &Path, signature: &Path, key: &str) -> Result<(), String> {
+    let output = Command::new("minisign")
+        .arg("-Vq")
+        .arg("-P")
+        .arg(key)
+        .arg("-m")
+        .arg(list)
+        .arg("-x")
+        .arg(signature)
+        .output()
+        .map_err(|e| format!("minisign is needed to verify the release: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "bad release signature: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// "0.10.0" новее "0.9.3": части сравниваются числами; суффиксы вроде -rc1 отбрасываются
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(latest) > parts(current)
+}
@@ -0,0 +1,170 @@
+use crate::plugin::{Control, InputSource};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{mem, ptr};
+
+const EVENTS: u32 = libc::IN_CREATE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_TO
+    | libc::IN_MOVED_FROM
+    | libc::IN_DELETE;
+
+// Новые и удалённые файлы в папке с музыкой попадают в библиотеку без перезапуска
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WatchConfig {
+    pub enabled: bool,
+    // Сколько ждать после последнего изменения: альбом копируется не мгновенно
+    pub settle_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            settle_ms: 2000,
+        }
+    }
+}
+
+pub struct LibraryWatch {
+    root: PathBuf,
+    settle: Duration,
+}
+
+impl LibraryWatch {
+    pub fn new(root: &Path, config: &WatchConfig) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            settle: Duration::from_millis(config.settle_ms),
+        }
+    }
+}
+
+impl InputSource for LibraryWatch {
+    fn name(&self) -> &str {
+        "watch"
+    }
+
+    // Изменения копятся, пока папка не затихнет на settle, затем каждая изменённая
+    // ветка пересканируется командой "rescan <путь>"
+    fn run(self: Box<Self>, control: Control) -> Result<(), String> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        let mut inotify = unsafe { File::from_raw_fd(fd) };
+        let mut dirs = HashMap::new();
+        watch_tree(fd, &self.root, &mut dirs);
+
+        let mut changed = BTreeSet::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let timeout = if changed.is_empty() {
+                -1
+            } else {
+                self.settle.as_millis() as i32
+            };
+            let mut poll = libc::pollfd {
+                fd: inotify.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut poll, 1, timeout) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error.to_string());
+            }
+            if ready == 0 {
+                for path in outermost(&changed) {
+                    if let Err(e) = control.send(&format!("rescan {}", path.display())) {
+                        eprintln!("Failed to rescan {}: {}", path.display(), e);
+                    }
+                }
+                changed.clear();
+                continue;
+            }
+
+            let length = inotify.read(&mut buffer).map_err(|e| e.to_string())?;
+            let mut offset = 0;
+            while offset + mem::size_of::<libc::inotify_event>() <= length {
+                let event: libc::inotify_event =
+                    unsafe { ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+                let name_start = offset + mem::size_of::<libc::inotify_event>();
+                let name = &buffer[name_start..name_start + event.len as usize];
+                offset = name_start + event.len as usize;
+
+                // Очередь ядра переполнилась: что изменилось, неизвестно
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    changed.insert(self.root.clone());
+                    continue;
+                }
+                if event.mask & libc::IN_IGNORED != 0 {
+                    dirs.remove(&event.wd);
+                    continue;
+                }
+                let Some(dir) = dirs.get(&event.wd) else {
+                    continue;
+                };
+                let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+                let path = dir.join(OsStr::from_bytes(name));
+                if event.mask & libc::IN_ISDIR != 0
+                    && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+                {
+                    watch_tree(fd, &path, &mut dirs);
+                }
+                changed.insert(path);
+            }
+        }
+    }
+}
+
+// Inotify не рекурсивен: каждая папка дерева наблюдается отдельно
+fn watch_tree(fd: i32, root: &Path, dirs: &mut HashMap<i32, PathBuf>) {
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(name) = CString::new(dir.as_os_str().as_bytes()) else {
+            continue;
+        };
+        let wd = unsafe { libc::inotify_add_watch(fd, name.as_ptr(), EVENTS) };
+        if wd < 0 {
+            // Обычно кончился fs.inotify.max_user_watches
+            eprintln!(
+                "Cannot watch {}: {}",
+                dir.display(),
+                io::Error::last_os_error()
+            );
+            continue;
+        }
+        if let Ok(entries) = fs::read_dir(&dir) {
+            pending.extend(
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_dir() && !path.is_symlink()),
+            );
+        }
+        dirs.insert(wd, dir);
+    }
+}
+
+// Вложенные пути уже покрыты пересканированием родителя
+fn outermost(paths: &BTreeSet<PathBuf>) -> Vec<&PathBuf> {
+    let mut result: Vec<&PathBuf> = Vec::new();
+    for path in paths {
+        if result.last().is_none_or(|last| !path.starts_with(last)) {
+            result.push(path);
+        }
+    }
+    result
+}
@@ -0,0 +1,84 @@
+use crate::digest::{base64, sha1};
+use crate::events::{self, Connection};
+use std::io::{self, Write};
+use std::sync::mpsc::{self, SyncSender};
+use std::time::Duration;
+use tiny_http::{Header, Request, Response};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Сколько событий может ждать отправки медленному клиенту; дальше он отключается,
+// чтобы не тормозить плеер, как и подписчик сокета с истёкшим таймаутом записи
+const BACKLOG: usize = 64;
+
+// События для веб-интерфейса: то же, что subscribe в сокете, но текстовыми
+// сообщениями WebSocket. Сообщения клиента не читаются — канал только на отправку
+pub fn serve(request: Request, topics: Vec<&'static str>) {
+    let key = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.as_str().trim().to_string());
+    let Some(key) = key else {
+        let response = Response::from_string("WebSocket upgrade required").with_status_code(426);
+        let _ = request.respond(response);
+        return;
+    };
+
+    let accept = base64(&sha1(format!("{}{}", key, GUID).as_bytes()));
+    let response = Response::empty(101).with_header(
+        Header::from_bytes("Sec-WebSocket-Accept", accept.as_bytes())
+            .expect("base64 header is valid"),
+    );
+    let mut stream = request.upgrade("websocket", response);
+
+    let (sender, receiver) = mpsc::sync_channel(BACKLOG);
+    events::subscribe(Box::new(WebSocket { sender }), topics);
+    for message in receiver {
+        if stream.write_all(&frame(&message)).is_err() || stream.flush().is_err() {
+            break;
+        }
+    }
+}
+
+// Подписчик событий: каждая строка — отдельное сообщение, отправляемое потоком запроса
+struct WebSocket {
+    sender: SyncSender<Vec<u8>>,
+}
+
+impl Write for WebSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = buf.strip_suffix(b"\n").unwrap_or(buf);
+        self.sender
+            .try_send(text.to_vec())
+            .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e.to_string()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for WebSocket {
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Текстовый кадр; сервер кадры не маскирует
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
@@ -0,0 +1,149 @@
+use nsmp::{handle_command, headless, Config, MusicPlayer};
+use rodio::Sink;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+struct Player {
+    dir: PathBuf,
+    player: Arc<Mutex<MusicPlayer>>,
+    sink: Arc<Mutex<Sink>>,
+}
+
+impl Player {
+    // Своя временная папка на тест: три трека и файлы плеера рядом с ними
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("nsmp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let music = dir.join("music");
+        fs::create_dir_all(&music).unwrap();
+        for track in ["a.wav", "b.wav", "c.wav"] {
+            fs::write(music.join(track), silence()).unwrap();
+        }
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "hotkeys": {},
+            "volume": 0.5,
+            "music_dir": path("music"),
+            "database": path("db.json"),
+            "library_db": path("library.db"),
+            "state_file": null,
+            "playlists_dir": path("playlists"),
+            "contexts_dir": path("contexts"),
+        }))
+        .unwrap();
+        let (player, sink) = headless(&config).unwrap();
+        Player { dir, player, sink }
+    }
+
+    fn send(&self, cmd: &str) -> String {
+        handle_command(&self.player, &self.sink, cmd, None)
+    }
+
+    fn json(&self, cmd: &str) -> Value {
+        let reply = self.send(cmd);
+        serde_json::from_str(&reply).unwrap_or_else(|e| panic!("{}: {:?}", e, reply))
+    }
+
+    fn track(&self) -> String {
+        let status = self.json("status");
+        let track = Path::new(status["track"].as_str().unwrap());
+        track.file_name().unwrap().to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Десятая доля секунды тишины: WAV, 8 кГц, моно, 16 бит
+fn silence() -> Vec<u8> {
+    let data = vec![0u8; 1600];
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&8000u32.to_le_bytes());
+    wav.extend_from_slice(&16000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+    wav
+}
+
+#[test]
+fn next_and_prev_walk_the_library() {
+    let player = Player::new("walk");
+    assert_eq!(player.track(), "a.wav");
+    assert_eq!(player.send("next"), "");
+    assert_eq!(player.track(), "b.wav");
+    assert_eq!(player.send("next"), "");
+    assert_eq!(player.track(), "c.wav");
+    assert_eq!(player.send("prev"), "");
+    assert_eq!(player.track(), "b.wav");
+}
+
+#[test]
+fn volume_is_set_and_clamped() {
+    let player = Player::new("volume");
+    assert_eq!(player.json("volume")["percent"], 50);
+    assert_eq!(player.json("volume 40")["percent"], 40);
+    assert_eq!(player.json("volume +80")["percent"], 100);
+    assert!(player.send("volume loud").starts_with("ERR invalid volume"));
+    assert_eq!(player.json("volume")["percent"], 100);
+}
+
+// В историю команд попадают только выполненные команды
+#[test]
+fn history_skips_failed_commands() {
+    let player = Player::new("history");
+    assert_eq!(player.send("shuffle on"), "");
+    assert_eq!(
+        player.send("frobnicate"),
+        "ERR unknown command: frobnicate\n"
+    );
+    assert!(player.send("shuffle sideways").starts_with("ERR"));
+    let history = player.json("history commands");
+    let commands: Vec<&str> = history["commands"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["command"].as_str().unwrap())
+        .collect();
+    assert_eq!(commands, ["shuffle on"]);
+}
+
+#[test]
+fn whoami_reports_the_sender() {
+    let player = Player::new("whoami");
+    assert_eq!(player.send("whoami"), "anonymous\n");
+    let reply = handle_command(&player.player, &player.sink, "whoami", Some("alice"));
+    assert_eq!(reply, "alice\n");
+}
+
+// Контекст, сохранённый из другого, после переключения остаётся текущим
+#[test]
+fn context_switch_keeps_the_target_name() {
+    let player = Player::new("context");
+    assert_eq!(player.send("context save focus"), "");
+    assert_eq!(player.send("next"), "");
+    assert_eq!(player.send("context switch focus"), "");
+    assert_eq!(player.json("context list")["current"], "focus");
+    assert_eq!(player.track(), "a.wav");
+    assert_eq!(player.send("next"), "");
+    assert_eq!(player.send("next"), "");
+    assert_eq!(player.send("context switch default"), "");
+    assert_eq!(player.json("context list")["current"], "default");
+    assert_eq!(player.track(), "b.wav");
+    assert_eq!(player.send("context switch focus"), "");
+    assert_eq!(player.json("context list")["current"], "focus");
+    assert_eq!(player.track(), "c.wav");
+}